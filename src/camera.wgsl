@@ -0,0 +1,44 @@
+// The camera uniform every scene pipeline binds at group 0, pulled in with #include "camera.wgsl"
+struct CameraUniform {
+    view_proj: mat4x4<f32>,
+    // (mode, zfar, c, 1 / log2(1 + c * zfar)), mode is 0 standard, 1 logarithmic, 2 reversed with c 1 if infinite
+    depth_params: vec4<f32>,
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    inv_view: mat4x4<f32>,
+    inv_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // Eye position in the same space as the instances, w is 1
+    position: vec4<f32>,
+    // (znear, zfar, aspect, tan(fovy / 2))
+    projection_params: vec4<f32>,
+    // x is seconds the scene has advanced, y the seconds since the last frame. For animating
+    // with, see SceneRenderer::advance
+    time: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+// Moves depth onto a log scale, which keeps precision over huge view distances
+fn apply_depth_mode(clip: vec4<f32>) -> vec4<f32> {
+    if camera.depth_params.x < 0.5 || camera.depth_params.x > 1.5 {
+        return clip;
+    }
+    let depth = log2(max(1e-6, 1.0 + camera.depth_params.z * clip.w)) * camera.depth_params.w;
+    return vec4<f32>(clip.xy, depth * clip.w, clip.w);
+}
+
+// Inverse of the depth mapping, gives the view distance a depth buffer value came from
+fn linearize_depth(depth: f32, znear: f32) -> f32 {
+    let zfar = camera.depth_params.y;
+    if camera.depth_params.x < 0.5 {
+        return zfar * znear / (zfar - depth * (zfar - znear));
+    }
+    if camera.depth_params.x > 1.5 {
+        if camera.depth_params.z > 0.5 {
+            return znear / depth;
+        }
+        return zfar * znear / (znear + depth * (zfar - znear));
+    }
+    return (exp2(depth / camera.depth_params.w) - 1.0) / camera.depth_params.z;
+}
@@ -0,0 +1,77 @@
+// Textures pressed onto whatever the view drew inside a box, see types::decal
+#include "camera.wgsl"
+
+struct DecalUniform {
+    model: mat4x4<f32>,
+    inverse_model: mat4x4<f32>,
+    color: vec4<f32>,
+    // (cosine of the max angle, 1 if textured, 0, 0)
+    params: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> decal: DecalUniform;
+@group(1) @binding(1)
+var scene_depth: texture_2d<f32>;
+
+@group(2) @binding(0)
+var decal_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var decal_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Divided out in the fragment shader, where on the view this is
+    @location(0) clip: vec4<f32>,
+};
+
+// A unit cube, wound counter clockwise from outside
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> VertexOutput {
+    var indices = array<u32, 36>(
+        5u, 1u, 3u, 5u, 3u, 7u,
+        0u, 4u, 6u, 0u, 6u, 2u,
+        6u, 7u, 3u, 6u, 3u, 2u,
+        0u, 1u, 5u, 0u, 5u, 4u,
+        4u, 5u, 7u, 4u, 7u, 6u,
+        1u, 0u, 2u, 1u, 2u, 3u,
+    );
+    let corner = indices[vertex];
+    let position = vec3<f32>(f32(corner & 1u), f32((corner >> 1u) & 1u), f32((corner >> 2u) & 1u)) - 0.5;
+    var out: VertexOutput;
+    out.clip = camera.view_proj * decal.model * vec4<f32>(position, 1.0);
+    out.clip_position = apply_depth_mode(out.clip);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Back to where the view drew, through what's in the depth buffer here
+    var depth = textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0).r;
+    if camera.depth_params.x > 0.5 && camera.depth_params.x < 1.5 {
+        let clip = camera.proj * vec4<f32>(0.0, 0.0, -linearize_depth(depth, camera.projection_params.x), 1.0);
+        depth = clip.z / clip.w;
+    }
+    let world = camera.inv_view_proj * vec4<f32>(in.clip.xy / in.clip.w, depth, 1.0);
+    let position = world.xyz / world.w;
+    let local = (decal.inverse_model * vec4<f32>(position, 1.0)).xyz;
+    let uv = vec2<f32>(local.x + 0.5, 0.5 - local.y);
+
+    // The surface's normal turned towards the camera, and how squarely it faces the decal
+    var normal = normalize(cross(dpdx(position), dpdy(position)));
+    if dot(normal, camera.position.xyz - position) < 0.0 {
+        normal = -normal;
+    }
+    let facing = dot(normal, normalize(decal.model[2].xyz));
+    let cutoff = decal.params.x;
+    let fade = smoothstep(cutoff, min(cutoff + 0.1, 1.0), facing);
+
+    var color = decal.color;
+    if decal.params.y > 0.5 {
+        color *= textureSampleGrad(decal_texture, decal_sampler, uv, dpdx(uv), dpdy(uv));
+    }
+    // Also catches pixels where nothing was drawn and the position came out as infinity
+    if !all(abs(local) <= vec3<f32>(0.5)) || fade <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, color.a * fade);
+}
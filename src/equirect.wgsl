@@ -0,0 +1,50 @@
+// Renders one face of a cubemap from an equirectangular image, see Cubemap::from_equirect
+struct Face {
+    // Which face, in wgpu's +x, -x, +y, -y, +z, -z order
+    index: u32,
+};
+@group(0) @binding(0)
+var<uniform> face: Face;
+@group(0) @binding(1)
+var equirect: texture_2d<f32>;
+@group(0) @binding(2)
+var equirect_sampler: sampler;
+
+const PI: f32 = 3.14159265;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle over the whole face
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(vertex == 1u) * 4.0 - 1.0, f32(vertex == 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+// The direction a texel of the face looks along. u runs right and v down across each face, as
+// seen from inside the cube
+fn face_direction(index: u32, u: f32, v: f32) -> vec3<f32> {
+    switch index {
+        case 0u: { return vec3<f32>(1.0, -v, -u); }
+        case 1u: { return vec3<f32>(-1.0, -v, u); }
+        case 2u: { return vec3<f32>(u, 1.0, v); }
+        case 3u: { return vec3<f32>(u, -1.0, -v); }
+        case 4u: { return vec3<f32>(u, -v, 1.0); }
+        default: { return vec3<f32>(-u, -v, -1.0); }
+    }
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(face_direction(face.index, in.ndc.x, -in.ndc.y));
+    // The image's middle looks down -z, with +x a quarter of the way to the right of it
+    let uv = vec2<f32>(atan2(direction.x, -direction.z) / (2.0 * PI) + 0.5, acos(clamp(direction.y, -1.0, 1.0)) / PI);
+    // A single level, and the seam where the longitude wraps would throw off derivatives anyway
+    return textureSampleLevel(equirect, equirect_sampler, uv, 0.0);
+}
@@ -0,0 +1,25 @@
+// Marks the frame as frozen after a caught panic, see types::error_overlay
+struct OverlayUniform {
+    // (width, height, border thickness, unused) in pixels
+    size: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> overlay: OverlayUniform;
+
+// One triangle over the whole screen
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// A solid red border with the frozen scene dimmed and tinted inside it
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = position.xy;
+    let edge = min(min(p.x, overlay.size.x - p.x), min(p.y, overlay.size.y - p.y));
+    if edge < overlay.size.z {
+        return vec4<f32>(0.8, 0.05, 0.05, 1.0);
+    }
+    return vec4<f32>(0.3, 0.0, 0.0, 0.35);
+}
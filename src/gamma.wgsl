@@ -0,0 +1,24 @@
+// Copies a finished frame onto a surface that stores colors as they are, encoding them to sRGB
+// on the way, see target::WindowTarget
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+
+// One triangle over the whole surface
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn encode(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        return c * 12.92;
+    }
+    return 1.055 * pow(c, 1.0 / 2.4) - 0.055;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = clamp(textureLoad(frame, vec2<i32>(position.xy), 0), vec4<f32>(0.0), vec4<f32>(1.0));
+    return vec4<f32>(encode(color.r), encode(color.g), encode(color.b), color.a);
+}
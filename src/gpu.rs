@@ -0,0 +1,82 @@
+use crate::types::sampler::SamplerConfig;
+
+// The device everything is drawn with. Shared by every window and scene, and usable without a
+// window at all
+pub struct Gpu {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    // Highest anisotropy level samplers can use on this adapter
+    pub max_anisotropy: u16,
+}
+
+impl Gpu {
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    pub fn create_instance() -> wgpu::Instance {
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            #[cfg(not(target_arch="wasm32"))]
+            backends: wgpu::Backends::PRIMARY,
+            #[cfg(target_arch="wasm32")]
+            backends: wgpu::Backends::GL,
+            ..Default::default()
+        })
+    }
+
+    // Picks an adapter that can draw to compatible_surface if there is one. Creating a surface
+    // needs the instance, so it's passed in rather than made here
+    pub async fn new(instance: wgpu::Instance, compatible_surface: Option<&wgpu::Surface<'_>>) -> Self {
+        let adapter = instance.request_adapter(
+            &wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface,
+                force_fallback_adapter: false,
+            },
+        ).await.unwrap();
+
+        let (device, queue) = adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                // Lets multisampling use the counts this adapter supports beyond the guaranteed 1 and 4,
+                // the frame governor use GPU timings, and KTX2 textures stay compressed when it can
+                required_features: adapter.features()
+                    & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                        | wgpu::Features::TIMESTAMP_QUERY
+                        | wgpu::Features::TEXTURE_COMPRESSION_BC
+                        | wgpu::Features::TEXTURE_COMPRESSION_ETC2
+                        | wgpu::Features::TEXTURE_COMPRESSION_ASTC),
+                // WebGL doesn't support all of wgpu's features, so if
+                // we're building for the web, we'll have to disable some.
+                required_limits: if cfg!(target_arch = "wasm32") {
+                    wgpu::Limits::downlevel_webgl2_defaults()
+                } else {
+                    wgpu::Limits::default()
+                },
+                label: Some("Renderer Device"),
+                memory_hints: Default::default(),
+            },
+            None, // Trace path
+        ).await.unwrap();
+
+        let max_anisotropy = SamplerConfig::max_anisotropy(&adapter);
+
+        Self { instance, adapter, device, queue, max_anisotropy }
+    }
+
+    // For rendering into textures with no window, like tests and thumbnails. Any backend will do
+    // without a surface to match, which lets it fall back to GL where nothing else is installed
+    pub async fn headless() -> Self {
+        Self::new(wgpu::Instance::default(), None).await
+    }
+
+    // Sample counts the adapter can draw to format with, alongside a depth_format depth buffer
+    pub fn supported_msaa_samples(&self, format: wgpu::TextureFormat, depth_format: wgpu::TextureFormat) -> Vec<u32> {
+        let adapter_specific = self.device.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        let color_flags = self.adapter.get_texture_format_features(format).flags;
+        let depth_flags = self.adapter.get_texture_format_features(depth_format).flags;
+        [1, 2, 4, 8, 16]
+            .into_iter()
+            .filter(|&count| adapter_specific || count == 1 || count == 4)
+            .filter(|&count| color_flags.sample_count_supported(count) && depth_flags.sample_count_supported(count))
+            .collect()
+    }
+}
@@ -0,0 +1,45 @@
+// Draws a finished frame through a 3D lookup table onto the real target, see types::grading
+struct Grading {
+    // Strength in w
+    domain_min: vec4<f32>,
+    // The table's size in w
+    domain_max: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+@group(0) @binding(1)
+var lut: texture_3d<f32>;
+@group(0) @binding(2)
+var lut_sampler: sampler;
+@group(0) @binding(3)
+var<uniform> grading: Grading;
+
+// One triangle over the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn encode(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn decode(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(frame, vec2<i32>(position.xy), 0);
+    // Tables are made for sRGB encoded colors, while the frame holds linear ones
+    let encoded = encode(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    let t = clamp((encoded - grading.domain_min.xyz) / (grading.domain_max.xyz - grading.domain_min.xyz), vec3<f32>(0.0), vec3<f32>(1.0));
+    // Onto the middles of the first and last texels, so the ends of the range hit the first and
+    // last entries exactly
+    let size = grading.domain_max.w;
+    let uvw = (t * (size - 1.0) + 0.5) / size;
+    let graded = decode(max(textureSampleLevel(lut, lut_sampler, uvw, 0.0).rgb, vec3<f32>(0.0)));
+    return vec4<f32>(mix(color.rgb, graded, grading.domain_min.w), color.a);
+}
@@ -0,0 +1,110 @@
+// Tint and outline over the mesh instance under the cursor, and a dot where the cursor's ray hit
+// it, see types::hover
+#include "camera.wgsl"
+
+struct HoverUniform {
+    // Premultiplied by their alpha
+    tint: vec4<f32>,
+    outline_color: vec4<f32>,
+    marker_color: vec4<f32>,
+    // xyz where the ray hit, w is 1 if there's a marker to draw
+    marker_position: vec4<f32>,
+    // (outline width, marker size, target width, target height), sizes in pixels
+    sizes: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> hover: HoverUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(11) normal_matrix_0: vec3<f32>,
+    @location(12) normal_matrix_1: vec3<f32>,
+    @location(13) normal_matrix_2: vec3<f32>,
+};
+
+fn world_position(model: VertexInput, instance: InstanceInput) -> vec4<f32> {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    return model_matrix * vec4<f32>(model.position, 1.0);
+}
+
+@vertex
+fn vs_tint(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    return apply_depth_mode(camera.view_proj * world_position(model, instance));
+}
+
+@fragment
+fn fs_tint() -> @location(0) vec4<f32> {
+    return hover.tint;
+}
+
+// The back faces pushed out along their normals by the outline's width on screen. Only the rim
+// around the edge shows, the front faces cover the rest
+@vertex
+fn vs_outline(model: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+    let clip = apply_depth_mode(camera.view_proj * world_position(model, instance));
+    let normal = (camera.view_proj * vec4<f32>(normal_matrix * model.normal, 0.0)).xy;
+    // Meshes without normals get no outline
+    if dot(normal, normal) < 1e-12 {
+        return clip;
+    }
+    let offset = normalize(normal) * hover.sizes.x * 2.0 / hover.sizes.zw;
+    return vec4<f32>(clip.xy + offset * clip.w, clip.zw);
+}
+
+@fragment
+fn fs_outline() -> @location(0) vec4<f32> {
+    return hover.outline_color;
+}
+
+struct MarkerOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+};
+
+// A dot the same size on screen however far away the hit is
+@vertex
+fn vs_marker(@builtin(vertex_index) vertex: u32) -> MarkerOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    var out: MarkerOutput;
+    out.corner = corner;
+    let clip = camera.view_proj * vec4<f32>(hover.marker_position.xyz, 1.0);
+    if hover.marker_position.w < 0.5 || clip.w <= 0.0 {
+        out.clip_position = vec4<f32>(0.0);
+        return out;
+    }
+    let offset = corner * hover.sizes.y / hover.sizes.zw;
+    out.clip_position = vec4<f32>(clip.xy / clip.w + offset, 0.5, 1.0);
+    return out;
+}
+
+// A ring with a dark edge so it shows up on light and dark surfaces alike
+@fragment
+fn fs_marker(in: MarkerOutput) -> @location(0) vec4<f32> {
+    let r = length(in.corner);
+    let disc = 1.0 - smoothstep(0.85, 1.0, r);
+    let inner = 1.0 - smoothstep(0.45, 0.6, r);
+    let color = mix(vec3<f32>(0.0), hover.marker_color.rgb, inner);
+    return vec4<f32>(color, 1.0) * disc * hover.marker_color.a;
+}
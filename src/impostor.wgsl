@@ -0,0 +1,52 @@
+// Camera facing quads showing baked pictures of distant instances, see types::impostor
+#include "camera.wgsl"
+
+@group(1) @binding(0)
+var atlas: texture_2d<f32>;
+@group(1) @binding(1)
+var atlas_sampler: sampler;
+
+struct BillboardInput {
+    // xyz is the middle of the instance, w the radius it was baked with
+    @location(0) sphere: vec4<f32>,
+    // The tile in the atlas, left top right bottom in uv
+    @location(1) rect: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, billboard: BillboardInput) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    // Turned to face the eye the same way the bake camera was, so the picture lines up
+    let towards = normalize(camera.position.xyz - billboard.sphere.xyz);
+    var right = cross(camera.inv_view[1].xyz, towards);
+    if dot(right, right) < 1e-6 {
+        right = camera.inv_view[0].xyz;
+    }
+    right = normalize(right);
+    let up = cross(towards, right);
+    let world = billboard.sphere.xyz + (right * corner.x + up * corner.y) * billboard.sphere.w;
+
+    var out: VertexOutput;
+    out.uv = mix(billboard.rect.xw, billboard.rect.zy, corner * 0.5 + 0.5);
+    out.clip_position = apply_depth_mode(camera.view_proj * vec4<f32>(world, 1.0));
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(atlas, atlas_sampler, in.uv);
+    // Cut out rather than blended so billboards don't need sorting
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb / color.a, 1.0);
+}
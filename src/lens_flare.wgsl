@@ -0,0 +1,121 @@
+// Sun glare and lens flare ghosts along the line from the sun through the middle of the view,
+// faded by how much of the sun the scene hides, see types::lens_flare
+#include "camera.wgsl"
+
+const MAX_ELEMENTS: u32 = 16u;
+// Probes are laid out in a PROBE_GRID by PROBE_GRID square over the sun
+const PROBE_GRID: u32 = 4u;
+
+struct FlareElement {
+    // rgb, a scales the whole element
+    color: vec4<f32>,
+    // (position along the axis, size, 1 if textured, 0)
+    shape: vec4<f32>,
+};
+
+struct FlareUniform {
+    // xyz towards the sun, w is half the probed square's size
+    sun_direction: vec4<f32>,
+    // The sun's color and intensity, times the flare's own intensity
+    sun_color: vec4<f32>,
+    elements: array<FlareElement, MAX_ELEMENTS>,
+};
+@group(1) @binding(0)
+var<uniform> flare: FlareUniform;
+
+// The occlusion query results for this view, a u64 per probe. Only whether any sample passed matters,
+// GL only ever writes 0 or 1
+struct Visibility {
+    samples: array<vec4<u32>, 8>,
+};
+@group(2) @binding(0)
+var<uniform> visibility: Visibility;
+
+@group(3) @binding(0)
+var element_texture: texture_2d<f32>;
+@group(3) @binding(1)
+var element_sampler: sampler;
+
+// Where the sun is on screen. w is 0 when it's behind the camera
+fn sun_ndc() -> vec3<f32> {
+    let clip = camera.view_proj * vec4<f32>(flare.sun_direction.xyz, 0.0);
+    if clip.w <= 1e-6 {
+        return vec3<f32>(0.0);
+    }
+    return vec3<f32>(clip.xy / clip.w, 1.0);
+}
+
+// Corners of two triangles from -1 to 1
+fn quad_corner(vertex: u32) -> vec2<f32> {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    return corners[vertex];
+}
+
+// One small square of the grid over the sun per instance, each in its own occlusion query. It's
+// drawn at the far plane, so it only passes where nothing of the scene is in front of the sky
+@vertex
+fn vs_probe(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> @builtin(position) vec4<f32> {
+    let sun = sun_ndc();
+    if sun.z < 0.5 {
+        return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    }
+    let half_size = vec2<f32>(flare.sun_direction.w / camera.projection_params.z, flare.sun_direction.w);
+    let cell = vec2<f32>(f32(instance % PROBE_GRID), f32(instance / PROBE_GRID));
+    let uv = (cell + quad_corner(vertex) * 0.5 + 0.5) / f32(PROBE_GRID);
+    let position = sun.xy + (uv * 2.0 - 1.0) * half_size;
+    // Standard and logarithmic depth clear to 1, reversed to 0
+    let far = select(1.0, 0.0, camera.depth_params.x > 1.5);
+    return vec4<f32>(position, far, 1.0);
+}
+
+// 0 when the scene covers the sun, 1 when it's in plain view
+fn sun_visibility() -> f32 {
+    var visible = 0u;
+    for (var i = 0u; i < 8u; i++) {
+        let pair = visibility.samples[i];
+        visible += u32((pair.x | pair.y) != 0u) + u32((pair.z | pair.w) != 0u);
+    }
+    return f32(visible) / f32(PROBE_GRID * PROBE_GRID);
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) textured: f32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let element = flare.elements[instance];
+    let sun = sun_ndc();
+    let strength = sun_visibility() * sun.z * element.color.a;
+    var out: VertexOutput;
+    if strength <= 0.0 {
+        out.clip_position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        return out;
+    }
+    // Position 0 is on the sun, 1 the middle of the view, and 2 mirrored across it
+    let center = sun.xy * (1.0 - element.shape.x);
+    let corner = quad_corner(vertex);
+    let half_size = vec2<f32>(element.shape.y / camera.projection_params.z, element.shape.y);
+    out.clip_position = vec4<f32>(center + corner * half_size, 0.0, 1.0);
+    out.corner = corner;
+    out.color = vec4<f32>(element.color.rgb * flare.sun_color.rgb, strength);
+    out.textured = element.shape.z;
+    return out;
+}
+
+// Added onto the scene, so black doesn't change anything
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = in.corner * vec2<f32>(0.5, -0.5) + 0.5;
+    let texel = textureSample(element_texture, element_sampler, uv);
+    // Without a texture it's a soft round glow
+    let falloff = 1.0 - saturate(length(in.corner));
+    let shape = select(vec4<f32>(vec3<f32>(falloff * falloff), 1.0), texel, in.textured > 0.5);
+    return vec4<f32>(in.color.rgb * shape.rgb * shape.a * in.color.a, 0.0);
+}
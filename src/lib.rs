@@ -1,58 +1,117 @@
-use cgmath::Vector3;
-use wgpu::util::DeviceExt;
+use std::sync::Arc;
+
+// Enters a tracing span that lasts until the end of the enclosing scope. Does nothing without the
+// tracing feature, so it costs nothing there
+macro_rules! trace_span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::info_span!($name $(, $($fields)*)?).entered();
+    };
+}
+
 use winit::{
     dpi::PhysicalSize, event::*, event_loop::EventLoop, keyboard::{KeyCode, PhysicalKey}, window::{Window, WindowBuilder}
 };
 
-mod types;
+pub mod types;
+pub mod gpu;
+pub mod target;
+pub mod scene;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod render_thread;
+use gpu::Gpu;
+use target::{FormatError, WindowTarget};
+use scene::SceneRenderer;
 use types::{
     color::Color, 
-    geometry::Vertex,
-    camera::*
+    geometry::{Vertex, Instance},
+    mesh::{MeshData, Mesh, DynamicMesh, FaceSettings},
+    camera::*,
+    camera_path::CameraPath,
+    sequence::{Sequence, SequenceEvent},
+    sampler::{SamplerConfig, SamplerError},
+    texture::Texture,
+    residency::StreamingSettings,
+    material::{Material, MaterialAnimator},
+    oit::TransparencyMode,
+    sky::SkySettings,
+    cubemap::Cubemap,
+    skybox::SkyboxSettings,
+    lighting::Lighting,
+    weather::WeatherSettings,
+    lens_flare::LensFlareSettings,
+    decal::Decal,
+    grading::ColorLut,
+    palette::Palette,
+    hover::HoverSettings,
+    stats::{FrameStats, PassTimes, StatsRecorder},
+    error_overlay::ErrorOverlay,
+    picking::{Pick, MeshId, RayHit},
+    settings::{Settings, CameraSettings},
+    quality::{QualityPreset, QualitySettings},
+    governor::FrameGovernor,
+    ray::Ray,
+    validation::ValidationReport,
+    triangulate,
+    voxel::VoxelWorld,
 };
 
 
+// Logs to stderr, filtered by RUST_LOG, unless the application already set up a logger of its
+// own. run calls this, anything embedding the renderer can set up logging however it likes first
+pub fn init_logging() {
+    let _ = env_logger::try_init();
+}
+
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen(start))]
 pub async fn run() {
     let vertices: &[Vertex] = &[
-        Vertex { position: [0.0, 0.5, 0.0], color: Color::new_hsv(36.0 * 10.0, 1.0, 1.0) },
-        Vertex { position: [0.17634, 0.24271, 0.0], color: Color::new_hsv(36.0 * 1.0, 1.0, 1.0) },
-        Vertex { position: [0.47553, 0.15451, 0.0], color: Color::new_hsv(36.0 * 2.0, 1.0, 1.0) },
-        Vertex { position: [0.28532, -0.09271, 0.0], color: Color::new_hsv(36.0 * 3.0, 1.0, 1.0) },
-        Vertex { position: [0.29389, -0.40451, 0.0], color: Color::new_hsv(36.0 * 4.0, 1.0, 1.0) },
-        Vertex { position: [0.0, -0.3, 0.0], color: Color::new_hsv(36.0 * 5.0, 1.0, 1.0) },
-        Vertex { position: [-0.29389, -0.40451, 0.0], color: Color::new_hsv(36.0 * 6.0, 1.0, 1.0) },
-        Vertex { position: [-0.28532, -0.09271, 0.0], color: Color::new_hsv(36.0 * 7.0, 1.0, 1.0) },
-        Vertex { position: [-0.47553, 0.15451, 0.0], color: Color::new_hsv(36.0 * 8.0, 1.0, 1.0) },
-        Vertex { position: [-0.17634, 0.24271, 0.0], color: Color::new_hsv(36.0 * 9.0, 1.0, 1.0) },
+        Vertex::new([0.0, 0.5, 0.0], Color::new_hsv(36.0 * 10.0, 1.0, 1.0)),
+        Vertex::new([0.17634, 0.24271, 0.0], Color::new_hsv(36.0 * 1.0, 1.0, 1.0)),
+        Vertex::new([0.47553, 0.15451, 0.0], Color::new_hsv(36.0 * 2.0, 1.0, 1.0)),
+        Vertex::new([0.28532, -0.09271, 0.0], Color::new_hsv(36.0 * 3.0, 1.0, 1.0)),
+        Vertex::new([0.29389, -0.40451, 0.0], Color::new_hsv(36.0 * 4.0, 1.0, 1.0)),
+        Vertex::new([0.0, -0.3, 0.0], Color::new_hsv(36.0 * 5.0, 1.0, 1.0)),
+        Vertex::new([-0.29389, -0.40451, 0.0], Color::new_hsv(36.0 * 6.0, 1.0, 1.0)),
+        Vertex::new([-0.28532, -0.09271, 0.0], Color::new_hsv(36.0 * 7.0, 1.0, 1.0)),
+        Vertex::new([-0.47553, 0.15451, 0.0], Color::new_hsv(36.0 * 8.0, 1.0, 1.0)),
+        Vertex::new([-0.17634, 0.24271, 0.0], Color::new_hsv(36.0 * 9.0, 1.0, 1.0)),
     ];
     
-    let indicies: &[u16] = &[
-        //FRONT
-        0, 1, 9,
-        1, 2, 3,
-        3, 4, 5,
-        5, 6, 7,
-        7, 8, 9,
-        9, 1, 3,
-        9, 3, 7,
-        3, 5, 7,
-
-        //BACK
-        9, 1, 0, 
-        3, 2, 1, 
-        5, 4, 3, 
-        7, 6, 5, 
-        9, 8, 7, 
-        3, 1, 9, 
-        7, 3, 9, 
-        7, 5, 3, 
-    ];
-    env_logger::init();
+    init_logging();
     let event_loop = EventLoop::new().unwrap();
-    let window = WindowBuilder::new().with_inner_size(PhysicalSize::new(2000, 2000)).build(&event_loop).unwrap();
+    let mut settings = Settings::load();
+    let [width, height] = settings.window_size.unwrap_or([2000, 2000]);
+    let mut builder = WindowBuilder::new().with_inner_size(PhysicalSize::new(width, height));
+    if let Some([x, y]) = settings.window_position {
+        builder = builder.with_position(winit::dpi::PhysicalPosition::new(x, y));
+    }
+    let window = Arc::new(builder.build(&event_loop).unwrap());
+    #[cfg(target_arch = "wasm32")]
+    {
+        types::web::attach_canvas(&window);
+        types::web::sync_canvas_size(&window);
+    }
+
+    let instances = &[Instance::default()];
 
-    let mut state = State::new(&window, vertices, indicies).await;
+    let mesh = triangulate::polygon_mesh(vertices, &[], wgpu::FrontFace::Cw);
+
+    let mut state = State::new(window).await;
+    // The decagon is flat, so draw it from both sides instead of duplicating every triangle
+    state.add_mesh(&mesh, instances, FaceSettings::DOUBLE_SIDED);
+    state.apply_settings(&settings);
+    // The page can close without a CloseRequested, so on the web the settings are kept up to date
+    // every frame and saved whenever the page is hidden
+    #[cfg(target_arch = "wasm32")]
+    let page_settings = std::rc::Rc::new(std::cell::RefCell::new(settings.clone()));
+    #[cfg(target_arch = "wasm32")]
+    {
+        let page_settings = page_settings.clone();
+        types::web::on_page_hide(move || page_settings.borrow().save());
+    }
     let mut surface_configured = false;
+    let mut last_frame = web_time::Instant::now();
 
     event_loop.run(move |event, control_flow| {
         match event {
@@ -71,26 +130,59 @@ pub async fn run() {
                                 ..
                             },
                         ..
-                    } => control_flow.exit(),
+                    } => {
+                        state.store_settings(&mut settings);
+                        settings.save();
+                        control_flow.exit();
+                    },
                     WindowEvent::Resized(physical_size) => {
                         surface_configured = true;
+                        let was_paused = state.is_paused();
                         state.resize(*physical_size);
+                        if was_paused && !state.is_paused() {
+                            state.window().request_redraw();
+                        }
+                    },
+                    WindowEvent::Occluded(occluded) => {
+                        let was_paused = state.is_paused();
+                        state.set_occluded(*occluded);
+                        if was_paused && !state.is_paused() {
+                            state.window().request_redraw();
+                        }
                     },
                     WindowEvent::RedrawRequested => {
+                        // Stop the redraw loop while there's nothing to see, it's restarted when
+                        // the window is restored
+                        if state.is_paused() {
+                            return;
+                        }
+
                         // This tells winit that we want another frame after this one
                         state.window().request_redraw();
+
+                        // The page can resize the canvas without the window hearing about it
+                        #[cfg(target_arch = "wasm32")]
+                        {
+                            types::web::sync_canvas_size(state.window());
+                            state.store_settings(&mut page_settings.borrow_mut());
+                        }
             
                         if !surface_configured {
                             return;
                         }
             
-                        state.update();
+                        // Movement is scaled by the time between frames so it's the same speed at any frame rate.
+                        // Capped so a long stall (dragging the window, a breakpoint) doesn't teleport the camera
+                        let now = web_time::Instant::now();
+                        let dt = (now - last_frame).min(MAX_FRAME_TIME).as_secs_f32();
+                        last_frame = now;
+                        state.update(dt);
                         match state.render() {
                             Ok(_) => {}
                             // Reconfigure the surface if it's lost or outdated
                             Err(
                                 wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated,
-                            ) => state.resize(state.size),
+                            ) => state.resize(state.size()),
                             // The system is out of memory, we should probably quit
                             Err(wgpu::SurfaceError::OutOfMemory) => {
                                 log::error!("OutOfMemory");
@@ -106,334 +198,1097 @@ pub async fn run() {
                     _ => {}
                 }
             },
+            Event::DeviceEvent { ref event, .. } => {
+                state.device_input(event);
+            },
             _ => {}
         }
     })
     .expect("FUCK!");
 }
 
-struct State<'a> {
-    camera: Camera,
-    camera_uniform: CameraUniform,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
-    camera_controller: CameraController,
-
-    surface: wgpu::Surface<'a>,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
-    size: winit::dpi::PhysicalSize<u32>,
-    // The window must be declared after the surface so
-    // it gets dropped after it as the surface contains
-    // unsafe references to the window's resources.
-    window: &'a Window,
-
-    clear_color: wgpu::Color,
-
-    render_pipeline: wgpu::RenderPipeline,
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-
-    n_indicies: u32,
+// Longest frame the camera controller is told about
+const MAX_FRAME_TIME: std::time::Duration = std::time::Duration::from_millis(100);
+
+// Called at the start of every update with the time since the last one, see State::set_update_hook
+pub type UpdateHook = Box<dyn FnMut(&mut State, f32)>;
+// Called after the scene has been drawn into the frame, to draw more over it
+pub type DrawHook = Box<dyn FnMut(&Gpu, &wgpu::TextureView)>;
+
+pub struct State {
+    gpu: Gpu,
+    scene: SceneRenderer,
+    target: WindowTarget,
+    camera_controller: Box<dyn CameraController>,
+    // Mesh instance whose position is passed to the camera controller every update
+    follow: Option<(MeshId, usize)>,
+    // Drives the active camera instead of the controller while it's playing
+    camera_path: Option<CameraPath>,
+    // The path moved the camera last update
+    camera_path_playing: bool,
+    // Scripted changes to the scene and camera, applied every update while it plays
+    sequence: Option<Sequence>,
+
+    quality: QualitySettings,
+    // What's actually in use, quality turned down by the governor if there is one
+    active_quality: QualitySettings,
+    // None once the quality has been changed by hand
+    quality_preset: Option<QualityPreset>,
+    governor: Option<FrameGovernor>,
+    // None if the platform has no gamepad support
+    #[cfg(feature = "gamepad")]
+    gamepads: Option<types::gamepad::Gamepads>,
+    // Set by watch_shaders
+    #[cfg(feature = "hot-reload")]
+    shader_watcher: Option<types::hot_reload::ShaderWatcher>,
+    // The file that last failed to reload and why, shown in the title until it compiles
+    #[cfg(feature = "hot-reload")]
+    shader_error: Option<(String, String)>,
+
+    // The cursor is locked and hidden, and mouse movement comes from raw device motion
+    cursor_grabbed: bool,
+    // Raw mouse motion since take_mouse_delta was last called, only gathered while grabbed
+    mouse_delta: [f64; 2],
+    // In pixels, None while it's outside the window or grabbed
+    cursor_position: Option<[f32; 2]>,
+    // Raycast under the cursor every update, highlighting what it hits
+    hover_mode: bool,
+
+    // Every frame's stats while a performance run is being recorded
+    stats_recorder: Option<StatsRecorder>,
+    // The last update's dt and how long it took, in seconds, waiting to be recorded with the frame
+    update_timing: [f32; 2],
+
+    update_hook: Option<UpdateHook>,
+    draw_hook: Option<DrawHook>,
+    // What a hook panicked with. Updates stop and the overlay is drawn until resume
+    frame_error: Option<String>,
+    error_overlay: Option<ErrorOverlay>,
+    // The window's own title while the error or what's hovered is shown after it
+    window_title: Option<String>,
 }
 
-impl<'a> State<'a> {
+impl State {
     // Creating some of the wgpu types requires async code
-    async fn new(window: &'a Window, vertices: &[Vertex], indices: &[u16]) -> State<'a> {        
-        let size = window.inner_size();
-
-        let n_indicies = indices.len() as u32;
-
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            #[cfg(not(target_arch="wasm32"))]
-            backends: wgpu::Backends::PRIMARY,
-            #[cfg(target_arch="wasm32")]
-            backends: wgpu::Backends::GL,
-            ..Default::default()
+    pub async fn new(window: Arc<Window>) -> State {
+        // The adapter has to be able to draw to the window, so the surface comes first
+        let instance = Gpu::create_instance();
+        let surface = instance.create_surface(window.clone()).unwrap();
+        let gpu = Gpu::new(instance, Some(&surface)).await;
+        let target = WindowTarget::from_surface(&gpu, window, surface);
+        let size = target.size();
+        let scene = SceneRenderer::new(&gpu, target.view_format(), [size.width, size.height]);
+
+        Self {
+            gpu,
+            scene,
+            target,
+            camera_controller: Box::new(OrbitCameraController::new(3.0)),
+            follow: None,
+            camera_path: None,
+            camera_path_playing: false,
+            sequence: None,
+
+            quality: QualityPreset::Medium.settings(),
+            active_quality: QualityPreset::Medium.settings(),
+            quality_preset: Some(QualityPreset::Medium),
+            governor: None,
+            #[cfg(feature = "gamepad")]
+            gamepads: types::gamepad::Gamepads::new(),
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: None,
+            #[cfg(feature = "hot-reload")]
+            shader_error: None,
+
+            cursor_grabbed: false,
+            mouse_delta: [0.0, 0.0],
+            cursor_position: None,
+            hover_mode: false,
+
+            stats_recorder: None,
+            update_timing: [0.0, 0.0],
+
+            update_hook: None,
+            draw_hook: None,
+            frame_error: None,
+            error_overlay: None,
+            window_title: None,
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        self.target.window()
+    }
+
+    pub fn gpu(&self) -> &Gpu {
+        &self.gpu
+    }
+
+    pub fn target(&self) -> &WindowTarget {
+        &self.target
+    }
+
+    pub fn scene(&self) -> &SceneRenderer {
+        &self.scene
+    }
+
+    pub fn scene_mut(&mut self) -> &mut SceneRenderer {
+        &mut self.scene
+    }
+
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.target.size()
+    }
+
+    // Hands drawing over to a thread of its own, fed through RenderHandles. The camera controller,
+    // paths and quality settings stay behind with State, the thread only draws what it's told to
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn into_render_thread(self) -> render_thread::RenderThread {
+        render_thread::RenderThread::spawn(self.gpu, self.target, self.scene)
+    }
+
+    // Uploads a mesh and returns its id, drawn once for every instance
+    pub fn add_mesh(&mut self, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        self.scene.add_mesh(&self.gpu, data, instances, faces)
+    }
+
+    // Same as add_mesh, with a name for its buffers and draws in graphics debuggers
+    pub fn add_named_mesh(&mut self, name: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        self.scene.add_named_mesh(&self.gpu, name, data, instances, faces)
+    }
+
+    pub fn mesh(&self, id: usize) -> Option<&Mesh> {
+        self.scene.mesh(id)
+    }
+
+    pub fn mesh_mut(&mut self, id: usize) -> Option<&mut Mesh> {
+        self.scene.mesh_mut(id)
+    }
+
+    // Safe at any time, the mesh's buffers outlive any frame still drawing it
+    pub fn remove_mesh(&mut self, id: usize) -> bool {
+        self.scene.remove_mesh(id)
+    }
+
+    pub fn set_instances(&mut self, id: usize, instances: &[Instance]) {
+        self.scene.set_instances(&self.gpu, id, instances);
+    }
+
+    // Replaces a mesh's geometry, keeping its instances and face settings
+    pub fn set_mesh_data(&mut self, id: usize, data: &MeshData) {
+        self.scene.set_mesh_data(&self.gpu, id, data);
+    }
+
+    // Uploads a texture for materials to use, sampled with config or the default sampler settings
+    pub fn add_texture(&mut self, texture: Texture, config: Option<SamplerConfig>) -> usize {
+        self.scene.add_texture(&self.gpu, texture, config)
+    }
+
+    // A PNG, JPEG or anything else the image crate can read
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_texture(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        self.scene.load_texture(&self.gpu, path)
+    }
+
+    pub fn remove_texture(&mut self, id: usize) -> bool {
+        self.scene.remove_texture(id)
+    }
+
+    // See SceneRenderer::load_streamed_texture
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_streamed_texture(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        self.scene.load_streamed_texture(&self.gpu, path)
+    }
+
+    pub fn set_streaming_settings(&mut self, settings: StreamingSettings) {
+        self.scene.set_streaming_settings(settings);
+    }
+
+    // See SceneRenderer::add_texture_array
+    pub fn add_texture_array(&mut self, texture: Texture, config: Option<SamplerConfig>) -> usize {
+        self.scene.add_texture_array(&self.gpu, texture, config)
+    }
+
+    pub fn remove_texture_array(&mut self, id: usize) -> bool {
+        self.scene.remove_texture_array(id)
+    }
+
+    // A procedural sky with drifting clouds behind the scene, None for the clear color
+    pub fn set_sky(&mut self, settings: Option<SkySettings>) {
+        self.scene.set_sky(&self.gpu, settings);
+    }
+
+    // A cubemap behind the scene in place of the sky, see Cubemap::from_images and from_equirect
+    pub fn set_skybox(&mut self, cubemap: Option<Cubemap>) {
+        self.scene.set_skybox(&self.gpu, cubemap);
+    }
+
+    pub fn set_skybox_settings(&mut self, settings: SkyboxSettings) {
+        self.scene.set_skybox_settings(settings);
+    }
+
+    // Rain or snow around the camera, None to stop it
+    pub fn set_weather(&mut self, settings: Option<WeatherSettings>) {
+        self.scene.set_weather(&self.gpu, settings);
+    }
+
+    // Glare and ghosts from the lighting's sun, None to turn them off
+    pub fn set_lens_flare(&mut self, settings: Option<LensFlareSettings>) {
+        self.scene.set_lens_flare(&self.gpu, settings);
+    }
+
+    // See SceneRenderer::add_decal
+    pub fn add_decal(&mut self, decal: Decal) -> usize {
+        self.scene.add_decal(decal)
+    }
+
+    pub fn set_decal(&mut self, id: usize, decal: Decal) -> bool {
+        self.scene.set_decal(id, decal)
+    }
+
+    pub fn remove_decal(&mut self, id: usize) -> bool {
+        self.scene.remove_decal(id)
+    }
+
+    // A look from a grading tool applied to every frame, None for none. See ColorLut
+    pub fn set_color_grading(&mut self, lut: Option<&ColorLut>) {
+        self.scene.set_color_grading(&self.gpu, lut);
+    }
+
+    pub fn set_color_grading_strength(&mut self, strength: f32) {
+        self.scene.set_color_grading_strength(strength);
+    }
+
+    // Snaps every frame to the palette's colors, None to turn it off. See SceneRenderer::set_palette
+    pub fn set_palette(&mut self, palette: Option<&Palette>) {
+        self.scene.set_palette(&self.gpu, palette);
+    }
+
+    pub fn set_palette_dithering(&mut self, dithering: f32) {
+        self.scene.set_palette_dithering(dithering);
+    }
+
+    // Lights materials with ShaderFeatures::PBR
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.scene.set_lighting(lighting);
+    }
+
+    // Returns its id for set_mesh_material
+    pub fn add_material(&mut self, material: Material) -> usize {
+        self.scene.add_material(&self.gpu, material)
+    }
+
+    // See SceneRenderer::set_material
+    pub fn set_material(&mut self, id: usize, material: Material) -> bool {
+        self.scene.set_material(&self.gpu, id, material)
+    }
+
+    // Meshes still using it go back to the default material
+    pub fn remove_material(&mut self, id: usize) -> bool {
+        self.scene.remove_material(id)
+    }
+
+    // None draws the mesh with the default material
+    pub fn set_mesh_material(&mut self, id: usize, material: Option<usize>) {
+        self.scene.set_mesh_material(id, material);
+    }
+
+    // See SceneRenderer::set_material_animator
+    pub fn set_material_animator(&mut self, id: usize, animator: Option<MaterialAnimator>) -> bool {
+        self.scene.set_material_animator(id, animator)
+    }
+
+    // See SceneRenderer::set_mesh_tint
+    pub fn set_mesh_tint(&mut self, id: MeshId, tint: Color) {
+        self.scene.set_mesh_tint(id, tint);
+    }
+
+    // See SceneRenderer::sync_voxels
+    pub fn sync_voxels(&mut self, world: &mut VoxelWorld) {
+        self.scene.sync_voxels(&self.gpu, world);
+    }
+
+    // Uploads a mesh whose vertices can be changed every frame through dynamic_mesh_mut
+    pub fn add_dynamic_mesh(&mut self, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        self.scene.add_dynamic_mesh(&self.gpu, data, instances, faces)
+    }
+
+    pub fn add_named_dynamic_mesh(&mut self, name: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        self.scene.add_named_dynamic_mesh(&self.gpu, name, data, instances, faces)
+    }
+
+    pub fn dynamic_mesh(&self, id: usize) -> Option<&DynamicMesh> {
+        self.scene.dynamic_mesh(id)
+    }
+
+    pub fn dynamic_mesh_mut(&mut self, id: usize) -> Option<&mut DynamicMesh> {
+        self.scene.dynamic_mesh_mut(id)
+    }
+
+    pub fn remove_dynamic_mesh(&mut self, id: usize) -> bool {
+        self.scene.remove_dynamic_mesh(id)
+    }
+
+    // Checks every mesh for problems that would make it render wrong and logs what it finds
+    pub fn validate(&self) -> ValidationReport {
+        self.scene.validate()
+    }
+
+    pub fn camera(&self) -> &Camera {
+        self.scene.camera()
+    }
+
+    // Call mark_dirty on it after changing its public fields
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        self.scene.camera_mut()
+    }
+
+    // See SceneRenderer::add_camera
+    pub fn add_camera(&mut self, camera: Camera) -> usize {
+        self.scene.add_camera(camera)
+    }
+
+    pub fn camera_by_id(&self, id: usize) -> Option<&Camera> {
+        self.scene.camera_by_id(id)
+    }
+
+    pub fn camera_by_id_mut(&mut self, id: usize) -> Option<&mut Camera> {
+        self.scene.camera_by_id_mut(id)
+    }
+
+    pub fn active_camera(&self) -> usize {
+        self.scene.active_camera()
+    }
+
+    // Switches which camera is drawn from and moved by the controller
+    pub fn set_active_camera(&mut self, id: usize) {
+        if self.scene.set_active_camera(id) {
+            self.attach_controller();
+        }
+    }
+
+    // As of the last update
+    pub fn camera_matrices(&self) -> &CameraMatrices {
+        self.scene.camera_matrices()
+    }
+
+    // Point in the world under a pixel, given the depth buffer's value there. Uses the matrices
+    // from the last update so it matches what was drawn
+    pub fn unproject(&self, pixel: [f32; 2], depth: f32) -> cgmath::Point3<f32> {
+        self.scene.unproject(pixel, depth)
+    }
+
+    // Ray through a pixel using the matrices from the last update
+    pub fn screen_ray(&self, pixel: [f32; 2]) -> Ray {
+        self.scene.screen_ray(pixel)
+    }
+
+    // The mesh instance under a pixel, found by rendering ids rather than intersecting rays, so it
+    // matches exactly what was drawn. Waits for the GPU
+    pub fn pick(&mut self, pixel: [f32; 2]) -> Option<Pick> {
+        self.scene.pick(&self.gpu, pixel)
+    }
+
+    // Casts a ray under the cursor every update, tinting and outlining the mesh instance it hits
+    // and marking the point. Its name and id go after the window's title
+    pub fn set_hover_mode(&mut self, enabled: bool) {
+        self.hover_mode = enabled;
+        if !enabled {
+            self.set_hover(None);
+        }
+    }
+
+    pub fn hover_mode(&self) -> bool {
+        self.hover_mode
+    }
+
+    // What's under the cursor as of the last update, always None with hover mode off
+    pub fn hovered(&self) -> Option<RayHit> {
+        self.scene.hovered()
+    }
+
+    pub fn set_hover_settings(&mut self, settings: HoverSettings) {
+        self.scene.set_hover_settings(settings);
+    }
+
+    fn set_hover(&mut self, hit: Option<RayHit>) {
+        let changed = self.scene.hovered().map(|hit| hit.pick) != hit.map(|hit| hit.pick);
+        self.scene.set_hover(hit);
+        // The error stays in the title while frozen
+        if !changed || self.frame_error.is_some() {
+            return;
+        }
+        let name = hit.and_then(|hit| {
+            let label = self.scene.mesh_by_id(hit.pick.mesh)?.label();
+            Some(format!("{label} ({:?} instance {})", hit.pick.mesh, hit.pick.instance))
         });
-        
-        let surface = instance.create_surface(window).unwrap();
-
-        let adapter = instance.request_adapter(
-            &wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            },
-        ).await.unwrap();
+        self.set_title_suffix(name.as_deref());
+    }
 
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.scene.set_frustum_culling(enabled);
+    }
 
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::empty(),
-                // WebGL doesn't support all of wgpu's features, so if
-                // we're building for the web, we'll have to disable some.
-                required_limits: if cfg!(target_arch = "wasm32") {
-                    wgpu::Limits::downlevel_webgl2_defaults()
-                } else {
-                    wgpu::Limits::default()
-                },
-                label: None,
-                memory_hints: Default::default(),
-            },
-            None, // Trace path
-        ).await.unwrap();
-
-        let surface_caps = surface.get_capabilities(&adapter);
-        // Shader code in this tutorial assumes an sRGB surface texture. Using a different
-        // one will result in all the colors coming out darker. If you want to support non
-        // sRGB surfaces, you'll need to account for that when drawing to the frame.
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()) });
-
-        let vertex_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            }
-        );
-        
-        let index_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(indices),
-                usage: wgpu::BufferUsages::INDEX,
+    // See TransparencyMode
+    pub fn set_transparency_mode(&mut self, mode: TransparencyMode) {
+        self.scene.set_transparency_mode(&self.gpu, mode);
+    }
+
+    // From the last frame that was rendered
+    pub fn stats(&self) -> FrameStats {
+        self.scene.stats()
+    }
+
+    // Application code to run every update. Panics in it are caught: the scene freezes where it
+    // was, the error is logged and shown in the title with a red frame around the window, and
+    // nothing updates until resume. Setting a new hook, after reloading a script say, doesn't
+    // resume by itself
+    pub fn set_update_hook(&mut self, hook: Option<UpdateHook>) {
+        self.update_hook = hook;
+    }
+
+    // Like set_update_hook, for drawing over the scene every frame. Skipped while frozen
+    pub fn set_draw_hook(&mut self, hook: Option<DrawHook>) {
+        self.draw_hook = hook;
+    }
+
+    // What the hook that froze the scene panicked with
+    pub fn frame_error(&self) -> Option<&str> {
+        self.frame_error.as_deref()
+    }
+
+    // Carries on after a caught panic, also bound to F5
+    pub fn resume(&mut self) {
+        if self.frame_error.take().is_some() {
+            self.set_title_suffix(None);
+            // The time spent frozen shouldn't all land on the next update
+            self.update_timing = [0.0, 0.0];
+        }
+    }
+
+    // Reloads the scene's shaders from dir whenever they're saved, hot_reload::SHADER_DIR for the
+    // ones in this crate. If one doesn't compile the error is logged and its name shown in the
+    // title, and the last version that did keeps drawing
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_shaders(&mut self, dir: impl AsRef<std::path::Path>) -> notify::Result<()> {
+        self.shader_watcher = Some(types::hot_reload::ShaderWatcher::new(dir)?);
+        Ok(())
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub fn stop_watching_shaders(&mut self) {
+        self.shader_watcher = None;
+    }
+
+    // Why the last shader saved didn't compile, None once it does
+    #[cfg(feature = "hot-reload")]
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_ref().map(|(_, error)| error.as_str())
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &self.shader_watcher else { return };
+        for path in watcher.changed() {
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else { continue };
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(e) => {
+                    log::warn!("Couldn't read {}: {e}", path.display());
+                    continue;
+                }
+            };
+            match self.scene.reload_shader(&self.gpu, name, &source) {
+                Ok(false) => {}
+                Ok(true) => {
+                    log::info!("Reloaded {name}");
+                    if self.shader_error.as_ref().is_some_and(|(failed, _)| failed == name) {
+                        self.shader_error = None;
+                        // A panic's message stays until resume
+                        if self.frame_error.is_none() {
+                            self.set_title_suffix(None);
+                        }
+                    }
+                }
+                Err(error) => {
+                    log::error!("{name} didn't compile, keeping the last version that did: {error}");
+                    if self.frame_error.is_none() {
+                        self.set_title_suffix(Some(&format!("{name} didn't compile")));
+                    }
+                    self.shader_error = Some((name.to_string(), error));
+                }
             }
-        );
-        
-        let camera = Camera {
-            // position the camera 1 unit up and 2 units back
-            // +z is out of the screen
-            eye: (0.0, 0.0, 2.0).into(),
-            // have it look at the origin
-            target: (0.0, 0.0, 0.0).into(),
-            // which way is "up"
-            up: cgmath::Vector3::unit_y(),
-            aspect: size.width as f32 / size.height as f32,
-            fovy: 45.0,
-            znear: 0.1,
-            zfar: 100.0,
-            rotation: Vector3::new(0.0, 0.0, 0.0)
-        };   
-        
-        let mut camera_uniform = CameraUniform::new();
-        camera_uniform.update_view_proj(&camera);
-        
-        let camera_buffer = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Camera Buffer"),
-                contents: bytemuck::cast_slice(&[camera_uniform]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        }
+    }
+
+    // Runs a hook, freezing the frame if it panics. On the web panics abort, so there's nothing to catch
+    fn guard(&mut self, f: impl FnOnce(&mut Self)) {
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self)));
+        if let Err(payload) = result {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            log::error!("Frame stopped by a panic, press F5 to resume: {message}");
+            self.set_title_suffix(Some(&format!("stopped: {message}")));
+            self.frame_error = Some(message);
+        }
+    }
+
+    // There's no text drawing, so the error and what's hovered are shown after the window's title.
+    // None puts the title back
+    fn set_title_suffix(&mut self, suffix: Option<&str>) {
+        match suffix {
+            Some(suffix) => {
+                let window = self.target.window();
+                let title = self.window_title.get_or_insert_with(|| window.title());
+                window.set_title(&format!("{title} - {suffix}"));
             }
-        );
-        
-        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                }
-            ],
-            label: Some("camera_bind_group_layout"),
-        });
-            
-        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &camera_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: camera_buffer.as_entire_binding(),
+            None => {
+                if let Some(title) = self.window_title.take() {
+                    self.window().set_title(&title);
                 }
-            ],
-            label: Some("camera_bind_group"),
-        });        
-
-        let camera_controller = CameraController::new(0.05);
-
-        let render_pipeline_layout =
-        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[
-                &camera_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
+            }
+        }
+    }
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main", // 1.
-                buffers: &[
-                    Vertex::desc(),
-                ],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            },
-            fragment: Some(wgpu::FragmentState { // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState { // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Cw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1, // 2.
-                mask: !0, // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-            cache: None, // 6.
+    // Starts keeping the timings and counts of every frame from here on, replacing any run
+    // that was already going
+    pub fn start_stats_recording(&mut self) {
+        self.stats_recorder = Some(StatsRecorder::new());
+    }
+
+    // Ends the run and hands back what was recorded, to be saved as CSV or JSON
+    pub fn stop_stats_recording(&mut self) -> Option<StatsRecorder> {
+        self.stats_recorder.take()
+    }
+
+    pub fn stats_recorder(&self) -> Option<&StatsRecorder> {
+        self.stats_recorder.as_ref()
+    }
+
+    // Turns on floating origin rendering: instance positions are kept in f64 and uploaded relative
+    // to an origin that follows the camera, so huge scenes don't jitter far from (0, 0, 0)
+    pub fn set_origin_rebase_distance(&mut self, distance: Option<f32>) {
+        self.scene.set_origin_rebase_distance(distance);
+    }
+
+    pub fn default_sampler_config(&self) -> &SamplerConfig {
+        self.scene.default_sampler_config()
+    }
+
+    pub fn set_default_sampler_config(&mut self, config: SamplerConfig) {
+        self.scene.set_default_sampler_config(config);
+    }
+
+    // See SceneRenderer::validate_sampler_config
+    pub fn validate_sampler_config(&self, config: &SamplerConfig) -> Result<(), SamplerError> {
+        self.scene.validate_sampler_config(config)
+    }
+
+    // Highest anisotropy the adapter can filter with, quality settings may cap it lower
+    pub fn max_anisotropy(&self) -> u16 {
+        self.scene.max_anisotropy()
+    }
+
+    // See SceneRenderer::use_pixel_art_defaults
+    pub fn use_pixel_art_defaults(&mut self) {
+        self.scene.use_pixel_art_defaults(&self.gpu);
+    }
+
+    // Creates a sampler from the per-texture override if there is one, otherwise from the global default
+    pub fn create_sampler(&self, config: Option<&SamplerConfig>) -> wgpu::Sampler {
+        self.scene.create_sampler(&self.gpu, config)
+    }
+
+    // See SceneRenderer::sampler
+    pub fn sampler(&mut self, config: Option<&SamplerConfig>) -> std::sync::Arc<wgpu::Sampler> {
+        self.scene.sampler(&self.gpu, config)
+    }
+
+    // Only records the new size, see WindowTarget::resize
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        self.target.resize(new_size);
+        self.scene.set_viewport([new_size.width, new_size.height]);
+    }
+
+    pub fn set_occluded(&mut self, occluded: bool) {
+        self.target.set_occluded(occluded);
+    }
+
+    // True while the window is minimized or fully covered, rendering is skipped until it isn't
+    pub fn is_paused(&self) -> bool {
+        self.target.is_paused()
+    }
+
+    // See WindowTarget::set_live_resize_scale
+    pub fn set_live_resize_scale(&mut self, scale: Option<f32>) {
+        self.target.set_live_resize_scale(scale);
+    }
+
+    // See WindowTarget::set_render_scale
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.target.set_render_scale(scale);
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.target.render_scale()
+    }
+
+    // Without vsync frames are presented as soon as they're done, which can tear
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.target.set_vsync(vsync);
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.target.vsync()
+    }
+
+    pub fn set_quality_preset(&mut self, preset: QualityPreset) {
+        self.set_quality(preset.settings());
+        self.quality_preset = Some(preset);
+    }
+
+    // Pipelines and render targets that depend on it are rebuilt before the next frame. Sample counts
+    // the adapter can't do drop to the next one down
+    pub fn set_quality(&mut self, quality: QualitySettings) {
+        self.quality = quality;
+        self.quality_preset = None;
+        if let Some(governor) = &mut self.governor {
+            governor.reset();
+        }
+        self.apply_quality(quality);
+    }
+
+    fn apply_quality(&mut self, quality: QualitySettings) {
+        self.scene.set_msaa_samples(&self.gpu, quality.msaa_samples);
+        self.scene.set_anisotropy_limit(quality.anisotropy);
+        self.active_quality = quality;
+        self.set_render_scale(quality.render_scale);
+    }
+
+    // The quality being drawn at right now, which the governor may have turned down from quality
+    pub fn active_quality(&self) -> &QualitySettings {
+        &self.active_quality
+    }
+
+    // Turns quality down when frames go over the governor's budget and back up when they don't.
+    // Removing it goes straight back to full quality
+    pub fn set_frame_governor(&mut self, governor: Option<FrameGovernor>) {
+        self.governor = governor;
+        if self.governor.is_none() && self.active_quality != self.quality {
+            self.apply_quality(self.quality);
+        }
+    }
+
+    pub fn frame_governor(&self) -> Option<&FrameGovernor> {
+        self.governor.as_ref()
+    }
+
+    // Seconds the last measured frame's passes took on the GPU, None without timestamp queries
+    pub fn gpu_frame_time(&self) -> Option<f32> {
+        self.scene.gpu_frame_time()
+    }
+
+    // See SceneRenderer::gpu_pass_times
+    pub fn gpu_pass_times(&self) -> Option<PassTimes> {
+        self.scene.gpu_pass_times()
+    }
+
+    // See SceneRenderer::cpu_pass_times
+    pub fn cpu_pass_times(&self) -> PassTimes {
+        self.scene.cpu_pass_times()
+    }
+
+    pub fn quality(&self) -> &QualitySettings {
+        &self.quality
+    }
+
+    pub fn quality_preset(&self) -> Option<QualityPreset> {
+        self.quality_preset
+    }
+
+    // Samples per pixel actually in use
+    pub fn msaa_samples(&self) -> u32 {
+        self.scene.msaa_samples()
+    }
+
+    // Restores what was saved by store_settings. The window's geometry is applied when it's built in run
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.set_quality_preset(settings.quality);
+        self.set_vsync(settings.vsync);
+        self.set_render_scale(settings.render_scale);
+        if let Some(saved) = &settings.camera {
+            let camera = self.camera_mut();
+            camera.eye = saved.eye.into();
+            camera.target = saved.target.into();
+            camera.up = saved.up.into();
+            let [x, y, z, w] = saved.orientation;
+            camera.orientation = cgmath::Quaternion::new(w, x, y, z);
+            camera.origin = saved.origin.into();
+            let _ = camera.set_fovy(saved.fovy);
+            camera.mark_dirty();
+            self.camera_controller.attach(self.scene.camera());
+        }
+    }
+
+    // Writes the current preferences into settings, leaving anything the state doesn't know about alone
+    pub fn store_settings(&self, settings: &mut Settings) {
+        let window = self.window();
+        // A minimized or fullscreen window's size isn't the one to come back to
+        if !self.target.is_minimized() && window.fullscreen().is_none() {
+            let size = self.size();
+            settings.window_size = Some([size.width, size.height]);
+            settings.window_position = window.outer_position().ok().map(|p| [p.x, p.y]);
+        }
+        settings.vsync = self.vsync();
+        settings.render_scale = self.render_scale();
+        if let Some(preset) = self.quality_preset {
+            settings.quality = preset;
+        }
+        let camera = self.camera();
+        let q = camera.orientation;
+        settings.camera = Some(CameraSettings {
+            eye: camera.eye.into(),
+            target: camera.target.into(),
+            up: camera.up.into(),
+            orientation: [q.v.x, q.v.y, q.v.z, q.s],
+            origin: camera.origin.into(),
+            fovy: camera.fovy(),
         });
-        
-        Self {
-            camera,
-            camera_uniform,
-            camera_buffer,
-            camera_bind_group,
-            camera_controller,
-
-            window,
-            surface,
-            device,
-            queue,
-            config,
-            size,
-
-            clear_color: wgpu::Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
-                a: 1.0,
-            },
+    }
+
+    // Bookmarks the active camera's view in a file, see Camera::save
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_camera(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        self.camera().save(path)
+    }
+
+    // Goes back to a view written by save_camera, with the controller picking up from there
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_camera(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        self.camera_mut().load(path)?;
+        self.camera_controller.attach(self.scene.camera());
+        Ok(())
+    }
+
+    // See WindowTarget::set_format. The scene switches over to the new view format along with it
+    pub fn set_surface_format(&mut self, format: wgpu::TextureFormat, view_format: Option<wgpu::TextureFormat>) -> Result<(), FormatError> {
+        self.target.set_format(&self.gpu, format, view_format)?;
+        self.scene.set_format(&self.gpu, self.target.view_format());
+        Ok(())
+    }
 
-            render_pipeline,
-            vertex_buffer,
-            index_buffer,
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.target.format()
+    }
+
+    // Borderless fullscreen on the current monitor, or the browser's fullscreen on the web
+    pub fn toggle_fullscreen(&self) {
+        self.target.toggle_fullscreen();
+    }
+
+    // Has the camera controller follow a mesh instance, see FollowCameraController. Other
+    // controllers ignore it
+    pub fn follow_instance(&mut self, target: Option<(MeshId, usize)>) {
+        self.follow = target;
+    }
+
+    // A scripted move for the active camera, which takes over from the controller while it plays.
+    // Call play on it, before or after it's set
+    pub fn set_camera_path(&mut self, path: Option<CameraPath>) {
+        self.camera_path = path;
+    }
+
+    pub fn camera_path(&self) -> Option<&CameraPath> {
+        self.camera_path.as_ref()
+    }
 
-            n_indicies
+    pub fn camera_path_mut(&mut self) -> Option<&mut CameraPath> {
+        self.camera_path.as_mut()
+    }
+
+    // A timeline of camera paths, clips, visibility and color changes. Call play on it, before or
+    // after it's set. Camera paths it starts replace the one from set_camera_path
+    pub fn set_sequence(&mut self, sequence: Option<Sequence>) {
+        self.sequence = sequence;
+    }
+
+    pub fn sequence(&self) -> Option<&Sequence> {
+        self.sequence.as_ref()
+    }
+
+    pub fn sequence_mut(&mut self) -> Option<&mut Sequence> {
+        self.sequence.as_mut()
+    }
+
+    // Events aimed at meshes or instances that don't exist are ignored
+    fn apply_sequence_event(&mut self, event: SequenceEvent) {
+        match event {
+            SequenceEvent::PlayCameraPath(path) => self.camera_path = Some(path),
+            SequenceEvent::SetVisible { mesh, visible } => {
+                if let Some(mesh) = self.scene.mesh_mut(mesh) {
+                    mesh.visible = visible;
+                }
+            }
+            SequenceEvent::Transform { mesh, instance: index, position, rotation, scale } => {
+                let Some(mut instance) = self.mesh(mesh).and_then(|m| m.instances().get(index).copied()) else { return };
+                instance.position = position.unwrap_or(instance.position);
+                instance.rotation = rotation.unwrap_or(instance.rotation);
+                instance.scale = scale.unwrap_or(instance.scale);
+                self.scene.set_instance(&self.gpu, mesh, index, instance);
+            }
+            SequenceEvent::SetColor { mesh, instance: index, color } => {
+                let Some(mut instance) = self.mesh(mesh).and_then(|m| m.instances().get(index).copied()) else { return };
+                instance.color = color;
+                self.scene.set_instance(&self.gpu, mesh, index, instance);
+            }
         }
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
+    // Hands the camera over to a different controller
+    pub fn set_camera_controller(&mut self, controller: Box<dyn CameraController>) {
+        self.set_cursor_grabbed(controller.grabs_cursor());
+        self.camera_controller = controller;
+        self.attach_controller();
     }
 
-    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
-        if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
-            self.camera.aspect = new_size.width as f32 / new_size.height as f32;
+    // Locks and hides the cursor for mouse look. While grabbed, movement is read from raw
+    // DeviceEvent motion, which keeps coming at the edge of the screen and isn't accelerated by
+    // the OS, and CursorMoved is ignored. Released when the window loses focus and grabbed again
+    // on the next click if the controller wants it
+    pub fn set_cursor_grabbed(&mut self, grab: bool) {
+        set_cursor_grab(self.window(), grab);
+        self.cursor_grabbed = grab;
+        self.mouse_delta = [0.0, 0.0];
+    }
+
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    // Raw mouse movement in device units since the last call, for mouse look that isn't done
+    // by a camera controller. Always zero while the cursor isn't grabbed
+    pub fn take_mouse_delta(&mut self) -> [f32; 2] {
+        let delta = std::mem::take(&mut self.mouse_delta);
+        [delta[0] as f32, delta[1] as f32]
+    }
+
+    // Hands the active camera to the controller, in the mode it expects
+    fn attach_controller(&mut self) {
+        let camera = self.scene.camera_mut();
+        if let Some(mode) = self.camera_controller.camera_mode() {
+            camera.mode = mode;
         }
+        self.camera_controller.attach(camera);
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
-        self.camera_controller.process_events(event);
+        self.camera_controller.process_event(event);
         match event {
+            // Switches between orbiting the target and flying around with first person mouse look
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::KeyF), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } => {
+                if self.camera_controller.grabs_cursor() {
+                    self.set_camera_controller(Box::new(OrbitCameraController::new(3.0)));
+                } else {
+                    self.set_camera_controller(Box::new(FpsCameraController::new(3.0, 0.003).with_inertia(Inertia::default())));
+                }
+                true
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::F11), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } => {
+                self.toggle_fullscreen();
+                true
+            },
+            // Cycles through the quality presets
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::F2), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } => {
+                let next = match self.quality_preset {
+                    Some(preset) => QualityPreset::ALL[(QualityPreset::ALL.iter().position(|p| *p == preset).unwrap() + 1) % QualityPreset::ALL.len()],
+                    None => QualityPreset::Low,
+                };
+                self.set_quality_preset(next);
+                log::info!("Quality: {next}");
+                true
+            },
+            // Cycles through the cameras
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::Tab), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } => {
+                self.set_active_camera((self.active_camera() + 1) % self.scene.camera_count());
+                true
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::F5), state: ElementState::Pressed, repeat: false, .. },
+                ..
+            } if self.frame_error.is_some() => {
+                self.resume();
+                true
+            },
+            // Alt-tabbing away shouldn't leave the cursor stuck
+            WindowEvent::Focused(false) if self.cursor_grabbed => {
+                self.set_cursor_grabbed(false);
+                false
+            },
+            // Clicking back in takes the cursor again. On the web this also has to wait for a
+            // click, browsers only allow pointer lock from an input event
+            WindowEvent::MouseInput { state: ElementState::Pressed, .. }
+                if !self.cursor_grabbed && self.camera_controller.grabs_cursor() => {
+                self.set_cursor_grabbed(true);
+                true
+            },
+            // The locked cursor's position means nothing
+            WindowEvent::CursorMoved { .. } if self.cursor_grabbed => true,
             WindowEvent::CursorMoved { position , ..} => {
-                self.clear_color = wgpu::Color {
-                    r: position.x / self.size.width as f64,
-                    g: position.y / self.size.height as f64,
+                self.cursor_position = Some([position.x as f32, position.y as f32]);
+                let size = self.size();
+                self.scene.set_clear_color(wgpu::Color {
+                    r: position.x / size.width as f64,
+                    g: position.y / size.height as f64,
                     b: 0.3,
                     a: 1.0,
-                };
+                });
                 true
             },
-            WindowEvent::KeyboardInput { event, .. } => {
-                match event {
-                    KeyEvent { physical_key: PhysicalKey::Code(KeyCode::Space), state: ElementState::Pressed, .. } => {
-                        true
-                    },
-                    _ => false
-                }
-            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+                false
+            },
+            WindowEvent::KeyboardInput {
+                event: KeyEvent { physical_key: PhysicalKey::Code(KeyCode::Space), state: ElementState::Pressed, .. },
+                ..
+            } => true,
             _ => false
         }
     }
 
-    fn update(&mut self) {
-        self.camera_controller.update_camera(&mut self.camera);
-        self.camera_uniform.update_view_proj(&self.camera);
-        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[self.camera_uniform]));
+    fn device_input(&mut self, event: &DeviceEvent) -> bool {
+        // Device events come whether or not the window has focus, so motion only counts while
+        // the cursor is ours
+        if let DeviceEvent::MouseMotion { delta } = event {
+            if !self.cursor_grabbed {
+                return false;
+            }
+            self.mouse_delta[0] += delta.0;
+            self.mouse_delta[1] += delta.1;
+        }
+        self.camera_controller.process_device_event(event)
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default()); 
-        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-            label: Some("Render Encoder"),
-        });
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-
-            render_pass.set_pipeline(&self.render_pipeline);
-            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..self.n_indicies, 0, 0..1);
+    // dt is the time since the last update in seconds
+    fn update(&mut self, dt: f32) {
+        trace_span!("update", dt);
+        // Before the freeze, fixing a shader shouldn't need a resume
+        #[cfg(feature = "hot-reload")]
+        self.reload_shaders();
+        // Frozen on a panic until resume
+        if self.frame_error.is_some() {
+            return;
         }
+        let started = web_time::Instant::now();
+        if let Some(mut hook) = self.update_hook.take() {
+            self.guard(|state| hook(state, dt));
+            // Unless the hook replaced itself
+            if self.update_hook.is_none() {
+                self.update_hook = Some(hook);
+            }
+            if self.frame_error.is_some() {
+                return;
+            }
+        }
+        if let Some(governor) = &mut self.governor {
+            // Without GPU timings the whole frame's time has to do, which can't drop below the
+            // refresh interval with vsync on
+            let frame_time = self.scene.gpu_frame_time().unwrap_or(dt);
+            if let Some(quality) = governor.update(frame_time, dt, &self.quality) {
+                self.apply_quality(quality);
+            }
+        }
+        if let Some(target) = self.follow.and_then(|(mesh, instance)| self.scene.follow_target(mesh, instance)) {
+            self.camera_controller.track(&target);
+        }
+        for id in 0..self.scene.camera_count() {
+            if let Some(camera) = self.scene.camera_by_id_mut(id) {
+                camera.advance_shake(dt);
+            }
+        }
+        self.scene.advance(dt);
+        let events = self.sequence.as_mut().map(|sequence| sequence.advance(dt)).unwrap_or_default();
+        for event in events {
+            self.apply_sequence_event(event);
+        }
+        let camera = self.scene.camera_mut();
+        match self.camera_path.as_mut().filter(|path| path.is_playing()) {
+            Some(path) => {
+                path.advance(dt);
+                path.apply(camera);
+                self.camera_path_playing = true;
+            }
+            None => {
+                // Carry on from wherever the path left the camera, whether it finished or was paused
+                if std::mem::take(&mut self.camera_path_playing) {
+                    self.attach_controller();
+                }
+                #[cfg(feature = "gamepad")]
+                if let Some(gamepads) = &mut self.gamepads {
+                    // Unplugging the gamepad lets go of everything
+                    self.camera_controller.process_gamepad(&gamepads.poll().unwrap_or_default());
+                }
+                self.camera_controller.update(self.scene.camera_mut(), dt);
+            }
+        }
+        self.scene.update(&self.gpu);
+        if self.hover_mode {
+            // A grabbed cursor isn't pointing at anything
+            let cursor = self.cursor_position.filter(|_| !self.cursor_grabbed);
+            let hit = cursor.and_then(|pixel| self.scene.raycast(&self.scene.screen_ray(pixel)));
+            self.set_hover(hit);
+        }
+        self.update_timing = [dt, started.elapsed().as_secs_f32()];
+    }
 
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        trace_span!("frame");
+        if self.target.apply_resize(&self.gpu) {
+            self.scene.resize_targets(&self.gpu, self.target.render_size());
+        }
+        let output = self.target.get_current_texture()?;
+        let view = self.target.create_view(&output);
+        let started = web_time::Instant::now();
+        self.scene.render(&self.gpu, &view);
+        if let Some(mut hook) = self.draw_hook.take() {
+            if self.frame_error.is_none() {
+                self.guard(|state| hook(&state.gpu, &view));
+            }
+            if self.draw_hook.is_none() {
+                self.draw_hook = Some(hook);
+            }
+        }
+        if self.frame_error.is_some() {
+            let format = self.target.view_format();
+            let overlay = match self.error_overlay.take() {
+                Some(overlay) if overlay.format() == format => overlay,
+                _ => ErrorOverlay::new(&self.gpu, format),
+            };
+            overlay.render(&self.gpu, &view, [output.texture.width(), output.texture.height()]);
+            self.error_overlay = Some(overlay);
+        }
+        let render_time = started.elapsed().as_secs_f32();
+        self.target.present(&self.gpu, output);
+        if let Some(recorder) = &mut self.stats_recorder {
+            let [dt, update_time] = self.update_timing;
+            recorder.record(dt, update_time, render_time, self.scene.cpu_pass_times(), self.scene.gpu_pass_times(), self.scene.stats());
+        }
 
         Ok(())
     }
 }
- 
@@ -0,0 +1,138 @@
+struct Camera2DUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // (width, height, units per pixel, zoom)
+    viewport: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: Camera2DUniform;
+
+struct Light {
+    // (x, y, height, radius)
+    position: vec4<f32>,
+    // Color times intensity, then the source radius
+    color: vec4<f32>,
+    // (casts shadows, unused...)
+    params: vec4<f32>,
+};
+
+struct LightingUniform {
+    // (edge count, shadow samples, light count, unused)
+    params: vec4<f32>,
+    lights: array<Light, 64>,
+    // (from x, from y, to x, to y)
+    edges: array<vec4<f32>, 256>,
+};
+@group(0) @binding(1)
+var<uniform> lighting: LightingUniform;
+
+// The normal buffer while lighting, the light map while compositing
+@group(1) @binding(0)
+var input: texture_2d<f32>;
+
+struct LightOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world: vec2<f32>,
+    @location(1) @interpolate(flat) light: u32,
+};
+
+// A quad over the light's radius, two triangles from the vertex index
+@vertex
+fn vs_light(@builtin(vertex_index) vertex: u32, @builtin(instance_index) light: u32) -> LightOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let position = lighting.lights[light].position;
+    var out: LightOutput;
+    out.world = position.xy + corners[vertex] * position.w;
+    out.clip_position = camera.view_proj * vec4<f32>(out.world, 0.0, 1.0);
+    out.light = light;
+    return out;
+}
+
+fn cross2(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+// Whether the segment from p to q crosses the one from a to b. Touching p doesn't count, so
+// pixels right on an occluder's edge aren't shadowed by it
+fn crosses(p: vec2<f32>, q: vec2<f32>, a: vec2<f32>, b: vec2<f32>) -> bool {
+    let r = q - p;
+    let s = b - a;
+    let denominator = cross2(r, s);
+    if abs(denominator) < 1e-8 {
+        return false;
+    }
+    let t = cross2(a - p, s) / denominator;
+    let u = cross2(a - p, r) / denominator;
+    return t > 1e-4 && t < 1.0 && u >= 0.0 && u <= 1.0;
+}
+
+// How much of the light reaches p, from rays to points spread across the light's width. Partly
+// blocked lights give the soft edges of a penumbra
+fn visibility(p: vec2<f32>, light: Light) -> f32 {
+    let edge_count = u32(lighting.params.x);
+    let to_light = light.position.xy - p;
+    if light.params.x < 0.5 || edge_count == 0u || length(to_light) < 1e-4 {
+        return 1.0;
+    }
+    let samples = max(u32(lighting.params.y), 1u);
+    let across = normalize(vec2<f32>(-to_light.y, to_light.x)) * light.color.w;
+    var lit = 0.0;
+    for (var i = 0u; i < samples; i++) {
+        var offset = 0.0;
+        if samples > 1u {
+            offset = f32(i) / f32(samples - 1u) * 2.0 - 1.0;
+        }
+        let towards = light.position.xy + across * offset;
+        var blocked = false;
+        for (var e = 0u; e < edge_count; e++) {
+            let edge = lighting.edges[e];
+            if crosses(p, towards, edge.xy, edge.zw) {
+                blocked = true;
+                break;
+            }
+        }
+        if !blocked {
+            lit += 1.0;
+        }
+    }
+    return lit / f32(samples);
+}
+
+@fragment
+fn fs_light(in: LightOutput) -> @location(0) vec4<f32> {
+    let light = lighting.lights[in.light];
+    let to_light = light.position.xy - in.world;
+    let distance = length(to_light) / light.position.w;
+    if distance >= 1.0 {
+        return vec4<f32>(0.0);
+    }
+    // Smooth all the way to nothing at the radius
+    let falloff = 1.0 - distance * distance;
+    let attenuation = falloff * falloff;
+
+    let packed = textureLoad(input, vec2<i32>(in.clip_position.xy), 0);
+    var normal = vec3<f32>(0.0, 0.0, 1.0);
+    if packed.a > 0.5 {
+        normal = normalize(packed.xyz * 2.0 - 1.0);
+    }
+    let direction = normalize(vec3<f32>(to_light, light.position.z));
+    let diffuse = max(dot(normal, direction), 0.0);
+
+    let shadow = visibility(in.world, light);
+    return vec4<f32>(light.color.rgb * attenuation * diffuse * shadow, 0.0);
+}
+
+// One triangle over the whole screen
+@vertex
+fn vs_composite(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_composite(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureLoad(input, vec2<i32>(position.xy), 0).rgb, 1.0);
+}
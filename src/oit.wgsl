@@ -0,0 +1,25 @@
+// Lays the transparent meshes a view accumulated over it, see types::oit
+@group(0) @binding(0)
+var accum_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var revealage_texture: texture_2d<f32>;
+
+// One triangle over the whole target, the scissor keeps it to the view
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The weighted average of every layer's color, covering as much as they all do together
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = vec2<i32>(position.xy);
+    let revealage = textureLoad(revealage_texture, p, 0).r;
+    // Nothing transparent here
+    if revealage > 0.9999 {
+        discard;
+    }
+    let accum = textureLoad(accum_texture, p, 0);
+    return vec4<f32>(accum.rgb / max(accum.a, 1e-5), 1.0 - revealage);
+}
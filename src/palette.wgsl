@@ -0,0 +1,78 @@
+// Snaps every pixel of a finished frame to the nearest palette color, see types::palette
+struct Params {
+    count: f32,
+    // 0 for none, 1 spreads pixels between two colors all the way
+    dithering: f32,
+}
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+// Linear colors in the first row, the same in OkLab in the second
+@group(0) @binding(1)
+var palette: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+// One triangle over the whole target
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((vertex << 1u) & 2u), f32(vertex & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Same as Color::to_oklab
+fn oklab(c: vec3<f32>) -> vec3<f32> {
+    let lms = vec3<f32>(
+        0.41222146 * c.r + 0.53633255 * c.g + 0.051445995 * c.b,
+        0.2119035 * c.r + 0.6806995 * c.g + 0.10739696 * c.b,
+        0.08830246 * c.r + 0.28171885 * c.g + 0.6299787 * c.b,
+    );
+    let l = pow(lms, vec3<f32>(1.0 / 3.0));
+    return vec3<f32>(
+        0.21045426 * l.x + 0.7936178 * l.y - 0.004072047 * l.z,
+        1.9779985 * l.x - 2.4285922 * l.y + 0.4505937 * l.z,
+        0.025904037 * l.x + 0.78277177 * l.y - 0.80867577 * l.z,
+    );
+}
+
+// 4x4 ordered dithering thresholds, spread evenly between 0 and 1
+fn bayer(p: vec2<u32>) -> f32 {
+    var matrix = array<f32, 16>(0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+    return (matrix[(p.y % 4u) * 4u + p.x % 4u] + 0.5) / 16.0;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(frame, vec2<i32>(position.xy), 0);
+    let lab = oklab(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)));
+    // The nearest two, so dithering can mix between them
+    var first = 0;
+    var second = 0;
+    var first_distance = 1e9;
+    var second_distance = 1e9;
+    for (var i = 0; i < i32(params.count); i++) {
+        let offset = textureLoad(palette, vec2<i32>(i, 1), 0).xyz - lab;
+        let distance = dot(offset, offset);
+        if distance < first_distance {
+            second = first;
+            second_distance = first_distance;
+            first = i;
+            first_distance = distance;
+        } else if distance < second_distance {
+            second = i;
+            second_distance = distance;
+        }
+    }
+    var chosen = first;
+    if params.dithering > 0.0 && second != first {
+        // How far along from the nearest to the next the pixel is, which is how many of the
+        // pixels around it should get the next one
+        let a = textureLoad(palette, vec2<i32>(first, 1), 0).xyz;
+        let b = textureLoad(palette, vec2<i32>(second, 1), 0).xyz;
+        let t = clamp(dot(lab - a, b - a) / max(dot(b - a, b - a), 1e-8), 0.0, 1.0) * params.dithering;
+        if t > bayer(vec2<u32>(position.xy)) {
+            chosen = second;
+        }
+    }
+    return vec4<f32>(textureLoad(palette, vec2<i32>(chosen, 0), 0).rgb, color.a);
+}
@@ -0,0 +1,59 @@
+// Draws every instance in a flat color that encodes which one it is, see types::picking
+struct PickUniform {
+    view_proj: mat4x4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> pick: PickUniform;
+
+struct MeshUniform {
+    // x is the id of the mesh's first instance
+    ids: vec4<u32>,
+};
+@group(1) @binding(0)
+var<uniform> mesh: MeshUniform;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+};
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+    instance: InstanceInput,
+    @builtin(instance_index) instance_index: u32,
+) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    var out: VertexOutput;
+    out.clip_position = pick.view_proj * model_matrix * vec4<f32>(model.position, 1.0);
+    // 0 is left for nothing
+    out.id = mesh.ids.x + instance_index + 1u;
+    return out;
+}
+
+// Split into bytes so it survives an rgba8 target, the only kind WebGL2 reliably reads back
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(
+        f32(in.id & 0xffu),
+        f32((in.id >> 8u) & 0xffu),
+        f32((in.id >> 16u) & 0xffu),
+        f32((in.id >> 24u) & 0xffu),
+    ) / 255.0;
+}
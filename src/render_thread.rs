@@ -0,0 +1,240 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc, Arc,
+};
+
+use crate::{
+    gpu::Gpu,
+    scene::SceneRenderer,
+    target::WindowTarget,
+    types::{
+        camera::Camera,
+        geometry::Instance,
+        mesh::{FaceSettings, MeshData},
+    },
+};
+
+// Runs on the render thread with full access to the scene
+pub type SceneFn = Box<dyn FnOnce(&mut SceneRenderer, &Gpu) + Send>;
+
+// A change to the scene, applied by the render thread before its next frame
+pub enum SceneCommand {
+    // Gets the next mesh id, which RenderHandle::add_mesh hands out ahead of time. name is for
+    // graphics debuggers, one is made up from the id without it
+    AddMesh { name: Option<String>, data: MeshData, instances: Vec<Instance>, faces: FaceSettings },
+    SetMeshData { mesh: usize, data: MeshData },
+    RemoveMesh(usize),
+    SetInstances { mesh: usize, instances: Vec<Instance> },
+    // Replaces one instance, for moving a single object
+    SetInstance { mesh: usize, index: usize, instance: Instance },
+    // Turns the active camera to look at target from eye, level with its frame's up
+    SetCamera { eye: cgmath::Point3<f32>, target: cgmath::Point3<f32> },
+    // Anything else about the active camera
+    UpdateCamera(Box<dyn FnOnce(&mut Camera) + Send>),
+    // Anything at all, run with the scene and the gpu on the render thread
+    Run(SceneFn),
+    Resize(winit::dpi::PhysicalSize<u32>),
+    SetOccluded(bool),
+    Shutdown,
+}
+
+// Sends commands to a render thread. Cheap to clone, so every thread that needs to change the
+// scene can have its own
+#[derive(Clone)]
+pub struct RenderHandle {
+    sender: mpsc::Sender<SceneCommand>,
+    // Mesh ids are given out as commands are sent, ahead of the meshes actually being added
+    next_mesh: Arc<AtomicUsize>,
+}
+
+impl RenderHandle {
+    // False once the render thread has stopped
+    pub fn send(&self, command: SceneCommand) -> bool {
+        self.sender.send(command).is_ok()
+    }
+
+    // Returns the id the mesh will have, which later commands can use straight away. Only correct
+    // while meshes are only added through handles
+    pub fn add_mesh(&self, data: MeshData, instances: Vec<Instance>, faces: FaceSettings) -> usize {
+        let id = self.next_mesh.fetch_add(1, Ordering::Relaxed);
+        self.send(SceneCommand::AddMesh { name: None, data, instances, faces });
+        id
+    }
+
+    pub fn add_named_mesh(&self, name: impl Into<String>, data: MeshData, instances: Vec<Instance>, faces: FaceSettings) -> usize {
+        let id = self.next_mesh.fetch_add(1, Ordering::Relaxed);
+        self.send(SceneCommand::AddMesh { name: Some(name.into()), data, instances, faces });
+        id
+    }
+
+    pub fn set_mesh_data(&self, mesh: usize, data: MeshData) {
+        self.send(SceneCommand::SetMeshData { mesh, data });
+    }
+
+    // Ids aren't reused, so ones handed out before stay correct
+    pub fn remove_mesh(&self, mesh: usize) {
+        self.send(SceneCommand::RemoveMesh(mesh));
+    }
+
+    pub fn set_instances(&self, mesh: usize, instances: Vec<Instance>) {
+        self.send(SceneCommand::SetInstances { mesh, instances });
+    }
+
+    pub fn set_instance(&self, mesh: usize, index: usize, instance: Instance) {
+        self.send(SceneCommand::SetInstance { mesh, index, instance });
+    }
+
+    pub fn set_camera(&self, eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>) {
+        self.send(SceneCommand::SetCamera { eye, target });
+    }
+
+    pub fn update_camera(&self, f: impl FnOnce(&mut Camera) + Send + 'static) {
+        self.send(SceneCommand::UpdateCamera(Box::new(f)));
+    }
+
+    pub fn run(&self, f: impl FnOnce(&mut SceneRenderer, &Gpu) + Send + 'static) {
+        self.send(SceneCommand::Run(Box::new(f)));
+    }
+
+    // Window events still arrive on the event loop's thread, these pass on the ones rendering cares about
+    pub fn resize(&self, size: winit::dpi::PhysicalSize<u32>) {
+        self.send(SceneCommand::Resize(size));
+    }
+
+    pub fn set_occluded(&self, occluded: bool) {
+        self.send(SceneCommand::SetOccluded(occluded));
+    }
+}
+
+// Renders on a thread of its own, so simulation code never has to touch GPU objects. Everything
+// else talks to it through a RenderHandle. The thread draws as fast as presenting allows, which
+// with vsync is once per refresh
+pub struct RenderThread {
+    handle: RenderHandle,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub fn spawn(gpu: Gpu, target: WindowTarget, scene: SceneRenderer) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let handle = RenderHandle {
+            sender,
+            next_mesh: Arc::new(AtomicUsize::new(scene.mesh_count())),
+        };
+        let thread = std::thread::Builder::new()
+            .name("render".into())
+            .spawn(move || render_loop(gpu, target, scene, receiver))
+            .expect("couldn't start the render thread");
+        Self { handle, thread: Some(thread) }
+    }
+
+    pub fn handle(&self) -> RenderHandle {
+        self.handle.clone()
+    }
+
+    // Stops the thread and waits for it. Also done on drop
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let Some(thread) = self.thread.take() else { return };
+        self.handle.send(SceneCommand::Shutdown);
+        if thread.join().is_err() {
+            log::error!("Render thread panicked");
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Longest time the shake and other camera effects are moved along in one frame
+const MAX_FRAME_TIME: std::time::Duration = std::time::Duration::from_millis(100);
+
+fn render_loop(gpu: Gpu, mut target: WindowTarget, mut scene: SceneRenderer, receiver: mpsc::Receiver<SceneCommand>) {
+    let mut last_frame = std::time::Instant::now();
+    loop {
+        // While paused there's nothing to draw, so wait for something to change instead of spinning
+        let next = if target.is_paused() {
+            match receiver.recv() {
+                Ok(command) => Some(command),
+                Err(_) => return,
+            }
+        } else {
+            match receiver.try_recv() {
+                Ok(command) => Some(command),
+                Err(mpsc::TryRecvError::Empty) => None,
+                // Every handle is gone, nothing can change the scene or stop the thread any more
+                Err(mpsc::TryRecvError::Disconnected) => return,
+            }
+        };
+        if let Some(command) = next {
+            if !apply(command, &gpu, &mut target, &mut scene) {
+                return;
+            }
+            continue;
+        }
+
+        trace_span!("frame");
+        let now = std::time::Instant::now();
+        let dt = (now - last_frame).min(MAX_FRAME_TIME).as_secs_f32();
+        last_frame = now;
+        scene.camera_mut().advance_shake(dt);
+        scene.advance(dt);
+        scene.update(&gpu);
+
+        if target.apply_resize(&gpu) {
+            scene.resize_targets(&gpu, target.render_size());
+        }
+        match target.get_current_texture() {
+            Ok(output) => {
+                let view = target.create_view(&output);
+                scene.render(&gpu, &view);
+                target.present(&gpu, output);
+            }
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => target.reconfigure(),
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                log::error!("OutOfMemory");
+                return;
+            }
+            Err(wgpu::SurfaceError::Timeout) => log::warn!("Surface timeout"),
+        }
+    }
+}
+
+// Returns false for Shutdown
+fn apply(command: SceneCommand, gpu: &Gpu, target: &mut WindowTarget, scene: &mut SceneRenderer) -> bool {
+    match command {
+        SceneCommand::AddMesh { name: Some(name), data, instances, faces } => {
+            scene.add_named_mesh(gpu, &name, &data, &instances, faces);
+        }
+        SceneCommand::AddMesh { name: None, data, instances, faces } => {
+            scene.add_mesh(gpu, &data, &instances, faces);
+        }
+        SceneCommand::SetMeshData { mesh, data } => scene.set_mesh_data(gpu, mesh, &data),
+        SceneCommand::RemoveMesh(mesh) => {
+            scene.remove_mesh(mesh);
+        }
+        SceneCommand::SetInstances { mesh, instances } => scene.set_instances(gpu, mesh, &instances),
+        SceneCommand::SetInstance { mesh, index, instance } => scene.set_instance(gpu, mesh, index, instance),
+        SceneCommand::SetCamera { eye, target } => {
+            let camera = scene.camera_mut();
+            camera.eye = eye;
+            camera.target = target;
+            camera.look_at_target();
+        }
+        SceneCommand::UpdateCamera(f) => f(scene.camera_mut()),
+        SceneCommand::Run(f) => f(scene, gpu),
+        SceneCommand::Resize(size) => {
+            target.resize(size);
+            scene.set_viewport([size.width, size.height]);
+        }
+        SceneCommand::SetOccluded(occluded) => target.set_occluded(occluded),
+        SceneCommand::Shutdown => return false,
+    }
+    true
+}
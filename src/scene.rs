@@ -0,0 +1,2034 @@
+use std::{collections::HashMap, sync::Arc};
+use cgmath::{EuclideanSpace, InnerSpace};
+use web_time::Instant;
+use wgpu::util::DeviceExt;
+
+use crate::gpu::Gpu;
+use crate::types::{
+    geometry::{Vertex, Instance, InstanceRaw},
+    mesh::{MeshData, Mesh, DynamicMesh, FaceSettings},
+    camera::*,
+    sampler::{SamplerCache, SamplerConfig, SamplerError},
+    stats::{FrameStats, Pass, PassTimes},
+    picking::{Picker, Pick, MeshId, RayHit},
+    ray::Ray,
+    timer::GpuTimer,
+    frames::FramesInFlight,
+    view::SceneView,
+    validation::{self, ValidationReport},
+    texture::{Texture, MeshTextures},
+    residency::{StreamingSettings, TextureStreamer},
+    voxel::VoxelWorld,
+    shader_features::{self, ShaderFeatures},
+    material::{Material, MaterialAnimator, MaterialKey, Materials, BlendMode},
+    impostor::{Impostors, ImpostorSettings},
+    cubemap::Cubemap,
+    sky::{Sky, SkySettings},
+    skybox::{Skybox, SkyboxSettings},
+    lighting::{Lighting, SceneLights, MAX_POINT_LIGHTS},
+    weather::{self, Weather, WeatherSettings},
+    lens_flare::{LensFlare, LensFlareSettings},
+    decal::{Decal, DecalRenderer},
+    grading::{ColorGrading, ColorLut},
+    palette::{Palette, PaletteRenderer},
+    hover::{Hover, HoverSettings},
+    tint::MeshTints,
+    oit::{self, TransparencyMode, WeightedBlendedOit},
+    color::Color,
+};
+#[cfg(feature = "hot-reload")]
+use crate::types::hot_reload;
+
+// The shaders the scene draws with that can be reloaded, by file name
+const SHADERS: [(&str, &str); 3] = [
+    ("shader.wgsl", include_str!("shader.wgsl")),
+    ("sky.wgsl", include_str!("sky.wgsl")),
+    ("skybox.wgsl", include_str!("skybox.wgsl")),
+];
+
+// Meshes, cameras and everything needed to draw them into a texture of one format. Doesn't care
+// whether that texture belongs to a window, so the same code draws on screen and off
+pub struct SceneRenderer {
+    // The active one drives the camera uniform
+    cameras: Vec<Camera>,
+    active_camera: usize,
+    camera_uniform: CameraUniform,
+    // Refreshed every update
+    camera_matrices: CameraMatrices,
+    // Empty draws the active camera over the whole target
+    views: Vec<SceneView>,
+    // What render draws, worked out by update
+    drawn_views: Vec<DrawnView>,
+    camera_bind_group_layout: wgpu::BindGroupLayout,
+    // One set per frame in flight, indexed by the frame's slot
+    frame_resources: Vec<FrameResources>,
+    frames: FramesInFlight,
+    // Skip meshes that are entirely outside the active camera's view
+    frustum_culling: bool,
+    stats: FrameStats,
+    // Created the first time something is picked
+    picker: Option<Picker>,
+    // Recenter the camera's origin once the eye gets this far from it, None to never move it.
+    // 0 keeps everything exactly camera-relative
+    origin_rebase_distance: Option<f32>,
+
+    format: wgpu::TextureFormat,
+    // Pixels that unproject, screen_ray and pick measure in, usually the window's size
+    viewport: [u32; 2],
+    // Size of what's drawn into, the depth and multisample textures match it
+    target_size: [u32; 2],
+    clear_color: wgpu::Color,
+    depth_texture: Texture,
+    // Drawn into and resolved onto the frame when multisampling, None when it's off
+    msaa_texture: Option<Texture>,
+    msaa_samples: u32,
+    // Sample counts the adapter can draw to format and the depth format with
+    supported_msaa_samples: Vec<u32>,
+    // Only with timestamp query support
+    gpu_timer: Option<GpuTimer>,
+    // Seconds each pass took on the GPU, as of the last measurement
+    gpu_pass_times: Option<PassTimes>,
+    // Seconds each pass of the last frame took to record
+    cpu_pass_times: PassTimes,
+
+    // Shaders and snippets by file name, as they were last reloaded. Empty without hot-reload
+    shader_sources: HashMap<String, String>,
+    // shader.wgsl compiled once for each set of features in use
+    shaders: HashMap<ShaderFeatures, wgpu::ShaderModule>,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    // One pipeline per combination of face settings, material variant and depth direction in use
+    render_pipelines: HashMap<(FaceSettings, MaterialKey, bool), wgpu::RenderPipeline>,
+    // Indexed by id, None once removed so the other ids stay put
+    meshes: Vec<Option<Mesh>>,
+    dynamic_meshes: Vec<Option<DynamicMesh>>,
+    // Created the first time a mesh has an impostor distance
+    impostors: Option<Impostors>,
+    impostor_settings: ImpostorSettings,
+    // Drawn behind everything instead of the clear color while there is one
+    sky: Option<Sky>,
+    // Drawn instead of the sky while there is one
+    skybox: Option<Skybox>,
+    skybox_settings: SkyboxSettings,
+    // Only lights materials with ShaderFeatures::PBR
+    lighting: Lighting,
+    lights: SceneLights,
+    // Each mesh's tint, written every frame
+    tints: MeshTints,
+    // Only while transparency is TransparencyMode::WeightedBlended
+    oit: Option<WeightedBlendedOit>,
+    // Drawn over each view once the rest of it is done
+    weather: Option<Weather>,
+    // How soaked lit surfaces are from rain, carries on drying after the weather is gone
+    wetness: f32,
+    // Glare from lighting's sun, drawn over each view last
+    lens_flare: Option<LensFlare>,
+    // Indexed by id like the meshes. The renderer is created the first time there's one to draw
+    decals: Vec<Option<Decal>>,
+    decal_renderer: Option<DecalRenderer>,
+    // Highlighted at the end of each view. The renderer is created the first time there's a hit
+    hover: Option<Hover>,
+    hovered: Option<RayHit>,
+    hover_settings: HoverSettings,
+    // While there's a LUT, the views are drawn into a frame of its own and graded onto the target
+    color_grading: Option<ColorGrading>,
+    color_grading_strength: f32,
+    // Last of all, after any grading, every pixel is snapped to the palette while there is one
+    palette: Option<PaletteRenderer>,
+    palette_dithering: f32,
+
+    textures: MeshTextures,
+    // Which of textures only have the mips the camera needs uploaded
+    streaming: TextureStreamer,
+    materials: Materials,
+    // By material id, run by update
+    material_animators: HashMap<usize, MaterialAnimator>,
+    // Seconds advance has moved everything on by in total, and the last time it was called
+    time: f32,
+    delta_time: f32,
+    // Set when the default sampler settings change, the samplers are made again by render
+    samplers_changed: bool,
+    // Set when a texture goes away, render binds the materials again
+    rebind_materials: bool,
+    // Used by every texture that doesn't bring its own sampler settings
+    sampler_config: SamplerConfig,
+    max_anisotropy: u16,
+    // Every texture and material sampler, one per distinct setting. Its anisotropy limit is the
+    // most any sampler gets, lowered by quality settings
+    samplers: SamplerCache,
+}
+
+// A view as of the last update
+struct DrawnView {
+    rect: [f32; 4],
+    uniform: CameraUniform,
+    matrices: CameraMatrices,
+    mirrored: bool,
+    depth_mode: DepthMode,
+}
+
+// What each frame in flight gets a copy of, so writing the next frame's never waits on the last
+#[derive(Default)]
+struct FrameResources {
+    // One per view
+    cameras: Vec<CameraBinding>,
+}
+
+struct CameraBinding {
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    // What the buffer holds, so it's only written when that changes
+    written: CameraUniform,
+}
+
+impl CameraBinding {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, camera_uniform: &CameraUniform, label: &str) -> Self {
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label} Camera Buffer")),
+                contents: bytemuck::cast_slice(&[*camera_uniform]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                }
+            ],
+            label: Some(&format!("{label} camera_bind_group")),
+        });
+
+        Self { camera_buffer, camera_bind_group, written: *camera_uniform }
+    }
+
+    fn write(&mut self, queue: &wgpu::Queue, camera_uniform: &CameraUniform) {
+        if bytemuck::bytes_of(&self.written) != bytemuck::bytes_of(camera_uniform) {
+            queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(camera_uniform));
+            self.written = *camera_uniform;
+        }
+    }
+}
+
+impl SceneRenderer {
+    // size is in pixels, both for drawing and for input
+    pub fn new(gpu: &Gpu, format: wgpu::TextureFormat, size: [u32; 2]) -> Self {
+        let device = &gpu.device;
+        let shader = Self::create_shader(device, &shader_features::compose(SHADERS[0].1), ShaderFeatures::DEFAULT);
+
+        let camera = Camera::new(
+            // position the camera 2 units back
+            // +z is out of the screen, and the camera looks down -z straight at the target
+            (0.0, 0.0, 2.0).into(),
+            // have it look at the origin
+            (0.0, 0.0, 0.0).into(),
+            // which way is "up"
+            cgmath::Vector3::unit_y(),
+            size[0] as f32 / size[1].max(1) as f32,
+        );
+
+        let camera_matrices = camera.matrices();
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&camera);
+
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }
+            ],
+            label: Some("camera_bind_group_layout"),
+        });
+
+        let frames = FramesInFlight::default();
+        let frame_resources = (0..frames.count()).map(|_| FrameResources::default()).collect();
+        let drawn_views = vec![DrawnView {
+            rect: SceneView::full(0).rect,
+            uniform: camera_uniform,
+            matrices: camera_matrices,
+            mirrored: camera.frame.is_mirrored(),
+            depth_mode: camera.depth_mode(),
+        }];
+
+        let mut samplers = SamplerCache::new(gpu.max_anisotropy);
+        let textures = MeshTextures::new(device, &gpu.queue, samplers.get(device, &SamplerConfig::default()));
+        let materials = Materials::new(device, &textures, &mut samplers);
+        let lighting = Lighting::default();
+        let lights = SceneLights::new(device, &lighting);
+        let tints = MeshTints::new(device);
+        let render_pipeline_layout =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                materials.layout(),
+                lights.layout(),
+                tints.layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let depth_texture = Texture::create_depth_texture(device, size, 1, "depth_texture");
+
+        let mut render_pipelines = HashMap::new();
+        render_pipelines.insert(
+            (FaceSettings::DEFAULT, Material::default().key(), false),
+            Self::create_render_pipeline(device, &render_pipeline_layout, &shader, format, 1, FaceSettings::DEFAULT, Material::default().key(), false, TransparencyMode::Sorted),
+        );
+        let shaders = HashMap::from([(ShaderFeatures::DEFAULT, shader)]);
+
+        Self {
+            cameras: vec![camera],
+            active_camera: 0,
+            camera_uniform,
+            camera_matrices,
+            views: Vec::new(),
+            drawn_views,
+            camera_bind_group_layout,
+            frame_resources,
+            frames,
+            frustum_culling: true,
+            stats: FrameStats::default(),
+            picker: None,
+            origin_rebase_distance: None,
+
+            format,
+            viewport: size,
+            target_size: size,
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+            depth_texture,
+            msaa_texture: None,
+            msaa_samples: 1,
+            supported_msaa_samples: gpu.supported_msaa_samples(format, Texture::DEPTH_FORMAT),
+            gpu_timer: GpuTimer::new(device, &gpu.queue),
+            gpu_pass_times: None,
+            cpu_pass_times: PassTimes::default(),
+
+            shader_sources: HashMap::new(),
+            shaders,
+            render_pipeline_layout,
+            render_pipelines,
+            meshes: Vec::new(),
+            dynamic_meshes: Vec::new(),
+            impostors: None,
+            impostor_settings: ImpostorSettings::default(),
+            sky: None,
+            skybox: None,
+            skybox_settings: SkyboxSettings::default(),
+            lighting,
+            lights,
+            tints,
+            oit: None,
+            weather: None,
+            wetness: 0.0,
+            lens_flare: None,
+            decals: Vec::new(),
+            decal_renderer: None,
+            hover: None,
+            hovered: None,
+            hover_settings: HoverSettings::default(),
+            color_grading: None,
+            color_grading_strength: 1.0,
+            palette: None,
+            palette_dithering: 0.0,
+
+            textures,
+            streaming: TextureStreamer::new(StreamingSettings::default()),
+            materials,
+            material_animators: HashMap::new(),
+            time: 0.0,
+            delta_time: 0.0,
+            samplers_changed: false,
+            rebind_materials: false,
+            sampler_config: SamplerConfig::default(),
+            max_anisotropy: gpu.max_anisotropy,
+            samplers,
+        }
+    }
+
+    // One of SHADERS with its includes pasted in, using what's been reloaded in place of what's
+    // built in
+    fn shader_source(&self, name: &str) -> String {
+        let reloaded = |name: &str| self.shader_sources.get(name).map(String::as_str);
+        let built_in = SHADERS.iter().find(|(shader, _)| *shader == name).map_or("", |(_, source)| source);
+        shader_features::compose_with(reloaded(name).unwrap_or(built_in), |include| reloaded(include).or_else(|| shader_features::include(include)))
+    }
+
+    // source has had its includes pasted in
+    fn create_shader(device: &wgpu::Device, source: &str, features: ShaderFeatures) -> wgpu::ShaderModule {
+        let max_point_lights = MAX_POINT_LIGHTS.to_string();
+        let source = shader_features::preprocess_with(source, features, &[("MAX_POINT_LIGHTS", &max_point_lights)]);
+        let label = format!("Shader ({})", features.defines().collect::<Vec<_>>().join(", "));
+        device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some(&label), source: wgpu::ShaderSource::Wgsl(source.into()) })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_render_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        faces: FaceSettings,
+        material: MaterialKey,
+        reversed_z: bool,
+        transparency: TransparencyMode,
+    ) -> wgpu::RenderPipeline {
+        // Tells the pipelines apart in captures
+        let label = format!(
+            "Render Pipeline ({:?}, {:?}, {:?}, {:?}{}{})",
+            faces.cull_mode, faces.front_face, material.features.defines().collect::<Vec<_>>(), material.blend,
+            if material.transparent { ", transparent" } else { "" }, if reversed_z { ", reversed z" } else { "" },
+        );
+        // Transparent meshes go into the accumulation targets instead of the frame
+        let oit = material.transparent && transparency == TransparencyMode::WeightedBlended;
+        let targets = if oit {
+            oit::accumulate_targets().to_vec()
+        } else {
+            vec![Some(wgpu::ColorTargetState { // 4.
+                format,
+                blend: Some(material.blend.state()),
+                write_mask: wgpu::ColorWrites::ALL,
+            })]
+        };
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main", // 1.
+                buffers: &[
+                    Vertex::desc(),
+                    InstanceRaw::desc(),
+                ],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState { // 3.
+                module: shader,
+                entry_point: if oit { "fs_oit" } else { "fs_main" },
+                targets: &targets,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+                strip_index_format: None,
+                front_face: faces.front_face, // 2.
+                cull_mode: faces.cull_mode,
+                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                // Transparent meshes are still hidden behind what's in front but don't hide what's
+                // behind them
+                depth_write_enabled: !material.transparent,
+                // Draw pixels that are closer than whatever is already there, which with reversed
+                // depth means greater
+                depth_compare: if reversed_z { wgpu::CompareFunction::Greater } else { wgpu::CompareFunction::Less },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count, // 2.
+                mask: !0, // 3.
+                alpha_to_coverage_enabled: false, // 4.
+            },
+            multiview: None, // 5.
+            cache: None, // 6.
+        })
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    // Switches to drawing into a different format, for a different target or a surface whose
+    // format changed. The pipelines are rebuilt by render, and the sample count drops if the new
+    // format can't do it
+    pub fn set_format(&mut self, gpu: &Gpu, format: wgpu::TextureFormat) {
+        if format == self.format {
+            return;
+        }
+        self.format = format;
+        self.supported_msaa_samples = gpu.supported_msaa_samples(format, Texture::DEPTH_FORMAT);
+        self.render_pipelines.clear();
+        // The atlas is in the old format too, so every picture is baked again
+        if let Some(impostors) = self.impostors.take() {
+            self.frames.retire(impostors);
+        }
+        if let Some(sky) = &mut self.sky {
+            sky.set_format(format);
+        }
+        if let Some(skybox) = &mut self.skybox {
+            skybox.set_format(format);
+        }
+        if let Some(weather) = &mut self.weather {
+            weather.set_format(format);
+        }
+        if let Some(lens_flare) = &mut self.lens_flare {
+            lens_flare.set_format(format);
+        }
+        if let Some(decals) = &mut self.decal_renderer {
+            decals.set_format(format);
+        }
+        if let Some(hover) = &mut self.hover {
+            hover.set_format(format);
+        }
+        if let Some(oit) = &mut self.oit {
+            oit.set_format(format);
+        }
+        if let Some(grading) = &mut self.color_grading {
+            grading.set_format(format);
+        }
+        if let Some(palette) = &mut self.palette {
+            palette.set_format(format);
+        }
+        let samples = self.msaa_samples;
+        self.msaa_samples = self.supported_msaa_samples.iter().copied().filter(|&count| count <= samples).max().unwrap_or(1);
+        // The multisample target is in the old format
+        self.resize_targets(gpu, self.target_size);
+    }
+
+    // Swaps in new source for one of SHADERS or a snippet they include, named by its file name,
+    // and rebuilds every pipeline it's in. If anything doesn't compile, the error comes back and
+    // the old shaders keep drawing. Ok(false) if the scene doesn't use a shader by that name.
+    // Shaders outside SHADERS, like the weather's, keep the snippets they were built with
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(&mut self, gpu: &Gpu, name: &str, source: &str) -> Result<bool, String> {
+        let snippet = shader_features::include(name).is_some();
+        if !snippet && SHADERS.iter().all(|(shader, _)| *shader != name) {
+            return Ok(false);
+        }
+        let old = self.shader_sources.insert(name.to_string(), source.to_string());
+        // There's no telling which shaders a snippet is in, so it rebuilds them all
+        let changed = |shader: &str| (snippet || shader == name).then(|| self.shader_source(shader));
+        let [mesh_source, sky_source, skybox_source] = SHADERS.map(|(shader, _)| changed(shader));
+        let device = &gpu.device;
+        let transparency = self.transparency();
+        let rebuilt = hot_reload::validated(device, || {
+            let mesh = mesh_source.map(|source| {
+                let shaders = self.shaders.keys()
+                    .map(|&features| (features, Self::create_shader(device, &source, features)))
+                    .collect::<HashMap<_, _>>();
+                let render_pipelines = self.render_pipelines.keys()
+                    .map(|&key @ (faces, material, reversed_z)| {
+                        let shader = &shaders[&material.features];
+                        (key, Self::create_render_pipeline(device, &self.render_pipeline_layout, shader, self.format, self.msaa_samples, faces, material, reversed_z, transparency))
+                    })
+                    .collect::<HashMap<_, _>>();
+                (shaders, render_pipelines)
+            });
+            // Without a sky it's only checked here, and used by the next one
+            let sky = sky_source.and_then(|source| match &self.sky {
+                Some(sky) => Some(sky.rebuild(device, &source)),
+                None => {
+                    device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Sky Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+                    None
+                }
+            });
+            let skybox = skybox_source.and_then(|source| match &self.skybox {
+                Some(skybox) => Some(skybox.rebuild(device, &source)),
+                None => {
+                    device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Skybox Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+                    None
+                }
+            });
+            (mesh, sky, skybox)
+        });
+        let (mesh, sky, skybox) = match rebuilt {
+            Ok(rebuilt) => rebuilt,
+            Err(error) => {
+                // So shaders compiled later don't get it either
+                match old {
+                    Some(old) => self.shader_sources.insert(name.to_string(), old),
+                    None => self.shader_sources.remove(name),
+                };
+                return Err(error);
+            }
+        };
+        if let Some((shaders, render_pipelines)) = mesh {
+            self.shaders = shaders;
+            self.render_pipelines = render_pipelines;
+            // The pictures were baked with the old shader
+            if let Some(impostors) = self.impostors.take() {
+                self.frames.retire(impostors);
+            }
+        }
+        if let (Some(sky), Some(rebuilt)) = (&mut self.sky, sky) {
+            sky.set_shader(rebuilt);
+        }
+        if let (Some(skybox), Some(rebuilt)) = (&mut self.skybox, skybox) {
+            skybox.set_shader(rebuilt);
+        }
+        Ok(true)
+    }
+
+    // The pixel space input is measured in, which sets every camera's aspect ratio
+    pub fn set_viewport(&mut self, size: [u32; 2]) {
+        if size[0] == 0 || size[1] == 0 {
+            return;
+        }
+        self.viewport = size;
+        // size is never zero here, so this can't fail
+        for camera in &mut self.cameras {
+            let _ = camera.set_aspect(size[0] as f32 / size[1] as f32);
+        }
+    }
+
+    pub fn viewport(&self) -> [u32; 2] {
+        self.viewport
+    }
+
+    // Recreates the depth and multisample textures for a new size of what's drawn into
+    pub fn resize_targets(&mut self, gpu: &Gpu, size: [u32; 2]) {
+        self.target_size = size;
+        self.depth_texture = Texture::create_depth_texture(&gpu.device, size, self.msaa_samples, "depth_texture");
+        self.msaa_texture = (self.msaa_samples > 1)
+            .then(|| Texture::create_msaa_texture(&gpu.device, size, self.format, self.msaa_samples, "msaa_texture"));
+    }
+
+    // Sample counts the adapter can't do drop to the next one down. Returns the count in use
+    pub fn set_msaa_samples(&mut self, gpu: &Gpu, samples: u32) -> u32 {
+        let samples = self.supported_msaa_samples
+            .iter()
+            .copied()
+            .filter(|&count| count <= samples)
+            .max()
+            .unwrap_or(1);
+        if samples != self.msaa_samples {
+            self.msaa_samples = samples;
+            // Rebuilt with the new sample count by render
+            self.render_pipelines.clear();
+            self.resize_targets(gpu, self.target_size);
+        }
+        samples
+    }
+
+    // How many frames the CPU can get ahead of the GPU. More smooths over uneven frames at the
+    // cost of latency, 1 never lets them overlap
+    pub fn set_frames_in_flight(&mut self, gpu: &Gpu, count: usize) {
+        self.frames.wait_idle(&gpu.device);
+        self.frames = FramesInFlight::new(count);
+        self.frame_resources = (0..self.frames.count()).map(|_| FrameResources::default()).collect();
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.count()
+    }
+
+    // Keeps something alive until every frame already submitted has finished, for GPU resources
+    // that are being replaced while frames that use them could still be in flight
+    pub fn retire<T: wgpu::WasmNotSend + 'static>(&mut self, resource: T) {
+        self.frames.retire(resource);
+    }
+
+    // Samples per pixel actually in use
+    pub fn msaa_samples(&self) -> u32 {
+        self.msaa_samples
+    }
+
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    // Uploads a mesh and returns its id, drawn once for every instance
+    pub fn add_mesh(&mut self, gpu: &Gpu, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        let name = format!("Mesh {}", self.meshes.len());
+        self.add_named_mesh(gpu, &name, data, instances, faces)
+    }
+
+    // Same as add_mesh, with a name for its buffers and draws in graphics debuggers
+    pub fn add_named_mesh(&mut self, gpu: &Gpu, name: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        let mut mesh = Mesh::named(&gpu.device, name, data, instances, faces);
+        mesh.set_origin(&gpu.device, &gpu.queue, self.camera().origin);
+        self.meshes.push(Some(mesh));
+        self.meshes.len() - 1
+    }
+
+    // Ids handed out so far, including removed meshes. Ids aren't reused, so this is also the next one
+    pub fn mesh_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    pub fn mesh(&self, id: usize) -> Option<&Mesh> {
+        self.meshes.get(id)?.as_ref()
+    }
+
+    pub fn mesh_mut(&mut self, id: usize) -> Option<&mut Mesh> {
+        self.meshes.get_mut(id)?.as_mut()
+    }
+
+    // Stops drawing a mesh. Its buffers are freed once the frames in flight that could still be
+    // drawing it have finished, so this is safe at any point. Returns false if there was no such mesh
+    pub fn remove_mesh(&mut self, id: usize) -> bool {
+        let Some(mesh) = self.meshes.get_mut(id).and_then(Option::take) else { return false };
+        self.frames.retire(mesh);
+        if let Some(impostors) = &mut self.impostors {
+            impostors.forget(id);
+        }
+        true
+    }
+
+    pub fn set_instances(&mut self, gpu: &Gpu, id: usize, instances: &[Instance]) {
+        if let Some(mesh) = self.mesh_mut(id) {
+            mesh.set_instances(&gpu.device, &gpu.queue, instances);
+        }
+    }
+
+    // Replaces a single instance, leaving the rest of the mesh's alone
+    pub fn set_instance(&mut self, gpu: &Gpu, id: usize, index: usize, instance: Instance) {
+        let Some(mesh) = self.mesh_mut(id) else { return };
+        let mut instances = mesh.instances().to_vec();
+        if let Some(slot) = instances.get_mut(index) {
+            *slot = instance;
+            mesh.set_instances(&gpu.device, &gpu.queue, &instances);
+        }
+    }
+
+    // Replaces a mesh's geometry, keeping its instances, face settings, material, impostor
+    // distance, visibility and tint. The old buffers are kept until the frames in flight are done with them
+    pub fn set_mesh_data(&mut self, gpu: &Gpu, id: usize, data: &MeshData) {
+        let origin = self.camera().origin;
+        let Some(mesh) = self.meshes.get_mut(id).and_then(Option::as_mut) else { return };
+        let mut replacement = Mesh::named(&gpu.device, mesh.label(), data, mesh.instances(), mesh.faces);
+        replacement.material = mesh.material;
+        replacement.impostor_distance = mesh.impostor_distance;
+        replacement.visible = mesh.visible;
+        replacement.tint = mesh.tint;
+        replacement.set_origin(&gpu.device, &gpu.queue, origin);
+        self.frames.retire(std::mem::replace(mesh, replacement));
+        if let Some(impostors) = &mut self.impostors {
+            impostors.forget(id);
+        }
+    }
+
+    // Remeshes and uploads every chunk that changed since the last call, chunks are drawn
+    // with the default pipeline as one instance placed at the chunk's origin
+    pub fn sync_voxels(&mut self, gpu: &Gpu, world: &mut VoxelWorld) {
+        for (pos, data) in world.take_dirty_meshes() {
+            match world.mesh_id(pos) {
+                Some(id) => self.set_mesh_data(gpu, id, &data),
+                None => {
+                    let instance = Instance { position: world.chunk_origin(pos).into(), ..Default::default() };
+                    let name = format!("Voxel Chunk {pos:?}");
+                    let id = self.add_named_mesh(gpu, &name, &data, &[instance], FaceSettings::DEFAULT);
+                    world.set_mesh_id(pos, id);
+                }
+            }
+        }
+    }
+
+    // Uploads a mesh whose vertices can be changed every frame through dynamic_mesh_mut
+    pub fn add_dynamic_mesh(&mut self, gpu: &Gpu, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        let name = format!("Dynamic Mesh {}", self.dynamic_meshes.len());
+        self.add_named_dynamic_mesh(gpu, &name, data, instances, faces)
+    }
+
+    pub fn add_named_dynamic_mesh(&mut self, gpu: &Gpu, name: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> usize {
+        let mut mesh = DynamicMesh::named(&gpu.device, name, data, instances, faces);
+        mesh.mesh_mut().set_origin(&gpu.device, &gpu.queue, self.camera().origin);
+        self.dynamic_meshes.push(Some(mesh));
+        self.dynamic_meshes.len() - 1
+    }
+
+    pub fn dynamic_mesh(&self, id: usize) -> Option<&DynamicMesh> {
+        self.dynamic_meshes.get(id)?.as_ref()
+    }
+
+    pub fn dynamic_mesh_mut(&mut self, id: usize) -> Option<&mut DynamicMesh> {
+        self.dynamic_meshes.get_mut(id)?.as_mut()
+    }
+
+    // Like remove_mesh. The staging belt goes with it, after anything it's uploading has landed
+    pub fn remove_dynamic_mesh(&mut self, id: usize) -> bool {
+        let Some(mesh) = self.dynamic_meshes.get_mut(id).and_then(Option::take) else { return false };
+        self.frames.retire(mesh);
+        true
+    }
+
+    // Every mesh that hasn't been removed, static ones first, with the id each goes by
+    pub fn mesh_by_id(&self, id: MeshId) -> Option<&Mesh> {
+        match id {
+            MeshId::Static(id) => self.mesh(id),
+            MeshId::Dynamic(id) => Some(self.dynamic_mesh(id)?.mesh()),
+        }
+    }
+
+    fn live_meshes(&self) -> impl Iterator<Item = (MeshId, &Mesh)> {
+        let statics = self.meshes.iter().enumerate()
+            .filter_map(|(id, mesh)| Some((MeshId::Static(id), mesh.as_ref()?)));
+        let dynamics = self.dynamic_meshes.iter().enumerate()
+            .filter_map(|(id, mesh)| Some((MeshId::Dynamic(id), mesh.as_ref()?.mesh())));
+        statics.chain(dynamics)
+    }
+
+    // Checks every mesh for problems that would make it render wrong, and every texture for being
+    // oversized, and logs what it finds
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        // Winding is checked in the camera's frame, a left handed one reverses it
+        let faces = |mesh: &Mesh| if self.camera().frame.is_mirrored() { mesh.faces.mirrored() } else { mesh.faces };
+        for (id, mesh) in self.live_meshes() {
+            let name = match id {
+                MeshId::Static(id) => format!("mesh {id}"),
+                MeshId::Dynamic(id) => format!("dynamic mesh {id}"),
+            };
+            report.add(name, validation::validate_mesh(mesh.data(), faces(mesh)));
+        }
+        // Streamed ones by the size they reach with every mip uploaded
+        for (id, texture) in self.textures.iter() {
+            let [width, height] = self.streaming.full_size(id).unwrap_or(texture.size());
+            report.add(format!("texture {id}"), validation::validate_texture_size(width, height));
+        }
+        for (id, texture) in self.textures.iter_arrays() {
+            let [width, height] = texture.size();
+            report.add(format!("texture array {id}"), validation::validate_texture_size(width, height));
+        }
+        report.log();
+        report
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.cameras[self.active_camera]
+    }
+
+    // Call mark_dirty on it after changing its public fields
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.cameras[self.active_camera]
+    }
+
+    // Adds another camera, for example a free debug camera next to the gameplay one. The
+    // first camera is created by new and has id 0
+    pub fn add_camera(&mut self, mut camera: Camera) -> usize {
+        let _ = camera.set_aspect(self.viewport[0] as f32 / self.viewport[1].max(1) as f32);
+        self.cameras.push(camera);
+        self.cameras.len() - 1
+    }
+
+    pub fn camera_by_id(&self, id: usize) -> Option<&Camera> {
+        self.cameras.get(id)
+    }
+
+    pub fn camera_by_id_mut(&mut self, id: usize) -> Option<&mut Camera> {
+        self.cameras.get_mut(id)
+    }
+
+    pub fn camera_count(&self) -> usize {
+        self.cameras.len()
+    }
+
+    pub fn active_camera(&self) -> usize {
+        self.active_camera
+    }
+
+    // Switches which camera is drawn from. Returns false if there's no such camera
+    pub fn set_active_camera(&mut self, id: usize) -> bool {
+        if id >= self.cameras.len() {
+            log::warn!("No camera {id}, there are only {}", self.cameras.len());
+            return false;
+        }
+        self.active_camera = id;
+        self.cameras[id].mark_dirty();
+        true
+    }
+
+    // Splits the target between cameras, see SceneView. Empty goes back to drawing the active
+    // camera over all of it. Input methods like pick and screen_ray still use the active camera
+    // over the whole viewport, view_at tells which view a pixel is in
+    pub fn set_views(&mut self, views: Vec<SceneView>) {
+        self.views = views;
+        // Cameras a view doesn't cover go back to the viewport's shape
+        self.set_viewport(self.viewport);
+    }
+
+    pub fn views(&self) -> &[SceneView] {
+        &self.views
+    }
+
+    // The topmost view drawn at a pixel of the viewport
+    pub fn view_at(&self, pixel: [f32; 2]) -> Option<usize> {
+        self.views.iter().rposition(|view| view.contains(pixel, self.viewport))
+    }
+
+    // As of the last update
+    pub fn camera_matrices(&self) -> &CameraMatrices {
+        &self.camera_matrices
+    }
+
+    fn to_screen(&self, pixel: [f32; 2]) -> [f32; 2] {
+        [pixel[0] / self.viewport[0] as f32, pixel[1] / self.viewport[1] as f32]
+    }
+
+    // Point in the world under a pixel, given the depth buffer's value there. Uses the matrices
+    // from the last update so it matches what was drawn
+    pub fn unproject(&self, pixel: [f32; 2], depth: f32) -> cgmath::Point3<f32> {
+        self.camera_matrices.unproject(self.to_screen(pixel), self.camera().standard_depth(depth))
+    }
+
+    // Ray through a pixel using the matrices from the last update
+    pub fn screen_ray(&self, pixel: [f32; 2]) -> Ray {
+        self.camera_matrices.screen_ray(self.to_screen(pixel))
+    }
+
+    // The mesh instance under a pixel, found by rendering ids rather than intersecting rays, so it
+    // matches exactly what was drawn. Waits for the GPU
+    pub fn pick(&mut self, gpu: &Gpu, pixel: [f32; 2]) -> Option<Pick> {
+        trace_span!("pick");
+        let screen = self.to_screen(pixel);
+        let mirrored = self.camera().frame.is_mirrored();
+        let faces = |mesh: &Mesh| if mirrored { mesh.faces.mirrored() } else { mesh.faces };
+        // Taken out while the meshes are borrowed
+        let mut picker = self.picker.take().unwrap_or_else(|| Picker::new(&gpu.device));
+        let (ids, meshes): (Vec<_>, Vec<_>) = self.live_meshes()
+            .filter(|(_, mesh)| mesh.visible)
+            .map(|(id, mesh)| (id, (mesh, faces(mesh))))
+            .unzip();
+
+        // The picker always tests with Less, so reversed depth is flipped back for it
+        let matrices = if self.camera_matrices.is_reversed_z() {
+            CameraMatrices::new(self.camera_matrices.view, REVERSE_Z_MATRIX * self.camera_matrices.projection)
+        } else {
+            self.camera_matrices
+        };
+        let picked = picker.pick(&gpu.device, &gpu.queue, &matrices, screen, self.viewport, &meshes);
+        drop(meshes);
+        self.picker = Some(picker);
+        let (index, instance) = picked?;
+        Some(Pick { mesh: ids[index], instance })
+    }
+
+    // Where a mesh instance is relative to the active camera's origin, for a controller to follow
+    pub fn follow_target(&self, mesh: MeshId, instance: usize) -> Option<FollowTarget> {
+        let mesh = self.mesh_by_id(mesh)?;
+        let instance = mesh.instances().get(instance)?;
+        let relative = (instance.position - self.camera().origin).cast::<f32>()?;
+        Some(FollowTarget { position: cgmath::Point3::from_vec(relative), rotation: instance.rotation })
+    }
+
+    // Uploads a texture for materials to use, sampled with config or the default sampler
+    // settings. Returns its id for Material::with_texture
+    pub fn add_texture(&mut self, gpu: &Gpu, texture: Texture, config: Option<SamplerConfig>) -> usize {
+        let sampler = self.sampler(gpu, config.as_ref());
+        self.textures.add(texture, config, sampler)
+    }
+
+    // A PNG, JPEG or anything else the image crate can read
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_texture(&mut self, gpu: &Gpu, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        let texture = Texture::load(&gpu.device, &gpu.queue, path)?;
+        Ok(self.add_texture(gpu, texture, None))
+    }
+
+    // Keeps every mip of the image on the CPU and uploads only what the camera needs of it, nearer
+    // and visible textures first, within StreamingSettings::budget. Starts out with just its
+    // smallest mip. Returns its id for Material::with_texture like add_texture
+    pub fn add_streamed_texture(&mut self, gpu: &Gpu, image: image::RgbaImage, config: Option<SamplerConfig>, label: &str) -> usize {
+        let mips = Texture::mip_chain(image);
+        let texture = TextureStreamer::initial_texture(&gpu.device, &gpu.queue, &mips, label);
+        let id = self.add_texture(gpu, texture, config);
+        self.streaming.add(id, mips, label);
+        id
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_streamed_texture(&mut self, gpu: &Gpu, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        let path = path.as_ref();
+        let image = image::open(path).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?.to_rgba8();
+        Ok(self.add_streamed_texture(gpu, image, None, &path.display().to_string()))
+    }
+
+    pub fn set_streaming_settings(&mut self, settings: StreamingSettings) {
+        self.streaming.set_settings(settings);
+    }
+
+    pub fn streaming_settings(&self) -> &StreamingSettings {
+        self.streaming.settings()
+    }
+
+    // Bytes of streamed textures on the GPU, what counts against the budget
+    pub fn streamed_texture_bytes(&self) -> u64 {
+        self.streaming.used()
+    }
+
+    pub fn texture(&self, id: usize) -> Option<&Texture> {
+        self.textures.get(id)
+    }
+
+    // Materials still using it are drawn untextured. The texture is kept until the frames in
+    // flight are done with it
+    pub fn remove_texture(&mut self, id: usize) -> bool {
+        let Some(texture) = self.textures.remove(id) else { return false };
+        self.frames.retire(texture);
+        self.streaming.remove(id);
+        self.rebind_materials = true;
+        self.forget_impostors(|mesh, materials| materials.material(mesh.material).uses_texture(id));
+        true
+    }
+
+    // A texture with a D2Array view, like Texture::from_layers makes. Returns its id for
+    // Material::with_texture_array
+    pub fn add_texture_array(&mut self, gpu: &Gpu, texture: Texture, config: Option<SamplerConfig>) -> usize {
+        let sampler = self.sampler(gpu, config.as_ref());
+        self.textures.add_array(texture, config, sampler)
+    }
+
+    pub fn texture_array(&self, id: usize) -> Option<&Texture> {
+        self.textures.get_array(id)
+    }
+
+    // Materials still using it are drawn with a white layer
+    pub fn remove_texture_array(&mut self, id: usize) -> bool {
+        let Some(texture) = self.textures.remove_array(id) else { return false };
+        self.frames.retire(texture);
+        self.rebind_materials = true;
+        self.forget_impostors(|mesh, materials| materials.material(mesh.material).uses_texture_array(id));
+        true
+    }
+
+    // Returns its id for set_mesh_material
+    pub fn add_material(&mut self, gpu: &Gpu, material: Material) -> usize {
+        self.materials.add(&gpu.device, &self.textures, &mut self.samplers, material)
+    }
+
+    pub fn material(&self, id: usize) -> Option<&Material> {
+        self.materials.get(id)
+    }
+
+    // Changes a material for every mesh using it. Returns false if there's no such material
+    pub fn set_material(&mut self, gpu: &Gpu, id: usize, material: Material) -> bool {
+        if !self.materials.set(&gpu.device, &gpu.queue, &self.textures, &mut self.samplers, Some(id), material) {
+            return false;
+        }
+        self.forget_impostors(|mesh, _| mesh.material == Some(id));
+        true
+    }
+
+    // Meshes still using it go back to the default material
+    pub fn remove_material(&mut self, id: usize) -> bool {
+        if self.materials.remove(id, &mut self.frames).is_none() {
+            return false;
+        }
+        self.material_animators.remove(&id);
+        self.forget_impostors(|mesh, _| mesh.material == Some(id));
+        true
+    }
+
+    // Changes the material every update from then on, for pulsing emissive, shifting colors and
+    // the like without rebuilding anything. Only what the animator changes is written, and bind
+    // groups are only made again if it swaps textures. Impostors keep the picture they were baked
+    // with. None stops it, leaving the material as it was last. Returns false if there's no such
+    // material
+    pub fn set_material_animator(&mut self, id: usize, animator: Option<MaterialAnimator>) -> bool {
+        if self.materials.get(id).is_none() {
+            return false;
+        }
+        match animator {
+            Some(animator) => self.material_animators.insert(id, animator),
+            None => self.material_animators.remove(&id),
+        };
+        true
+    }
+
+    // What meshes without a material of their own are drawn with
+    pub fn default_material(&self) -> &Material {
+        self.materials.material(None)
+    }
+
+    pub fn set_default_material(&mut self, gpu: &Gpu, material: Material) {
+        self.materials.set(&gpu.device, &gpu.queue, &self.textures, &mut self.samplers, None, material);
+        self.forget_impostors(|mesh, _| mesh.material.is_none());
+    }
+
+    // None draws the mesh with the default material
+    pub fn set_mesh_material(&mut self, id: usize, material: Option<usize>) {
+        let Some(mesh) = self.meshes.get_mut(id).and_then(Option::as_mut) else { return };
+        mesh.material = material;
+        if let Some(impostors) = &mut self.impostors {
+            impostors.forget(id);
+        }
+    }
+
+    // Multiplies the mesh's colors, see Mesh::tint. White draws it as it is
+    pub fn set_mesh_tint(&mut self, id: MeshId, tint: Color) {
+        match id {
+            MeshId::Static(id) => {
+                let Some(mesh) = self.meshes.get_mut(id).and_then(Option::as_mut) else { return };
+                mesh.tint = tint;
+                if let Some(impostors) = &mut self.impostors {
+                    impostors.forget(id);
+                }
+            }
+            MeshId::Dynamic(id) => {
+                if let Some(mesh) = self.dynamic_mesh_mut(id) {
+                    mesh.mesh_mut().tint = tint;
+                }
+            }
+        }
+    }
+
+    // Drops the impostor pictures of meshes that would look different now
+    fn forget_impostors(&mut self, changed: impl Fn(&Mesh, &Materials) -> bool) {
+        let Some(impostors) = &mut self.impostors else { return };
+        for (id, mesh) in self.meshes.iter().enumerate() {
+            if mesh.as_ref().is_some_and(|mesh| changed(mesh, &self.materials)) {
+                impostors.forget(id);
+            }
+        }
+    }
+
+    // Instances further off than this are drawn as baked pictures, None draws them in full
+    pub fn set_impostor_distance(&mut self, id: usize, distance: Option<f32>) {
+        if let Some(mesh) = self.mesh_mut(id) {
+            mesh.impostor_distance = distance;
+        }
+    }
+
+    // Starts the atlas over with the new settings, every picture is baked again
+    pub fn set_impostor_settings(&mut self, settings: ImpostorSettings) {
+        self.impostor_settings = settings;
+        if let Some(impostors) = self.impostors.take() {
+            self.frames.retire(impostors);
+        }
+    }
+
+    pub fn impostor_settings(&self) -> ImpostorSettings {
+        self.impostor_settings
+    }
+
+    // None goes back to the clear color. The clouds carry on from where they were
+    pub fn set_sky(&mut self, gpu: &Gpu, settings: Option<SkySettings>) {
+        match (&mut self.sky, settings) {
+            (Some(sky), Some(settings)) => sky.set_settings(settings),
+            (None, Some(settings)) => self.sky = Some(Sky::new(&gpu.device, &self.camera_bind_group_layout, self.format, &self.shader_source("sky.wgsl"), settings)),
+            (_, None) => {
+                if let Some(sky) = self.sky.take() {
+                    self.frames.retire(sky);
+                }
+            }
+        }
+    }
+
+    pub fn sky(&self) -> Option<&SkySettings> {
+        self.sky.as_ref().map(Sky::settings)
+    }
+
+    // Draws cubemap behind the scene in place of the sky, None goes back to the sky or the clear
+    // color. The old cubemap is kept until the frames in flight are done with it
+    pub fn set_skybox(&mut self, gpu: &Gpu, cubemap: Option<Cubemap>) {
+        let source = self.shader_source("skybox.wgsl");
+        let skybox = cubemap.map(|cubemap| Skybox::new(&gpu.device, &self.camera_bind_group_layout, self.format, &source, cubemap));
+        if let Some(old) = std::mem::replace(&mut self.skybox, skybox) {
+            self.frames.retire(old);
+        }
+    }
+
+    pub fn skybox(&self) -> Option<&Cubemap> {
+        self.skybox.as_ref().map(Skybox::cubemap)
+    }
+
+    pub fn set_skybox_settings(&mut self, settings: SkyboxSettings) {
+        self.skybox_settings = settings;
+    }
+
+    pub fn skybox_settings(&self) -> SkyboxSettings {
+        self.skybox_settings
+    }
+
+    pub fn lighting(&self) -> &Lighting {
+        &self.lighting
+    }
+
+    // Uploaded with the next frame. Impostors of lit meshes are baked again, so lights that move
+    // every frame keep those meshes drawn in full
+    pub fn set_lighting(&mut self, lighting: Lighting) {
+        self.lighting = lighting;
+        self.forget_impostors(|mesh, materials| materials.material(mesh.material).features.contains(ShaderFeatures::PBR));
+    }
+
+    // None stops the precipitation, surfaces it wet carry on drying
+    pub fn set_weather(&mut self, gpu: &Gpu, settings: Option<WeatherSettings>) {
+        match (&mut self.weather, settings) {
+            (Some(weather), Some(settings)) => weather.set_settings(settings),
+            (None, Some(settings)) => self.weather = Some(Weather::new(&gpu.device, &self.camera_bind_group_layout, self.format, settings)),
+            (_, None) => {
+                if let Some(weather) = self.weather.take() {
+                    self.frames.retire(weather);
+                }
+            }
+        }
+    }
+
+    pub fn weather(&self) -> Option<&WeatherSettings> {
+        self.weather.as_ref().map(Weather::settings)
+    }
+
+    // 0 for dry to 1 for soaked
+    pub fn wetness(&self) -> f32 {
+        self.wetness
+    }
+
+    // The nearest visible mesh instance the ray goes through. Tested against the triangles on the
+    // CPU, so unlike pick it doesn't wait on the GPU, but it doesn't know about culling or
+    // anything a shader moves. The ray is relative to the active camera's origin, like screen_ray's
+    pub fn raycast(&self, ray: &Ray) -> Option<RayHit> {
+        self.live_meshes()
+            .filter(|(_, mesh)| mesh.visible)
+            .filter_map(|(id, mesh)| {
+                let (instance, distance) = mesh.raycast(ray)?;
+                Some(RayHit { pick: Pick { mesh: id, instance }, distance, point: ray.at(distance) })
+            })
+            .min_by(|a, b| a.distance.total_cmp(&b.distance))
+    }
+
+    // Tints and outlines the hit's mesh instance and marks the point, until it's set to None
+    pub fn set_hover(&mut self, hit: Option<RayHit>) {
+        self.hovered = hit;
+    }
+
+    pub fn hovered(&self) -> Option<RayHit> {
+        self.hovered
+    }
+
+    pub fn set_hover_settings(&mut self, settings: HoverSettings) {
+        self.hover_settings = settings;
+    }
+
+    pub fn hover_settings(&self) -> &HoverSettings {
+        &self.hover_settings
+    }
+
+    // Follows the lighting's sun, dimming as it goes behind things. None turns it off
+    pub fn set_lens_flare(&mut self, gpu: &Gpu, settings: Option<LensFlareSettings>) {
+        match (&mut self.lens_flare, settings) {
+            (Some(lens_flare), Some(settings)) => lens_flare.set_settings(settings),
+            (None, Some(settings)) => self.lens_flare = Some(LensFlare::new(&gpu.device, &self.camera_bind_group_layout, self.format, settings)),
+            (_, None) => {
+                if let Some(lens_flare) = self.lens_flare.take() {
+                    self.frames.retire(lens_flare);
+                }
+            }
+        }
+    }
+
+    pub fn lens_flare(&self) -> Option<&LensFlareSettings> {
+        self.lens_flare.as_ref().map(LensFlare::settings)
+    }
+
+    // Projected onto the opaque meshes and impostors of every view, after them and before anything
+    // transparent. Needs the depth buffer to be readable, so decals are only drawn while
+    // msaa_samples is 1. Returns its id for set_decal and remove_decal
+    pub fn add_decal(&mut self, decal: Decal) -> usize {
+        self.decals.push(Some(decal));
+        self.decals.len() - 1
+    }
+
+    pub fn decal(&self, id: usize) -> Option<&Decal> {
+        self.decals.get(id)?.as_ref()
+    }
+
+    // Moves or changes one already added, false if there's none with that id
+    pub fn set_decal(&mut self, id: usize, decal: Decal) -> bool {
+        let Some(slot) = self.decals.get_mut(id).filter(|slot| slot.is_some()) else { return false };
+        *slot = Some(decal);
+        true
+    }
+
+    pub fn remove_decal(&mut self, id: usize) -> bool {
+        self.decals.get_mut(id).and_then(Option::take).is_some()
+    }
+
+    pub fn clear_decals(&mut self) {
+        self.decals.clear();
+    }
+
+    // Every frame is drawn through lut last, see ColorLut. None goes back to drawing straight onto
+    // the target
+    pub fn set_color_grading(&mut self, gpu: &Gpu, lut: Option<&ColorLut>) {
+        let grading = lut.map(|lut| ColorGrading::new(&gpu.device, &gpu.queue, self.format, lut));
+        if let Some(old) = std::mem::replace(&mut self.color_grading, grading) {
+            self.frames.retire(old);
+        }
+    }
+
+    pub fn has_color_grading(&self) -> bool {
+        self.color_grading.is_some()
+    }
+
+    // How much of the LUT's look is used, from none at 0 to all of it at 1
+    pub fn set_color_grading_strength(&mut self, strength: f32) {
+        self.color_grading_strength = strength.clamp(0.0, 1.0);
+    }
+
+    pub fn color_grading_strength(&self) -> f32 {
+        self.color_grading_strength
+    }
+
+    // Every frame ends up in only the palette's colors, for pixel art that sticks to a fixed set.
+    // None goes back to any color
+    pub fn set_palette(&mut self, gpu: &Gpu, palette: Option<&Palette>) {
+        let renderer = palette.map(|palette| PaletteRenderer::new(&gpu.device, &gpu.queue, self.format, palette));
+        if let Some(old) = std::mem::replace(&mut self.palette, renderer) {
+            self.frames.retire(old);
+        }
+    }
+
+    pub fn has_palette(&self) -> bool {
+        self.palette.is_some()
+    }
+
+    // Ordered dithering between the two palette colors nearest each pixel, so gradients come out
+    // as patterns instead of bands. 0, the default, snaps every pixel to the nearest color
+    pub fn set_palette_dithering(&mut self, dithering: f32) {
+        self.palette_dithering = dithering.clamp(0.0, 1.0);
+    }
+
+    pub fn palette_dithering(&self) -> f32 {
+        self.palette_dithering
+    }
+
+    // Drifts the clouds, moves the precipitation and wets or dries surfaces by dt seconds
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+        self.delta_time = dt;
+        if let Some(sky) = &mut self.sky {
+            sky.advance(dt);
+        }
+        if let Some(weather) = &mut self.weather {
+            weather.advance(dt);
+        }
+        self.wetness = weather::step_wetness(self.wetness, self.weather(), dt);
+    }
+
+    // Seconds advance has moved the scene on by, what shaders see as camera.time
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn set_frustum_culling(&mut self, enabled: bool) {
+        self.frustum_culling = enabled;
+    }
+
+    // How meshes with transparent materials are blended, see TransparencyMode
+    pub fn set_transparency_mode(&mut self, gpu: &Gpu, mode: TransparencyMode) {
+        if mode == self.transparency_mode() {
+            return;
+        }
+        match mode {
+            TransparencyMode::Sorted => {
+                if let Some(oit) = self.oit.take() {
+                    self.frames.retire(oit);
+                }
+            }
+            TransparencyMode::WeightedBlended => self.oit = Some(WeightedBlendedOit::new(gpu, self.format)),
+        }
+        // Rebuilt for the new targets by render
+        self.render_pipelines.retain(|(_, material, _), _| !material.transparent);
+    }
+
+    // The mode asked for, which may be falling back to sorting, see TransparencyMode
+    pub fn transparency_mode(&self) -> TransparencyMode {
+        if self.oit.is_some() { TransparencyMode::WeightedBlended } else { TransparencyMode::Sorted }
+    }
+
+    // The mode transparent meshes are actually drawn with at the current sample count. The
+    // sample count can only change along with every pipeline, so this does too
+    fn transparency(&self) -> TransparencyMode {
+        match &self.oit {
+            Some(oit) if oit.supports(self.msaa_samples) => TransparencyMode::WeightedBlended,
+            _ => TransparencyMode::Sorted,
+        }
+    }
+
+    // From the last frame that was rendered
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    // Seconds the last measured frame's passes took on the GPU, None without timestamp queries
+    pub fn gpu_frame_time(&self) -> Option<f32> {
+        self.gpu_pass_times.map(|times| times.total())
+    }
+
+    // The same split by pass
+    pub fn gpu_pass_times(&self) -> Option<PassTimes> {
+        self.gpu_pass_times
+    }
+
+    // Seconds each pass of the last frame took to record on the CPU
+    pub fn cpu_pass_times(&self) -> PassTimes {
+        self.cpu_pass_times
+    }
+
+    // Turns on floating origin rendering: instance positions are kept in f64 and uploaded relative
+    // to an origin that follows the camera, so huge scenes don't jitter far from (0, 0, 0)
+    pub fn set_origin_rebase_distance(&mut self, distance: Option<f32>) {
+        self.origin_rebase_distance = distance;
+    }
+
+    pub fn default_sampler_config(&self) -> &SamplerConfig {
+        &self.sampler_config
+    }
+
+    pub fn set_default_sampler_config(&mut self, config: SamplerConfig) {
+        self.sampler_config = config.clamped(self.max_anisotropy);
+        self.samplers_changed = true;
+    }
+
+    // Settings for crisp pixel art: nearest sampling by default, so texels stay square blocks, and
+    // no multisampling, which would only blur the edges of sprites sitting on the pixel grid anyway
+    pub fn use_pixel_art_defaults(&mut self, gpu: &Gpu) {
+        self.set_default_sampler_config(SamplerConfig::nearest());
+        self.set_msaa_samples(gpu, 1);
+    }
+
+    // Caps the anisotropy of samplers created from now on, below what the adapter allows
+    pub fn set_anisotropy_limit(&mut self, limit: u16) {
+        self.samplers.set_anisotropy_limit(limit.min(self.max_anisotropy));
+        self.samplers_changed = true;
+    }
+
+    pub fn anisotropy_limit(&self) -> u16 {
+        self.samplers.anisotropy_limit()
+    }
+
+    // Highest anisotropy the adapter can filter with, 1 if it can't at all
+    pub fn max_anisotropy(&self) -> u16 {
+        self.max_anisotropy
+    }
+
+    // Checks config against what the adapter allows. Samplers are made from it either way, with
+    // anything it can't have clamped away
+    pub fn validate_sampler_config(&self, config: &SamplerConfig) -> Result<(), SamplerError> {
+        config.validate(self.max_anisotropy)
+    }
+
+    // Creates a sampler from the per-texture override if there is one, otherwise from the global default
+    pub fn create_sampler(&self, gpu: &Gpu, config: Option<&SamplerConfig>) -> wgpu::Sampler {
+        config
+            .unwrap_or(&self.sampler_config)
+            .clamped(self.samplers.anisotropy_limit())
+            .create_sampler(&gpu.device, Some("Sampler"))
+    }
+
+    // Like create_sampler, but shared with everything else in the scene asking for the same
+    // settings. Material textures can't use comparison samplers, so theirs lose compare
+    pub fn sampler(&mut self, gpu: &Gpu, config: Option<&SamplerConfig>) -> Arc<wgpu::Sampler> {
+        let config = config.unwrap_or(&self.sampler_config).filtering();
+        self.samplers.get(&gpu.device, &config)
+    }
+
+    // Samplers in use by the scene's textures and materials, after duplicates are shared
+    pub fn sampler_count(&self) -> usize {
+        self.samplers.len()
+    }
+
+    // After the active camera has been moved for the frame
+    pub fn update(&mut self, gpu: &Gpu) {
+        trace_span!("scene update");
+        if let Some(times) = self.gpu_timer.as_mut().and_then(|timer| timer.poll(&gpu.device)) {
+            self.gpu_pass_times = Some(times);
+        }
+        let camera = &mut self.cameras[self.active_camera];
+        if let Some(distance) = self.origin_rebase_distance {
+            if camera.eye.to_vec().magnitude() > distance {
+                camera.recenter();
+            }
+        }
+        for (&id, animate) in &mut self.material_animators {
+            let Some(mut material) = self.materials.get(id).cloned() else { continue };
+            animate(&mut material, self.time);
+            self.materials.set(&gpu.device, &gpu.queue, &self.textures, &mut self.samplers, Some(id), material);
+        }
+
+        // Only does anything if the origin moved
+        let origin = camera.origin;
+        for mesh in self.meshes.iter_mut().flatten() {
+            mesh.set_origin(&gpu.device, &gpu.queue, origin);
+        }
+        for mesh in self.dynamic_meshes.iter_mut().flatten() {
+            mesh.mesh_mut().set_origin(&gpu.device, &gpu.queue, origin);
+        }
+
+        // Each view's camera takes the shape of its rect rather than the whole viewport's
+        for view in &self.views {
+            let Some([_, _, width, height]) = view.pixel_rect(self.target_size) else { continue };
+            if let Some(camera) = self.cameras.get_mut(view.camera) {
+                let aspect = width as f32 / height as f32;
+                if camera.aspect() != aspect {
+                    let _ = camera.set_aspect(aspect);
+                }
+            }
+        }
+
+        // Meshes are uploaded relative to the active camera's origin, other cameras see them shifted
+        if self.cameras.iter().any(|camera| camera.auto_clip_planes().is_some()) {
+            let boxes = self.live_meshes()
+                .filter(|(_, mesh)| mesh.visible)
+                .filter_map(|(_, mesh)| mesh.bounding_box())
+                .collect::<Vec<_>>();
+            for camera in &mut self.cameras {
+                let offset = (origin - camera.origin).cast::<f32>().unwrap();
+                let shift = |p: [f32; 3]| [p[0] + offset.x, p[1] + offset.y, p[2] + offset.z];
+                camera.fit_clip_planes(boxes.iter().map(|&(min, max)| (shift(min), shift(max))));
+            }
+        }
+
+        let camera = &mut self.cameras[self.active_camera];
+        if camera.take_dirty() {
+            self.camera_matrices = camera.matrices();
+            self.camera_uniform.update(camera, &self.camera_matrices);
+        }
+
+        // Every view's uniform is worked out again, any of their cameras could have moved
+        let views = self.views.iter().filter(|view| view.camera < self.cameras.len()).copied().collect::<Vec<_>>();
+        let views = if views.is_empty() { vec![SceneView::full(self.active_camera)] } else { views };
+        self.drawn_views = views
+            .into_iter()
+            .map(|view| {
+                let camera = &self.cameras[view.camera];
+                let (mut uniform, matrices) = if view.camera == self.active_camera {
+                    (self.camera_uniform, self.camera_matrices)
+                } else {
+                    Self::offset_camera(camera, origin)
+                };
+                uniform.time = [self.time, self.delta_time, 0.0, 0.0];
+                DrawnView {
+                    rect: view.rect,
+                    uniform,
+                    matrices,
+                    mirrored: camera.frame.is_mirrored(),
+                    depth_mode: camera.depth_mode(),
+                }
+            })
+            .collect();
+    }
+
+    // Matrices for a camera that isn't the active one, so its origin isn't the one the meshes
+    // were uploaded relative to. The view is shifted to make up the difference
+    fn offset_camera(camera: &Camera, origin: cgmath::Vector3<f64>) -> (CameraUniform, CameraMatrices) {
+        let offset = (origin - camera.origin).cast::<f32>().unwrap();
+        let matrices = CameraMatrices::new(
+            camera.view_matrix() * cgmath::Matrix4::from_translation(offset),
+            camera.projection_matrix(),
+        );
+        let mut uniform = CameraUniform::new();
+        uniform.update(camera, &matrices);
+        uniform.position = (camera.eye - offset).to_homogeneous().into();
+        (uniform, matrices)
+    }
+
+    // Draws meshes in the order given into one of drawn's passes, which has the camera and lights
+    // bound already
+    fn draw_meshes<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, meshes: &[(MeshId, &'a Mesh)], drawn: &DrawnView, stats: &mut FrameStats) {
+        let frustum = drawn.matrices.frustum();
+        // Only switched when the next mesh needs something different
+        let mut bound_pipeline = None;
+        let mut bound_material = None;
+        for &(id, mesh) in meshes {
+            let Some((min, max)) = mesh.bounding_box() else { continue };
+            if self.frustum_culling && !frustum.intersects_aabb(min, max) {
+                stats.meshes_culled += 1;
+                continue;
+            }
+            let material = self.materials.material(mesh.material);
+            let faces = material.faces(mesh.faces);
+            let faces = if drawn.mirrored { faces.mirrored() } else { faces };
+            let key = (faces, material.key(), drawn.depth_mode.is_reversed());
+            if bound_pipeline != Some(key) {
+                render_pass.set_pipeline(&self.render_pipelines[&key]);
+                bound_pipeline = Some(key);
+            }
+            if bound_material != Some(mesh.material) {
+                render_pass.set_bind_group(1, self.materials.bind_group(mesh.material), &[]);
+                bound_material = Some(mesh.material);
+            }
+            render_pass.set_bind_group(3, self.tints.bind_group(), &[self.tints.offset(id)]);
+            let near = match id {
+                MeshId::Static(id) => self.impostors.as_ref().and_then(|impostors| impostors.near_instances(id)),
+                MeshId::Dynamic(_) => None,
+            };
+            match near {
+                Some((instances, count)) => {
+                    if count > 0 {
+                        mesh.draw_with_instances(render_pass, instances, count);
+                    }
+                    stats.instances_drawn += count as usize;
+                }
+                None => {
+                    mesh.draw(render_pass);
+                    stats.instances_drawn += mesh.instances().len();
+                }
+            }
+            stats.meshes_drawn += 1;
+        }
+    }
+
+    // Tells the streamer how near each view sees every texture, and swaps in whatever it changes
+    fn stream_textures(&mut self, gpu: &Gpu) {
+        let mut uses = Vec::new();
+        for drawn in &self.drawn_views {
+            let frustum = drawn.matrices.frustum();
+            let eye = drawn.matrices.inverse_view.w.truncate();
+            for (_, mesh) in self.live_meshes().filter(|(_, mesh)| mesh.visible) {
+                let Some((min, max)) = mesh.bounding_box() else { continue };
+                if !frustum.intersects_aabb(min, max) {
+                    continue;
+                }
+                // From the eye to the nearest point of the box, 0 inside it
+                let nearest = cgmath::Vector3::new(eye.x.clamp(min[0], max[0]), eye.y.clamp(min[1], max[1]), eye.z.clamp(min[2], max[2]));
+                let distance = (nearest - eye).magnitude();
+                uses.extend(self.materials.material(mesh.material).textures().into_iter().flatten().map(|id| (id, distance)));
+            }
+        }
+        for (id, texture) in self.streaming.update(&gpu.device, &gpu.queue, uses) {
+            if let Some(old) = self.textures.replace(id, texture) {
+                self.frames.retire(old);
+            }
+            self.rebind_materials = true;
+        }
+    }
+
+    // Draws the scene into view, which has to be the format and size the renderer was set up with
+    pub fn render(&mut self, gpu: &Gpu, view: &wgpu::TextureView) {
+        trace_span!("scene render");
+        if std::mem::take(&mut self.samplers_changed) {
+            let (default, samplers) = (self.sampler_config, &mut self.samplers);
+            self.textures.rebuild_samplers(|config| samplers.get(&gpu.device, &config.unwrap_or(&default).filtering()));
+            self.rebind_materials = true;
+        }
+        if !self.streaming.is_empty() {
+            self.stream_textures(gpu);
+        }
+        if std::mem::take(&mut self.rebind_materials) {
+            self.materials.rebind(&gpu.device, &self.textures, &mut self.samplers);
+            // Whatever the old settings made isn't bound anywhere now
+            self.samplers.trim();
+            if let Some(lens_flare) = &mut self.lens_flare {
+                lens_flare.rebind();
+            }
+            if let Some(decals) = &mut self.decal_renderer {
+                decals.rebind();
+            }
+        }
+
+        // Left handed frames are drawn through a reflection, so what faces the camera is reversed
+        let materials = &self.materials;
+        let faces = |mesh: &Mesh, mirrored: bool| {
+            let faces = materials.material(mesh.material).faces(mesh.faces);
+            if mirrored { faces.mirrored() } else { faces }
+        };
+
+        // Build pipelines for any face settings, materials or depth modes that have been set since
+        // the last frame, compiling the shader for features that haven't been seen before
+        let transparency = self.transparency();
+        let missing = self.drawn_views.iter()
+            .flat_map(|drawn| self.live_meshes().map(move |(_, mesh)| (faces(mesh, drawn.mirrored), materials.material(mesh.material).key(), drawn.depth_mode.is_reversed())))
+            .filter(|key| !self.render_pipelines.contains_key(key))
+            .collect::<Vec<_>>();
+        for key @ (faces, material, reversed_z) in missing {
+            if !self.render_pipelines.contains_key(&key) {
+                if !self.shaders.contains_key(&material.features) {
+                    let source = self.shader_source("shader.wgsl");
+                    self.shaders.insert(material.features, Self::create_shader(&gpu.device, &source, material.features));
+                }
+                let shader = &self.shaders[&material.features];
+                let pipeline = Self::create_render_pipeline(&gpu.device, &self.render_pipeline_layout, shader, self.format, self.msaa_samples, faces, material, reversed_z, transparency);
+                self.render_pipelines.insert(key, pipeline);
+            }
+        }
+
+        // Waits here if the GPU is already count frames behind
+        let frame = self.frames.begin_frame(&gpu.device);
+        let cameras = &mut self.frame_resources[frame].cameras;
+        for (i, drawn) in self.drawn_views.iter().enumerate() {
+            match cameras.get_mut(i) {
+                Some(binding) => binding.write(&gpu.queue, &drawn.uniform),
+                None => {
+                    let label = format!("View {i} Frame {frame}");
+                    cameras.push(CameraBinding::new(&gpu.device, &self.camera_bind_group_layout, &drawn.uniform, &label));
+                }
+            }
+        }
+
+        // Out of the scene until the frame is submitted, while the passes hold on to the rest of it
+        let mut gpu_timer = self.gpu_timer.take();
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Render Encoder"),
+        });
+
+        encoder.push_debug_group("Upload Dynamic Meshes");
+        for mesh in self.dynamic_meshes.iter_mut().flatten() {
+            mesh.upload(&gpu.device, &mut encoder);
+            mesh.finish_upload();
+        }
+        encoder.pop_debug_group();
+
+        self.lights.write(&gpu.queue, &self.lighting, self.cameras[self.active_camera].origin, self.wetness);
+        let tints = self.live_meshes().map(|(id, mesh)| (id, mesh.tint)).collect::<Vec<_>>();
+        self.tints.write(&gpu.device, &gpu.queue, tints);
+
+        // Far off instances are split from the rest by distance to the active camera, and the
+        // pictures that are missing or out of date are baked before anything is drawn
+        if self.impostors.is_none() && self.meshes.iter().flatten().any(|mesh| mesh.impostor_distance.is_some()) {
+            self.impostors = Some(Impostors::new(&gpu.device, &self.camera_bind_group_layout, self.format, self.impostor_settings));
+        }
+        if let Some(impostors) = &mut self.impostors {
+            let origin = self.cameras[self.active_camera].origin;
+            impostors.prepare(&gpu.device, &gpu.queue, &mut encoder, &self.meshes, &self.shaders, &self.materials, self.lights.bind_group(), &self.tints, &self.render_pipeline_layout, &self.camera_matrices, origin);
+            impostors.prepare_pipelines(&gpu.device, self.msaa_samples, self.drawn_views.iter().map(|drawn| drawn.depth_mode.is_reversed()));
+        }
+        if let Some(skybox) = &mut self.skybox {
+            skybox.prepare(&gpu.device, &gpu.queue, &self.skybox_settings, self.msaa_samples);
+        } else if let Some(sky) = &mut self.sky {
+            sky.prepare(&gpu.device, &gpu.queue, self.msaa_samples);
+        }
+        if let Some(weather) = &mut self.weather {
+            weather.prepare(&gpu.device, &gpu.queue, &self.depth_texture.view, self.msaa_samples, self.drawn_views.iter().map(|drawn| drawn.depth_mode.is_reversed()));
+        }
+        let lens_flare = self.lens_flare.as_mut().filter(|lens_flare| lens_flare.is_visible(&self.lighting.sun));
+        if let Some(lens_flare) = lens_flare {
+            let reversed_z = self.drawn_views.iter().map(|drawn| drawn.depth_mode.is_reversed());
+            lens_flare.prepare(&gpu.device, &gpu.queue, &self.textures, &self.lighting.sun, self.msaa_samples, self.drawn_views.len(), reversed_z);
+        }
+        let lens_flare = self.lens_flare.as_ref().filter(|lens_flare| lens_flare.is_visible(&self.lighting.sun));
+        if self.msaa_samples == 1 && self.decals.iter().any(Option::is_some) {
+            let decals = self.decal_renderer.get_or_insert_with(|| DecalRenderer::new(&gpu.device, &self.camera_bind_group_layout, self.format));
+            let mirrored = self.drawn_views.iter().map(|drawn| drawn.mirrored);
+            decals.prepare(&gpu.device, &gpu.queue, &self.textures, &self.depth_texture.view, self.decals.iter().flatten(), self.cameras[self.active_camera].origin, mirrored);
+        }
+        let decals = self.decal_renderer.as_ref().filter(|decals| self.msaa_samples == 1 && self.decals.iter().any(Option::is_some) && !decals.is_empty());
+        if transparency == TransparencyMode::WeightedBlended {
+            if let Some(oit) = &mut self.oit {
+                oit.prepare(&gpu.device, self.target_size, self.msaa_samples);
+            }
+        }
+        if let Some(grading) = &mut self.color_grading {
+            grading.prepare(&gpu.device, &gpu.queue, self.target_size, self.color_grading_strength);
+        }
+        if let Some(palette) = &mut self.palette {
+            palette.prepare(&gpu.device, &gpu.queue, self.target_size, self.palette_dithering);
+        }
+        // Every pass below draws into the grading frame instead, which is graded onto the palette's
+        // frame if there's a palette, which is snapped onto the target
+        let target = view;
+        let graded = self.palette.as_ref().map_or(target, PaletteRenderer::frame_view);
+        let view = self.color_grading.as_ref().map_or(graded, ColorGrading::frame_view);
+
+        // Let go of if its mesh or instance has gone since
+        let hovered = self.hovered
+            .filter(|hit| self.mesh_by_id(hit.pick.mesh).is_some_and(|mesh| mesh.visible && hit.pick.instance < mesh.instances().len()));
+        if let Some((hit, mesh)) = hovered.and_then(|hit| Some((hit, self.mesh_by_id(hit.pick.mesh)?))) {
+            let view_sizes = self.drawn_views.iter()
+                .map(|drawn| SceneView::new(0, drawn.rect).pixel_rect(self.target_size).map_or([1, 1], |[_, _, width, height]| [width, height]))
+                .collect::<Vec<_>>();
+            let pipelines = self.drawn_views.iter().map(|drawn| (faces(mesh, drawn.mirrored), drawn.depth_mode.is_reversed())).collect::<Vec<_>>();
+            let hover = self.hover.get_or_insert_with(|| Hover::new(&gpu.device, &self.camera_bind_group_layout, self.format));
+            hover.prepare(&gpu.device, &gpu.queue, &self.hover_settings, Some(hit.point), &view_sizes, self.msaa_samples, pipelines);
+        }
+        let hovered = hovered.and_then(|hit| Some((hit, self.mesh_by_id(hit.pick.mesh)?)));
+
+        // Opaque meshes are grouped by material so each is bound once. Blended ones keep the order
+        // they were added in, which is the only thing keeping them behind-to-front. Transparent
+        // ones are sorted for each view, or accumulated in a pass of their own
+        let (mut order, transparent): (Vec<_>, Vec<_>) = self.live_meshes()
+            .filter(|(_, mesh)| mesh.visible)
+            .partition(|(_, mesh)| !materials.material(mesh.material).transparent);
+        order.sort_by_key(|(_, mesh)| match materials.material(mesh.material).blend {
+            BlendMode::Opaque => (0, mesh.material),
+            _ => (1, None),
+        });
+
+        // A pass per view, so each starts with a clear depth buffer and an inset isn't hidden behind
+        // what's under it. Only the first clears the color, later views are drawn straight over it
+        let mut stats = FrameStats::default();
+        let mut cpu = PassTimes::default();
+        let oit = self.oit.as_ref().filter(|_| transparency == TransparencyMode::WeightedBlended && !transparent.is_empty());
+        for (i, drawn) in self.drawn_views.iter().enumerate() {
+            let Some([x, y, width, height]) = SceneView::new(0, drawn.rect).pixel_rect(self.target_size) else { continue };
+            trace_span!("render pass", view = i);
+            let mut since = Instant::now();
+            let label = format!("Render Pass (View {i})");
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: self.msaa_texture.as_ref().map_or(view, |msaa| &msaa.view),
+                    resolve_target: self.msaa_texture.as_ref().map(|_| view),
+                    ops: wgpu::Operations {
+                        load: if i == 0 { wgpu::LoadOp::Clear(self.clear_color) } else { wgpu::LoadOp::Load },
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(drawn.depth_mode.clear_depth()),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Scene)),
+            });
+
+            render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+            render_pass.set_bind_group(2, self.lights.bind_group(), &[]);
+            if let Some(skybox) = &self.skybox {
+                skybox.draw(&mut render_pass, self.msaa_samples);
+            } else if let Some(sky) = &self.sky {
+                sky.draw(&mut render_pass, self.msaa_samples);
+            }
+            self.draw_meshes(&mut render_pass, &order, drawn, &mut stats);
+            if let Some(impostors) = &self.impostors {
+                impostors.draw(&mut render_pass, self.msaa_samples, drawn.depth_mode.is_reversed());
+                stats.impostors_drawn += impostors.billboard_count() as usize;
+            }
+            if let Some(decals) = decals {
+                drop(render_pass);
+                cpu.lap(Pass::Scene, &mut since);
+                // Reads the depth the opaque meshes left, so it can't be attached
+                let mut decal_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("Decal Pass (View {i})")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Decals)),
+                });
+                decal_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                decal_pass.set_scissor_rect(x, y, width, height);
+                decal_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                decals.draw(&mut decal_pass, drawn.mirrored);
+                drop(decal_pass);
+                cpu.lap(Pass::Decals, &mut since);
+
+                // Carries on with the view's color and depth for the rest of it
+                render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("Render Pass (View {i}, After Decals)")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Scene)),
+                });
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                render_pass.set_bind_group(2, self.lights.bind_group(), &[]);
+            }
+            if let Some(oit) = oit {
+                drop(render_pass);
+                cpu.lap(Pass::Scene, &mut since);
+                // Added up in whatever order they come in
+                let mut accumulate_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("OIT Accumulate Pass (View {i})")),
+                    color_attachments: &oit.attachments(),
+                    // Read only, so they're hidden behind what's in front without hiding each other
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: None,
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::OitAccumulate)),
+                });
+                accumulate_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                accumulate_pass.set_scissor_rect(x, y, width, height);
+                accumulate_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                accumulate_pass.set_bind_group(2, self.lights.bind_group(), &[]);
+                self.draw_meshes(&mut accumulate_pass, &transparent, drawn, &mut stats);
+                drop(accumulate_pass);
+                cpu.lap(Pass::OitAccumulate, &mut since);
+
+                // Carries on with the view's color and depth, so the hover highlight still goes on top
+                render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("OIT Composite Pass (View {i})")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: self.msaa_texture.as_ref().map_or(view, |msaa| &msaa.view),
+                        resolve_target: self.msaa_texture.as_ref().map(|_| view),
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store }),
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Scene)),
+                });
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                oit.composite(&mut render_pass, self.msaa_samples);
+            } else if !transparent.is_empty() {
+                // Furthest from this view's camera first
+                let eye = drawn.matrices.inverse_view.w.truncate();
+                let distance = |mesh: &Mesh| mesh.bounding_box().map_or(0.0, |(min, max)| {
+                    ((cgmath::Vector3::from(min) + cgmath::Vector3::from(max)) * 0.5 - eye).magnitude2()
+                });
+                let mut sorted = transparent.clone();
+                sorted.sort_by(|(_, a), (_, b)| distance(b).total_cmp(&distance(a)));
+                render_pass.push_debug_group("Transparent");
+                self.draw_meshes(&mut render_pass, &sorted, drawn, &mut stats);
+                render_pass.pop_debug_group();
+            }
+            if let (Some(hover), Some((hit, mesh))) = (&self.hover, hovered) {
+                hover.draw(&mut render_pass, i, mesh, hit.pick.instance, faces(mesh, drawn.mirrored), self.msaa_samples, drawn.depth_mode.is_reversed());
+            }
+            drop(render_pass);
+            cpu.lap(Pass::Scene, &mut since);
+
+            // Reads the depth the view left behind, so it needs a pass of its own
+            if let Some(weather) = &self.weather {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("Weather Pass (View {i})")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: self.msaa_texture.as_ref().map_or(view, |msaa| &msaa.view),
+                        resolve_target: self.msaa_texture.as_ref().map(|_| view),
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    // Read only, so the particles are tested against it
+                    depth_stencil_attachment: Weather::needs_depth_attachment(self.msaa_samples).then_some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: None,
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Weather)),
+                });
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                weather.draw(&mut render_pass, self.msaa_samples, drawn.depth_mode.is_reversed());
+                drop(render_pass);
+                cpu.lap(Pass::Weather, &mut since);
+            }
+
+            // The sun is tested against the view's depth first, and the results copied over
+            // before the flare is drawn with them
+            if let Some(lens_flare) = lens_flare {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("Lens Flare Probe Pass (View {i})")),
+                    color_attachments: &[],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.depth_texture.view,
+                        depth_ops: None,
+                        stencil_ops: None,
+                    }),
+                    occlusion_query_set: Some(lens_flare.query_set()),
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::LensFlare)),
+                });
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                lens_flare.probe(&mut render_pass, i, self.msaa_samples, drawn.depth_mode.is_reversed());
+                drop(render_pass);
+
+                lens_flare.resolve(&mut encoder, i);
+
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&format!("Lens Flare Pass (View {i})")),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: self.msaa_texture.as_ref().map_or(view, |msaa| &msaa.view),
+                        resolve_target: self.msaa_texture.as_ref().map(|_| view),
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: None,
+                    occlusion_query_set: None,
+                    timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::LensFlare)),
+                });
+                render_pass.set_viewport(x as f32, y as f32, width as f32, height as f32, 0.0, 1.0);
+                render_pass.set_scissor_rect(x, y, width, height);
+                render_pass.set_bind_group(0, &self.frame_resources[frame].cameras[i].camera_bind_group, &[]);
+                lens_flare.draw(&mut render_pass, i, self.msaa_samples);
+                drop(render_pass);
+                cpu.lap(Pass::LensFlare, &mut since);
+            }
+        }
+        let mut since = Instant::now();
+        if let Some(grading) = &self.color_grading {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Color Grading Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: graded,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::ColorGrading)),
+            });
+            grading.draw(&mut render_pass);
+            drop(render_pass);
+            cpu.lap(Pass::ColorGrading, &mut since);
+        }
+        if let Some(palette) = &self.palette {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Palette Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: gpu_timer.as_mut().and_then(|timer| timer.timestamp_writes(Pass::Palette)),
+            });
+            palette.draw(&mut render_pass);
+            drop(render_pass);
+            cpu.lap(Pass::Palette, &mut since);
+        }
+        stats.buffer_bytes = self.live_meshes().map(|(_, mesh)| mesh.buffer_bytes()).sum();
+        stats.texture_bytes = self.textures.iter().chain(self.textures.iter_arrays()).map(|(_, texture)| texture.bytes()).sum();
+        self.cpu_pass_times = cpu;
+        self.stats = stats;
+
+        if let Some(timer) = &mut gpu_timer {
+            timer.resolve(&mut encoder);
+        }
+
+        // submit will accept anything that implements IntoIter
+        let submission = gpu.queue.submit(std::iter::once(encoder.finish()));
+        self.frames.end_frame(submission);
+        if let Some(timer) = &mut gpu_timer {
+            timer.submitted();
+        }
+        self.gpu_timer = gpu_timer;
+        for mesh in self.dynamic_meshes.iter_mut().flatten() {
+            mesh.recall();
+        }
+    }
+}
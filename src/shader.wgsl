@@ -1,33 +1,337 @@
 // Vertex shader
-struct CameraUniform {
-    view_proj: mat4x4<f32>,
-};
-@group(0) @binding(0) // 1.
-var<uniform> camera: CameraUniform;
+// Cut down per mesh by ShaderFeatures before it's compiled, see shader_features::preprocess
+#include "camera.wgsl"
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
-    @location(1) color: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) tex_coords: vec2<f32>,
+    // w is which way the bitangent points, all zero if the mesh has no tangents
+    @location(4) tangent: vec4<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) color: vec4<f32>,
+    @location(10) data: vec4<f32>,
+    @location(11) normal_matrix_0: vec3<f32>,
+    @location(12) normal_matrix_1: vec3<f32>,
+    @location(13) normal_matrix_2: vec3<f32>,
+#ifdef TEXTURE_ARRAY
+    @location(14) layer: u32,
+#endif
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
-    @location(0) color: vec3<f32>,
-};  
+    // White without VERTEX_COLOR
+    @location(0) color: vec4<f32>,
+    @location(7) instance_color: vec4<f32>,
+    @location(1) data: vec4<f32>,
+    @location(2) world_normal: vec3<f32>,
+#ifdef HAS_UV
+    @location(3) tex_coords: vec2<f32>,
+#endif
+#ifdef PBR
+    // Relative to the camera's origin, the same space as camera.position
+    @location(4) world_position: vec3<f32>,
+#endif
+#ifdef HAS_NORMAL_MAP
+    @location(5) world_tangent: vec4<f32>,
+#endif
+#ifdef TEXTURE_ARRAY
+    @location(6) @interpolate(flat) layer: u32,
+#endif
+};
 
 @vertex
 fn vs_main(
     model: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+    // Inverse transpose of the model matrix, so normals survive non-uniform scaling
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
     var out: VertexOutput;
+#ifdef VERTEX_COLOR
     out.color = model.color;
-    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0); // 2.
+#else
+    out.color = vec4<f32>(1.0);
+#endif
+    out.instance_color = instance.color;
+#ifdef HAS_UV
+    // Wrapped, so they don't lose precision as time goes on
+    out.tex_coords = model.tex_coords + fract(material.uv_scroll.xy * camera.time.x);
+#endif
+    // Not normalized here, it has to be renormalized after interpolation anyway
+    out.world_normal = normal_matrix * model.normal;
+    out.data = instance.data;
+#ifdef TEXTURE_ARRAY
+    out.layer = instance.layer;
+#endif
+#ifdef HAS_NORMAL_MAP
+    // Tangents lie along the surface, so they're moved by the model matrix itself. A mirroring
+    // instance flips which way the bitangent goes
+    let model_3x3 = mat3x3<f32>(model_matrix[0].xyz, model_matrix[1].xyz, model_matrix[2].xyz);
+    out.world_tangent = vec4<f32>(model_3x3 * model.tangent.xyz, model.tangent.w * sign(determinant(model_3x3)));
+#endif
+    let world_position = model_matrix * vec4<f32>(model.position, 1.0);
+#ifdef PBR
+    out.world_position = world_position.xyz;
+#endif
+    out.clip_position = apply_depth_mode(camera.view_proj * world_position); // 2.
     return out;
 }
 
 // Fragment shader
 
+#ifdef HAS_UV
+// The material's textures, white pixels where it doesn't have one
+@group(1) @binding(0)
+var base_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var base_sampler: sampler;
+#ifdef TEXTURE_ARRAY
+// Read in place of base_texture, one layer per instance
+@group(1) @binding(11)
+var base_array: texture_2d_array<f32>;
+@group(1) @binding(12)
+var base_array_sampler: sampler;
+#endif
+#endif
+
+struct MaterialUniform {
+    base_color: vec4<f32>,
+    emissive: vec4<f32>,
+    // (metallic, roughness, normal scale, occlusion strength)
+    pbr: vec4<f32>,
+    // x is the ColorCombine
+    options: vec4<u32>,
+    // xy is how far the texture coordinates move each second
+    uv_scroll: vec4<f32>,
+};
+@group(1) @binding(2)
+var<uniform> material: MaterialUniform;
+
+#ifdef HAS_UV
+@group(1) @binding(9)
+var emissive_texture: texture_2d<f32>;
+@group(1) @binding(10)
+var emissive_sampler: sampler;
+#endif
+
+// The whole mesh's tint, see Mesh::tint
+@group(3) @binding(0)
+var<uniform> tint: vec4<f32>;
+
+#ifdef PBR
+#ifdef HAS_UV
+// Blue is metallic, green is roughness
+@group(1) @binding(3)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var metallic_roughness_sampler: sampler;
+@group(1) @binding(7)
+var occlusion_texture: texture_2d<f32>;
+@group(1) @binding(8)
+var occlusion_sampler: sampler;
+#ifdef HAS_NORMAL_MAP
+@group(1) @binding(5)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(6)
+var normal_sampler: sampler;
+#endif
+#endif
+
+struct PointLight {
+    // xyz relative to the camera's origin, w the range
+    position: vec4<f32>,
+    // Already multiplied by the intensity
+    color: vec4<f32>,
+};
+
+struct Lighting {
+    // w is how wet surfaces are
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    // w is how many point lights are in use
+    ambient: vec4<f32>,
+    point_lights: array<PointLight, ${MAX_POINT_LIGHTS}>,
+};
+@group(2) @binding(0)
+var<uniform> lighting: Lighting;
+
+const PI: f32 = 3.14159265;
+
+// The glTF metallic-roughness BRDF: GGX distribution, height correlated Smith visibility and
+// Schlick fresnel over a Lambert diffuse
+fn brdf(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, diffuse: vec3<f32>, f0: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let h = normalize(l + v);
+    let n_dot_l = saturate(dot(n, l));
+    let n_dot_v = max(dot(n, v), 1e-4);
+    let n_dot_h = saturate(dot(n, h));
+    let v_dot_h = saturate(dot(v, h));
+    let a2 = alpha * alpha;
+    let d = a2 / (PI * pow(n_dot_h * n_dot_h * (a2 - 1.0) + 1.0, 2.0));
+    let vis = 0.5 / max(n_dot_l * sqrt(n_dot_v * n_dot_v * (1.0 - a2) + a2) + n_dot_v * sqrt(n_dot_l * n_dot_l * (1.0 - a2) + a2), 1e-5);
+    let f = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+    return ((1.0 - f) * diffuse / PI + f * d * vis) * n_dot_l;
+}
+
+// Tangent space from how the position and texture coordinates change across the pixel, so
+// normal maps work without the mesh carrying tangents
+fn perturb_normal(n: vec3<f32>, p: vec3<f32>, uv: vec2<f32>, sampled: vec3<f32>) -> vec3<f32> {
+    let dp1 = dpdx(p);
+    let dp2 = dpdy(p);
+    let duv1 = dpdx(uv);
+    let duv2 = dpdy(uv);
+    let dp2perp = cross(dp2, n);
+    let dp1perp = cross(n, dp1);
+    let t = dp2perp * duv1.x + dp1perp * duv2.x;
+    let b = dp2perp * duv1.y + dp1perp * duv2.y;
+    // Screen y points down here, unlike GL where this frame comes from, which flips it. Which
+    // way the pixel's axes wind around the normal sets it right either way
+    let flip = sign(dot(cross(dp1, dp2), n));
+    // Each made unit length like vertex tangents are, so both ways look the same
+    let tangent = t * inverseSqrt(max(dot(t, t), 1e-12)) * flip;
+    let bitangent = b * inverseSqrt(max(dot(b, b), 1e-12)) * flip;
+    return normalize(mat3x3<f32>(tangent, bitangent, n) * sampled);
+}
+
+fn shade(in: VertexOutput, front_facing: bool, base: vec3<f32>) -> vec3<f32> {
+    var n = in.world_normal;
+    // Hand written geometry often has no normals, so fall back to the triangle's. Worked out up
+    // here since derivatives can't be taken inside the branch
+    let geometric = cross(dpdx(in.world_position), dpdy(in.world_position));
+    if dot(n, n) < 1e-12 {
+        // Turned towards the eye, whichever way the derivatives went
+        n = geometric * sign(dot(geometric, camera.position.xyz - in.world_position));
+    } else if !front_facing {
+        n = -n;
+    }
+    n = normalize(n);
+
+    var metallic = material.pbr.x;
+    var roughness = material.pbr.y;
+    var occlusion = 1.0;
+#ifdef HAS_UV
+    let metallic_roughness = textureSample(metallic_roughness_texture, metallic_roughness_sampler, in.tex_coords);
+    metallic *= metallic_roughness.b;
+    roughness *= metallic_roughness.g;
+    occlusion = mix(1.0, textureSample(occlusion_texture, occlusion_sampler, in.tex_coords).r, material.pbr.w);
+#ifdef HAS_NORMAL_MAP
+    let sampled = textureSample(normal_texture, normal_sampler, in.tex_coords).xyz * 2.0 - 1.0;
+    // Seen from behind bumps are dents, so the whole frame turns around, not just the normal
+    let facing = select(1.0, -1.0, !front_facing);
+    let detail = normalize(vec3<f32>(sampled.xy * material.pbr.z * facing, sampled.z));
+    // Taken whether or not it's used, derivatives can't be inside the branch
+    let per_pixel = perturb_normal(n, in.world_position, in.tex_coords, detail);
+    let t = in.world_tangent.xyz - n * dot(n, in.world_tangent.xyz);
+    if dot(t, t) > 1e-12 {
+        let tangent = normalize(t);
+        // n has already been turned around for back faces, which would turn this with it
+        let bitangent = cross(n, tangent) * in.world_tangent.w * facing;
+        n = normalize(mat3x3<f32>(tangent, bitangent, n) * detail);
+    } else {
+        n = per_pixel;
+    }
+#endif
+#endif
+    metallic = saturate(metallic);
+    // Water pools on whatever faces up, which darkens it and gives it a sheen
+    let wet = lighting.sun_direction.w * saturate(n.y * 2.0);
+    let albedo = base * mix(1.0, 0.45, wet);
+    roughness = mix(roughness, 0.08, wet);
+    let alpha = pow(clamp(roughness, 0.045, 1.0), 2.0);
+    let diffuse = albedo * (1.0 - metallic);
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let v = normalize(camera.position.xyz - in.world_position);
+
+    var color = brdf(n, v, lighting.sun_direction.xyz, diffuse, f0, alpha) * lighting.sun_color.rgb;
+    let count = u32(lighting.ambient.w);
+    for (var i = 0u; i < count; i++) {
+        let light = lighting.point_lights[i];
+        let to_light = light.position.xyz - in.world_position;
+        let distance2 = max(dot(to_light, to_light), 1e-4);
+        // Inverse square, windowed so it reaches 0 at the range like KHR_lights_punctual
+        let window = pow(saturate(1.0 - pow(distance2 / (light.position.w * light.position.w), 2.0)), 2.0);
+        color += brdf(n, v, to_light * inverseSqrt(distance2), diffuse, f0, alpha) * light.color.rgb * window / distance2;
+    }
+    color += lighting.ambient.rgb * (diffuse + f0) * occlusion;
+    return color;
+}
+#endif
+
+// How the vertex colors and the base texture go together, see Material::color_combine
+fn combine(vertex: vec4<f32>, texel: vec4<f32>) -> vec4<f32> {
+    switch material.options.x {
+        case 1u: { return texel; }
+        case 2u: { return vec4<f32>(vertex.rgb + texel.rgb, vertex.a * texel.a); }
+        case 3u: { return vertex; }
+        default: { return vertex * texel; }
+    }
+}
+
+// Straight alpha, the same whichever pass it's drawn in
+fn surface(in: VertexOutput, front_facing: bool) -> vec4<f32> {
+    var texel = vec4<f32>(1.0);
+#ifdef HAS_UV
+#ifdef TEXTURE_ARRAY
+    texel = textureSample(base_array, base_array_sampler, in.tex_coords, in.layer);
+#else
+    texel = textureSample(base_texture, base_sampler, in.tex_coords);
+#endif
+#endif
+    var base = combine(in.color, texel) * in.instance_color * tint * material.base_color;
+#ifdef PBR
+    var color = shade(in, front_facing, base.rgb);
+#else
+    var color = base.rgb;
+#endif
+    // Lit or not, glowing surfaces give off the same light. Not clamped, so with a float target
+    // it can go past white
+    var emissive = material.emissive.rgb;
+#ifdef HAS_UV
+    emissive *= textureSample(emissive_texture, emissive_sampler, in.tex_coords).rgb;
+#endif
+    color += emissive;
+    // The default use of the instance data is a highlight color (xyz) with a strength (w)
+    color = mix(color, in.data.xyz, in.data.w);
+    return vec4<f32>(color, base.a);
+}
+
+@fragment
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    return surface(in, front_facing);
+}
+
+// Transparent materials with TransparencyMode::WeightedBlended, see types::oit
+struct OitOutput {
+    @location(0) accum: vec4<f32>,
+    @location(1) revealage: f32,
+};
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
-}
\ No newline at end of file
+fn fs_oit(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> OitOutput {
+    let color = surface(in, front_facing);
+    // McGuire and Bavoil's weight by view distance, so nearer layers win out over further ones
+    let distance = linearize_depth(in.clip_position.z, camera.projection_params.x);
+    let weight = color.a * clamp(10.0 / (1e-5 + pow(distance / 5.0, 2.0) + pow(distance / 200.0, 6.0)), 1e-2, 3e3);
+    var out: OitOutput;
+    out.accum = vec4<f32>(color.rgb * color.a, color.a) * weight;
+    out.revealage = color.a;
+    return out;
+}
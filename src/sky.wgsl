@@ -0,0 +1,138 @@
+// Gradient sky with a sun and a drifting cloud layer, drawn behind everything, see types::sky
+#include "camera.wgsl"
+
+struct SkyUniform {
+    zenith: vec4<f32>,
+    horizon: vec4<f32>,
+    ground: vec4<f32>,
+    // xyz towards the sun, w the cosine of its radius
+    sun_direction: vec4<f32>,
+    sun_color: vec4<f32>,
+    cloud_color: vec4<f32>,
+    // (coverage, density, height, scale)
+    cloud_shape: vec4<f32>,
+    // (wind x, wind z, time, thickness)
+    cloud_motion: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> sky: SkyUniform;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle over the whole viewport
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(vertex == 1u) * 4.0 - 1.0, f32(vertex == 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+fn hash(p: vec2<f32>) -> f32 {
+    let q = fract(p * vec2<f32>(123.34, 456.21));
+    let r = q + dot(q, q + 45.32);
+    return fract(r.x * r.y);
+}
+
+fn value_noise(p: vec2<f32>) -> f32 {
+    let cell = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let a = hash(cell);
+    let b = hash(cell + vec2<f32>(1.0, 0.0));
+    let c = hash(cell + vec2<f32>(0.0, 1.0));
+    let d = hash(cell + vec2<f32>(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+fn fbm(p: vec2<f32>) -> f32 {
+    var total = 0.0;
+    var amplitude = 0.5;
+    var q = p;
+    for (var i = 0; i < 5; i++) {
+        total += value_noise(q) * amplitude;
+        q = q * 2.03 + vec2<f32>(17.1, 9.7);
+        amplitude *= 0.5;
+    }
+    return total;
+}
+
+// How much cloud there is at a point in the layer, h is how far up through it from 0 to 1
+fn cloud_density(p: vec3<f32>, h: f32) -> f32 {
+    let coverage = sky.cloud_shape.x;
+    let uv = p.xz * sky.cloud_shape.w + sky.cloud_motion.xy * sky.cloud_motion.z;
+    let n = fbm(uv + h * 0.35);
+    // Thinner at the top and bottom of the layer so they're rounded instead of slabs
+    let profile = 4.0 * h * (1.0 - h);
+    return saturate((n - (1.0 - coverage)) / max(coverage, 1e-3)) * profile;
+}
+
+const CLOUD_STEPS: i32 = 8;
+
+// Marches through the layer towards dir, returning the cloud color and how much it covers
+fn clouds(dir: vec3<f32>) -> vec4<f32> {
+    if sky.cloud_shape.x <= 0.0 || dir.y <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    let height = sky.cloud_shape.z;
+    let thickness = sky.cloud_motion.w;
+    let start = height / dir.y;
+    // Near the horizon the path through the layer gets very long, there's nothing to see there
+    let path = min(thickness / dir.y, thickness * 4.0);
+    let stride = path / f32(CLOUD_STEPS);
+    let sun = sky.sun_direction.xyz;
+
+    var transmittance = 1.0;
+    var light = vec3<f32>(0.0);
+    for (var i = 0; i < CLOUD_STEPS; i++) {
+        let t = start + (f32(i) + 0.5) * stride;
+        let p = dir * t;
+        let h = saturate((p.y - height) / thickness);
+        let density = cloud_density(p, h) * sky.cloud_shape.y;
+        if density <= 0.0 {
+            continue;
+        }
+        // One look towards the sun for how shadowed this bit is
+        let towards_sun = p + sun * thickness * 0.5;
+        let shadow = cloud_density(towards_sun, saturate((towards_sun.y - height) / thickness)) * sky.cloud_shape.y;
+        let lit = sky.cloud_color.rgb * mix(0.55, 1.0, exp(-shadow * 3.0)) + sky.sun_color.rgb * 0.15 * exp(-shadow * 6.0);
+        let absorbed = 1.0 - exp(-density * stride * 30.0);
+        light += lit * absorbed * transmittance;
+        transmittance *= 1.0 - absorbed;
+    }
+    let alpha = (1.0 - transmittance) * smoothstep(0.0, 0.12, dir.y);
+    return vec4<f32>(light / max(1.0 - transmittance, 1e-4), alpha);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Two points along the pixel's ray, which works for orthographic cameras as well
+    let near = camera.inv_view_proj * vec4<f32>(in.ndc, 0.25, 1.0);
+    let far = camera.inv_view_proj * vec4<f32>(in.ndc, 0.75, 1.0);
+    var dir = normalize(far.xyz / far.w - near.xyz / near.w);
+    // Reversed depth has the far plane at 0
+    if camera.depth_params.x > 1.5 {
+        dir = -dir;
+    }
+
+    var color: vec3<f32>;
+    if dir.y >= 0.0 {
+        color = mix(sky.horizon.rgb, sky.zenith.rgb, pow(dir.y, 0.5));
+    } else {
+        color = mix(sky.horizon.rgb, sky.ground.rgb, saturate(-dir.y * 6.0));
+    }
+
+    let sun = dot(dir, sky.sun_direction.xyz);
+    let radius = sky.sun_direction.w;
+    let disc = smoothstep(radius, radius + (1.0 - radius) * 0.1, sun);
+    let glow = pow(max(sun, 0.0), 64.0) * 0.3;
+    color += sky.sun_color.rgb * (disc + glow) * step(0.0, dir.y);
+
+    let cloud = clouds(dir);
+    color = mix(color, cloud.rgb, cloud.a);
+    return vec4<f32>(color, 1.0);
+}
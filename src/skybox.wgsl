@@ -0,0 +1,45 @@
+// A cubemap drawn behind everything, see types::skybox
+#include "camera.wgsl"
+
+struct SkyboxUniform {
+    // (intensity, cos rotation, sin rotation, unused)
+    params: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> skybox: SkyboxUniform;
+@group(1) @binding(1)
+var skybox_texture: texture_cube<f32>;
+@group(1) @binding(2)
+var skybox_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) ndc: vec2<f32>,
+};
+
+// One triangle over the whole viewport
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> VertexOutput {
+    let ndc = vec2<f32>(f32(vertex == 1u) * 4.0 - 1.0, f32(vertex == 2u) * 4.0 - 1.0);
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.ndc = ndc;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // Same as sky.wgsl, two points along the pixel's ray
+    let near = camera.inv_view_proj * vec4<f32>(in.ndc, 0.25, 1.0);
+    let far = camera.inv_view_proj * vec4<f32>(in.ndc, 0.75, 1.0);
+    var dir = normalize(far.xyz / far.w - near.xyz / near.w);
+    if camera.depth_params.x > 1.5 {
+        dir = -dir;
+    }
+    // Turned around y
+    let c = skybox.params.y;
+    let s = skybox.params.z;
+    dir = vec3<f32>(c * dir.x - s * dir.z, dir.y, s * dir.x + c * dir.z);
+    let color = textureSampleLevel(skybox_texture, skybox_sampler, dir, 0.0).rgb;
+    return vec4<f32>(color * skybox.params.x, 1.0);
+}
@@ -0,0 +1,394 @@
+use std::sync::Arc;
+
+use winit::window::Window;
+
+use crate::{gpu::Gpu, types::texture::Texture};
+
+// Why a surface format was refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormatError {
+    // Not one of the surface's supported_formats
+    Unsupported(wgpu::TextureFormat),
+    // A view format can only differ from the format by being sRGB or not
+    IncompatibleViewFormat { format: wgpu::TextureFormat, view_format: wgpu::TextureFormat },
+    // The backend can't view frames as a different format at all, GL and Vulkan on Android
+    ViewFormatsUnsupported,
+}
+
+impl std::fmt::Display for FormatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormatError::Unsupported(format) => write!(f, "the surface doesn't support {format:?}"),
+            FormatError::IncompatibleViewFormat { format, view_format } => write!(f, "{format:?} can't be viewed as {view_format:?}"),
+            FormatError::ViewFormatsUnsupported => write!(f, "this backend can't view the surface as another format"),
+        }
+    }
+}
+
+impl std::error::Error for FormatError {}
+
+// How long after the last resize event the window still counts as being resized
+const RESIZE_SETTLE_TIME: std::time::Duration = std::time::Duration::from_millis(150);
+
+// Whether drawing straight into format would come out too dark. Everything is drawn expecting
+// the target to encode to sRGB, floating point surfaces are linear on purpose
+fn needs_gamma_encoding(format: wgpu::TextureFormat) -> bool {
+    matches!(format, wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Rgb10a2Unorm)
+}
+
+// Frames drawn into an sRGB texture of their own and copied onto a linear surface, encoded on
+// the way, when the surface can't be viewed as sRGB
+struct GammaBlit {
+    // What frames are drawn into instead, the surface format's sRGB twin if it has one
+    format: wgpu::TextureFormat,
+    layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::RenderPipeline,
+    // Made again whenever the surface is configured
+    frame: Option<(Texture, wgpu::BindGroup)>,
+}
+
+impl GammaBlit {
+    fn new(gpu: &Gpu, surface_format: wgpu::TextureFormat) -> Self {
+        let device = &gpu.device;
+        let format = match surface_format.add_srgb_suffix() {
+            format if format.is_srgb() => format,
+            _ => Texture::HDR_FORMAT,
+        };
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Gamma Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("gamma.wgsl").into()) });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("gamma_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gamma Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Gamma Pipeline Layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: surface_format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        Self { format, layout, pipeline, frame: None }
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, size: [u32; 2]) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Gamma Frame"),
+            size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("gamma_bind_group"),
+            layout: &self.layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) }],
+        });
+        self.frame = Some((Texture { texture, view }, bind_group));
+    }
+
+    fn blit(&self, gpu: &Gpu, target: &wgpu::TextureView) {
+        let Some((_, bind_group)) = &self.frame else { return };
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Gamma Encoder") });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Gamma Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        drop(render_pass);
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+// A window and the surface that's presented to it. Knows nothing about what's drawn. Holds on to
+// the window, so it can be stored or moved anywhere without borrowing it
+pub struct WindowTarget {
+    surface: wgpu::Surface<'static>,
+    config: wgpu::SurfaceConfiguration,
+    // What the surface can be configured with, the platform's preferred format first
+    formats: Vec<wgpu::TextureFormat>,
+    size: winit::dpi::PhysicalSize<u32>,
+    // Resizes are applied once per frame rather than once per event, see apply_resize
+    resize_pending: bool,
+    last_resize: web_time::Instant,
+    // Draw at this fraction of the window's resolution while it's being resized, None for full resolution
+    live_resize_scale: Option<f32>,
+    // Fraction of the window's resolution the surface is configured at
+    render_scale: f32,
+    // Only when view_format is linear, see needs_gamma_encoding
+    gamma: Option<GammaBlit>,
+    // Nothing is drawn while the window has no area or is hidden
+    minimized: bool,
+    occluded: bool,
+    // The surface keeps its own reference to the window alive, this one is for everything else
+    window: Arc<Window>,
+}
+
+impl WindowTarget {
+    // For another window on a Gpu that already exists
+    pub fn new(gpu: &Gpu, window: Arc<Window>) -> Self {
+        let surface = gpu.instance.create_surface(window.clone()).unwrap();
+        Self::from_surface(gpu, window, surface)
+    }
+
+    // The surface has to have been created from gpu's instance. Not configured until the first apply_resize
+    pub fn from_surface(gpu: &Gpu, window: Arc<Window>, surface: wgpu::Surface<'static>) -> Self {
+        let size = window.inner_size();
+        let surface_caps = surface.get_capabilities(&gpu.adapter);
+        // Everything is drawn expecting the frame to encode to sRGB. A linear surface is viewed as
+        // its sRGB twin where the backend allows it, otherwise frames are encoded by a final copy
+        let surface_format = surface_caps.formats.iter()
+            .find(|f| f.is_srgb())
+            .copied()
+            .unwrap_or(surface_caps.formats[0]);
+        let flags = gpu.adapter.get_downlevel_capabilities().flags;
+        let view_formats = match surface_format.add_srgb_suffix() {
+            view_format if view_format != surface_format && flags.contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS) => vec![view_format],
+            _ => vec![],
+        };
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats,
+            desired_maximum_frame_latency: 2,
+        };
+        let gamma = needs_gamma_encoding(config.view_formats.first().copied().unwrap_or(surface_format)).then(|| GammaBlit::new(gpu, surface_format));
+
+        Self {
+            surface,
+            config,
+            formats: surface_caps.formats,
+            size,
+            resize_pending: false,
+            last_resize: web_time::Instant::now(),
+            live_resize_scale: None,
+            render_scale: 1.0,
+            gamma,
+            minimized: false,
+            occluded: false,
+            window,
+        }
+    }
+
+    pub fn window(&self) -> &Arc<Window> {
+        &self.window
+    }
+
+    // What the surface's textures are stored as, which is what the compositor sees
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    // What frames are drawn into as, format unless set_format was given a view format or the
+    // frames are encoded to sRGB on the way to a linear surface. Pipelines drawing to the window
+    // have to target this one
+    pub fn view_format(&self) -> wgpu::TextureFormat {
+        match &self.gamma {
+            Some(gamma) => gamma.format,
+            None => self.surface_view_format(),
+        }
+    }
+
+    fn surface_view_format(&self) -> wgpu::TextureFormat {
+        self.config.view_formats.first().copied().unwrap_or(self.config.format)
+    }
+
+    // Whether frames go through a copy that encodes them, because the surface is linear and can't
+    // be viewed as sRGB. Costs a full screen pass each frame
+    pub fn encodes_gamma(&self) -> bool {
+        self.gamma.is_some()
+    }
+
+    pub fn supported_formats(&self) -> &[wgpu::TextureFormat] {
+        &self.formats
+    }
+
+    // Picks the surface format rather than the first sRGB one, for compositors that manage color
+    // themselves. view_format draws into it as the sRGB or linear twin instead, so a linear
+    // surface can be rendered through an sRGB view or the other way around. Applied next frame
+    pub fn set_format(&mut self, gpu: &Gpu, format: wgpu::TextureFormat, view_format: Option<wgpu::TextureFormat>) -> Result<(), FormatError> {
+        if !self.formats.contains(&format) {
+            return Err(FormatError::Unsupported(format));
+        }
+        let view_format = view_format.filter(|&view_format| view_format != format);
+        if let Some(view_format) = view_format {
+            if view_format.remove_srgb_suffix() != format.remove_srgb_suffix() {
+                return Err(FormatError::IncompatibleViewFormat { format, view_format });
+            }
+            let flags = gpu.adapter.get_downlevel_capabilities().flags;
+            if !flags.contains(wgpu::DownlevelFlags::SURFACE_VIEW_FORMATS) {
+                return Err(FormatError::ViewFormatsUnsupported);
+            }
+        }
+        self.config.format = format;
+        self.config.view_formats = view_format.into_iter().collect();
+        self.gamma = needs_gamma_encoding(self.surface_view_format()).then(|| GammaBlit::new(gpu, self.surface_view_format()));
+        self.resize_pending = true;
+        Ok(())
+    }
+
+    // A view of a frame in view_format, to draw into
+    pub fn create_view(&self, frame: &wgpu::SurfaceTexture) -> wgpu::TextureView {
+        if let Some((texture, _)) = self.gamma.as_ref().and_then(|gamma| gamma.frame.as_ref()) {
+            return texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        }
+        frame.texture.create_view(&wgpu::TextureViewDescriptor {
+            format: Some(self.view_format()),
+            ..Default::default()
+        })
+    }
+
+    // Shows a frame once everything has been drawn into its view, encoding it first if needed
+    pub fn present(&self, gpu: &Gpu, frame: wgpu::SurfaceTexture) {
+        if let Some(gamma) = &self.gamma {
+            let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+                format: Some(self.surface_view_format()),
+                ..Default::default()
+            });
+            gamma.blit(gpu, &view);
+        }
+        frame.present();
+    }
+
+    // The window's size, which input is measured in
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
+    // The size actually drawn at, which render_scale makes different from the window's
+    pub fn render_size(&self) -> [u32; 2] {
+        [self.config.width, self.config.height]
+    }
+
+    // Only records the new size, the surface is reconfigured at the start of the next frame. Dragging
+    // a window edge sends a flood of these and configuring the surface for each one stutters
+    pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
+        // Minimizing resizes to 0x0 on some platforms, which can't be configured
+        self.minimized = new_size.width == 0 || new_size.height == 0;
+        if !self.minimized {
+            self.size = new_size;
+            self.resize_pending = true;
+            self.last_resize = web_time::Instant::now();
+        }
+    }
+
+    // For when a frame couldn't be acquired because the surface was lost or went out of date
+    pub fn reconfigure(&mut self) {
+        self.resize_pending = true;
+    }
+
+    pub fn set_occluded(&mut self, occluded: bool) {
+        self.occluded = occluded;
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    // True while the window is minimized or fully covered, rendering is skipped until it isn't
+    pub fn is_paused(&self) -> bool {
+        self.minimized || self.occluded
+    }
+
+    // Renders at a lower resolution while the window is being resized, which the compositor
+    // stretches to fit. Full resolution comes back once resizing stops
+    pub fn set_live_resize_scale(&mut self, scale: Option<f32>) {
+        self.live_resize_scale = scale.map(|scale| scale.clamp(0.1, 1.0));
+    }
+
+    // Renders at a fraction of the window's resolution all the time, which the compositor stretches
+    // to fit. Above 1 supersamples
+    pub fn set_render_scale(&mut self, scale: f32) {
+        self.render_scale = scale.clamp(0.1, 2.0);
+        self.resize_pending = true;
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    // Without vsync frames are presented as soon as they're done, which can tear
+    pub fn set_vsync(&mut self, vsync: bool) {
+        self.config.present_mode = if vsync { wgpu::PresentMode::AutoVsync } else { wgpu::PresentMode::AutoNoVsync };
+        self.resize_pending = true;
+    }
+
+    pub fn vsync(&self) -> bool {
+        matches!(self.config.present_mode, wgpu::PresentMode::Fifo | wgpu::PresentMode::AutoVsync)
+    }
+
+    // Configures the surface if the size or settings changed since the last frame. Returns true
+    // if it did, anything sized to match it needs recreating
+    pub fn apply_resize(&mut self, gpu: &Gpu) -> bool {
+        let resizing = self.last_resize.elapsed() < RESIZE_SETTLE_TIME;
+        let scale = self.render_scale * self.live_resize_scale.filter(|_| resizing).unwrap_or(1.0);
+        let width = ((self.size.width as f32 * scale) as u32).max(1);
+        let height = ((self.size.height as f32 * scale) as u32).max(1);
+        if !self.resize_pending && width == self.config.width && height == self.config.height {
+            return false;
+        }
+        self.resize_pending = false;
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(&gpu.device, &self.config);
+        if let Some(gamma) = &mut self.gamma {
+            gamma.resize(&gpu.device, [width, height]);
+        }
+        true
+    }
+
+    pub fn get_current_texture(&self) -> Result<wgpu::SurfaceTexture, wgpu::SurfaceError> {
+        self.surface.get_current_texture()
+    }
+
+    // Borderless fullscreen on the current monitor, or the browser's fullscreen on the web
+    pub fn toggle_fullscreen(&self) {
+        let fullscreen = match self.window.fullscreen() {
+            Some(_) => None,
+            None => Some(winit::window::Fullscreen::Borderless(None)),
+        };
+        self.window.set_fullscreen(fullscreen);
+    }
+}
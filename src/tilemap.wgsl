@@ -0,0 +1,103 @@
+struct Camera2DUniform {
+    view_proj: mat4x4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // (width, height, units per pixel, zoom)
+    viewport: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: Camera2DUniform;
+
+struct TilesetUniform {
+    // (tile width, tile height, margin, spacing) in pixels
+    grid: vec4<f32>,
+    // (image width, image height, columns, unused)
+    image: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> tileset: TilesetUniform;
+@group(1) @binding(1)
+var atlas: texture_2d<f32>;
+@group(1) @binding(2)
+var atlas_sampler: sampler;
+// The tile each tile is showing right now, which only differs for animated ones
+@group(1) @binding(3)
+var frames: texture_2d<u32>;
+// Laid out like the atlas, a flat 1x1 for tilesets without one
+@group(1) @binding(4)
+var normal_atlas: texture_2d<f32>;
+
+struct LayerUniform {
+    translation: vec2<f32>,
+    opacity: f32,
+};
+@group(2) @binding(0)
+var<uniform> layer: LayerUniform;
+
+struct VertexInput {
+    @location(0) position: vec2<f32>,
+    @location(1) corner: vec2<f32>,
+    // Index in the low bits, Tiled's flip bits on top
+    @location(2) tile: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // Pixels into the atlas
+    @location(0) texel: vec2<f32>,
+    // The tile's rectangle in the atlas, left top right bottom
+    @location(1) @interpolate(flat) rect: vec4<f32>,
+    // Horizontal, vertical and diagonal flips in bits 2, 1 and 0
+    @location(2) @interpolate(flat) flips: u32,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    let index = in.tile & 0x0fffffffu;
+    let tile = textureLoad(frames, vec2<i32>(i32(index % 256u), i32(index / 256u)), 0).r;
+    let columns = max(u32(tileset.image.z), 1u);
+    let cell = vec2<f32>(f32(tile % columns), f32(tile / columns));
+    let size = tileset.grid.xy;
+    let origin = tileset.grid.z + cell * (size + tileset.grid.w);
+
+    var out: VertexOutput;
+    out.texel = origin + in.corner * size;
+    out.rect = vec4<f32>(origin, origin + size);
+    out.flips = in.tile >> 29u;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position + layer.translation, 0.0, 1.0);
+    return out;
+}
+
+// Kept half a pixel inside the tile so filtering never picks up its neighbours in the atlas
+fn atlas_uv(in: VertexOutput) -> vec2<f32> {
+    let texel = clamp(in.texel, in.rect.xy + 0.5, in.rect.zw - 0.5);
+    return texel / tileset.image.xy;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(atlas, atlas_sampler, atlas_uv(in));
+    return vec4<f32>(color.rgb, color.a * layer.opacity);
+}
+
+// Normals in the world, packed into 0 to 1. Mostly see through pixels are left for what's behind
+@fragment
+fn fs_normal(in: VertexOutput) -> @location(0) vec4<f32> {
+    let uv = atlas_uv(in);
+    let alpha = textureSample(atlas, atlas_sampler, uv).a * layer.opacity;
+    var normal = textureSample(normal_atlas, atlas_sampler, uv).xyz * 2.0 - 1.0;
+    if alpha < 0.5 {
+        discard;
+    }
+    // Flipping a tile flips its normals. They have +y up while the atlas has it down, so the
+    // diagonal flip swaps and negates
+    if (in.flips & 1u) != 0u {
+        normal = vec3<f32>(-normal.y, -normal.x, normal.z);
+    }
+    if (in.flips & 4u) != 0u {
+        normal.x = -normal.x;
+    }
+    if (in.flips & 2u) != 0u {
+        normal.y = -normal.y;
+    }
+    return vec4<f32>(normalize(normal) * 0.5 + 0.5, 1.0);
+}
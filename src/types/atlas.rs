@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use crate::types::texture::Texture;
+
+#[derive(Debug)]
+pub enum AtlasError {
+    // Bigger than the whole atlas, even on its own
+    TooLarge { name: String, size: [u32; 2] },
+    // There's no room left for it
+    Full(String),
+    DuplicateName(String),
+}
+
+impl std::fmt::Display for AtlasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AtlasError::TooLarge { name, size: [width, height] } => write!(f, "{name} is {width}x{height}, bigger than the atlas"),
+            AtlasError::Full(name) => write!(f, "no room left in the atlas for {name}"),
+            AtlasError::DuplicateName(name) => write!(f, "the atlas already has an image called {name}"),
+        }
+    }
+}
+
+impl std::error::Error for AtlasError {}
+
+// Where one image ended up in the atlas
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AtlasRegion {
+    // Left, top, right and bottom in pixels, like Tileset::tile_rect
+    pub rect: [u32; 4],
+    // The same edges as texture coordinates
+    pub uv: [f32; 4],
+}
+
+impl AtlasRegion {
+    // Texture coordinates from 0 to 1 across the image on its own, moved to where it is in the atlas
+    pub fn map_uv(&self, uv: [f32; 2]) -> [f32; 2] {
+        let [left, top, right, bottom] = self.uv;
+        [left + (right - left) * uv[0], top + (bottom - top) * uv[1]]
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.rect[2] - self.rect[0], self.rect[3] - self.rect[1]]
+    }
+}
+
+// A row of images as tall as the tallest one in it
+struct Shelf {
+    top: u32,
+    height: u32,
+    // Where the next image goes
+    right: u32,
+}
+
+// Packs many images into one texture, so meshes and sprites using different ones can share a
+// material and be drawn without switching bind groups. Images are packed in rows, tallest first
+// works best. Each is surrounded by padding copied from its own edge pixels, so filtering never
+// pulls in its neighbours
+pub struct TextureAtlas {
+    image: image::RgbaImage,
+    padding: u32,
+    shelves: Vec<Shelf>,
+    regions: HashMap<String, AtlasRegion>,
+}
+
+impl TextureAtlas {
+    pub fn new(size: [u32; 2], padding: u32) -> Self {
+        Self { image: image::RgbaImage::new(size[0].max(1), size[1].max(1)), padding, shelves: Vec::new(), regions: HashMap::new() }
+    }
+
+    // Packs every image at once, sorted tallest first so less space is wasted
+    pub fn pack<'a>(size: [u32; 2], padding: u32, images: impl IntoIterator<Item = (&'a str, &'a image::RgbaImage)>) -> Result<Self, AtlasError> {
+        let mut images = images.into_iter().collect::<Vec<_>>();
+        images.sort_by_key(|(_, image)| std::cmp::Reverse(image.height()));
+        let mut atlas = Self::new(size, padding);
+        for (name, image) in images {
+            atlas.add(name, image)?;
+        }
+        Ok(atlas)
+    }
+
+    pub fn add(&mut self, name: &str, image: &image::RgbaImage) -> Result<AtlasRegion, AtlasError> {
+        if self.regions.contains_key(name) {
+            return Err(AtlasError::DuplicateName(name.to_string()));
+        }
+        let (width, height) = image.dimensions();
+        let padded = [width + self.padding * 2, height + self.padding * 2];
+        let [atlas_width, atlas_height] = self.size();
+        if padded[0] > atlas_width || padded[1] > atlas_height {
+            return Err(AtlasError::TooLarge { name: name.to_string(), size: [width, height] });
+        }
+        let [x, y] = self.allocate(padded).ok_or_else(|| AtlasError::Full(name.to_string()))?;
+        // The padding repeats the nearest edge pixel
+        if width > 0 && height > 0 {
+            for py in 0..padded[1] {
+                for px in 0..padded[0] {
+                    let sx = px.saturating_sub(self.padding).min(width - 1);
+                    let sy = py.saturating_sub(self.padding).min(height - 1);
+                    self.image.put_pixel(x + px, y + py, *image.get_pixel(sx, sy));
+                }
+            }
+        }
+        let rect = [x + self.padding, y + self.padding, x + self.padding + width, y + self.padding + height];
+        let uv = [
+            rect[0] as f32 / atlas_width as f32,
+            rect[1] as f32 / atlas_height as f32,
+            rect[2] as f32 / atlas_width as f32,
+            rect[3] as f32 / atlas_height as f32,
+        ];
+        let region = AtlasRegion { rect, uv };
+        self.regions.insert(name.to_string(), region);
+        Ok(region)
+    }
+
+    // Top left corner of a free space this big, starting a new shelf if none of them fit it
+    fn allocate(&mut self, [width, height]: [u32; 2]) -> Option<[u32; 2]> {
+        let [atlas_width, atlas_height] = self.size();
+        // The shortest shelf it fits on wastes the least
+        let shelf = self.shelves.iter_mut()
+            .filter(|shelf| shelf.height >= height && atlas_width - shelf.right >= width)
+            .min_by_key(|shelf| shelf.height);
+        if let Some(shelf) = shelf {
+            let position = [shelf.right, shelf.top];
+            shelf.right += width;
+            return Some(position);
+        }
+        let top = self.shelves.last().map_or(0, |shelf| shelf.top + shelf.height);
+        if atlas_height - top < height {
+            return None;
+        }
+        self.shelves.push(Shelf { top, height, right: width });
+        Some([0, top])
+    }
+
+    pub fn region(&self, name: &str) -> Option<AtlasRegion> {
+        self.regions.get(name).copied()
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = (&str, AtlasRegion)> {
+        self.regions.iter().map(|(name, region)| (name.as_str(), *region))
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.image.width(), self.image.height()]
+    }
+
+    pub fn image(&self) -> &image::RgbaImage {
+        &self.image
+    }
+
+    // An sRGB texture of everything packed so far, for SceneRenderer::add_texture. Images added
+    // after this aren't in it
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, label: &str) -> Texture {
+        Texture::from_image(device, queue, &self.image, label)
+    }
+}
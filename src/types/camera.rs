@@ -1,48 +1,712 @@
 use winit::{
     event::{
         WindowEvent,
+        DeviceEvent,
         KeyEvent,
-        ElementState
+        ElementState,
+        MouseScrollDelta,
     },
-    keyboard::{
-        KeyCode,
-        PhysicalKey
-    }
+    keyboard::PhysicalKey,
+    window::{CursorGrabMode, Window},
+};
+use crate::types::{
+    frame::CoordinateFrame,
+    frustum::{Frustum, Plane},
+    gamepad::{GamepadInput, GamepadSettings},
+    keymap::{CameraAction, KeyMap},
+    ray::Ray,
 };
-use cgmath::{Vector3, InnerSpace};
-use std::f32::consts::PI;
+use serde::{Deserialize, Serialize};
+use cgmath::{Matrix4, SquareMatrix, Vector1, Vector3, Vector4, InnerSpace, Quaternion, Rotation, Rotation3, Rad, EuclideanSpace};
+
+// Maps OpenGL's -1 to 1 depth range onto wgpu's 0 to 1. cgmath takes the columns in order, so
+// the bottom row is really the translation column
+pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
 
-pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+// Flips wgpu's depth range so the near plane is at 1 and the far plane at 0. Its own inverse
+pub const REVERSE_Z_MATRIX: Matrix4<f32> = Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, -1.0, 0.0,
+    0.0, 0.0, 1.0, 1.0,
 );
 
+// How distances are mapped into the depth buffer
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+pub enum DepthMode {
+    // Ordinary perspective depth, most of the precision is spent right in front of the near plane
+    #[default]
+    Standard,
+    // Depth is log2(1 + c * w) / log2(1 + c * zfar), written in the vertex shader. Spreads precision
+    // evenly enough for planet or space scale scenes. Smaller c gives more precision up close
+    Logarithmic { c: f32 },
+    // 1 at the near plane down to 0 at the far one. Floating point has the most precision near 0,
+    // which cancels out perspective bunching it all up near the camera. Drawn with a Greater depth
+    // test and cleared to 0. infinite drops the far plane altogether, zfar is ignored
+    Reversed { infinite: bool },
+}
+
+impl DepthMode {
+    pub fn is_reversed(&self) -> bool {
+        matches!(self, Self::Reversed { .. })
+    }
+
+    // What the depth buffer is cleared to, the furthest value there is
+    pub fn clear_depth(&self) -> f32 {
+        if self.is_reversed() { 0.0 } else { 1.0 }
+    }
+}
+
+// Which end of the view stays put when the camera turns
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CameraMode {
+    // The eye stays where it is and the target swings around in front of it
+    FreeFly,
+    // The target stays where it is and the eye circles around it
+    #[default]
+    Orbital,
+}
+
+// Why a camera setting was refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraError {
+    // znear has to be above 0 and below zfar
+    InvalidClipPlanes { znear: f32, zfar: f32 },
+    // Degrees, has to be between 0 and 180
+    InvalidFov(f32),
+    InvalidAspect(f32),
+    // c has to be above 0
+    InvalidDepthMode(DepthMode),
+}
+
+impl std::fmt::Display for CameraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CameraError::InvalidClipPlanes { znear, zfar } => write!(f, "clip planes {znear} to {zfar} need 0 < znear < zfar"),
+            CameraError::InvalidFov(fovy) => write!(f, "field of view {fovy} isn't between 0 and 180 degrees"),
+            CameraError::InvalidAspect(aspect) => write!(f, "aspect ratio {aspect} isn't positive"),
+            CameraError::InvalidDepthMode(mode) => write!(f, "{mode:?} needs a positive c"),
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+// The projection settings are behind setters so they can't be given values that make the
+// projection matrix NaN. The rest can be changed directly, followed by mark_dirty
 pub struct Camera {
     pub eye: cgmath::Point3<f32>,
     pub target: cgmath::Point3<f32>,
     pub up: cgmath::Vector3<f32>,
-    pub aspect: f32,
-    pub fovy: f32,
-    pub znear: f32,
-    pub zfar: f32,
+    aspect: f32,
+    // Degrees
+    fovy: f32,
+    znear: f32,
+    zfar: f32,
+    depth_mode: DepthMode,
+
+    // Rotation from the camera's local space, where it looks down -z with +y up, into the world.
+    // up and one of eye or target, depending on mode, are derived from this by apply_orientation
+    pub orientation: Quaternion<f32>,
+
+    // Whether turning moves the target around the eye or the eye around the target. Controllers
+    // set this to what they need when they're attached
+    pub mode: CameraMode,
+
+    // World position that eye and target are relative to. Moving it closer to the eye keeps
+    // everything near the camera in f32 range, see recenter
+    pub origin: Vector3<f64>,
+
+    // The axis conventions eye, target and up are given in. orientation is always in the
+    // render frame, forward, right and up_direction convert it
+    pub frame: CoordinateFrame,
+
+    // Shakes that haven't died out yet, see add_shake
+    shakes: Vec<Shake>,
+    // Picks a different noise curve for each shake
+    next_shake_seed: u32,
 
-    // If you're wondering, we're not using a Quaternion because that adds an extra level of complication
-    // when we don't need to worry about gimbal lock - all rotations will be manual, so it won't affect any calculations
-    pub rotation: Vector3<f32>
+    // Fit znear and zfar to the geometry in view every frame instead of keeping them fixed
+    auto_clip_planes: Option<AutoClipPlanes>,
+
+    // Something changed since the uniform was last written
+    dirty: bool,
+}
+
+// How fit_clip_planes pulls the clip planes in around what's in view
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoClipPlanes {
+    // znear never goes below this, however close geometry gets. Most of the depth buffer's
+    // precision goes on the range just past znear, so it's best kept as high as the scene allows
+    pub min_near: f32,
+    // Fraction of the distance left spare in front of and behind the geometry, so it isn't clipped
+    // before the planes catch up with it
+    pub margin: f32,
+}
+
+impl Default for AutoClipPlanes {
+    fn default() -> Self {
+        Self { min_near: 0.01, margin: 0.05 }
+    }
+}
+
+// One add_shake, wobbling the view until it runs out
+#[derive(Clone, Copy, Debug)]
+struct Shake {
+    // Degrees
+    amplitude: f32,
+    frequency: f32,
+    duration: f32,
+    elapsed: f32,
+    seed: u32,
+}
+
+impl Shake {
+    // (yaw, pitch, roll) in radians right now. Fades out quadratically, so it tails off rather than
+    // stopping dead
+    fn angles(&self) -> Vector3<f32> {
+        let fade = (1.0 - self.elapsed / self.duration).max(0.0);
+        let amplitude = self.amplitude.to_radians() * fade * fade;
+        let t = self.elapsed * self.frequency;
+        Vector3::new(
+            smooth_noise(t, self.seed),
+            smooth_noise(t, self.seed.wrapping_add(1)),
+            // Rolling as much as turning looks like the whole world tipping over
+            0.5 * smooth_noise(t, self.seed.wrapping_add(2)),
+        ) * amplitude
+    }
+}
+
+// Value noise between -1 and 1, passing through a new random value at every whole t. seed picks
+// an unrelated curve
+fn smooth_noise(t: f32, seed: u32) -> f32 {
+    let hash = |n: i32| {
+        let mut x = (n as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x9e37_79b9);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x85eb_ca6b);
+        x ^= x >> 13;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+    let i = t.floor();
+    let f = t - i;
+    let (a, b) = (hash(i as i32), hash(i as i32 + 1));
+    a + (b - a) * f * f * (3.0 - 2.0 * f)
 }
 
 impl Camera {
-    pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        // 1.
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        // 2.
-        let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+    // Looking down -z with a 45 degree field of view and clip planes at 0.1 and 100
+    pub fn new(eye: cgmath::Point3<f32>, target: cgmath::Point3<f32>, up: Vector3<f32>, aspect: f32) -> Self {
+        Self {
+            eye,
+            target,
+            up,
+            aspect: if aspect > 0.0 && aspect.is_finite() { aspect } else { 1.0 },
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+            depth_mode: DepthMode::Standard,
+            orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            mode: CameraMode::default(),
+            origin: Vector3::new(0.0, 0.0, 0.0),
+            frame: CoordinateFrame::default(),
+            shakes: Vec::new(),
+            next_shake_seed: 0,
+            auto_clip_planes: None,
+            dirty: true,
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    pub fn fovy(&self) -> f32 {
+        self.fovy
+    }
+
+    pub fn znear(&self) -> f32 {
+        self.znear
+    }
+
+    pub fn zfar(&self) -> f32 {
+        self.zfar
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    pub fn set_aspect(&mut self, aspect: f32) -> Result<(), CameraError> {
+        if !(aspect > 0.0 && aspect.is_finite()) {
+            return Err(CameraError::InvalidAspect(aspect));
+        }
+        self.aspect = aspect;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn set_fovy(&mut self, fovy: f32) -> Result<(), CameraError> {
+        if !(fovy > 0.0 && fovy < 180.0) {
+            return Err(CameraError::InvalidFov(fovy));
+        }
+        self.fovy = fovy;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn set_clip_planes(&mut self, znear: f32, zfar: f32) -> Result<(), CameraError> {
+        if !(znear > 0.0 && znear < zfar && zfar.is_finite()) {
+            return Err(CameraError::InvalidClipPlanes { znear, zfar });
+        }
+        self.znear = znear;
+        self.zfar = zfar;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub fn set_znear(&mut self, znear: f32) -> Result<(), CameraError> {
+        self.set_clip_planes(znear, self.zfar)
+    }
+
+    pub fn set_zfar(&mut self, zfar: f32) -> Result<(), CameraError> {
+        self.set_clip_planes(self.znear, zfar)
+    }
+
+    // With Some, SceneRenderer::update sets znear and zfar from the meshes in view every frame,
+    // see fit_clip_planes. None leaves them where they were last fitted
+    pub fn set_auto_clip_planes(&mut self, auto: Option<AutoClipPlanes>) {
+        self.auto_clip_planes = auto;
+    }
+
+    pub fn auto_clip_planes(&self) -> Option<AutoClipPlanes> {
+        self.auto_clip_planes
+    }
+
+    // Pulls the clip planes in to just around the boxes the camera can see, given in the same
+    // space as eye. Only the sides of the view decide what's seen, since the old planes could
+    // be cutting off exactly what's being fitted to. Nothing in view keeps the old planes
+    pub fn fit_clip_planes(&mut self, boxes: impl IntoIterator<Item = ([f32; 3], [f32; 3])>) {
+        let Some(auto) = self.auto_clip_planes else { return };
+        let view = self.view_matrix();
+        let mut sides = Frustum::from_matrix(self.projection_matrix() * view);
+        for plane in &mut sides.planes[4..] {
+            *plane = Plane { normal: Vector3::new(0.0, 0.0, 0.0), distance: f32::INFINITY };
+        }
+        let mut range: Option<(f32, f32)> = None;
+        for (min, max) in boxes {
+            if !sides.intersects_aabb(min, max) {
+                continue;
+            }
+            // Distance in front of the camera of each corner
+            let depths = (0..8).map(|i| {
+                let corner = Vector4::new(
+                    if i & 1 == 0 { min[0] } else { max[0] },
+                    if i & 2 == 0 { min[1] } else { max[1] },
+                    if i & 4 == 0 { min[2] } else { max[2] },
+                    1.0,
+                );
+                -(view * corner).z
+            });
+            let (near, far) = depths.fold((f32::INFINITY, f32::NEG_INFINITY), |(near, far), d| (near.min(d), far.max(d)));
+            // Entirely behind the camera
+            if far <= 0.0 {
+                continue;
+            }
+            range = Some(range.map_or((near, far), |(n, f)| (n.min(near), f.max(far))));
+        }
+        let Some((near, far)) = range else { return };
+        let znear = (near * (1.0 - auto.margin)).max(auto.min_near);
+        let zfar = (far * (1.0 + auto.margin)).max(znear + auto.min_near);
+        if (znear, zfar) != (self.znear, self.zfar) {
+            let _ = self.set_clip_planes(znear, zfar);
+        }
+    }
+
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) -> Result<(), CameraError> {
+        if let DepthMode::Logarithmic { c } = depth_mode {
+            if !(c > 0.0 && c.is_finite()) {
+                return Err(CameraError::InvalidDepthMode(depth_mode));
+            }
+        }
+        self.depth_mode = depth_mode;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // Has to be called after changing any of the public fields directly
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Whether the camera changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.frame.vector_from_render(self.orientation.rotate_vector(-Vector3::unit_z()))
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.frame.vector_from_render(self.orientation.rotate_vector(Vector3::unit_x()))
+    }
+
+    pub fn up_direction(&self) -> Vector3<f32> {
+        self.frame.vector_from_render(self.orientation.rotate_vector(Vector3::unit_y()))
+    }
+
+    // Rotates the camera around its own axes: yaw around up, pitch around right and roll around forward.
+    // Being local, roll behaves the same no matter how far the camera is pitched
+    pub fn rotate_local(&mut self, yaw: f32, pitch: f32, roll: f32) {
+        self.orientation = (self.orientation
+            * Quaternion::from_angle_y(Rad(yaw))
+            * Quaternion::from_angle_x(Rad(pitch))
+            * Quaternion::from_angle_z(Rad(roll))).normalize();
+        self.dirty = true;
+    }
+
+    pub fn distance(&self) -> f32 {
+        (self.target - self.eye).magnitude()
+    }
+
+    // Puts the eye and target distance apart along forward and sets up, so the view matrix matches
+    // the orientation. Orbital moves the eye, FreeFly moves the target
+    pub fn set_distance(&mut self, distance: f32) {
+        match self.mode {
+            CameraMode::Orbital => self.eye = self.target - self.forward() * distance,
+            CameraMode::FreeFly => self.target = self.eye + self.forward() * distance,
+        }
+        self.up = self.up_direction();
+        self.dirty = true;
+    }
+
+    // Lines the view matrix up with the orientation, keeping the distance between eye and target
+    pub fn apply_orientation(&mut self) {
+        // With the eye on the target there's no direction to look in
+        let distance = self.distance();
+        self.set_distance(if distance > 0.0 { distance } else { 1.0 });
+    }
+
+    // Turns the orientation to face the target from the eye, levelled to the frame's up, and sets up
+    // to match. The opposite of apply_orientation
+    pub fn look_at_target(&mut self) {
+        let forward = self.frame.vector_to_render(self.target - self.eye);
+        let right = forward.cross(Vector3::unit_y());
+        // Looking straight up or down keeps the old orientation, there's no level to go by
+        if forward.magnitude2() > 0.0 && right.magnitude2() > 1e-12 {
+            let forward = forward.normalize();
+            let right = right.normalize();
+            let up = right.cross(forward);
+            self.orientation = Quaternion::from(cgmath::Matrix3::from_cols(right, up, -forward));
+        }
+        self.up = self.up_direction();
+        self.dirty = true;
+    }
+
+    // Everything about where the camera is and how it sees, apart from the aspect ratio which comes
+    // from whatever it's drawn into
+    pub fn pose(&self) -> CameraPose {
+        let q = self.orientation;
+        CameraPose {
+            eye: self.eye.into(),
+            target: self.target.into(),
+            up: self.up.into(),
+            orientation: [q.v.x, q.v.y, q.v.z, q.s],
+            origin: self.origin.into(),
+            mode: self.mode,
+            frame: self.frame,
+            fovy: self.fovy,
+            znear: self.znear,
+            zfar: self.zfar,
+            depth_mode: self.depth_mode,
+        }
+    }
+
+    // Moves the camera to a pose from pose. Leaves the camera as it was if any of the projection
+    // settings are invalid. Controllers keep their own copy of the angles, so reattach them after
+    pub fn set_pose(&mut self, pose: &CameraPose) -> Result<(), CameraError> {
+        let previous = (self.fovy, self.znear, self.zfar, self.depth_mode);
+        let result = self.set_fovy(pose.fovy)
+            .and_then(|_| self.set_clip_planes(pose.znear, pose.zfar))
+            .and_then(|_| self.set_depth_mode(pose.depth_mode));
+        if let Err(e) = result {
+            (self.fovy, self.znear, self.zfar, self.depth_mode) = previous;
+            return Err(e);
+        }
+        self.eye = pose.eye.into();
+        self.target = pose.target.into();
+        self.up = pose.up.into();
+        let [x, y, z, w] = pose.orientation;
+        self.orientation = Quaternion::new(w, x, y, z).normalize();
+        self.origin = pose.origin.into();
+        self.mode = pose.mode;
+        self.frame = pose.frame;
+        self.dirty = true;
+        Ok(())
+    }
+
+    // Bookmarks the view in a JSON file, to come back to with load
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(&self.pose())?;
+        std::fs::write(path, json)
+    }
 
-        // 3.
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+    // Restores a view written by save
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(&mut self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let pose: CameraPose = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        self.set_pose(&pose).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    // Wobbles the view for duration seconds, like an explosion or a heavy landing. amplitude is the
+    // most it turns in degrees and frequency how many times a second it changes direction. Shakes
+    // add up, and only change what's drawn, not eye, target or orientation
+    pub fn add_shake(&mut self, amplitude: f32, frequency: f32, duration: f32) {
+        if !(amplitude > 0.0 && frequency > 0.0 && duration > 0.0) {
+            return;
+        }
+        self.shakes.push(Shake { amplitude, frequency, duration, elapsed: 0.0, seed: self.next_shake_seed });
+        // Three curves per shake, one for each axis
+        self.next_shake_seed = self.next_shake_seed.wrapping_add(3);
+        self.dirty = true;
     }
+
+    pub fn is_shaking(&self) -> bool {
+        !self.shakes.is_empty()
+    }
+
+    pub fn stop_shake(&mut self) {
+        if self.is_shaking() {
+            self.shakes.clear();
+            self.dirty = true;
+        }
+    }
+
+    // Moves shakes along by dt seconds, dropping the ones that have finished
+    pub fn advance_shake(&mut self, dt: f32) {
+        if !self.is_shaking() {
+            return;
+        }
+        for shake in &mut self.shakes {
+            shake.elapsed += dt;
+        }
+        self.shakes.retain(|shake| shake.elapsed < shake.duration);
+        self.dirty = true;
+    }
+
+    // View space rotation the shakes add on top of the view
+    fn shake_rotation(&self) -> Matrix4<f32> {
+        let angles = self.shakes.iter().map(Shake::angles).sum::<Vector3<f32>>();
+        Matrix4::from(
+            Quaternion::from_angle_y(Rad(angles.x))
+                * Quaternion::from_angle_x(Rad(angles.y))
+                * Quaternion::from_angle_z(Rad(angles.z)),
+        )
+    }
+
+    // Eye position in world space, at full precision
+    pub fn world_eye(&self) -> cgmath::Point3<f64> {
+        cgmath::Point3::from_vec(self.origin + self.eye.to_vec().cast::<f64>().unwrap())
+    }
+
+    // Moves the origin to the eye, so the camera sits at (0, 0, 0) in f32 space
+    pub fn recenter(&mut self) {
+        let offset = self.eye.to_vec();
+        self.origin += offset.cast::<f64>().unwrap();
+        self.eye -= offset;
+        self.target -= offset;
+        self.dirty = true;
+    }
+
+    // World (in the camera's frame) to view space, which is right handed and looks down -z.
+    // Includes any shake
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let to_render = |p: cgmath::Point3<f32>| cgmath::Point3::from(self.frame.to_render(p.into()));
+        let view = Matrix4::look_at_rh(to_render(self.eye), to_render(self.target), self.frame.vector_to_render(self.up))
+            * self.frame.to_render_matrix();
+        if self.is_shaking() {
+            self.shake_rotation() * view
+        } else {
+            view
+        }
+    }
+
+    // View space to clip space, with wgpu's 0 to 1 depth range
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.depth_mode {
+            DepthMode::Reversed { infinite: true } => {
+                // Depth is znear / distance, which only reaches 0 infinitely far away
+                let f = 1.0 / (self.fovy.to_radians() / 2.0).tan();
+                Matrix4::new(
+                    f / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, f, 0.0, 0.0,
+                    0.0, 0.0, 0.0, -1.0,
+                    0.0, 0.0, self.znear, 0.0,
+                )
+            }
+            DepthMode::Reversed { infinite: false } => REVERSE_Z_MATRIX * self.standard_projection_matrix(),
+            _ => self.standard_projection_matrix(),
+        }
+    }
+
+    fn standard_projection_matrix(&self) -> Matrix4<f32> {
+        OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    pub fn matrices(&self) -> CameraMatrices {
+        CameraMatrices::new(self.view_matrix(), self.projection_matrix())
+    }
+
+    // Relative to the camera's origin, like the instances
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.build_view_projection_matrix())
+    }
+
+    // The depth the projection matrix would have written at the same distance, which is what the
+    // matrices work with. Only differs for logarithmic depth, which is written in the shader
+    pub fn standard_depth(&self, depth: f32) -> f32 {
+        match self.depth_mode {
+            DepthMode::Standard | DepthMode::Reversed { .. } => depth,
+            DepthMode::Logarithmic { .. } => {
+                let distance = self.linearize_depth(depth);
+                self.zfar / (self.zfar - self.znear) * (1.0 - self.znear / distance)
+            }
+        }
+    }
+
+    // Point in the world under a position on screen. screen goes from (0, 0) at the top left of the
+    // viewport to (1, 1) at the bottom right, and depth is the value the depth buffer holds there
+    pub fn unproject(&self, screen: [f32; 2], depth: f32) -> cgmath::Point3<f32> {
+        self.matrices().unproject(screen, self.standard_depth(depth))
+    }
+
+    // Ray from the near plane through a pixel, for picking and placing things with the mouse.
+    // Relative to the camera's origin like everything else
+    pub fn screen_ray(&self, cursor_pos: [f32; 2], viewport_size: [f32; 2]) -> Ray {
+        self.matrices().screen_ray([cursor_pos[0] / viewport_size[0], cursor_pos[1] / viewport_size[1]])
+    }
+
+    // Converts a value read back from the depth buffer into distance along the view direction
+    pub fn linearize_depth(&self, depth: f32) -> f32 {
+        match self.depth_mode {
+            DepthMode::Standard => self.zfar * self.znear / (self.zfar - depth * (self.zfar - self.znear)),
+            DepthMode::Logarithmic { c } => ((depth * (1.0 + c * self.zfar).log2()).exp2() - 1.0) / c,
+            DepthMode::Reversed { infinite: false } => self.zfar * self.znear / (self.znear + depth * (self.zfar - self.znear)),
+            DepthMode::Reversed { infinite: true } => self.znear / depth,
+        }
+    }
+
+    // The value the depth buffer will hold for something at the given view distance
+    pub fn depth_at(&self, distance: f32) -> f32 {
+        match self.depth_mode {
+            DepthMode::Standard => self.zfar / (self.zfar - self.znear) * (1.0 - self.znear / distance),
+            DepthMode::Logarithmic { c } => (1.0 + c * distance).log2() / (1.0 + c * self.zfar).log2(),
+            DepthMode::Reversed { infinite: false } => 1.0 - self.zfar / (self.zfar - self.znear) * (1.0 - self.znear / distance),
+            DepthMode::Reversed { infinite: true } => self.znear / distance,
+        }
+    }
+
+    // Packed for the shader: (mode, zfar, c, 1 / log2(1 + c * zfar)). mode is 0 standard, 1 logarithmic
+    // and 2 reversed, with c as 1 for an infinite far plane
+    fn depth_params(&self) -> [f32; 4] {
+        match self.depth_mode {
+            DepthMode::Standard => [0.0, self.zfar, 0.0, 0.0],
+            DepthMode::Logarithmic { c } => [1.0, self.zfar, c, 1.0 / (1.0 + c * self.zfar).log2()],
+            DepthMode::Reversed { infinite } => [2.0, self.zfar, infinite as u32 as f32, 0.0],
+        }
+    }
+}
+
+// The camera's matrices and their inverses, worth keeping around rather than inverting every
+// time something needs to go from screen space back into the world
+#[derive(Clone, Copy, Debug)]
+pub struct CameraMatrices {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+    pub view_projection: Matrix4<f32>,
+    pub inverse_view: Matrix4<f32>,
+    pub inverse_projection: Matrix4<f32>,
+    pub inverse_view_projection: Matrix4<f32>,
+}
+
+impl CameraMatrices {
+    pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>) -> Self {
+        let view_projection = projection * view;
+        Self {
+            view,
+            projection,
+            view_projection,
+            inverse_view: view.invert().unwrap_or_else(Matrix4::identity),
+            inverse_projection: projection.invert().unwrap_or_else(Matrix4::identity),
+            inverse_view_projection: view_projection.invert().unwrap_or_else(Matrix4::identity),
+        }
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(self.view_projection)
+    }
+
+    // Same as Camera::unproject, but depth has to be standard rather than logarithmic
+    pub fn unproject(&self, screen: [f32; 2], depth: f32) -> cgmath::Point3<f32> {
+        let ndc = Vector4::new(screen[0] * 2.0 - 1.0, 1.0 - screen[1] * 2.0, depth, 1.0);
+        let world = self.inverse_view_projection * ndc;
+        cgmath::Point3::from_homogeneous(world)
+    }
+
+    // Same as Camera::screen_ray, with screen going from (0, 0) at the top left to (1, 1)
+    pub fn screen_ray(&self, screen: [f32; 2]) -> Ray {
+        // Reversed depth has its near plane at 1, and with no far plane 0 is infinitely far away.
+        // Any further point is as good for the direction
+        let (near, far) = if self.is_reversed_z() { (1.0, 0.5) } else { (0.0, 1.0) };
+        let near = self.unproject(screen, near);
+        let far = self.unproject(screen, far);
+        Ray::new(near, far - near)
+    }
+
+    // Whether the projection is one of the DepthMode::Reversed ones. Those are the only ones where
+    // depth doesn't go down with distance
+    pub fn is_reversed_z(&self) -> bool {
+        self.projection.z.z >= 0.0
+    }
+
+    // Where a point in the world ends up on screen, in the same coordinates unproject takes,
+    // along with its standard depth. None if it's behind the camera
+    pub fn project(&self, point: cgmath::Point3<f32>) -> Option<([f32; 2], f32)> {
+        let clip = self.view_projection * point.to_homogeneous();
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let ndc = clip.truncate() / clip.w;
+        Some(([(ndc.x + 1.0) * 0.5, (1.0 - ndc.y) * 0.5], ndc.z))
+    }
+}
+
+// A saved viewpoint, see Camera::pose. Plain arrays so the file reads the same as the fields
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    // x, y, z, w
+    pub orientation: [f32; 4],
+    pub origin: [f64; 3],
+    pub mode: CameraMode,
+    pub frame: CoordinateFrame,
+    // Degrees
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+    pub depth_mode: DepthMode,
 }
 
 // We need this for Rust to store our data correctly for the shaders
@@ -53,23 +717,233 @@ pub struct CameraUniform {
     // We can't use cgmath with bytemuck directly, so we'll have
     // to convert the Matrix4 into a 4x4 f32 array
     pub view_proj: [[f32; 4]; 4],
+    pub depth_params: [f32; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub inv_view: [[f32; 4]; 4],
+    pub inv_proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    // Eye position relative to the camera's origin, w is 1
+    pub position: [f32; 4],
+    // (znear, zfar, aspect, tan(fovy / 2)), enough to rebuild view space positions from depth
+    pub projection_params: [f32; 4],
+    // (seconds the scene has advanced, seconds since the last frame), set by the scene rather
+    // than update
+    pub time: [f32; 4],
 }
 
 impl CameraUniform {
     pub fn new() -> Self {
-        use cgmath::SquareMatrix;
+        let identity = Matrix4::identity().into();
         Self {
-            view_proj: cgmath::Matrix4::identity().into(),
+            view_proj: identity,
+            depth_params: [0.0; 4],
+            view: identity,
+            proj: identity,
+            inv_view: identity,
+            inv_proj: identity,
+            inv_view_proj: identity,
+            position: [0.0, 0.0, 0.0, 1.0],
+            projection_params: [0.0; 4],
+            time: [0.0; 4],
         }
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        self.update(camera, &camera.matrices());
+    }
+
+    // Same as update_view_proj, reusing matrices that have already been worked out
+    pub fn update(&mut self, camera: &Camera, matrices: &CameraMatrices) {
+        self.view_proj = matrices.view_projection.into();
+        self.depth_params = camera.depth_params();
+        self.view = matrices.view.into();
+        self.proj = matrices.projection.into();
+        self.inv_view = matrices.inverse_view.into();
+        self.inv_proj = matrices.inverse_projection.into();
+        self.inv_view_proj = matrices.inverse_view_projection.into();
+        self.position = camera.eye.to_homogeneous().into();
+        self.projection_params = [camera.znear, camera.zfar, camera.aspect, (camera.fovy.to_radians() / 2.0).tan()];
+    }
+}
+
+impl Default for CameraUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Something that moves the camera in response to input. State holds one as a Box, so
+// applications can plug in their own control scheme
+pub trait CameraController {
+    // Returns true if the event was used
+    fn process_event(&mut self, event: &WindowEvent) -> bool;
+
+    // Raw device input, like mouse motion that isn't limited by the window
+    fn process_device_event(&mut self, _event: &DeviceEvent) -> bool {
+        false
+    }
+
+    // The gamepad's state as of this frame, given before update. Sticks add to the keys rather
+    // than replacing them
+    fn process_gamepad(&mut self, _input: &GamepadInput) {}
+
+    // dt is the time since the last update in seconds
+    fn update(&mut self, camera: &mut Camera, dt: f32);
+
+    // Called when the controller takes over the camera, so it can pick up where the last one left off
+    fn attach(&mut self, _camera: &Camera) {}
+
+    // Whether the cursor should be grabbed and hidden while this controller is in use
+    fn grabs_cursor(&self) -> bool {
+        false
+    }
+
+    // Where the thing being followed is this frame, for controllers that follow something
+    fn track(&mut self, _target: &FollowTarget) {}
+
+    // The mode the camera is switched to when this controller is attached, None leaves it alone
+    fn camera_mode(&self) -> Option<CameraMode> {
+        None
     }
-} 
+}
+
+// Something for the camera to follow, relative to the camera's origin like the camera itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FollowTarget {
+    pub position: cgmath::Point3<f32>,
+    pub rotation: Quaternion<f32>,
+}
 
-pub struct CameraController {
+// Acceleration and damping for controllers, so the camera eases in and out of motion instead of
+// jumping straight to full speed and stopping dead
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Inertia {
+    // How fast velocity builds up while a key is held, in speeds per second. The camera reaches full
+    // speed after about 1 / acceleration seconds
+    pub acceleration: f32,
+    // Velocity is multiplied by e^(-damping * dt) every update, so higher values stop sooner
+    pub damping: f32,
+}
+
+impl Inertia {
+    // Moves velocity towards input * max_speed. With no inertia it gets there straight away
+    pub fn step<V: InnerSpace<Scalar = f32>>(inertia: Option<Self>, velocity: V, input: V, max_speed: f32, dt: f32) -> V {
+        let Some(inertia) = inertia else { return input * max_speed };
+        let velocity = (velocity + input * (inertia.acceleration * max_speed * dt)) * (-inertia.damping * dt).exp();
+        let speed = velocity.magnitude();
+        if speed > max_speed {
+            velocity * (max_speed / speed)
+        } else if input.magnitude2() == 0.0 && speed < max_speed * 1e-3 {
+            // Settle instead of creeping along forever
+            V::zero()
+        } else {
+            velocity
+        }
+    }
+}
+
+impl Default for Inertia {
+    fn default() -> Self {
+        Self { acceleration: 8.0, damping: 6.0 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoomMode {
+    // Moves the eye towards or away from the target
+    Dolly,
+    // Narrows or widens the field of view, leaving the eye where it is
+    Fov,
+}
+
+// Scroll wheel zoom for controllers. Each notch scales the distance or field of view by the same
+// fraction, so it feels the same close up and far away
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WheelZoom {
+    pub mode: ZoomMode,
+    // Fraction zoomed per notch
+    pub sensitivity: f32,
+    // Distance to the target for Dolly, degrees of fovy for Fov
+    pub min: f32,
+    pub max: f32,
+}
+
+impl WheelZoom {
+    // Touchpads scroll in pixels, this many count as one notch
+    const PIXELS_PER_LINE: f32 = 40.0;
+
+    pub fn dolly(min_distance: f32, max_distance: f32) -> Self {
+        Self { mode: ZoomMode::Dolly, sensitivity: 0.1, min: min_distance, max: max_distance }
+    }
+
+    pub fn fov(min_fovy: f32, max_fovy: f32) -> Self {
+        Self { mode: ZoomMode::Fov, sensitivity: 0.05, min: min_fovy, max: max_fovy }
+    }
+
+    pub fn with_sensitivity(mut self, sensitivity: f32) -> Self {
+        self.sensitivity = sensitivity;
+        self
+    }
+
+    // Notches scrolled, positive away from the user which zooms in
+    pub fn scroll_lines(delta: &MouseScrollDelta) -> f32 {
+        match delta {
+            MouseScrollDelta::LineDelta(_, y) => *y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / Self::PIXELS_PER_LINE,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera, lines: f32) {
+        if lines == 0.0 {
+            return;
+        }
+        let scale = (1.0 - self.sensitivity.clamp(0.0, 0.99)).powf(lines);
+        match self.mode {
+            ZoomMode::Dolly => {
+                let offset = camera.eye - camera.target;
+                let distance = offset.magnitude();
+                if distance <= 0.0 {
+                    return;
+                }
+                let new_distance = (distance * scale).clamp(self.min, self.max);
+                match camera.mode {
+                    CameraMode::Orbital => camera.eye = camera.target + offset * (new_distance / distance),
+                    // The target is carried along, so each notch is a step forward of the same size
+                    CameraMode::FreeFly => {
+                        let step = offset * ((new_distance - distance) / distance);
+                        camera.eye += step;
+                        camera.target += step;
+                    }
+                }
+                camera.mark_dirty();
+            }
+            ZoomMode::Fov => {
+                // Limits outside what set_fovy takes just leave the fov alone
+                let _ = camera.set_fovy((camera.fovy() * scale).clamp(self.min, self.max));
+            }
+        }
+    }
+}
+
+// Orbits the target, W and S zoom in and out
+pub struct OrbitCameraController {
+    // Radians or units per second
     pub speed: f32,
+    // None moves at full speed only while keys are held
+    pub inertia: Option<Inertia>,
+    // (yaw, pitch, roll) in radians per second
+    angular_velocity: Vector3<f32>,
+    // Units per second towards the target
+    zoom_velocity: Vector1<f32>,
+    pub keys: KeyMap,
+    // None ignores the scroll wheel
+    pub wheel_zoom: Option<WheelZoom>,
+    // Notches scrolled since the last update
+    scroll: f32,
+    // The left stick orbits, the triggers zoom and the bumpers roll
+    pub gamepad: GamepadSettings,
+    gamepad_input: GamepadInput,
     pub is_forward_pressed: bool,
     pub is_backward_pressed: bool,
     pub is_left_pressed: bool,
@@ -81,10 +955,18 @@ pub struct CameraController {
     pub is_debug_pressed: bool,
 }
 
-impl CameraController {
+impl OrbitCameraController {
     pub fn new(speed: f32) -> Self {
         Self {
             speed,
+            inertia: None,
+            angular_velocity: Vector3::new(0.0, 0.0, 0.0),
+            zoom_velocity: Vector1::new(0.0),
+            keys: KeyMap::orbit(),
+            wheel_zoom: Some(WheelZoom::dolly(0.5, 50.0)),
+            scroll: 0.0,
+            gamepad: GamepadSettings::default(),
+            gamepad_input: GamepadInput::default(),
             is_forward_pressed: false,
             is_backward_pressed: false,
             is_left_pressed: false,
@@ -97,166 +979,432 @@ impl CameraController {
         }
     }
 
-    pub fn process_events(&mut self, event: &WindowEvent) -> bool {
+    pub fn with_inertia(mut self, inertia: Inertia) -> Self {
+        self.inertia = Some(inertia);
+        self
+    }
+
+    pub fn with_key_map(mut self, keys: KeyMap) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_wheel_zoom(mut self, wheel_zoom: Option<WheelZoom>) -> Self {
+        self.wheel_zoom = wheel_zoom;
+        self
+    }
+
+    pub fn with_gamepad(mut self, gamepad: GamepadSettings) -> Self {
+        self.gamepad = gamepad;
+        self
+    }
+}
+
+impl CameraController for OrbitCameraController {
+    fn process_gamepad(&mut self, input: &GamepadInput) {
+        self.gamepad_input = *input;
+    }
+
+    fn camera_mode(&self) -> Option<CameraMode> {
+        Some(CameraMode::Orbital)
+    }
+
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            self.scroll += WheelZoom::scroll_lines(delta);
+            return self.wheel_zoom.is_some();
+        }
+        let Some((actions, is_pressed)) = bound_actions(&self.keys, event) else { return false };
+        for action in actions {
+            let pressed = match action {
+                CameraAction::Forward => &mut self.is_forward_pressed,
+                CameraAction::Backward => &mut self.is_backward_pressed,
+                CameraAction::Left => &mut self.is_left_pressed,
+                CameraAction::Right => &mut self.is_right_pressed,
+                CameraAction::Up => &mut self.is_up_pressed,
+                CameraAction::Down => &mut self.is_down_pressed,
+                CameraAction::RollClockwise => &mut self.is_zcw_pressed,
+                CameraAction::RollCounterClockwise => &mut self.is_zccw_pressed,
+                CameraAction::Debug => &mut self.is_debug_pressed,
+            };
+            *pressed = is_pressed;
+        }
+        true
+    }
+
+    fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let scroll = std::mem::take(&mut self.scroll);
+        if let Some(wheel_zoom) = self.wheel_zoom {
+            wheel_zoom.apply(camera, scroll);
+        }
+
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let pad = &self.gamepad_input;
+        let stick = self.gamepad.stick_dead_zone.apply(pad.left_stick);
+        let triggers = self.gamepad.trigger_dead_zone.apply_axis(pad.right_trigger)
+            - self.gamepad.trigger_dead_zone.apply_axis(pad.left_trigger);
+        let zoom = Vector1::new((axis(self.is_forward_pressed, self.is_backward_pressed) + triggers).clamp(-1.0, 1.0));
+        let rotation = Vector3::new(
+            (axis(self.is_right_pressed, self.is_left_pressed) + stick.x).clamp(-1.0, 1.0),
+            (axis(self.is_down_pressed, self.is_up_pressed) - stick.y).clamp(-1.0, 1.0),
+            axis(self.is_zcw_pressed || pad.right_shoulder, self.is_zccw_pressed || pad.left_shoulder),
+        );
+        self.zoom_velocity = Inertia::step(self.inertia, self.zoom_velocity, zoom, self.speed, dt);
+        self.angular_velocity = if self.inertia.is_some() {
+            Inertia::step(self.inertia, self.angular_velocity, rotation, self.speed, dt)
+        } else {
+            // Each axis at full speed, like before inertia
+            rotation * self.speed
+        };
+
+        let step = self.zoom_velocity.x * dt;
+        let mut distance = camera.distance();
+        // Prevents glitching when the camera gets too close to the
+        // center of the scene.
+        if step < 0.0 || distance > step {
+            distance -= step;
+        }
+
+        let rotation = self.angular_velocity * dt;
+        if rotation != Vector3::new(0.0, 0.0, 0.0) || step != 0.0 {
+            camera.rotate_local(rotation.x, rotation.y, rotation.z);
+            camera.set_distance(distance);
+        }
+
+        if self.is_debug_pressed {
+            println!(
+                "UP: {:#?} \nFORWARD: {:#?} \nRIGHT: {:#?} \nORIENTATION: {:#?} \nEYE: {:#?} \nTARGET: {:#?}",
+                camera.up, camera.forward(), camera.right(), camera.orientation, camera.eye, camera.target
+            );
+        }
+    }
+}
+
+// First person controls: the mouse turns the camera, WASD moves it along the ground relative to
+// where it's facing and Space/Shift move it straight up and down
+pub struct FpsCameraController {
+    // Units per second
+    pub speed: f32,
+    // Radians per pixel of mouse movement
+    pub sensitivity: f32,
+    // None moves at full speed only while keys are held
+    pub inertia: Option<Inertia>,
+    // How quickly the view catches up with the mouse, per second. None turns instantly
+    pub look_damping: Option<f32>,
+    velocity: Vector3<f32>,
+    // Where the mouse has asked the view to turn to, yaw and pitch trail behind it with look_damping
+    target_yaw: f32,
+    target_pitch: f32,
+    pub keys: KeyMap,
+    // None ignores the scroll wheel. Dolly steps the camera forwards
+    pub wheel_zoom: Option<WheelZoom>,
+    // Notches scrolled since the last update
+    scroll: f32,
+    // The left stick moves, the right one looks and the triggers go down and up
+    pub gamepad: GamepadSettings,
+    gamepad_input: GamepadInput,
+    pub is_forward_pressed: bool,
+    pub is_backward_pressed: bool,
+    pub is_left_pressed: bool,
+    pub is_right_pressed: bool,
+    pub is_up_pressed: bool,
+    pub is_down_pressed: bool,
+    yaw: f32,
+    pitch: f32,
+    // Mouse movement since the last update
+    mouse_delta: (f64, f64),
+    // yaw and pitch haven't been applied to the camera yet
+    dirty: bool,
+}
+
+impl FpsCameraController {
+    // Stops just short of straight up or down, where yaw stops meaning anything
+    const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+    pub fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            speed,
+            sensitivity,
+            inertia: None,
+            look_damping: None,
+            velocity: Vector3::new(0.0, 0.0, 0.0),
+            target_yaw: 0.0,
+            target_pitch: 0.0,
+            keys: KeyMap::fps(),
+            wheel_zoom: Some(WheelZoom::fov(20.0, 90.0)),
+            scroll: 0.0,
+            gamepad: GamepadSettings::default(),
+            gamepad_input: GamepadInput::default(),
+            is_forward_pressed: false,
+            is_backward_pressed: false,
+            is_left_pressed: false,
+            is_right_pressed: false,
+            is_up_pressed: false,
+            is_down_pressed: false,
+            yaw: 0.0,
+            pitch: 0.0,
+            mouse_delta: (0.0, 0.0),
+            dirty: true,
+        }
+    }
+
+    pub fn with_inertia(mut self, inertia: Inertia) -> Self {
+        self.inertia = Some(inertia);
+        self
+    }
+
+    pub fn with_look_damping(mut self, damping: f32) -> Self {
+        self.look_damping = Some(damping);
+        self
+    }
+
+    pub fn with_key_map(mut self, keys: KeyMap) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_wheel_zoom(mut self, wheel_zoom: Option<WheelZoom>) -> Self {
+        self.wheel_zoom = wheel_zoom;
+        self
+    }
+
+    pub fn with_gamepad(mut self, gamepad: GamepadSettings) -> Self {
+        self.gamepad = gamepad;
+        self
+    }
+}
+
+impl CameraController for FpsCameraController {
+    fn process_gamepad(&mut self, input: &GamepadInput) {
+        self.gamepad_input = *input;
+    }
+
+    // Picks up the direction the camera is currently facing, so switching to this controller
+    // doesn't snap the view. Any roll is dropped
+    fn attach(&mut self, camera: &Camera) {
+        let forward = camera.frame.vector_to_render(camera.forward());
+        self.yaw = (-forward.x).atan2(-forward.z);
+        self.pitch = forward.y.clamp(-1.0, 1.0).asin().clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        self.target_yaw = self.yaw;
+        self.target_pitch = self.pitch;
+        self.velocity = Vector3::new(0.0, 0.0, 0.0);
+        self.mouse_delta = (0.0, 0.0);
+        self.dirty = true;
+    }
+
+    fn grabs_cursor(&self) -> bool {
+        true
+    }
+
+    fn camera_mode(&self) -> Option<CameraMode> {
+        Some(CameraMode::FreeFly)
+    }
+
+    fn process_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::MouseWheel { delta, .. } = event {
+            self.scroll += WheelZoom::scroll_lines(delta);
+            return self.wheel_zoom.is_some();
+        }
+        let Some((actions, is_pressed)) = bound_actions(&self.keys, event) else { return false };
+        let mut handled = false;
+        for action in actions {
+            let pressed = match action {
+                CameraAction::Forward => &mut self.is_forward_pressed,
+                CameraAction::Backward => &mut self.is_backward_pressed,
+                CameraAction::Left => &mut self.is_left_pressed,
+                CameraAction::Right => &mut self.is_right_pressed,
+                CameraAction::Up => &mut self.is_up_pressed,
+                CameraAction::Down => &mut self.is_down_pressed,
+                // Nothing to roll or print
+                _ => continue,
+            };
+            *pressed = is_pressed;
+            handled = true;
+        }
+        handled
+    }
+
+    // Raw mouse motion rather than CursorMoved, so turning isn't stopped by the edge of the window
+    fn process_device_event(&mut self, event: &DeviceEvent) -> bool {
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    KeyEvent {
-                        state,
-                        physical_key: PhysicalKey::Code(keycode),
-                        ..
-                    },
-                ..
-            } => {
-                let is_pressed = *state == ElementState::Pressed;
-                match keycode {KeyCode::KeyW | KeyCode::ArrowUp => {
-                        self.is_forward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyA | KeyCode::ArrowLeft => {
-                        self.is_left_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyS | KeyCode::ArrowDown => {
-                        self.is_backward_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyD | KeyCode::ArrowRight => {
-                        self.is_right_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyE => {
-                        self.is_up_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyQ => {
-                        self.is_down_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyC => {
-                        self.is_zcw_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::KeyZ => {
-                        self.is_zccw_pressed = is_pressed;
-                        true
-                    }
-                    KeyCode::Backquote => {
-                        self.is_debug_pressed = is_pressed;
-                        true
-                    }
-                    _ => false,
-                }
+            DeviceEvent::MouseMotion { delta } => {
+                self.mouse_delta.0 += delta.0;
+                self.mouse_delta.1 += delta.1;
+                true
             }
             _ => false,
         }
     }
 
-    pub fn update_camera(&self, camera: &mut Camera) {
-        use cgmath::InnerSpace;
-        let forward = camera.target - camera.eye;
-        let forward_norm = forward.normalize();
-        let forward_mag = forward.magnitude();
+    fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let (dx, dy) = std::mem::take(&mut self.mouse_delta);
+        // The stick turns at a rate rather than by a distance like the mouse
+        let look = self.gamepad.stick_dead_zone.apply(self.gamepad_input.right_stick) * self.gamepad.look_speed * dt;
+        let look_y = if self.gamepad.invert_y { -look.y } else { look.y };
+        self.target_yaw -= dx as f32 * self.sensitivity + look.x;
+        self.target_pitch = (self.target_pitch - dy as f32 * self.sensitivity + look_y).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        let (yaw, pitch) = (self.yaw, self.pitch);
+        match self.look_damping {
+            Some(damping) => {
+                let t = 1.0 - (-damping * dt).exp();
+                self.yaw += (self.target_yaw - self.yaw) * t;
+                self.pitch += (self.target_pitch - self.pitch) * t;
+                // Snap once it's close enough that nobody could tell
+                if (self.target_yaw - self.yaw).abs() < 1e-5 && (self.target_pitch - self.pitch).abs() < 1e-5 {
+                    self.yaw = self.target_yaw;
+                    self.pitch = self.target_pitch;
+                }
+            }
+            None => {
+                self.yaw = self.target_yaw;
+                self.pitch = self.target_pitch;
+            }
+        }
+        let turned = yaw != self.yaw || pitch != self.pitch;
+        let scroll = std::mem::take(&mut self.scroll);
+        if let Some(wheel_zoom) = self.wheel_zoom.filter(|_| scroll != 0.0) {
+            wheel_zoom.apply(camera, scroll);
+            self.dirty = true;
+        }
+        camera.orientation = Quaternion::from_angle_y(Rad(self.yaw)) * Quaternion::from_angle_x(Rad(self.pitch));
 
-        // Prevents glitching when the camera gets too close to the
-        // center of the scene.
-        if self.is_forward_pressed && forward_mag > self.speed {
-            camera.eye += forward_norm * self.speed;
+        // Moving is kept level no matter how far up or down the camera is looking
+        let forward = camera.frame.vector_from_render(Vector3::new(-self.yaw.sin(), 0.0, -self.yaw.cos()));
+        let right = camera.frame.vector_from_render(Vector3::new(self.yaw.cos(), 0.0, -self.yaw.sin()));
+        let mut movement = Vector3::new(0.0, 0.0, 0.0);
+        if self.is_forward_pressed {
+            movement += forward;
         }
         if self.is_backward_pressed {
-            camera.eye -= forward_norm * self.speed;
+            movement -= forward;
         }
-
         if self.is_right_pressed {
-            camera.rotation.y += self.speed;
+            movement += right;
         }
         if self.is_left_pressed {
-            camera.rotation.y -= self.speed;
+            movement -= right;
         }
-        if self.is_down_pressed {
-            camera.rotation.x += self.speed;
-        }     
         if self.is_up_pressed {
-            camera.rotation.x -= self.speed;
-        }
-        if self.is_zcw_pressed {
-            camera.rotation.z += self.speed;
-        }     
-        if self.is_zccw_pressed {
-            camera.rotation.z -= self.speed;
-        }
-
-        // Recalculate the forward vector based on its new direction and magnitude
-        let forward = Vector3::new(
-            forward_mag * camera.rotation.x.cos() * camera.rotation.y.cos(),
-            forward_mag * camera.rotation.x.sin(),
-            forward_mag * camera.rotation.x.cos() * camera.rotation.y.sin(),
-        );        
-        // Reposition eye so that forward points at the target again
-        camera.eye = camera.target - forward;
-        camera.up = self.recalculate_up(forward, camera);
-    }
-
-    fn recalculate_up(&self, forward: Vector3<f32>, camera: &mut Camera) -> Vector3<f32> {
-        // Recalculates up vector based on new rotations
-
-        // Precompute values which are used a lot (and expensive)
-        let camera_rotation_x = camera.rotation.x.rem_euclid(2.0 * PI);
-        let sin_z = camera.rotation.z.sin();
-        let cos_z = camera.rotation.z.cos();
-
-        // Calculate the right vector
-        // We use a global up vector because the real up vector actually doesn't effect the right vector (think about it)
-        let mut right = forward.cross(Vector3::new(0.0, 1.0, 0.0)).normalize();
-        // Change the signs of x and z so they work with every rotation
-        // (each octant has different signs that follow this rule based on the forward vector)
-        right.x = right.x.abs() * forward.z.signum();
-        right.z = right.z.abs() * -forward.x.signum();
-
-        // Calculate the up vector similarly to the right vector, only with different signs
-        let mut up = forward.cross(right).normalize();
-        up.x = up.x.abs() * forward.x.signum();
-        up.y = up.y.abs();
-        up.z = up.z.abs() * forward.z.signum();
-
-        // Flip the up vector values if the camera is rotated upside down by the x axis
-        // These fractions of PI come from trial and error and seeing which rotations break the up vector
-        // If anyone knows their significance, please tell me (maybe I messed up the octant signs?)
-        if (camera_rotation_x > 0.25 * PI && camera_rotation_x <= 0.5 * PI)
-        || (camera_rotation_x >= 0.75 * PI && camera_rotation_x < 1.5 * PI) { up *= -1.0; }
-
-        // Rotate the right vector around the forward vector
-        // Effectively applies z rotation after the fact, 
-        // so we dont have to deal with that messing up the previous calculations
-        let forward_dot = forward.dot(forward);
-        let parallel = (right.dot(forward) / forward_dot) * forward;
-        let orthogonal = right - parallel;
-        let w = forward.cross(orthogonal);
-        let orthogonal_magnitude = orthogonal.magnitude();
-
-        let x1 = cos_z / orthogonal_magnitude;
-        let x2 = sin_z / w.magnitude();
-        let orthogonal_rotated = orthogonal_magnitude * (x1 * orthogonal + x2 * w);
-        right = orthogonal_rotated + parallel;
-
-        // Rotate the up vector the same way
-        let parallel = (up.dot(forward) / forward_dot) * forward;
-        let orthogonal = up - parallel;
-        let w = forward.cross(orthogonal);
-        let orthogonal_magnitude = orthogonal.magnitude();
-
-        let x1 = cos_z / orthogonal_magnitude;
-        let x2 = sin_z / w.magnitude();
-        let orthogonal_rotated = orthogonal_magnitude * (x1 * orthogonal + x2 * w);
-        up = orthogonal_rotated + parallel;
+            movement += camera.frame.up_vector();
+        }
+        if self.is_down_pressed {
+            movement -= camera.frame.up_vector();
+        }
+        if movement.magnitude2() > 0.0 {
+            movement = movement.normalize();
+        }
+        // Half way over on the stick is half speed, unlike the keys
+        let stick = self.gamepad.stick_dead_zone.apply(self.gamepad_input.left_stick);
+        let triggers = self.gamepad.trigger_dead_zone.apply_axis(self.gamepad_input.right_trigger)
+            - self.gamepad.trigger_dead_zone.apply_axis(self.gamepad_input.left_trigger);
+        movement += forward * stick.y + right * stick.x + camera.frame.up_vector() * triggers;
+        if movement.magnitude2() > 1.0 {
+            movement = movement.normalize();
+        }
+        self.velocity = Inertia::step(self.inertia, self.velocity, movement, self.speed, dt);
+        if self.velocity.magnitude2() > 0.0 {
+            camera.eye += self.velocity * dt;
+            self.dirty = true;
+        }
 
-        if self.is_debug_pressed {
-            println!(
-                "UP: {:#?} \nFORWARD: {:#?} \nRIGHT: {:#?} \nROT: {:#?} \nEYE: {:#?} \nTARGET: {:#?}",
-                camera.up, forward.normalize(), right, camera.rotation, camera.eye, camera.target
-            );
+        if std::mem::take(&mut self.dirty) || turned {
+            camera.set_distance(1.0);
+        }
+    }
+}
+
+// Chase camera: stays at an offset from a target and looks at it, catching up over time rather
+// than being rigidly attached. State feeds it the target through track
+pub struct FollowCameraController {
+    // Where the eye sits relative to the target
+    pub offset: Vector3<f32>,
+    // What the camera looks at relative to the target, a little above it usually reads better
+    pub look_offset: Vector3<f32>,
+    // Roughly how many seconds the camera takes to catch up, None keeps it locked on
+    pub lag: Option<f32>,
+    // Turn the offsets with the target, so the camera stays behind it as it turns
+    pub rotate_with_target: bool,
+    target: Option<FollowTarget>,
+    // Jump straight to the target on the next update instead of gliding there
+    snap: bool,
+}
+
+impl FollowCameraController {
+    pub fn new(offset: Vector3<f32>) -> Self {
+        Self {
+            offset,
+            look_offset: Vector3::new(0.0, 0.0, 0.0),
+            lag: Some(0.2),
+            rotate_with_target: true,
+            target: None,
+            snap: true,
         }
+    }
+
+    pub fn with_look_offset(mut self, look_offset: Vector3<f32>) -> Self {
+        self.look_offset = look_offset;
+        self
+    }
 
-        up
+    pub fn with_lag(mut self, lag: Option<f32>) -> Self {
+        self.lag = lag;
+        self
+    }
+}
+
+impl CameraController for FollowCameraController {
+    fn process_event(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    fn attach(&mut self, _camera: &Camera) {
+        self.snap = true;
+    }
+
+    fn track(&mut self, target: &FollowTarget) {
+        self.target = Some(*target);
+    }
+
+    fn update(&mut self, camera: &mut Camera, dt: f32) {
+        let Some(target) = self.target else { return };
+        let rotate = |v: Vector3<f32>| if self.rotate_with_target { target.rotation.rotate_vector(v) } else { v };
+        let eye = target.position + rotate(self.offset);
+        let look_at = target.position + rotate(self.look_offset);
+
+        // Exponential catch up, which behaves the same at any frame rate
+        let t = match self.lag {
+            Some(lag) if lag > 0.0 && !self.snap => 1.0 - (-dt / lag).exp(),
+            _ => 1.0,
+        };
+        self.snap = false;
+        camera.eye += (eye - camera.eye) * t;
+        camera.target += (look_at - camera.target) * t;
+
+        camera.look_at_target();
+    }
+}
+
+// The actions a key event is bound to and whether the key went down, None if it isn't a bound key
+fn bound_actions(keys: &KeyMap, event: &WindowEvent) -> Option<(Vec<CameraAction>, bool)> {
+    let WindowEvent::KeyboardInput {
+        event: KeyEvent { state, physical_key: PhysicalKey::Code(keycode), .. },
+        ..
+    } = event else { return None };
+    let actions = keys.actions(*keycode).collect::<Vec<_>>();
+    (!actions.is_empty()).then_some((actions, *state == ElementState::Pressed))
+}
+
+// Confines and hides the cursor for mouse look, or gives it back. On the web Locked is the
+// Pointer Lock API, which browsers only allow from an input event handler
+pub fn set_cursor_grab(window: &Window, grab: bool) {
+    if grab {
+        // Not every platform supports both modes
+        if let Err(e) = window.set_cursor_grab(CursorGrabMode::Locked)
+            .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined)) {
+            log::warn!("Couldn't grab the cursor: {e}");
+        }
+    } else if let Err(e) = window.set_cursor_grab(CursorGrabMode::None) {
+        log::warn!("Couldn't release the cursor: {e}");
     }
+    window.set_cursor_visible(!grab);
 }
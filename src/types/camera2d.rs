@@ -0,0 +1,287 @@
+use cgmath::{Matrix4, Point2, Rad, SquareMatrix, Vector2, Vector3};
+use crate::types::camera::{CameraMatrices, OPENGL_TO_WGPU_MATRIX};
+
+// Depth range sprites and text can be layered in, anything outside it is clipped
+const Z_RANGE: f32 = 1000.0;
+
+// What one world unit is on screen at zoom 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Units2D {
+    // One pixel, so sizes in the world are sizes on screen
+    Pixels,
+    // Fits height units top to bottom whatever size the target is
+    World { height: f32 },
+}
+
+// Orthographic camera for flat scenes. The world has +y up, and position is the world point in
+// the middle of the view. Screen coordinates are pixels from the top left, like everywhere else
+pub struct Camera2D {
+    pub position: Point2<f32>,
+    // Counterclockwise, radians. Turns the view, so the world looks like it turns the other way
+    pub rotation: f32,
+    zoom: f32,
+    min_zoom: f32,
+    max_zoom: f32,
+    units: Units2D,
+    viewport: [u32; 2],
+    // Whole number zoom and a view lined up with the screen's pixels, see pixel_art
+    pixel_perfect: bool,
+
+    // Something changed since the uniform was last written
+    dirty: bool,
+}
+
+impl Camera2D {
+    pub fn new(units: Units2D, viewport: [u32; 2]) -> Self {
+        Self {
+            position: Point2::new(0.0, 0.0),
+            rotation: 0.0,
+            zoom: 1.0,
+            min_zoom: 0.01,
+            max_zoom: 100.0,
+            units,
+            viewport: [viewport[0].max(1), viewport[1].max(1)],
+            pixel_perfect: false,
+            dirty: true,
+        }
+    }
+
+    // For pixel art: one world unit is one art pixel, drawn as a zoom by zoom block of screen
+    // pixels. Zoom only takes whole numbers and the view is kept on the screen's pixel grid, so
+    // art pixels never come out different sizes or shimmer while scrolling. Rotation is ignored,
+    // nothing turned stays on the grid. Pair with nearest sampling, see
+    // SceneRenderer::use_pixel_art_defaults
+    pub fn pixel_art(viewport: [u32; 2], zoom: u32) -> Self {
+        let mut camera = Self::new(Units2D::Pixels, viewport).with_zoom_limits(1.0, 64.0);
+        camera.pixel_perfect = true;
+        camera.set_zoom(zoom as f32);
+        camera
+    }
+
+    pub fn is_pixel_perfect(&self) -> bool {
+        self.pixel_perfect
+    }
+
+    pub fn set_pixel_perfect(&mut self, pixel_perfect: bool) {
+        self.pixel_perfect = pixel_perfect;
+        self.set_zoom(self.zoom);
+    }
+
+    // Biggest whole number zoom that fits a virtual resolution of size art pixels on screen,
+    // for games drawn at a fixed low resolution. Never below 1
+    pub fn fit_zoom(&mut self, size: [u32; 2]) {
+        let scale = |viewport: u32, size: u32| viewport / size.max(1);
+        let zoom = scale(self.viewport[0], size[0]).min(scale(self.viewport[1], size[1])).max(1);
+        self.set_zoom(zoom as f32);
+    }
+
+    pub fn with_zoom_limits(mut self, min_zoom: f32, max_zoom: f32) -> Self {
+        self.min_zoom = min_zoom;
+        self.max_zoom = max_zoom.max(min_zoom);
+        self.set_zoom(self.zoom);
+        self
+    }
+
+    pub fn units(&self) -> Units2D {
+        self.units
+    }
+
+    pub fn set_units(&mut self, units: Units2D) {
+        self.units = units;
+        self.dirty = true;
+    }
+
+    pub fn viewport(&self) -> [u32; 2] {
+        self.viewport
+    }
+
+    // Size in pixels of what the camera draws into
+    pub fn set_viewport(&mut self, size: [u32; 2]) {
+        if size[0] == 0 || size[1] == 0 {
+            return;
+        }
+        self.viewport = size;
+        self.dirty = true;
+    }
+
+    // Bigger is closer
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    // Rounded to a whole number while pixel perfect
+    pub fn set_zoom(&mut self, zoom: f32) {
+        if zoom.is_finite() {
+            let zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+            self.zoom = if self.pixel_perfect { zoom.round().max(1.0) } else { zoom };
+            self.dirty = true;
+        }
+    }
+
+    // Has to be called after changing position or rotation directly
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Whether the camera changed since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // World units one pixel covers at the current zoom
+    pub fn units_per_pixel(&self) -> f32 {
+        let at_zoom_1 = match self.units {
+            Units2D::Pixels => 1.0,
+            Units2D::World { height } => height / self.viewport[1] as f32,
+        };
+        at_zoom_1 / self.zoom
+    }
+
+    // Width and height of the world the view covers
+    pub fn visible_size(&self) -> Vector2<f32> {
+        Vector2::new(self.viewport[0] as f32, self.viewport[1] as f32) * self.units_per_pixel()
+    }
+
+    // Moves the view by a distance in pixels, with what's under the cursor following a drag
+    pub fn pan(&mut self, pixels: [f32; 2]) {
+        let offset = self.screen_vector_to_world(pixels);
+        self.position -= offset;
+        self.dirty = true;
+    }
+
+    // Multiplies the zoom by factor, keeping the world point under pixel where it is on screen.
+    // While pixel perfect it steps to the next whole number in factor's direction instead
+    pub fn zoom_at(&mut self, pixel: [f32; 2], factor: f32) {
+        let before = self.screen_to_world(pixel);
+        let zoom = match self.pixel_perfect {
+            true if factor > 1.0 => self.zoom + 1.0,
+            true if factor < 1.0 => self.zoom - 1.0,
+            _ => self.zoom * factor,
+        };
+        self.set_zoom(zoom);
+        let after = self.screen_to_world(pixel);
+        self.position += before - after;
+    }
+
+    // Turns the view by angle radians around the world point under pixel
+    pub fn rotate_at(&mut self, pixel: [f32; 2], angle: f32) {
+        let before = self.screen_to_world(pixel);
+        self.rotation += angle;
+        let after = self.screen_to_world(pixel);
+        self.position += before - after;
+        self.dirty = true;
+    }
+
+    // Where the view is actually centred. While pixel perfect that's position moved onto the
+    // screen's pixel grid, so whole world units land on pixel edges even with an odd sized viewport
+    pub fn view_center(&self) -> Point2<f32> {
+        if !self.pixel_perfect {
+            return self.position;
+        }
+        let step = self.units_per_pixel();
+        let snap = |value: f32, viewport: u32| {
+            // An odd number of pixels puts the middle of the screen inside a pixel rather than on an edge
+            let offset = if viewport % 2 == 1 { step / 2.0 } else { 0.0 };
+            ((value - offset) / step).round() * step + offset
+        };
+        Point2::new(snap(self.position.x, self.viewport[0]), snap(self.position.y, self.viewport[1]))
+    }
+
+    // Rotation actually used, none while pixel perfect
+    fn view_rotation(&self) -> f32 {
+        if self.pixel_perfect { 0.0 } else { self.rotation }
+    }
+
+    // Rounds a sprite's position to the nearest whole art pixel while pixel perfect, or the nearest
+    // screen pixel otherwise, so its texels line up with the screen's
+    pub fn snap(&self, point: Point2<f32>) -> Point2<f32> {
+        let step = if self.pixel_perfect { self.units_per_pixel() * self.zoom } else { self.units_per_pixel() };
+        Point2::new((point.x / step).round() * step, (point.y / step).round() * step)
+    }
+
+    // The world point under a pixel
+    pub fn screen_to_world(&self, pixel: [f32; 2]) -> Point2<f32> {
+        let center = [self.viewport[0] as f32 / 2.0, self.viewport[1] as f32 / 2.0];
+        self.view_center() + self.screen_vector_to_world([pixel[0] - center[0], pixel[1] - center[1]])
+    }
+
+    // The pixel a world point is drawn at
+    pub fn world_to_screen(&self, point: Point2<f32>) -> [f32; 2] {
+        let offset = rotate(point - self.view_center(), -self.view_rotation()) / self.units_per_pixel();
+        [
+            self.viewport[0] as f32 / 2.0 + offset.x,
+            self.viewport[1] as f32 / 2.0 - offset.y,
+        ]
+    }
+
+    // Smallest world space box around everything the camera sees, as min and max corners
+    pub fn visible_bounds(&self) -> (Point2<f32>, Point2<f32>) {
+        let [width, height] = self.viewport.map(|x| x as f32);
+        let corners = [[0.0, 0.0], [width, 0.0], [width, height], [0.0, height]].map(|pixel| self.screen_to_world(pixel));
+        corners.iter().fold(
+            (Point2::new(f32::INFINITY, f32::INFINITY), Point2::new(f32::NEG_INFINITY, f32::NEG_INFINITY)),
+            |(min, max), p| (Point2::new(min.x.min(p.x), min.y.min(p.y)), Point2::new(max.x.max(p.x), max.y.max(p.y))),
+        )
+    }
+
+    // A movement on screen as a movement in the world, flipping y and undoing the rotation
+    fn screen_vector_to_world(&self, pixels: [f32; 2]) -> Vector2<f32> {
+        rotate(Vector2::new(pixels[0], -pixels[1]) * self.units_per_pixel(), self.view_rotation())
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        let center = self.view_center();
+        Matrix4::from_angle_z(Rad(-self.view_rotation()))
+            * Matrix4::from_translation(Vector3::new(-center.x, -center.y, 0.0))
+    }
+
+    // World z from -1000 to 1000 is kept, nearer the camera (higher z) is drawn on top
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        let half = self.visible_size() / 2.0;
+        OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-half.x, half.x, -half.y, half.y, -Z_RANGE, Z_RANGE)
+    }
+
+    pub fn matrices(&self) -> CameraMatrices {
+        CameraMatrices::new(self.view_matrix(), self.projection_matrix())
+    }
+}
+
+fn rotate(v: Vector2<f32>, angle: f32) -> Vector2<f32> {
+    let (sin, cos) = angle.sin_cos();
+    Vector2::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos)
+}
+
+// What 2D shaders get, laid out for a uniform buffer
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Camera2DUniform {
+    pub view_proj: [[f32; 4]; 4],
+    pub inv_view_proj: [[f32; 4]; 4],
+    // (width, height) in pixels, then units per pixel and zoom
+    pub viewport: [f32; 4],
+}
+
+impl Camera2DUniform {
+    pub fn new() -> Self {
+        let identity = Matrix4::identity().into();
+        Self { view_proj: identity, inv_view_proj: identity, viewport: [1.0, 1.0, 1.0, 1.0] }
+    }
+
+    pub fn update(&mut self, camera: &Camera2D) {
+        let matrices = camera.matrices();
+        self.view_proj = matrices.view_projection.into();
+        self.inv_view_proj = matrices.inverse_view_projection.into();
+        self.viewport = [
+            camera.viewport[0] as f32,
+            camera.viewport[1] as f32,
+            camera.units_per_pixel(),
+            camera.zoom,
+        ];
+    }
+}
+
+impl Default for Camera2DUniform {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,183 @@
+use cgmath::{EuclideanSpace, Point3};
+
+use crate::types::camera::Camera;
+
+// Where the camera should be at a point in a path, in the same space as the camera's eye and target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    // Seconds from the start of the path
+    pub time: f32,
+    pub position: Point3<f32>,
+    pub target: Point3<f32>,
+    // Degrees
+    pub fovy: f32,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, position: Point3<f32>, target: Point3<f32>, fovy: f32) -> Self {
+        Self { time, position, target, fovy }
+    }
+
+    // The camera's current pose at the given time, for recording a path by flying it
+    pub fn from_camera(time: f32, camera: &Camera) -> Self {
+        Self::new(time, camera.eye, camera.target, camera.fovy())
+    }
+}
+
+// A scripted camera move through keyframes, smoothly curved between them. State plays it in place
+// of the camera controller, see State::set_camera_path
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPath {
+    // Sorted by time
+    keyframes: Vec<Keyframe>,
+    time: f32,
+    playing: bool,
+    // Start again from the beginning after the last keyframe instead of stopping
+    pub looping: bool,
+    // 2 plays twice as fast
+    pub speed: f32,
+}
+
+impl Default for CameraPath {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cubic Hermite with Catmull-Rom tangents, which take the time between keyframes into account so
+// unevenly spaced keyframes don't speed up and slow down around each one
+fn spline<V>(values: [V; 4], times: [f32; 4], t: f32) -> V
+where
+    V: Copy + std::ops::Add<Output = V> + std::ops::Sub<Output = V> + std::ops::Mul<f32, Output = V>,
+{
+    let [p0, p1, p2, p3] = values;
+    let [t0, t1, t2, t3] = times;
+    let span = t2 - t1;
+    let tangent = |before: V, after: V, dt: f32| if dt > 0.0 { (after - before) * (span / dt) } else { after - before };
+    let m1 = tangent(p0, p2, t2 - t0);
+    let m2 = tangent(p1, p3, t3 - t1);
+    let (t2_, t3_) = (t * t, t * t * t);
+    p1 * (2.0 * t3_ - 3.0 * t2_ + 1.0)
+        + m1 * (t3_ - 2.0 * t2_ + t)
+        + p2 * (-2.0 * t3_ + 3.0 * t2_)
+        + m2 * (t3_ - t2_)
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self { keyframes: Vec::new(), time: 0.0, playing: false, looping: false, speed: 1.0 }
+    }
+
+    pub fn with_keyframes(mut self, keyframes: impl IntoIterator<Item = Keyframe>) -> Self {
+        for keyframe in keyframes {
+            self.add_keyframe(keyframe);
+        }
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    // Keeps the keyframes in time order, a keyframe at the same time as another goes after it
+    pub fn add_keyframe(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn remove_keyframe(&mut self, index: usize) -> Keyframe {
+        self.keyframes.remove(index)
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // Time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    pub fn play(&mut self) {
+        // Playing a finished path starts it over
+        if !self.looping && self.time >= self.duration() {
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    // Pauses and goes back to the start
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.time = 0.0;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Moves the playhead on, stopping at the end unless the path loops
+    pub fn advance(&mut self, dt: f32) {
+        if !self.playing {
+            return;
+        }
+        let duration = self.duration();
+        self.time += dt * self.speed;
+        if self.time >= duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+    }
+
+    // The pose at a time, None without any keyframes. Before the first keyframe or after the last
+    // it holds still
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let last = self.keyframes.len().checked_sub(1)?;
+        if time <= self.keyframes[0].time {
+            return Some(Keyframe { time, ..self.keyframes[0] });
+        }
+        // The keyframe the time is at or after, past the first one so there's always one
+        let i = self.keyframes.partition_point(|k| k.time <= time) - 1;
+        if i == last {
+            return Some(Keyframe { time, ..self.keyframes[last] });
+        }
+        let (next, a, b) = (i + 1, self.keyframes[i], self.keyframes[i + 1]);
+        let t = (time - a.time) / (b.time - a.time);
+        // The ends use themselves as their missing neighbour
+        let k = [self.keyframes[i.saturating_sub(1)], a, b, self.keyframes[(next + 1).min(last)]];
+        let times = k.map(|k| k.time);
+        let position = spline(k.map(|k| k.position.to_vec()), times, t);
+        let target = spline(k.map(|k| k.target.to_vec()), times, t);
+        // An overshooting field of view looks like a glitch, so it's just eased between the two
+        let fovy = a.fovy + (b.fovy - a.fovy) * (t * t * (3.0 - 2.0 * t));
+        Some(Keyframe::new(time, Point3::from_vec(position), Point3::from_vec(target), fovy))
+    }
+
+    // Puts the camera where the path is at the current time
+    pub fn apply(&self, camera: &mut Camera) {
+        let Some(pose) = self.sample(self.time) else { return };
+        camera.eye = pose.position;
+        camera.target = pose.target;
+        // A keyframe with an unusable field of view keeps the old one
+        let _ = camera.set_fovy(pose.fovy);
+        camera.look_at_target();
+    }
+}
+
@@ -1,4 +1,4 @@
-use std::ops::Mul;
+use std::{fmt, ops::{Add, Mul}, str::FromStr};
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -6,14 +6,123 @@ pub struct Color {
     r: f32,
     g: f32,
     b: f32,
+    // 1 is opaque
+    a: f32,
 }
 
+// The CSS colors of the same names, already converted to linear so they look right on screen
 impl Color {
+    pub const TRANSPARENT: Color = Color::new_rgba(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::new(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::new(1.0, 1.0, 1.0);
+    pub const GRAY: Color = Color::new(0.2159, 0.2159, 0.2159);
+    pub const SILVER: Color = Color::new(0.5271, 0.5271, 0.5271);
+    pub const RED: Color = Color::new(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::new(0.0, 0.2159, 0.0);
+    pub const LIME: Color = Color::new(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::new(0.0, 0.0, 1.0);
+    pub const YELLOW: Color = Color::new(1.0, 1.0, 0.0);
+    pub const CYAN: Color = Color::new(0.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::new(1.0, 0.0, 1.0);
+    pub const ORANGE: Color = Color::new(1.0, 0.3763, 0.0);
+    pub const PURPLE: Color = Color::new(0.2159, 0.0, 0.2159);
+    pub const PINK: Color = Color::new(1.0, 0.5271, 0.5972);
+    pub const BROWN: Color = Color::new(0.3763, 0.0232, 0.0232);
+    pub const NAVY: Color = Color::new(0.0, 0.0, 0.2159);
+    pub const TEAL: Color = Color::new(0.0, 0.2159, 0.2159);
+    pub const OLIVE: Color = Color::new(0.2159, 0.2159, 0.0);
+    pub const MAROON: Color = Color::new(0.2159, 0.0, 0.0);
+    pub const GOLD: Color = Color::new(1.0, 0.6795, 0.0);
+    pub const SKY_BLUE: Color = Color::new(0.2423, 0.6172, 0.8308);
+    pub const CORNFLOWER_BLUE: Color = Color::new(0.1274, 0.3005, 0.8469);
+
+    // Every named color, with the name FromStr knows it by
+    pub const NAMED: &'static [(&'static str, Color)] = &[
+        ("transparent", Color::TRANSPARENT),
+        ("black", Color::BLACK),
+        ("white", Color::WHITE),
+        ("gray", Color::GRAY),
+        ("silver", Color::SILVER),
+        ("red", Color::RED),
+        ("green", Color::GREEN),
+        ("lime", Color::LIME),
+        ("blue", Color::BLUE),
+        ("yellow", Color::YELLOW),
+        ("cyan", Color::CYAN),
+        ("magenta", Color::MAGENTA),
+        ("orange", Color::ORANGE),
+        ("purple", Color::PURPLE),
+        ("pink", Color::PINK),
+        ("brown", Color::BROWN),
+        ("navy", Color::NAVY),
+        ("teal", Color::TEAL),
+        ("olive", Color::OLIVE),
+        ("maroon", Color::MAROON),
+        ("gold", Color::GOLD),
+        ("sky_blue", Color::SKY_BLUE),
+        ("cornflower_blue", Color::CORNFLOWER_BLUE),
+    ];
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ColorParseError {
+    // Hex colors need 6 or 8 digits, after an optional #
+    InvalidLength(usize),
+    InvalidDigit(char),
+    UnknownName(String),
+}
+
+impl fmt::Display for ColorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorParseError::InvalidLength(len) => write!(f, "hex colors need 6 or 8 digits, not {len}"),
+            ColorParseError::InvalidDigit(c) => write!(f, "{c:?} isn't a hex digit"),
+            ColorParseError::UnknownName(name) => write!(f, "no color named {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ColorParseError {}
+
+// A hex color like "#6495ed" or a name from Color::NAMED, ignoring case. Spaces or dashes in a
+// name count as underscores, so "Cornflower Blue" works too
+impl FromStr for Color {
+    type Err = ColorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        // Without a # only the lengths from_hex takes count as hex, so a short word is looked up by name
+        let bare_hex = matches!(s.len(), 6 | 8) && s.chars().all(|c| c.is_ascii_hexdigit());
+        if s.starts_with('#') || bare_hex {
+            return Color::from_hex(s);
+        }
+        let name = s.to_ascii_lowercase().replace([' ', '-'], "_");
+        Color::NAMED
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(_, color)| color)
+            .ok_or_else(|| ColorParseError::UnknownName(s.to_string()))
+    }
+}
+
+// For clear colors
+impl From<Color> for wgpu::Color {
+    fn from(color: Color) -> Self {
+        let [r, g, b, a] = color.buffer().map(|c| c as f64);
+        wgpu::Color { r, g, b, a }
+    }
+}
+
+impl Color {
+    // Opaque
     pub const fn new(r: f32, g: f32, b: f32) -> Color {
-        Color {r, g, b}
+        Color {r, g, b, a: 1.0}
+    }
+    pub const fn new_rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color {r, g, b, a}
     }
     pub fn _new_rgb(r: f32, g: f32, b: f32) -> Color {
-        Color {r: r / 255.0, g: g / 255.0, b: b / 255.0}
+        Color {r: r / 255.0, g: g / 255.0, b: b / 255.0, a: 1.0}
     }
     pub fn new_hsv(h:f32, s: f32, v: f32) -> Color {
         let c = v * s;
@@ -42,12 +151,209 @@ impl Color {
         Color::new(r, g, b)
     }
 
+    // "#RRGGBB" or "#RRGGBBAA", with or without the #. Hex colors are sRGB, so the color is
+    // converted to linear, alpha isn't
+    pub fn from_hex(hex: &str) -> Result<Color, ColorParseError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+            return Err(ColorParseError::InvalidDigit(c));
+        }
+        if digits.len() != 6 && digits.len() != 8 {
+            return Err(ColorParseError::InvalidLength(digits.len()));
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).unwrap() as f32 / 255.0;
+        let a = if digits.len() == 8 { channel(3) } else { 1.0 };
+        Ok(Color::new(channel(0), channel(1), channel(2)).to_linear().with_alpha(a))
+    }
+
+    // Hue in degrees, saturation and lightness 0 to 1. Like new_hsv the channels are used as
+    // they are, call to_linear on the result if they were picked on an sRGB screen
+    pub fn new_hsl(h: f32, s: f32, l: f32) -> Color {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h_prime = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h_prime % 2.0 - 1.0).abs());
+        let m = l - c / 2.0;
+        let (r, g, b) = match h_prime as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        Color::new(r + m, g + m, b + m)
+    }
+
+    // Colors are drawn as they're given and the surface encodes them to sRGB, so they're linear.
+    // Colors picked in an image editor or written as hex are sRGB and need this to come out the same
+    pub fn to_linear(self) -> Color {
+        let decode = |c: f32| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+        Color::new_rgba(decode(self.r), decode(self.g), decode(self.b), self.a)
+    }
+
+    // The other way round from to_linear, for writing a color out as sRGB. Alpha is never encoded
+    pub fn to_srgb(self) -> Color {
+        let encode = |c: f32| if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 };
+        Color::new_rgba(encode(self.r), encode(self.g), encode(self.b), self.a)
+    }
+
+    // From OkLab's lightness and a / b axes, where equal steps look like equal changes. The result
+    // is linear, like to_linear's
+    pub fn from_oklab(l: f32, a: f32, b: f32) -> Color {
+        let l_ = l + 0.396_337_78 * a + 0.215_803_76 * b;
+        let m_ = l - 0.105_561_346 * a - 0.063_854_17 * b;
+        let s_ = l - 0.089_484_18 * a - 1.291_485_5 * b;
+        let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+        Color::new(
+            4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+            -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+            -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+        )
+    }
+
+    // [lightness, a, b], treating the color as linear. Alpha is left out
+    pub fn to_oklab(&self) -> [f32; 3] {
+        let l = 0.412_221_46 * self.r + 0.536_332_55 * self.g + 0.051_445_995 * self.b;
+        let m = 0.211_903_5 * self.r + 0.680_699_5 * self.g + 0.107_396_96 * self.b;
+        let s = 0.088_302_46 * self.r + 0.281_718_85 * self.g + 0.629_978_7 * self.b;
+        let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+        [
+            0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+            1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+            0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+        ]
+    }
+
     pub fn _rgb(&self) -> (f32, f32, f32) {
         (self.r * 255.0, self.g * 255.0, self.b * 255.0)
     }
 
-    pub fn _buffer(&self) -> [f32; 3] {
-        [self.r, self.g, self.b]
+    pub const fn with_alpha(self, a: f32) -> Color {
+        Color {a, ..self}
+    }
+
+    pub fn alpha(&self) -> f32 {
+        self.a
+    }
+
+    pub fn buffer(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    fn map2(self, other: Color, f: impl Fn(f32, f32) -> f32) -> Color {
+        Color::new_rgba(f(self.r, other.r), f(self.g, other.g), f(self.b, other.b), f(self.a, other.a))
+    }
+
+    // Straight between the channels, t of 0 is self and 1 is other. Not clamped, so t outside
+    // 0 to 1 carries on past either end
+    pub fn lerp(self, other: Color, t: f32) -> Color {
+        self.map2(other, |a, b| a + (b - a) * t)
+    }
+
+    // Like lerp but through OkLab, so the halfway point looks halfway and colors in between
+    // don't go muddy or dark. Alpha is still lerped
+    pub fn mix(self, other: Color, t: f32) -> Color {
+        let ([l0, a0, b0], [l1, a1, b1]) = (self.to_oklab(), other.to_oklab());
+        let lerp = |x: f32, y: f32| x + (y - x) * t;
+        Color::from_oklab(lerp(l0, l1), lerp(a0, a1), lerp(b0, b1)).with_alpha(lerp(self.a, other.a))
+    }
+
+    // Light adding up, which can go over 1
+    pub fn blend_add(self, other: Color) -> Color {
+        self + other
+    }
+
+    // Darkens, like light through two filters. Same as *
+    pub fn blend_multiply(self, other: Color) -> Color {
+        self * other
+    }
+
+    // Lightens without going over 1, the opposite of multiply
+    pub fn blend_screen(self, other: Color) -> Color {
+        self.map2(other, |a, b| 1.0 - (1.0 - a) * (1.0 - b))
+    }
+
+    // self drawn on top of below with its alpha, the way alpha blending draws it
+    pub fn over(self, below: Color) -> Color {
+        let a = self.a + below.a * (1.0 - self.a);
+        if a <= 0.0 {
+            return Color::TRANSPARENT;
+        }
+        let channel = |top: f32, bottom: f32| (top * self.a + bottom * below.a * (1.0 - self.a)) / a;
+        Color::new_rgba(channel(self.r, below.r), channel(self.g, below.g), channel(self.b, below.b), a)
+    }
+}
+
+impl Add<Color> for Color {
+    type Output = Color;
+    fn add(self, rhs: Color) -> Self::Output {
+        self.map2(rhs, |a, b| a + b)
+    }
+}
+
+// How a gradient blends between its stops
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GradientSpace {
+    // Color::lerp, what the GPU does interpolating vertex colors
+    Linear,
+    // Color::mix, evenly spaced to the eye
+    #[default]
+    OkLab,
+}
+
+// Colors at positions along a line, sampled anywhere between. Handy for coloring vertices by
+// height or any other value
+#[derive(Clone, Debug, Default)]
+pub struct Gradient {
+    // Sorted by position
+    stops: Vec<(f32, Color)>,
+    pub space: GradientSpace,
+}
+
+impl Gradient {
+    pub fn new(space: GradientSpace) -> Self {
+        Self { stops: Vec::new(), space }
+    }
+
+    // Evenly spaced from 0 to 1
+    pub fn even(colors: &[Color]) -> Self {
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        colors.iter().enumerate().fold(Self::default(), |gradient, (i, &color)| gradient.with_stop(i as f32 / last, color))
+    }
+
+    pub fn with_stop(mut self, position: f32, color: Color) -> Self {
+        self.add_stop(position, color);
+        self
+    }
+
+    // A stop at the same position as another goes after it, making a hard edge
+    pub fn add_stop(&mut self, position: f32, color: Color) {
+        let index = self.stops.partition_point(|&(p, _)| p <= position);
+        self.stops.insert(index, (position, color));
+    }
+
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    // Holds the end colors outside the stops, transparent without any. NaN gets the first color
+    pub fn sample(&self, position: f32) -> Color {
+        let (Some(&(first, first_color)), Some(&(last, last_color))) = (self.stops.first(), self.stops.last()) else {
+            return Color::TRANSPARENT;
+        };
+        if position.is_nan() || position <= first {
+            return first_color;
+        }
+        if position >= last {
+            return last_color;
+        }
+        let i = self.stops.partition_point(|&(p, _)| p <= position);
+        let ((p0, c0), (p1, c1)) = (self.stops[i - 1], self.stops[i]);
+        let t = (position - p0) / (p1 - p0);
+        match self.space {
+            GradientSpace::Linear => c0.lerp(c1, t),
+            GradientSpace::OkLab => c0.mix(c1, t),
+        }
     }
 }
 
@@ -57,7 +363,8 @@ impl Mul<Color> for Color {
         Color {
             r: self.r * rhs.r,
             g: self.g * rhs.g,
-            b: self.b * rhs.b
+            b: self.b * rhs.b,
+            a: self.a * rhs.a,
         }
     }
 }
\ No newline at end of file
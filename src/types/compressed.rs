@@ -0,0 +1,157 @@
+use std::io::{self, Read};
+
+use ktx2::{Format, SupercompressionScheme};
+
+// A KTX2 file's mip levels, decompressed from any supercompression but still in the GPU format
+// they were encoded in
+pub struct Ktx2Image {
+    pub format: wgpu::TextureFormat,
+    pub size: [u32; 2],
+    // Largest first
+    pub levels: Vec<Vec<u8>>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn unsupported(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message.into())
+}
+
+// The wgpu format a KTX2 (Vulkan) format is uploaded as, None for the ones there's no match for
+pub fn wgpu_format(format: Format) -> Option<wgpu::TextureFormat> {
+    use wgpu::{AstcBlock, AstcChannel, TextureFormat as T};
+    let astc = |block, srgb| T::Astc { block, channel: if srgb { AstcChannel::UnormSrgb } else { AstcChannel::Unorm } };
+    Some(match format {
+        Format::R8_UNORM => T::R8Unorm,
+        Format::R8G8_UNORM => T::Rg8Unorm,
+        Format::R8G8B8A8_UNORM => T::Rgba8Unorm,
+        Format::R8G8B8A8_SRGB => T::Rgba8UnormSrgb,
+        Format::B8G8R8A8_UNORM => T::Bgra8Unorm,
+        Format::B8G8R8A8_SRGB => T::Bgra8UnormSrgb,
+        Format::R16G16B16A16_SFLOAT => T::Rgba16Float,
+        Format::R32G32B32A32_SFLOAT => T::Rgba32Float,
+        // BC1 without alpha is the same blocks, the alpha just always comes out opaque
+        Format::BC1_RGB_UNORM_BLOCK | Format::BC1_RGBA_UNORM_BLOCK => T::Bc1RgbaUnorm,
+        Format::BC1_RGB_SRGB_BLOCK | Format::BC1_RGBA_SRGB_BLOCK => T::Bc1RgbaUnormSrgb,
+        Format::BC2_UNORM_BLOCK => T::Bc2RgbaUnorm,
+        Format::BC2_SRGB_BLOCK => T::Bc2RgbaUnormSrgb,
+        Format::BC3_UNORM_BLOCK => T::Bc3RgbaUnorm,
+        Format::BC3_SRGB_BLOCK => T::Bc3RgbaUnormSrgb,
+        Format::BC4_UNORM_BLOCK => T::Bc4RUnorm,
+        Format::BC4_SNORM_BLOCK => T::Bc4RSnorm,
+        Format::BC5_UNORM_BLOCK => T::Bc5RgUnorm,
+        Format::BC5_SNORM_BLOCK => T::Bc5RgSnorm,
+        Format::BC6H_UFLOAT_BLOCK => T::Bc6hRgbUfloat,
+        Format::BC6H_SFLOAT_BLOCK => T::Bc6hRgbFloat,
+        Format::BC7_UNORM_BLOCK => T::Bc7RgbaUnorm,
+        Format::BC7_SRGB_BLOCK => T::Bc7RgbaUnormSrgb,
+        Format::ETC2_R8G8B8_UNORM_BLOCK => T::Etc2Rgb8Unorm,
+        Format::ETC2_R8G8B8_SRGB_BLOCK => T::Etc2Rgb8UnormSrgb,
+        Format::ETC2_R8G8B8A1_UNORM_BLOCK => T::Etc2Rgb8A1Unorm,
+        Format::ETC2_R8G8B8A1_SRGB_BLOCK => T::Etc2Rgb8A1UnormSrgb,
+        Format::ETC2_R8G8B8A8_UNORM_BLOCK => T::Etc2Rgba8Unorm,
+        Format::ETC2_R8G8B8A8_SRGB_BLOCK => T::Etc2Rgba8UnormSrgb,
+        Format::EAC_R11_UNORM_BLOCK => T::EacR11Unorm,
+        Format::EAC_R11_SNORM_BLOCK => T::EacR11Snorm,
+        Format::EAC_R11G11_UNORM_BLOCK => T::EacRg11Unorm,
+        Format::EAC_R11G11_SNORM_BLOCK => T::EacRg11Snorm,
+        Format::ASTC_4x4_UNORM_BLOCK => astc(AstcBlock::B4x4, false),
+        Format::ASTC_4x4_SRGB_BLOCK => astc(AstcBlock::B4x4, true),
+        Format::ASTC_5x4_UNORM_BLOCK => astc(AstcBlock::B5x4, false),
+        Format::ASTC_5x4_SRGB_BLOCK => astc(AstcBlock::B5x4, true),
+        Format::ASTC_5x5_UNORM_BLOCK => astc(AstcBlock::B5x5, false),
+        Format::ASTC_5x5_SRGB_BLOCK => astc(AstcBlock::B5x5, true),
+        Format::ASTC_6x5_UNORM_BLOCK => astc(AstcBlock::B6x5, false),
+        Format::ASTC_6x5_SRGB_BLOCK => astc(AstcBlock::B6x5, true),
+        Format::ASTC_6x6_UNORM_BLOCK => astc(AstcBlock::B6x6, false),
+        Format::ASTC_6x6_SRGB_BLOCK => astc(AstcBlock::B6x6, true),
+        Format::ASTC_8x5_UNORM_BLOCK => astc(AstcBlock::B8x5, false),
+        Format::ASTC_8x5_SRGB_BLOCK => astc(AstcBlock::B8x5, true),
+        Format::ASTC_8x6_UNORM_BLOCK => astc(AstcBlock::B8x6, false),
+        Format::ASTC_8x6_SRGB_BLOCK => astc(AstcBlock::B8x6, true),
+        Format::ASTC_8x8_UNORM_BLOCK => astc(AstcBlock::B8x8, false),
+        Format::ASTC_8x8_SRGB_BLOCK => astc(AstcBlock::B8x8, true),
+        Format::ASTC_10x5_UNORM_BLOCK => astc(AstcBlock::B10x5, false),
+        Format::ASTC_10x5_SRGB_BLOCK => astc(AstcBlock::B10x5, true),
+        Format::ASTC_10x6_UNORM_BLOCK => astc(AstcBlock::B10x6, false),
+        Format::ASTC_10x6_SRGB_BLOCK => astc(AstcBlock::B10x6, true),
+        Format::ASTC_10x8_UNORM_BLOCK => astc(AstcBlock::B10x8, false),
+        Format::ASTC_10x8_SRGB_BLOCK => astc(AstcBlock::B10x8, true),
+        Format::ASTC_10x10_UNORM_BLOCK => astc(AstcBlock::B10x10, false),
+        Format::ASTC_10x10_SRGB_BLOCK => astc(AstcBlock::B10x10, true),
+        Format::ASTC_12x10_UNORM_BLOCK => astc(AstcBlock::B12x10, false),
+        Format::ASTC_12x10_SRGB_BLOCK => astc(AstcBlock::B12x10, true),
+        Format::ASTC_12x12_UNORM_BLOCK => astc(AstcBlock::B12x12, false),
+        Format::ASTC_12x12_SRGB_BLOCK => astc(AstcBlock::B12x12, true),
+        _ => return None,
+    })
+}
+
+// Whether the device was created with what it takes to sample format, compressed formats each
+// need their family's feature
+pub fn is_supported(device: &wgpu::Device, format: wgpu::TextureFormat) -> bool {
+    device.features().contains(format.required_features())
+}
+
+// The format a KTX2 file is stored in, read from its header alone. Errors if it's not a KTX2
+// file or its format has no wgpu match
+pub fn ktx2_format(bytes: &[u8]) -> io::Result<wgpu::TextureFormat> {
+    let reader = ktx2::Reader::new(bytes).map_err(|e| invalid(format!("not a KTX2 file: {e:?}")))?;
+    let header = reader.header();
+    // No format means Basis Universal, which has to be transcoded first
+    let format = header.format.ok_or_else(|| unsupported("Basis Universal KTX2 files aren't supported, only ones already in a GPU format"))?;
+    wgpu_format(format).ok_or_else(|| unsupported(format!("KTX2 format {format:?} isn't supported")))
+}
+
+// Reads a 2D KTX2 texture and undoes its supercompression. Arrays, cubemaps and 3D textures
+// aren't supported
+pub fn read_ktx2(bytes: &[u8]) -> io::Result<Ktx2Image> {
+    let format = ktx2_format(bytes)?;
+    let reader = ktx2::Reader::new(bytes).map_err(|e| invalid(format!("not a KTX2 file: {e:?}")))?;
+    let header = reader.header();
+    if header.pixel_height == 0 || header.pixel_depth > 0 || header.layer_count > 0 || header.face_count > 1 {
+        return Err(unsupported("only single 2D KTX2 textures are supported"));
+    }
+    let size = [header.pixel_width, header.pixel_height];
+    let (block_width, block_height) = format.block_dimensions();
+    if size[0] % block_width != 0 || size[1] % block_height != 0 {
+        return Err(invalid(format!("{}x{} isn't a whole number of {block_width}x{block_height} blocks", size[0], size[1])));
+    }
+    let levels = reader.levels()
+        .map(|level| match header.supercompression_scheme {
+            None => Ok(level.to_vec()),
+            Some(SupercompressionScheme::Zstandard) => {
+                let mut decoded = Vec::new();
+                ruzstd::StreamingDecoder::new(level)
+                    .map_err(|e| invalid(format!("bad zstd data: {e}")))?
+                    .read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            Some(SupercompressionScheme::ZLIB) => {
+                let mut decoded = Vec::new();
+                flate2::read::ZlibDecoder::new(level).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            Some(scheme) => Err(unsupported(format!("KTX2 supercompression {scheme:?} isn't supported"))),
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    if levels.is_empty() {
+        return Err(invalid("KTX2 file has no mip levels"));
+    }
+    // Every level has to be big enough for its blocks
+    let block_size = format.block_copy_size(None).unwrap_or(4) as usize;
+    for (i, level) in levels.iter().enumerate() {
+        let [width, height] = level_size(size, i as u32);
+        let expected = width.div_ceil(block_width) as usize * height.div_ceil(block_height) as usize * block_size;
+        if level.len() < expected {
+            return Err(invalid(format!("KTX2 level {i} is {} bytes, needs {expected}", level.len())));
+        }
+    }
+    Ok(Ktx2Image { format, size, levels })
+}
+
+pub fn level_size(size: [u32; 2], level: u32) -> [u32; 2] {
+    [(size[0] >> level).max(1), (size[1] >> level).max(1)]
+}
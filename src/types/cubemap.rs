@@ -0,0 +1,220 @@
+use std::io;
+
+use crate::types::texture::Texture;
+
+// Each face reads its index from its own slice of one buffer. 256 is the most any device asks
+// dynamic offsets to be aligned to
+const FACE_STRIDE: u64 = 256;
+
+// Six square faces in wgpu's +x, -x, +y, -y, +z, -z order, viewed through a cube view so shaders
+// can sample it with a direction through texture_cube. World y is up. Used for skyboxes, and
+// reflections and point light shadows can render into the faces through face_view
+pub struct Cubemap {
+    pub texture: wgpu::Texture,
+    // Cube dimension, for texture_cube bindings
+    pub view: wgpu::TextureView,
+}
+
+impl Cubemap {
+    // An empty cubemap for rendering into, usage is on top of TEXTURE_BINDING
+    pub fn new(device: &wgpu::Device, size: u32, format: wgpu::TextureFormat, usage: wgpu::TextureUsages, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size.max(1), height: size.max(1), depth_or_array_layers: 6 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
+            ..Default::default()
+        });
+        Self { texture, view }
+    }
+
+    // sRGB faces in the same order as the cubemap, laid out the way OpenGL and Vulkan expect: the
+    // side faces have -y at the bottom and the +x face has -z on its right, the +y face has -z
+    // at its top and the -y face +z. They all have to be the same square size
+    pub fn from_images(device: &wgpu::Device, queue: &wgpu::Queue, faces: [&image::RgbaImage; 6], label: &str) -> io::Result<Self> {
+        let size = faces[0].width();
+        if let Some(face) = faces.iter().position(|face| face.dimensions() != (size, size)) {
+            let (width, height) = faces[face].dimensions();
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("cubemap face {face} is {width}x{height}, needs to be {size}x{size}")));
+        }
+        let cubemap = Self::new(device, size, wgpu::TextureFormat::Rgba8UnormSrgb, wgpu::TextureUsages::COPY_DST, label);
+        for (layer, face) in faces.into_iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &cubemap.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: 0, y: 0, z: layer as u32 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                face,
+                wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * size), rows_per_image: None },
+                wgpu::Extent3d { width: size, height: size, depth_or_array_layers: 1 },
+            );
+        }
+        Ok(cubemap)
+    }
+
+    // Six image files in +x, -x, +y, -y, +z, -z order, like from_images
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_faces(device: &wgpu::Device, queue: &wgpu::Queue, paths: [impl AsRef<std::path::Path>; 6]) -> io::Result<Self> {
+        let label = paths[0].as_ref().display().to_string();
+        let faces = paths
+            .iter()
+            .map(|path| Ok(image::open(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?.to_rgba8()))
+            .collect::<io::Result<Vec<_>>>()?;
+        let faces: [&image::RgbaImage; 6] = std::array::from_fn(|i| &faces[i]);
+        Self::from_images(device, queue, faces, &label)
+    }
+
+    // Resamples an equirectangular image, like the ones Texture::from_hdr_bytes loads, onto faces
+    // face_size texels across. The image's middle looks down -z. The cubemap is in the image's
+    // format, so HDR environments stay HDR
+    pub fn from_equirect(device: &wgpu::Device, queue: &wgpu::Queue, equirect: &Texture, face_size: u32, label: &str) -> Self {
+        let format = equirect.texture.format();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Equirect Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../equirect.wgsl").into()) });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("equirect_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(4),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Equirect Pipeline"),
+            layout: Some(&device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Equirect Pipeline Layout"),
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let faces = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Equirect Face Buffer"),
+            size: FACE_STRIDE * 6,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        for face in 0..6u32 {
+            queue.write_buffer(&faces, face as u64 * FACE_STRIDE, bytemuck::bytes_of(&face));
+        }
+        // Repeats around, clamps at the poles
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Equirect Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("equirect_bind_group"),
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding { buffer: &faces, offset: 0, size: wgpu::BufferSize::new(4) }),
+                },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&equirect.view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(&sampler) },
+            ],
+        });
+
+        let cubemap = Self::new(device, face_size, format, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC, label);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Equirect Encoder") });
+        for face in 0..6u32 {
+            let view = cubemap.face_view(face);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(&format!("Equirect Pass (Face {face})")),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::BLACK), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&pipeline);
+            render_pass.set_bind_group(0, &bind_group, &[(face as u64 * FACE_STRIDE) as u32]);
+            render_pass.draw(0..3, 0..1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        cubemap
+    }
+
+    // An .hdr or .exr equirectangular environment, straight to a cubemap
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_environment(device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<std::path::Path>, face_size: u32) -> io::Result<Self> {
+        let path = path.as_ref();
+        let equirect = Texture::load_hdr(device, queue, path)?;
+        Ok(Self::from_equirect(device, queue, &equirect, face_size, &path.display().to_string()))
+    }
+
+    // One face on its own as a 2D view, for render passes drawing into it
+    pub fn face_view(&self, face: u32) -> wgpu::TextureView {
+        self.texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("Cubemap Face {face}")),
+            dimension: Some(wgpu::TextureViewDimension::D2),
+            base_array_layer: face,
+            array_layer_count: Some(1),
+            ..Default::default()
+        })
+    }
+
+    // Texels across each face
+    pub fn size(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Quaternion, Rotation3, SquareMatrix, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::types::{color::Color, shader_features, texture::MeshTextures};
+
+// Each decal reads its uniform from its own slice of one buffer. 256 is the most any device asks
+// dynamic offsets to be aligned to
+const DECAL_STRIDE: u64 = 256;
+
+// A texture projected onto whatever is inside a box, like a bullet hole or a painted marking. The
+// texture is stretched over the box's x and y and pressed down its z, onto every surface in the
+// box that faces back up it. Added with SceneRenderer::add_decal
+#[derive(Clone, Copy, Debug)]
+pub struct Decal {
+    // Middle of the box, double precision like Instance::position
+    pub position: Vector3<f64>,
+    pub rotation: Quaternion<f32>,
+    // x and y are the texture's width and height, z how deep the box is through the surface
+    pub size: Vector3<f32>,
+    // A texture added with SceneRenderer::add_texture, None for a plain patch of color
+    pub texture: Option<usize>,
+    // Multiplies the texture, alpha is how opaque it is
+    pub color: Color,
+    // Degrees. Surfaces turned further than this from facing the decal fade out, so it doesn't
+    // smear down the sides of whatever it's on
+    pub max_angle: f32,
+}
+
+impl Decal {
+    pub fn new(position: Vector3<f64>, size: Vector3<f32>) -> Self {
+        Self {
+            position,
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            size,
+            texture: None,
+            color: Color::new(1.0, 1.0, 1.0),
+            max_angle: 60.0,
+        }
+    }
+
+    // Flat on a surface at position, facing out along its normal, like a RayHit's point and the
+    // normal of what it hit. depth is how far the box reaches either side, enough to cover bumps
+    pub fn on_surface(position: Vector3<f64>, normal: Vector3<f32>, size: [f32; 2], depth: f32) -> Self {
+        let rotation = if normal.magnitude2() > 0.0 {
+            Quaternion::from_arc(Vector3::unit_z(), normal.normalize(), None)
+        } else {
+            Quaternion::new(1.0, 0.0, 0.0, 0.0)
+        };
+        Self { rotation, ..Self::new(position, Vector3::new(size[0], size[1], depth * 2.0)) }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion<f32>) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // Turns it around its facing, keeping it on the same surface
+    pub fn with_spin(mut self, degrees: f32) -> Self {
+        self.rotation = self.rotation * Quaternion::from_angle_z(cgmath::Deg(degrees));
+        self
+    }
+
+    pub fn with_texture(mut self, texture: usize) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_max_angle(mut self, degrees: f32) -> Self {
+        self.max_angle = degrees;
+        self
+    }
+
+    // Takes the box from a unit cube around the origin to where it is, relative to origin
+    fn model(&self, origin: Vector3<f64>) -> cgmath::Matrix4<f32> {
+        let relative = (self.position - origin).cast::<f32>().unwrap();
+        cgmath::Matrix4::from_translation(relative)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.size.x, self.size.y, self.size.z)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DecalUniform {
+    model: [[f32; 4]; 4],
+    // Back into the box, where it spans -0.5 to 0.5
+    inverse_model: [[f32; 4]; 4],
+    color: [f32; 4],
+    // Cosine of the max angle, 1 if textured
+    params: [f32; 4],
+}
+
+// Draws the scene's decals over each view, between its opaque and transparent meshes. Each decal's
+// box is drawn from the inside and every pixel it covers finds what's there from the depth the
+// view left, so the depth has to be readable: decals are only drawn without multisampling
+pub(crate) struct DecalRenderer {
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    decal_layout: wgpu::BindGroupLayout,
+    texture_layout: wgpu::BindGroupLayout,
+    // By whether the view is mirrored, which turns the box inside out
+    pipelines: HashMap<bool, wgpu::RenderPipeline>,
+    // DECAL_STRIDE apart, grown when there are more decals
+    buffer: wgpu::Buffer,
+    capacity: usize,
+    // The buffer and the scene's depth, made each frame since the depth is replaced on resize
+    bind_group: Option<wgpu::BindGroup>,
+    // By texture id, made again when the scene's textures change
+    texture_bind_groups: HashMap<Option<usize>, wgpu::BindGroup>,
+    // The texture each decal written by prepare is drawn with, in order
+    drawn: Vec<Option<usize>>,
+}
+
+impl DecalRenderer {
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Decal Shader"), source: wgpu::ShaderSource::Wgsl(shader_features::compose(include_str!("../decal.wgsl")).into()) });
+        let decal_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("decal_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<DecalUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    // Read as a plain float texture, GL can't load from depth textures
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("decal_texture_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Decal Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &decal_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        let capacity = 16;
+        let buffer = Self::create_buffer(device, capacity);
+        Self {
+            format,
+            shader,
+            layout,
+            decal_layout,
+            texture_layout,
+            pipelines: HashMap::new(),
+            buffer,
+            capacity,
+            bind_group: None,
+            texture_bind_groups: HashMap::new(),
+            drawn: Vec::new(),
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Decal Buffer"),
+            contents: &vec![0; capacity * DECAL_STRIDE as usize],
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        })
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    // Drops the texture bind groups, for when the scene's textures or samplers change
+    pub fn rebind(&mut self) {
+        self.texture_bind_groups.clear();
+    }
+
+    fn pipeline(&mut self, device: &wgpu::Device, mirrored: bool) {
+        if self.pipelines.contains_key(&mirrored) {
+            return;
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Decal Pipeline{}", if mirrored { " (mirrored)" } else { "" })),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // Only the far side of the box, so each pixel is covered once and the camera can be
+            // inside it
+            primitive: wgpu::PrimitiveState {
+                front_face: if mirrored { wgpu::FrontFace::Cw } else { wgpu::FrontFace::Ccw },
+                cull_mode: Some(wgpu::Face::Front),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert(mirrored, pipeline);
+    }
+
+    // Uploads the decals relative to origin and builds what the coming passes need, render passes
+    // can't. depth is the scene's depth texture, which has to be single sampled
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare<'a>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: &MeshTextures,
+        depth: &wgpu::TextureView,
+        decals: impl IntoIterator<Item = &'a Decal>,
+        origin: Vector3<f64>,
+        mirrored: impl IntoIterator<Item = bool>,
+    ) {
+        let mut uniforms = Vec::new();
+        self.drawn.clear();
+        for decal in decals {
+            let model = decal.model(origin);
+            // Flattened boxes can't be drawn into
+            let Some(inverse_model) = model.invert() else { continue };
+            // A removed texture leaves a plain patch rather than nothing
+            let texture = decal.texture.filter(|&id| textures.get(id).is_some());
+            let uniform = DecalUniform {
+                model: model.into(),
+                inverse_model: inverse_model.into(),
+                color: decal.color.buffer(),
+                params: [decal.max_angle.clamp(0.0, 90.0).to_radians().cos(), if texture.is_some() { 1.0 } else { 0.0 }, 0.0, 0.0],
+            };
+            let mut bytes = bytemuck::bytes_of(&uniform).to_vec();
+            bytes.resize(DECAL_STRIDE as usize, 0);
+            uniforms.extend(bytes);
+            self.drawn.push(texture);
+        }
+        if self.drawn.is_empty() {
+            return;
+        }
+        if self.drawn.len() > self.capacity {
+            self.capacity = self.drawn.len().next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, &uniforms);
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("decal_bind_group"),
+            layout: &self.decal_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<DecalUniform>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(depth) },
+            ],
+        }));
+        for mirrored in mirrored {
+            self.pipeline(device, mirrored);
+        }
+        for &texture in &self.drawn {
+            self.texture_bind_groups.entry(texture).or_insert_with(|| {
+                let (view, sampler) = textures.binding(texture);
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("decal_texture_bind_group"),
+                    layout: &self.texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(view) },
+                        wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+                    ],
+                })
+            });
+        }
+    }
+
+    // Whether the last prepare left anything to draw
+    pub(crate) fn is_empty(&self) -> bool {
+        self.drawn.is_empty()
+    }
+
+    // Draws into a pass that has the camera bound, the scene's color loaded and no depth attached
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, mirrored: bool) {
+        let Some(bind_group) = &self.bind_group else { return };
+        render_pass.push_debug_group("Decals");
+        render_pass.set_pipeline(&self.pipelines[&mirrored]);
+        for (i, texture) in self.drawn.iter().enumerate() {
+            render_pass.set_bind_group(1, bind_group, &[(i as u64 * DECAL_STRIDE) as u32]);
+            render_pass.set_bind_group(2, &self.texture_bind_groups[texture], &[]);
+            render_pass.draw(0..36, 0..1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,107 @@
+use cgmath::{Matrix4, Vector3};
+use serde::{Deserialize, Serialize};
+
+// Which axis points up
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpAxis {
+    Y,
+    // Blender, most CAD packages and Unreal
+    Z,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Handedness {
+    Right,
+    // Unity and Unreal
+    Left,
+}
+
+// The axis conventions a scene or file is written in. Rendering always happens in Y_UP_RH, everything
+// else is converted into it with to_render and back with from_render
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoordinateFrame {
+    pub up: UpAxis,
+    pub handedness: Handedness,
+}
+
+impl CoordinateFrame {
+    // +y up, -z forward, +x right, the crate's own frame
+    pub const Y_UP_RH: CoordinateFrame = CoordinateFrame { up: UpAxis::Y, handedness: Handedness::Right };
+    // Unity
+    pub const Y_UP_LH: CoordinateFrame = CoordinateFrame { up: UpAxis::Y, handedness: Handedness::Left };
+    // Blender
+    pub const Z_UP_RH: CoordinateFrame = CoordinateFrame { up: UpAxis::Z, handedness: Handedness::Right };
+    // Unreal
+    pub const Z_UP_LH: CoordinateFrame = CoordinateFrame { up: UpAxis::Z, handedness: Handedness::Left };
+
+    // True if converting to the render frame is a reflection, which turns triangles inside out
+    pub fn is_mirrored(&self) -> bool {
+        self.handedness == Handedness::Left
+    }
+
+    // Up in this frame's own coordinates
+    pub fn up_vector(&self) -> Vector3<f32> {
+        match self.up {
+            UpAxis::Y => Vector3::unit_y(),
+            UpAxis::Z => Vector3::unit_z(),
+        }
+    }
+
+    pub fn to_render(&self, v: [f32; 3]) -> [f32; 3] {
+        // Left handed frames are made right handed by negating the axis that's neither up nor x
+        let v = match (self.handedness, self.up) {
+            (Handedness::Right, _) => v,
+            (Handedness::Left, UpAxis::Y) => [v[0], v[1], -v[2]],
+            (Handedness::Left, UpAxis::Z) => [v[0], -v[1], v[2]],
+        };
+        match self.up {
+            UpAxis::Y => v,
+            // Rotates -90 degrees around x, so +z becomes +y and +y becomes -z
+            UpAxis::Z => [v[0], v[2], -v[1]],
+        }
+    }
+
+    pub fn from_render(&self, v: [f32; 3]) -> [f32; 3] {
+        let v = match self.up {
+            UpAxis::Y => v,
+            UpAxis::Z => [v[0], -v[2], v[1]],
+        };
+        match (self.handedness, self.up) {
+            (Handedness::Right, _) => v,
+            (Handedness::Left, UpAxis::Y) => [v[0], v[1], -v[2]],
+            (Handedness::Left, UpAxis::Z) => [v[0], -v[1], v[2]],
+        }
+    }
+
+    pub fn vector_to_render(&self, v: Vector3<f32>) -> Vector3<f32> {
+        self.to_render(v.into()).into()
+    }
+
+    pub fn vector_from_render(&self, v: Vector3<f32>) -> Vector3<f32> {
+        self.from_render(v.into()).into()
+    }
+
+    // Converts a point in this frame into another one
+    pub fn convert(&self, v: [f32; 3], to: &CoordinateFrame) -> [f32; 3] {
+        to.from_render(self.to_render(v))
+    }
+
+    // Same as to_render, as a matrix for the GPU
+    pub fn to_render_matrix(&self) -> Matrix4<f32> {
+        let x = self.to_render([1.0, 0.0, 0.0]);
+        let y = self.to_render([0.0, 1.0, 0.0]);
+        let z = self.to_render([0.0, 0.0, 1.0]);
+        Matrix4::new(
+            x[0], x[1], x[2], 0.0,
+            y[0], y[1], y[2], 0.0,
+            z[0], z[1], z[2], 0.0,
+            0.0, 0.0, 0.0, 1.0,
+        )
+    }
+}
+
+impl Default for CoordinateFrame {
+    fn default() -> Self {
+        Self::Y_UP_RH
+    }
+}
@@ -0,0 +1,79 @@
+use std::any::Any;
+
+// Anything kept alive until the GPU is done with it
+#[cfg(not(target_arch = "wasm32"))]
+type Retired = Box<dyn Any + Send>;
+#[cfg(target_arch = "wasm32")]
+type Retired = Box<dyn Any>;
+
+// Lets the CPU get up to count frames ahead of the GPU and no further. Each frame in flight has a
+// slot of its own, so anything written every frame can have one copy per slot that the GPU isn't
+// still reading from. Resources replaced along the way are held on to until the last frame that
+// could have used them is finished
+pub struct FramesInFlight {
+    current: usize,
+    // What was submitted the last time each slot was used
+    submissions: Vec<Option<wgpu::SubmissionIndex>>,
+    // Freed once the frame in the same slot has finished
+    retired: Vec<Vec<Retired>>,
+}
+
+impl FramesInFlight {
+    // Same as the surface's desired_maximum_frame_latency
+    pub const DEFAULT: usize = 2;
+
+    pub fn new(count: usize) -> Self {
+        let count = count.max(1);
+        Self {
+            current: 0,
+            submissions: (0..count).map(|_| None).collect(),
+            retired: (0..count).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.submissions.len()
+    }
+
+    // Slot of the frame being recorded, or the next one if none is
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    // Waits for the GPU to finish the frame that last used the current slot, then frees what was
+    // retired in it. Returns the slot this frame uses
+    pub fn begin_frame(&mut self, device: &wgpu::Device) -> usize {
+        if let Some(submission) = self.submissions[self.current].take() {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        self.retired[self.current].clear();
+        self.current
+    }
+
+    // Takes what submitting the frame returned and moves on to the next slot
+    pub fn end_frame(&mut self, submission: wgpu::SubmissionIndex) {
+        self.submissions[self.current] = Some(submission);
+        self.current = (self.current + 1) % self.count();
+    }
+
+    // Holds on to something that's been replaced until the GPU has finished every frame already
+    // submitted, which is the last any of them could have used it
+    pub fn retire<T: wgpu::WasmNotSend + 'static>(&mut self, resource: T) {
+        let last = (self.current + self.count() - 1) % self.count();
+        self.retired[last].push(Box::new(resource));
+    }
+
+    // Waits for every frame in flight and frees everything retired
+    pub fn wait_idle(&mut self, device: &wgpu::Device) {
+        for submission in self.submissions.iter_mut().filter_map(Option::take) {
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+        }
+        self.retired.iter_mut().for_each(Vec::clear);
+    }
+}
+
+impl Default for FramesInFlight {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT)
+    }
+}
@@ -0,0 +1,71 @@
+use cgmath::{InnerSpace, Matrix4, Vector3, Vector4};
+
+// Points with normal . p + distance >= 0 are on the inside
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    fn from_row(row: Vector4<f32>) -> Self {
+        let normal = row.truncate();
+        let length = normal.magnitude();
+        // An infinite far plane comes out with no normal. Either everything's inside it or nothing is
+        if length == 0.0 {
+            return Self { normal, distance: if row.w >= 0.0 { f32::INFINITY } else { f32::NEG_INFINITY } };
+        }
+        Self { normal: normal / length, distance: row.w / length }
+    }
+
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+// The volume a camera can see, in the space its view-projection matrix takes points from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    // Left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Pulls the planes straight out of a view-projection matrix with wgpu's 0 to 1 depth range
+    pub fn from_matrix(m: Matrix4<f32>) -> Self {
+        // cgmath stores columns, the planes are made from rows
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::from_row(r3 + r0),
+                Plane::from_row(r3 - r0),
+                Plane::from_row(r3 + r1),
+                Plane::from_row(r3 - r1),
+                Plane::from_row(r2),
+                Plane::from_row(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Vector3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    // Conservative: boxes near the frustum's corners can pass without actually being visible
+    pub fn intersects_aabb(&self, min: [f32; 3], max: [f32; 3]) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.normal.x >= 0.0 { max[0] } else { min[0] },
+                if plane.normal.y >= 0.0 { max[1] } else { min[1] },
+                if plane.normal.z >= 0.0 { max[2] } else { min[2] },
+            );
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+}
@@ -0,0 +1,121 @@
+use cgmath::{InnerSpace, Vector2};
+
+// Stick travel that's ignored or treated as fully pushed. Sticks rarely rest at exactly zero or
+// reach exactly one, so without this the camera drifts and can't reach full speed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeadZone {
+    // Below this much deflection counts as none
+    pub inner: f32,
+    // Above this counts as all the way
+    pub outer: f32,
+}
+
+impl DeadZone {
+    pub const DEFAULT: DeadZone = DeadZone { inner: 0.15, outer: 0.95 };
+
+    pub fn new(inner: f32, outer: f32) -> Self {
+        Self { inner, outer }
+    }
+
+    // Rescales what's between inner and outer onto 0 to 1
+    pub fn apply_axis(&self, value: f32) -> f32 {
+        let range = (self.outer - self.inner).max(1e-6);
+        ((value.abs() - self.inner) / range).clamp(0.0, 1.0).copysign(value)
+    }
+
+    // Measured on the length of the stick's deflection rather than each axis, so diagonals aren't
+    // cut off and small movements along one axis don't snap to the other
+    pub fn apply(&self, stick: [f32; 2]) -> Vector2<f32> {
+        let stick = Vector2::from(stick);
+        let length = stick.magnitude();
+        if length <= self.inner {
+            return Vector2::new(0.0, 0.0);
+        }
+        stick * (self.apply_axis(length) / length)
+    }
+}
+
+impl Default for DeadZone {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// How a controller reads the gamepad
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GamepadSettings {
+    pub stick_dead_zone: DeadZone,
+    pub trigger_dead_zone: DeadZone,
+    // Radians per second with the look stick all the way over
+    pub look_speed: f32,
+    // Pushing the look stick up looks down, like a flight stick
+    pub invert_y: bool,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            stick_dead_zone: DeadZone::DEFAULT,
+            trigger_dead_zone: DeadZone::new(0.05, 1.0),
+            look_speed: 2.5,
+            invert_y: false,
+        }
+    }
+}
+
+// A gamepad's state at one moment, the raw values before any dead zone. Sticks go from -1 to 1
+// with +y up, triggers from 0 to 1. Can be filled in from any input library, or from Gamepads
+// with the gamepad feature
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadInput {
+    pub left_stick: [f32; 2],
+    pub right_stick: [f32; 2],
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    // The bumpers above the triggers
+    pub left_shoulder: bool,
+    pub right_shoulder: bool,
+}
+
+// Polls every connected gamepad through gilrs and reports the one that was used last
+#[cfg(feature = "gamepad")]
+pub struct Gamepads {
+    gilrs: gilrs::Gilrs,
+    active: Option<gilrs::GamepadId>,
+}
+
+#[cfg(feature = "gamepad")]
+impl Gamepads {
+    // None if the platform has no gamepad support
+    pub fn new() -> Option<Self> {
+        match gilrs::Gilrs::new() {
+            Ok(gilrs) => Some(Self { gilrs, active: None }),
+            Err(e) => {
+                log::warn!("Gamepads aren't available: {e}");
+                None
+            }
+        }
+    }
+
+    // Has to be called every frame, gilrs only updates its state as events are read. None while
+    // no gamepad is connected
+    pub fn poll(&mut self) -> Option<GamepadInput> {
+        while let Some(gilrs::Event { id, .. }) = self.gilrs.next_event() {
+            self.active = Some(id);
+        }
+        let gamepad = match self.active.and_then(|id| self.gilrs.connected_gamepad(id)) {
+            Some(gamepad) => gamepad,
+            // Fall back on any that's connected until one is touched
+            None => self.gilrs.gamepads().next()?.1,
+        };
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+        Some(GamepadInput {
+            left_stick: [gamepad.value(gilrs::Axis::LeftStickX), gamepad.value(gilrs::Axis::LeftStickY)],
+            right_stick: [gamepad.value(gilrs::Axis::RightStickX), gamepad.value(gilrs::Axis::RightStickY)],
+            left_trigger: trigger(gilrs::Button::LeftTrigger2),
+            right_trigger: trigger(gilrs::Button::RightTrigger2),
+            left_shoulder: gamepad.is_pressed(gilrs::Button::LeftTrigger),
+            right_shoulder: gamepad.is_pressed(gilrs::Button::RightTrigger),
+        })
+    }
+}
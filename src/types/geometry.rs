@@ -1,16 +1,28 @@
+use cgmath::{Matrix, Matrix3, Quaternion, SquareMatrix, Vector3, Zero};
 use crate::types::color::Color;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     pub position: [f32; 3],
-    pub color: Color
+    pub color: Color,
+    pub normal: [f32; 3],
+    pub tex_coords: [f32; 2],
+    // Along the texture's u, with w the sign the bitangent is flipped by. Zero if the mesh
+    // doesn't have them, normal maps then work out the tangents per pixel instead
+    pub tangent: [f32; 4],
 }
 
 impl Vertex {
+    // A vertex with no normal or texture coordinates, for hand-written geometry
+    pub const fn new(position: [f32; 3], color: Color) -> Vertex {
+        Vertex { position, color, normal: [0.0; 3], tex_coords: [0.0; 2], tangent: [0.0; 4] }
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
             attributes: &[
                 wgpu::VertexAttribute {
@@ -19,10 +31,161 @@ impl Vertex {
                     format: wgpu::VertexFormat::Float32x3,
                 },
                 wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<Color>() as wgpu::BufferAddress,
+                    offset: mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
                     shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 3]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 6]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 3,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 8]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 4,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ]
+        }
+    }
+}
+
+// A single placement of a mesh, drawn in the same call as every other instance
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    // World position in double precision, converted to f32 relative to the camera's origin when uploaded
+    pub position: Vector3<f64>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+    // Multiplied with the vertex colors
+    pub color: Color,
+    // Free for the shader to use, by default xyz is a highlight color mixed in by w
+    pub data: [f32; 4],
+    // Which layer of the material's texture array it's drawn with, see Material::with_texture_array
+    pub layer: u32,
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: Vector3::zero(),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            color: Color::new(1.0, 1.0, 1.0),
+            data: [0.0; 4],
+            layer: 0,
+        }
+    }
+}
+
+impl Instance {
+    pub fn has_uniform_scale(&self) -> bool {
+        let epsilon = 1e-6 * self.scale.x.abs().max(1.0);
+        (self.scale.x - self.scale.y).abs() <= epsilon && (self.scale.x - self.scale.z).abs() <= epsilon
+    }
+
+    // Transforms normals into world space. Rotation on its own does that as long as the scale is
+    // uniform, otherwise normals need the inverse transpose of the model matrix to stay perpendicular
+    pub fn normal_matrix(&self) -> Matrix3<f32> {
+        let rotation = Matrix3::from(self.rotation);
+        if self.has_uniform_scale() {
+            return rotation;
+        }
+        let model = rotation * Matrix3::from_diagonal(self.scale);
+        model.invert().map(|inverse| inverse.transpose()).unwrap_or(rotation)
+    }
+
+    // Subtracting the origin in f64 first keeps far away instances from jittering
+    pub fn to_raw(&self, origin: Vector3<f64>) -> InstanceRaw {
+        let relative = (self.position - origin).cast::<f32>().unwrap();
+        let model = cgmath::Matrix4::from_translation(relative)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z);
+        InstanceRaw {
+            model: model.into(),
+            color: self.color,
+            data: self.data,
+            normal: self.normal_matrix().into(),
+            layer: self.layer,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct InstanceRaw {
+    pub model: [[f32; 4]; 4],
+    pub color: Color,
+    pub data: [f32; 4],
+    pub normal: [[f32; 3]; 3],
+    pub layer: u32,
+}
+
+impl InstanceRaw {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            // Only advance to the next instance once every vertex has been processed
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                // A mat4 takes up 4 vertex slots, one for each column
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
+                    shader_location: 7,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+                    shader_location: 8,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 16]>() as wgpu::BufferAddress,
+                    shader_location: 9,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 16]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 10,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+                // The normal matrix is a mat3, so 3 more slots
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 20]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 11,
                     format: wgpu::VertexFormat::Float32x3,
-                }
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 23]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 12,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 26]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 13,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: (mem::size_of::<[f32; 29]>() + mem::size_of::<Color>()) as wgpu::BufferAddress,
+                    shader_location: 14,
+                    format: wgpu::VertexFormat::Uint32,
+                },
             ]
         }
     }
@@ -0,0 +1,138 @@
+use crate::types::quality::QualitySettings;
+
+// Parts of QualitySettings the governor can turn down, one step at a time
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QualityFeature {
+    Msaa,
+    RenderScale,
+    Anisotropy,
+    Shadows,
+    Ssao,
+    Bloom,
+}
+
+// Render scale is never taken below this
+const MIN_RENDER_SCALE: f32 = 0.5;
+const RENDER_SCALE_STEP: f32 = 0.125;
+
+impl QualityFeature {
+    // quality with this feature one step lower, None if it's already as low as it goes
+    pub fn step_down(self, quality: &QualitySettings) -> Option<QualitySettings> {
+        let mut lower = *quality;
+        match self {
+            Self::Msaa if quality.msaa_samples > 1 => lower.msaa_samples /= 2,
+            Self::RenderScale if quality.render_scale > MIN_RENDER_SCALE => {
+                lower.render_scale = (quality.render_scale - RENDER_SCALE_STEP).max(MIN_RENDER_SCALE);
+            }
+            Self::Anisotropy if quality.anisotropy > 1 => lower.anisotropy /= 2,
+            // Fewer cascades first, then smaller maps
+            Self::Shadows if quality.shadow_cascades > 1 => lower.shadow_cascades -= 1,
+            Self::Shadows if quality.shadow_resolution > 512 => lower.shadow_resolution /= 2,
+            Self::Ssao if quality.ssao => lower.ssao = false,
+            Self::Bloom if quality.bloom => lower.bloom = false,
+            _ => return None,
+        }
+        Some(lower)
+    }
+}
+
+// Watches how long frames take and lowers quality when they go over budget, raising it again once
+// there's room. Steps are spaced out by a cooldown and going back up needs frames well under budget,
+// so it doesn't flicker between two levels
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameGovernor {
+    // Seconds a frame should take
+    pub budget: f32,
+    // Features are turned down in this order and back up in reverse. Anything not listed is left alone
+    pub priorities: Vec<QualityFeature>,
+    // Quality only goes back up while frames take less than this fraction of the budget
+    pub headroom: f32,
+    // Seconds to wait after a step before taking another, giving the frame time a chance to settle
+    pub cooldown: f32,
+    // How quickly the average follows new frame times, per second
+    pub smoothing: f32,
+    // The features turned down so far, in order
+    steps: Vec<QualityFeature>,
+    average: Option<f32>,
+    since_step: f32,
+}
+
+impl FrameGovernor {
+    // MSAA goes first and resolution last, since it's the most visible. Shadows, SSAO and bloom
+    // aren't drawn by anything yet, so turning them down wouldn't buy any time
+    pub fn new(budget: f32) -> Self {
+        Self {
+            budget,
+            priorities: vec![QualityFeature::Msaa, QualityFeature::Anisotropy, QualityFeature::RenderScale],
+            headroom: 0.7,
+            cooldown: 1.0,
+            smoothing: 4.0,
+            steps: Vec::new(),
+            average: None,
+            since_step: 0.0,
+        }
+    }
+
+    // Budget for a frame rate, 60 gives about 16.7 ms
+    pub fn for_frame_rate(fps: f32) -> Self {
+        Self::new(1.0 / fps)
+    }
+
+    pub fn with_priorities(mut self, priorities: Vec<QualityFeature>) -> Self {
+        self.priorities = priorities;
+        self
+    }
+
+    // Smoothed frame time in seconds
+    pub fn average(&self) -> Option<f32> {
+        self.average
+    }
+
+    // Features currently turned down, a feature turned down twice shows up twice
+    pub fn steps(&self) -> &[QualityFeature] {
+        &self.steps
+    }
+
+    // Back to full quality, for when the quality it's degrading has changed
+    pub fn reset(&mut self) {
+        self.steps.clear();
+        self.average = None;
+        self.since_step = 0.0;
+    }
+
+    // quality with every step taken so far applied
+    pub fn degrade(&self, quality: &QualitySettings) -> QualitySettings {
+        self.steps
+            .iter()
+            .fold(*quality, |quality, feature| feature.step_down(&quality).unwrap_or(quality))
+    }
+
+    // Takes a frame's time in seconds and dt since the last call. Returns the quality to switch to
+    // when it decides to step, worked out from the full quality
+    pub fn update(&mut self, frame_time: f32, dt: f32, quality: &QualitySettings) -> Option<QualitySettings> {
+        let t = 1.0 - (-self.smoothing * dt).exp();
+        let average = match self.average {
+            Some(average) => average + (frame_time - average) * t,
+            None => frame_time,
+        };
+        self.average = Some(average);
+        self.since_step += dt;
+        if self.since_step < self.cooldown {
+            return None;
+        }
+
+        if average > self.budget {
+            let current = self.degrade(quality);
+            let feature = self.priorities.iter().copied().find(|feature| feature.step_down(&current).is_some())?;
+            log::info!("Frame time {:.1} ms is over budget, turning down {feature:?}", average * 1e3);
+            self.steps.push(feature);
+        } else if average < self.budget * self.headroom {
+            let feature = self.steps.pop()?;
+            log::info!("Frame time {:.1} ms has room, turning {feature:?} back up", average * 1e3);
+        } else {
+            return None;
+        }
+        self.since_step = 0.0;
+        Some(self.degrade(quality))
+    }
+}
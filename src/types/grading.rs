@@ -0,0 +1,352 @@
+use std::io;
+
+use wgpu::util::DeviceExt;
+
+use crate::types::texture::Texture;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// A 3D color lookup table. Each color of a finished frame is swapped for where it lands in the
+// cube, so a look made in a grading tool can be dropped in without touching any shaders. Inputs
+// and outputs are both sRGB encoded, the way grading tools export them
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorLut {
+    size: u32,
+    // size cubed, red changing fastest and blue slowest like .cube files
+    entries: Vec<[f32; 3]>,
+    // The input colors the cube's first and last entries stand for
+    domain: [[f32; 3]; 2],
+}
+
+impl ColorLut {
+    // What .cube files are allowed to have across each side
+    pub const MAX_SIZE: u32 = 256;
+
+    // Every entry worked out from the input color it stands for, each channel 0 to 1
+    pub fn from_fn(size: u32, f: impl Fn([f32; 3]) -> [f32; 3]) -> Self {
+        let size = size.clamp(2, Self::MAX_SIZE);
+        let step = 1.0 / (size - 1) as f32;
+        let entries = (0..size)
+            .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| [r, g, b])))
+            .map(|rgb| f(rgb.map(|c| c as f32 * step)))
+            .collect();
+        Self { size, entries, domain: [[0.0; 3], [1.0; 3]] }
+    }
+
+    // Leaves every color as it is, handy for checking that grading itself doesn't shift anything
+    pub fn identity(size: u32) -> Self {
+        Self::from_fn(size, |rgb| rgb)
+    }
+
+    // The text format most grading tools export, with a LUT_3D_SIZE and an optional DOMAIN_MIN and
+    // DOMAIN_MAX. TITLE and any other keywords are skipped
+    pub fn parse_cube(text: &str) -> io::Result<Self> {
+        let mut size = None;
+        let mut domain = [[0.0; 3], [1.0; 3]];
+        let mut entries = Vec::new();
+        let floats = |words: std::str::SplitWhitespace, line: usize| {
+            words.map(|word| word.parse::<f32>().map_err(|_| invalid(format!("line {line}: {word:?} isn't a number")))).collect::<io::Result<Vec<_>>>()
+        };
+        let rgb = |values: Vec<f32>, line: usize| match values[..] {
+            [r, g, b] => Ok([r, g, b]),
+            _ => Err(invalid(format!("line {line}: expected 3 numbers, found {}", values.len()))),
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let first = words.clone().next().unwrap_or_default();
+            if !first.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                entries.push(rgb(floats(words, line_number)?, line_number)?);
+                continue;
+            }
+            words.next();
+            match first {
+                "LUT_3D_SIZE" => {
+                    let value = words.next().and_then(|word| word.parse::<u32>().ok()).filter(|size| (2..=Self::MAX_SIZE).contains(size));
+                    size = Some(value.ok_or_else(|| invalid(format!("line {line_number}: LUT_3D_SIZE has to be 2 to {}", Self::MAX_SIZE)))?);
+                }
+                "LUT_1D_SIZE" => return Err(io::Error::new(io::ErrorKind::Unsupported, "1D LUTs aren't supported, only 3D ones")),
+                "DOMAIN_MIN" => domain[0] = rgb(floats(words, line_number)?, line_number)?,
+                "DOMAIN_MAX" => domain[1] = rgb(floats(words, line_number)?, line_number)?,
+                // Resolve's older way of writing the same domain for all three channels
+                "LUT_3D_INPUT_RANGE" => match floats(words, line_number)?[..] {
+                    [min, max] => domain = [[min; 3], [max; 3]],
+                    _ => return Err(invalid(format!("line {line_number}: LUT_3D_INPUT_RANGE needs a min and max"))),
+                },
+                _ => {}
+            }
+        }
+        let size = size.ok_or_else(|| invalid("no LUT_3D_SIZE"))?;
+        if entries.len() != size.pow(3) as usize {
+            return Err(invalid(format!("a {size} LUT needs {} entries, found {}", size.pow(3), entries.len())));
+        }
+        if (0..3).any(|c| domain[1][c] <= domain[0][c]) {
+            return Err(invalid("DOMAIN_MAX has to be above DOMAIN_MIN"));
+        }
+        Ok(Self { size, entries, domain })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_cube(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        Self::parse_cube(&std::fs::read_to_string(path)?)
+    }
+
+    // The strip images game engines use: size square slices side by side, blue going up a slice at
+    // a time from the left, red across each slice and green down it. A 16 LUT is 256x16
+    pub fn from_strip(image: &image::RgbaImage) -> io::Result<Self> {
+        let (width, height) = image.dimensions();
+        if !(2..=Self::MAX_SIZE).contains(&height) || width != height * height {
+            return Err(invalid(format!("a LUT strip is as many squares as it is tall side by side, not {width}x{height}")));
+        }
+        let size = height;
+        let entries = (0..size)
+            .flat_map(|b| (0..size).flat_map(move |g| (0..size).map(move |r| (b * size + r, g))))
+            .map(|(x, y)| {
+                let [r, g, b, _] = image.get_pixel(x, y).0;
+                [r, g, b].map(|c| c as f32 / 255.0)
+            })
+            .collect();
+        Ok(Self { size, entries, domain: [[0.0; 3], [1.0; 3]] })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_strip(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let image = image::open(path).map_err(|e| invalid(e.to_string()))?.to_rgba8();
+        Self::from_strip(&image)
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn domain(&self) -> [[f32; 3]; 2] {
+        self.domain
+    }
+
+    // Where an sRGB encoded color lands, blended between the nearest entries the same way the GPU
+    // filters them. For checking a table without drawing anything
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let position: [f32; 3] = std::array::from_fn(|c| {
+            let [min, max] = [self.domain[0][c], self.domain[1][c]];
+            ((color[c] - min) / (max - min)).clamp(0.0, 1.0) * last
+        });
+        let low = position.map(|p| (p.floor() as u32).min(self.size - 2));
+        let t: [f32; 3] = std::array::from_fn(|c| position[c] - low[c] as f32);
+        let entry = |dr: u32, dg: u32, db: u32| {
+            let [r, g, b] = [low[0] + dr, low[1] + dg, low[2] + db];
+            self.entries[((b * self.size + g) * self.size + r) as usize]
+        };
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| std::array::from_fn::<f32, 3, _>(|c| a[c] + (b[c] - a[c]) * t);
+        let bottom = lerp(lerp(entry(0, 0, 0), entry(1, 0, 0), t[0]), lerp(entry(0, 1, 0), entry(1, 1, 0), t[0]), t[1]);
+        let top = lerp(lerp(entry(0, 0, 1), entry(1, 0, 1), t[0]), lerp(entry(0, 1, 1), entry(1, 1, 1), t[0]), t[1]);
+        lerp(bottom, top, t[2])
+    }
+
+    // Uploaded as 16 bit floats, so tables that reach past 1 keep it
+    fn texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let size = wgpu::Extent3d { width: self.size, height: self.size, depth_or_array_layers: self.size };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("color_grading_lut"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: Texture::HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let texels = self.entries.iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 1.0])
+            .map(|value| half::f16::from_f32(value.clamp(-65504.0, 65504.0)).to_bits())
+            .collect::<Vec<u16>>();
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(8 * self.size), rows_per_image: Some(self.size) },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Texture { texture, view }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradingUniform {
+    // Strength in w
+    domain_min: [f32; 4],
+    // The table's size in w
+    domain_max: [f32; 4],
+}
+
+// The frame the scene is drawn into while grading is on
+struct Target {
+    size: [u32; 2],
+    texture: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// The last pass of a frame, drawing the scene's colors through a ColorLut onto the real target.
+// Created by SceneRenderer::set_color_grading
+pub(crate) struct ColorGrading {
+    format: wgpu::TextureFormat,
+    size: u32,
+    domain: [[f32; 3]; 2],
+    shader: wgpu::ShaderModule,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    // Made on the first prepare after a format change
+    pipeline: Option<wgpu::RenderPipeline>,
+    lut: Texture,
+    sampler: wgpu::Sampler,
+    uniform: wgpu::Buffer,
+    // Made by prepare, and again when the size changes
+    target: Option<Target>,
+}
+
+impl ColorGrading {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat, lut: &ColorLut) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Color Grading Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../grading.wgsl").into()) });
+        let texture_entry = |binding, view_dimension, filterable| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture { sample_type: wgpu::TextureSampleType::Float { filterable }, view_dimension, multisampled: false },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("color_grading_bind_group_layout"),
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::D2, false),
+                texture_entry(1, wgpu::TextureViewDimension::D3, true),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Color Grading Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        // Between entries is where the table's filtering happens, so it's linear in all three
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Color Grading Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Color Grading Buffer"),
+            contents: bytemuck::bytes_of(&GradingUniform { domain_min: [0.0; 4], domain_max: [0.0; 4] }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            format,
+            size: lut.size,
+            domain: lut.domain,
+            shader,
+            layout,
+            pipeline_layout,
+            pipeline: None,
+            lut: lut.texture(device, queue),
+            sampler,
+            uniform,
+            target: None,
+        }
+    }
+
+    // The pipeline and the frame the scene draws into are both made again on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipeline = None;
+        self.target = None;
+    }
+
+    // strength blends from the scene as it is at 0 to fully graded at 1
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2], strength: f32) {
+        let [min, max] = self.domain;
+        let uniform = GradingUniform {
+            domain_min: [min[0], min[1], min[2], strength.clamp(0.0, 1.0)],
+            domain_max: [max[0], max[1], max[2], self.size as f32],
+        };
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&uniform));
+        if self.target.as_ref().is_none_or(|target| target.size != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("color_grading_frame"),
+                size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let texture = Texture { texture, view };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("color_grading_bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture.view) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&self.lut.view) },
+                    wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                    wgpu::BindGroupEntry { binding: 3, resource: self.uniform.as_entire_binding() },
+                ],
+            });
+            self.target = Some(Target { size, texture, bind_group });
+        }
+        if self.pipeline.is_some() {
+            return;
+        }
+        self.pipeline = Some(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Color Grading Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+    }
+
+    // What the scene's passes draw into instead of the target they were given
+    pub fn frame_view(&self) -> &wgpu::TextureView {
+        &self.target.as_ref().expect("ColorGrading::prepare wasn't called").texture.view
+    }
+
+    // Draws into a pass over the real target, covering all of it
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let (Some(target), Some(pipeline)) = (&self.target, &self.pipeline) else { return };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
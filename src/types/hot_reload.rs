@@ -0,0 +1,59 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+use notify::Watcher;
+
+// Where the crate's own shaders are, for watching them while working on the renderer itself
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+// Reports WGSL files in a folder as they're saved, see State::watch_shaders
+pub struct ShaderWatcher {
+    // Stops watching when it's dropped
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::Receiver<PathBuf>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: impl AsRef<Path>) -> notify::Result<Self> {
+        let (sender, changes) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else { return };
+            // Editors often save by writing a new file and renaming it over the old one
+            if !(event.kind.is_modify() || event.kind.is_create()) {
+                return;
+            }
+            for path in event.paths {
+                if path.extension().is_some_and(|extension| extension == "wgsl") {
+                    let _ = sender.send(path);
+                }
+            }
+        })?;
+        watcher.watch(dir.as_ref(), notify::RecursiveMode::NonRecursive)?;
+        Ok(Self { _watcher: watcher, changes })
+    }
+
+    // Files saved since the last call, each once however many events it got
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for path in self.changes.try_iter() {
+            if !changed.contains(&path) {
+                changed.push(path);
+            }
+        }
+        changed
+    }
+}
+
+// Runs build with validation errors caught instead of panicking. Errors come back as wgpu's
+// message, which for shaders includes where in the source it went wrong
+pub fn validated<T>(device: &wgpu::Device, build: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = build();
+    match pollster::block_on(device.pop_error_scope()) {
+        None => Ok(built),
+        Some(wgpu::Error::Validation { description, .. }) => Err(description),
+        Some(error) => Err(error.to_string()),
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use cgmath::Point3;
+
+use crate::types::{
+    color::Color,
+    geometry::{Vertex, InstanceRaw},
+    mesh::{FaceSettings, Mesh},
+    shader_features,
+    texture::Texture,
+};
+
+// How the hovered mesh instance stands out
+#[derive(Clone, Copy, Debug)]
+pub struct HoverSettings {
+    // Blended over the instance, alpha is how strongly
+    pub tint: Color,
+    pub outline_color: Color,
+    // In pixels, 0 for no outline
+    pub outline_width: f32,
+    // The dot where the ray hit, alpha 0 hides it
+    pub marker_color: Color,
+    // Across, in pixels
+    pub marker_size: f32,
+}
+
+impl Default for HoverSettings {
+    fn default() -> Self {
+        Self {
+            tint: Color::new_rgba(1.0, 0.8, 0.2, 0.25),
+            outline_color: Color::new_rgba(1.0, 0.7, 0.0, 1.0),
+            outline_width: 2.0,
+            marker_color: Color::new_rgba(1.0, 1.0, 1.0, 1.0),
+            marker_size: 10.0,
+        }
+    }
+}
+
+impl HoverSettings {
+    pub fn with_tint(mut self, tint: Color) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn with_outline(mut self, color: Color, width: f32) -> Self {
+        self.outline_color = color;
+        self.outline_width = width;
+        self
+    }
+
+    pub fn with_marker(mut self, color: Color, size: f32) -> Self {
+        self.marker_color = color;
+        self.marker_size = size;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct HoverUniform {
+    tint: [f32; 4],
+    outline_color: [f32; 4],
+    marker_color: [f32; 4],
+    // xyz relative to the origin, w 1 if the marker is drawn
+    marker_position: [f32; 4],
+    // Outline width, marker size, view width and height
+    sizes: [f32; 4],
+}
+
+impl HoverUniform {
+    fn new(settings: &HoverSettings, point: Option<Point3<f32>>, view_size: [u32; 2]) -> Self {
+        let premultiplied = |color: Color| {
+            let [r, g, b, a] = color.buffer();
+            [r * a, g * a, b * a, a]
+        };
+        let marker_position = match point {
+            Some(point) if settings.marker_size > 0.0 => [point.x, point.y, point.z, 1.0],
+            _ => [0.0; 4],
+        };
+        Self {
+            tint: premultiplied(settings.tint),
+            outline_color: premultiplied(settings.outline_color),
+            marker_color: premultiplied(settings.marker_color),
+            marker_position,
+            sizes: [settings.outline_width.max(0.0), settings.marker_size.max(0.0), view_size[0].max(1) as f32, view_size[1].max(1) as f32],
+        }
+    }
+}
+
+// Draws the tint, outline and marker over whatever's hovered, at the end of each view's pass.
+// Created by SceneRenderer the first time something is hovered
+pub struct Hover {
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    hover_layout: wgpu::BindGroupLayout,
+    // By the mesh's faces, depth direction and sample count
+    tint_pipelines: HashMap<(FaceSettings, bool, u32), wgpu::RenderPipeline>,
+    outline_pipelines: HashMap<(wgpu::FrontFace, bool, u32), wgpu::RenderPipeline>,
+    // By sample count, drawn over everything
+    marker_pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    // One uniform per view, since the pixel sizes depend on the view's size
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    stride: wgpu::BufferAddress,
+    views: usize,
+    // Skipped when it's 0 wide, the back faces would only fight with the front ones
+    outline: bool,
+}
+
+impl Hover {
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Hover Shader"), source: wgpu::ShaderSource::Wgsl(shader_features::compose(include_str!("../hover.wgsl")).into()) });
+        let hover_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("hover_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                // Offset to the view being drawn
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<HoverUniform>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Hover Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &hover_layout],
+            push_constant_ranges: &[],
+        });
+        let stride = (device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress).max(std::mem::size_of::<HoverUniform>() as u64);
+        let (buffer, bind_group) = Self::create_buffer(device, &hover_layout, stride, 1);
+        Self {
+            format,
+            shader,
+            layout,
+            hover_layout,
+            tint_pipelines: HashMap::new(),
+            outline_pipelines: HashMap::new(),
+            marker_pipelines: HashMap::new(),
+            buffer,
+            bind_group,
+            stride,
+            views: 1,
+            outline: true,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, stride: wgpu::BufferAddress, views: usize) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Hover Buffer"),
+            size: stride * views as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("hover_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<HoverUniform>() as u64),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.tint_pipelines.clear();
+        self.outline_pipelines.clear();
+        self.marker_pipelines.clear();
+    }
+
+    fn depth_compare(reversed_z: bool) -> wgpu::CompareFunction {
+        if reversed_z { wgpu::CompareFunction::GreaterEqual } else { wgpu::CompareFunction::LessEqual }
+    }
+
+    fn blend_target(&self) -> [Option<wgpu::ColorTargetState>; 1] {
+        [Some(wgpu::ColorTargetState {
+            format: self.format,
+            blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        })]
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn create_pipeline(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+        vertex: &str,
+        fragment: &str,
+        mesh: bool,
+        primitive: wgpu::PrimitiveState,
+        depth_stencil: wgpu::DepthStencilState,
+        sample_count: u32,
+    ) -> wgpu::RenderPipeline {
+        let buffers = [Vertex::desc(), InstanceRaw::desc()];
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: vertex,
+                buffers: if mesh { &buffers } else { &[] },
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: fragment,
+                targets: &self.blend_target(),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive,
+            depth_stencil: Some(depth_stencil),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    // Writes each view's uniform and builds the pipelines the coming passes need, render passes
+    // can't. point is where the ray hit, relative to the origin. pipelines is the faces the mesh
+    // is drawn with and the depth direction, for each view
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        settings: &HoverSettings,
+        point: Option<Point3<f32>>,
+        view_sizes: &[[u32; 2]],
+        sample_count: u32,
+        pipelines: impl IntoIterator<Item = (FaceSettings, bool)>,
+    ) {
+        if view_sizes.len() > self.views {
+            (self.buffer, self.bind_group) = Self::create_buffer(device, &self.hover_layout, self.stride, view_sizes.len());
+            self.views = view_sizes.len();
+        }
+        self.outline = settings.outline_width > 0.0;
+        for (i, &size) in view_sizes.iter().enumerate() {
+            queue.write_buffer(&self.buffer, i as u64 * self.stride, bytemuck::bytes_of(&HoverUniform::new(settings, point, size)));
+        }
+        for (faces, reversed_z) in pipelines {
+            if !self.tint_pipelines.contains_key(&(faces, reversed_z, sample_count)) {
+                let pipeline = self.create_pipeline(
+                    device,
+                    &format!("Hover Tint Pipeline ({sample_count} samples)"),
+                    "vs_tint",
+                    "fs_tint",
+                    true,
+                    // Culled like the mesh itself, so only what was drawn gets tinted
+                    wgpu::PrimitiveState { front_face: faces.front_face, cull_mode: faces.cull_mode, ..Default::default() },
+                    // Pulled a little towards the camera so it wins against the mesh's own depth
+                    wgpu::DepthStencilState {
+                        format: Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: Self::depth_compare(reversed_z),
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState { constant: if reversed_z { 2 } else { -2 }, slope_scale: if reversed_z { 1.0 } else { -1.0 }, clamp: 0.0 },
+                    },
+                    sample_count,
+                );
+                self.tint_pipelines.insert((faces, reversed_z, sample_count), pipeline);
+            }
+            if !self.outline_pipelines.contains_key(&(faces.front_face, reversed_z, sample_count)) {
+                let pipeline = self.create_pipeline(
+                    device,
+                    &format!("Hover Outline Pipeline ({sample_count} samples)"),
+                    "vs_outline",
+                    "fs_outline",
+                    true,
+                    // Only the back faces, pushed out past the edge
+                    wgpu::PrimitiveState { front_face: faces.front_face, cull_mode: Some(wgpu::Face::Front), ..Default::default() },
+                    wgpu::DepthStencilState {
+                        format: Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: Self::depth_compare(reversed_z),
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    },
+                    sample_count,
+                );
+                self.outline_pipelines.insert((faces.front_face, reversed_z, sample_count), pipeline);
+            }
+        }
+        if !self.marker_pipelines.contains_key(&sample_count) {
+            let pipeline = self.create_pipeline(
+                device,
+                &format!("Hover Marker Pipeline ({sample_count} samples)"),
+                "vs_marker",
+                "fs_marker",
+                false,
+                wgpu::PrimitiveState::default(),
+                // Always in front, the hit point is on the surface and would fight with it
+                wgpu::DepthStencilState {
+                    format: Texture::DEPTH_FORMAT,
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Always,
+                    stencil: wgpu::StencilState::default(),
+                    bias: wgpu::DepthBiasState::default(),
+                },
+                sample_count,
+            );
+            self.marker_pipelines.insert(sample_count, pipeline);
+        }
+    }
+
+    // Inside view's pass, once the scene is drawn. Binds group 1, so anything drawn after has to
+    // bind its own again
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        view: usize,
+        mesh: &'a Mesh,
+        instance: usize,
+        faces: FaceSettings,
+        sample_count: u32,
+        reversed_z: bool,
+    ) {
+        render_pass.push_debug_group("Hover");
+        render_pass.set_bind_group(1, &self.bind_group, &[(view as u64 * self.stride) as u32]);
+        if self.outline {
+            render_pass.set_pipeline(&self.outline_pipelines[&(faces.front_face, reversed_z, sample_count)]);
+            mesh.draw_instance(render_pass, instance);
+        }
+        render_pass.set_pipeline(&self.tint_pipelines[&(faces, reversed_z, sample_count)]);
+        mesh.draw_instance(render_pass, instance);
+        render_pass.set_pipeline(&self.marker_pipelines[&sample_count]);
+        render_pass.draw(0..6, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
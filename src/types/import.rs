@@ -0,0 +1,156 @@
+use std::{io, path::Path};
+use crate::types::{color::Color, frame::CoordinateFrame, geometry::Vertex, mesh::{FaceSettings, MeshData}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindingCorrection {
+    // Leave the triangles as they are in the file
+    Keep,
+    // Reverse every triangle
+    Flip,
+    // Work out which way the mesh is wound and flip it if it doesn't match front_face
+    Detect,
+}
+
+// What one unit in the source file is, the crate works in meters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Units {
+    Meters,
+    Centimeters,
+    Millimeters,
+    Inches,
+    Feet,
+    // Meters per file unit
+    Custom(f32),
+}
+
+impl Units {
+    pub fn to_meters(&self) -> f32 {
+        match self {
+            Units::Meters => 1.0,
+            Units::Centimeters => 0.01,
+            Units::Millimeters => 0.001,
+            Units::Inches => 0.0254,
+            Units::Feet => 0.3048,
+            Units::Custom(scale) => *scale,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImportOptions {
+    // The axis conventions of the source file
+    pub frame: CoordinateFrame,
+    // The axis conventions of the scene the mesh is loaded into
+    pub target_frame: CoordinateFrame,
+    pub units: Units,
+    // Negates x, y and z respectively, applied after the up axis conversion
+    pub mirror: [bool; 3],
+    pub winding: WindingCorrection,
+    // The winding the mesh will be drawn with, so the crate's default unless it's drawn with other face settings
+    pub front_face: wgpu::FrontFace,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            frame: CoordinateFrame::Y_UP_RH,
+            target_frame: CoordinateFrame::Y_UP_RH,
+            units: Units::Meters,
+            mirror: [false; 3],
+            winding: WindingCorrection::Detect,
+            front_face: FaceSettings::DEFAULT.front_face,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn apply(&self, mesh: &mut MeshData) {
+        let scale = self.units.to_meters();
+        let mirror = self.mirror.map(|m| if m { -1.0 } else { 1.0 });
+        let convert = |v: [f32; 3]| {
+            let v = self.frame.convert(v, &self.target_frame);
+            [v[0] * mirror[0], v[1] * mirror[1], v[2] * mirror[2]]
+        };
+        // Mirroring an odd number of axes turns every triangle inside out, and so does
+        // switching handedness
+        let mirrors = self.mirror.iter().filter(|&&m| m).count() + (self.frame.is_mirrored() != self.target_frame.is_mirrored()) as usize;
+        for vertex in &mut mesh.vertices {
+            vertex.position = convert(vertex.position).map(|x| x * scale);
+            vertex.normal = convert(vertex.normal);
+            // The bitangent is a cross product, which comes out the other way round in a mirror
+            let [x, y, z, w] = vertex.tangent;
+            let [x, y, z] = convert([x, y, z]);
+            vertex.tangent = [x, y, z, if mirrors % 2 == 1 { -w } else { w }];
+        }
+        if mirrors % 2 == 1 {
+            mesh.flip_winding();
+        }
+
+        match self.winding {
+            WindingCorrection::Keep => {}
+            WindingCorrection::Flip => mesh.flip_winding(),
+            WindingCorrection::Detect => {
+                // Left handed scenes are drawn mirrored, which reverses the winding on screen
+                let mut faces = FaceSettings { front_face: self.front_face, cull_mode: None };
+                if self.target_frame.is_mirrored() {
+                    faces = faces.mirrored();
+                }
+                if mesh.fix_winding(faces.front_face) {
+                    log::info!("Flipped the winding of an imported mesh to match its front face");
+                }
+            }
+        }
+    }
+}
+
+// Loads every model in an OBJ file into a single mesh, corrected according to the options
+pub fn load_obj(path: &Path, options: &ImportOptions) -> io::Result<MeshData> {
+    let mut mesh = load_obj_raw(path)?;
+    mesh.generate_tangents();
+    options.apply(&mut mesh);
+    Ok(mesh)
+}
+
+// Same as load_obj, but goes through the binary mesh cache so only the first load is slow.
+// The cache holds the file as it is, so changing the options doesn't invalidate it. Only OBJ is
+// cached for now since there's no glTF importer yet, one would go through MeshData::load_cached
+// the same way
+pub fn load_obj_cached(path: &Path, options: &ImportOptions) -> io::Result<MeshData> {
+    let mut mesh = MeshData::load_cached(path, load_obj_raw)?;
+    mesh.generate_tangents();
+    options.apply(&mut mesh);
+    Ok(mesh)
+}
+
+fn load_obj_raw(path: &Path) -> io::Result<MeshData> {
+    let (models, _materials) = tobj::load_obj(path, &tobj::LoadOptions {
+        single_index: true,
+        triangulate: true,
+        ..Default::default()
+    }).map_err(io::Error::other)?;
+
+    let mut mesh = MeshData::default();
+    for model in models {
+        let m = &model.mesh;
+        let base = mesh.vertices.len() as u32;
+        for i in 0..m.positions.len() / 3 {
+            let color = if m.vertex_color.len() >= i * 3 + 3 {
+                Color::new(m.vertex_color[i * 3], m.vertex_color[i * 3 + 1], m.vertex_color[i * 3 + 2])
+            } else {
+                Color::new(1.0, 1.0, 1.0)
+            };
+            let mut vertex = Vertex::new([m.positions[i * 3], m.positions[i * 3 + 1], m.positions[i * 3 + 2]], color);
+            if m.normals.len() >= i * 3 + 3 {
+                vertex.normal = [m.normals[i * 3], m.normals[i * 3 + 1], m.normals[i * 3 + 2]];
+            }
+            if m.texcoords.len() >= i * 2 + 2 {
+                // OBJ puts v = 0 at the bottom of the image, wgpu puts it at the top
+                vertex.tex_coords = [m.texcoords[i * 2], 1.0 - m.texcoords[i * 2 + 1]];
+            }
+            mesh.vertices.push(vertex);
+        }
+        mesh.indices.extend(m.indices.iter().map(|index| base + index));
+    }
+
+    Ok(mesh)
+}
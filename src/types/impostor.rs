@@ -0,0 +1,551 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Rotation, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::scene::SceneRenderer;
+use crate::types::{
+    camera::{CameraMatrices, CameraUniform, OPENGL_TO_WGPU_MATRIX},
+    geometry::{Instance, InstanceRaw},
+    mesh::{FaceSettings, Mesh},
+    shader_features::{self, ShaderFeatures},
+    picking::MeshId,
+    tint::MeshTints,
+    material::{MaterialKey, Materials},
+    oit::TransparencyMode,
+    texture::Texture,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImpostorSettings {
+    // Pixels along each side of one instance's picture
+    pub tile_size: u32,
+    // Tiles along each side of the atlas, so tiles * tiles instances can be impostors at once.
+    // Any more are drawn in full
+    pub tiles: u32,
+    // Radians the view of an instance can swing through before its picture is baked again
+    pub rebake_angle: f32,
+    // Most pictures baked in one frame, the rest keep their old picture or are drawn in full
+    // until their turn comes
+    pub bakes_per_frame: usize,
+}
+
+impl Default for ImpostorSettings {
+    fn default() -> Self {
+        Self { tile_size: 128, tiles: 16, rebake_angle: 0.1, bakes_per_frame: 16 }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct BillboardRaw {
+    // Center and radius
+    sphere: [f32; 4],
+    // Left top right bottom in the atlas
+    rect: [f32; 4],
+}
+
+impl BillboardRaw {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<BillboardRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+// An instance's picture in the atlas and what it was baked from
+struct Tile {
+    slot: u32,
+    // Towards the eye and the camera's up at the time, in the instance's own space so turning
+    // the instance counts as much as moving the camera
+    towards: Vector3<f32>,
+    up: Vector3<f32>,
+    // Anything else that changes how it looks means baking again
+    color: [f32; 4],
+    data: [f32; 4],
+    scale: Vector3<f32>,
+    // Still wanted this frame
+    used: bool,
+}
+
+impl Tile {
+    fn new(slot: u32, instance: &Instance, towards: Vector3<f32>, up: Vector3<f32>) -> Self {
+        Self { slot, towards, up, color: instance.color.buffer(), data: instance.data, scale: instance.scale, used: true }
+    }
+}
+
+// Where each mesh with impostors got split this frame
+struct MeshSplit {
+    // The instances still drawn in full
+    near_buffer: wgpu::Buffer,
+    near_count: u32,
+}
+
+struct Bake {
+    mesh: usize,
+    instance: usize,
+    slot: u32,
+    uniform: CameraUniform,
+    raw: InstanceRaw,
+}
+
+struct BakeCamera {
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+// Swaps far off instances of meshes with an impostor distance for flat pictures of themselves,
+// baked into a shared atlas from roughly where the camera is looking from. Each picture is
+// redone once the view of it has turned by more than rebake_angle, so a forest of thousands of
+// trees costs a handful of tree draws per frame plus two triangles a tree. Created by
+// SceneRenderer the first time a mesh needs it, only static meshes get impostors
+pub struct Impostors {
+    settings: ImpostorSettings,
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    // By sample count and depth direction
+    pipelines: HashMap<(u32, bool), wgpu::RenderPipeline>,
+    // Drawn with the scene's shader into a tile sized target, one sample and standard depth
+    bake_pipelines: HashMap<(FaceSettings, MaterialKey), wgpu::RenderPipeline>,
+    atlas: wgpu::Texture,
+    atlas_bind_group: wgpu::BindGroup,
+    bake_target: Texture,
+    bake_depth: Texture,
+    bake_cameras: Vec<BakeCamera>,
+    // bakes_per_frame instances, one for each bake
+    bake_instances: wgpu::Buffer,
+    // Keyed by mesh and instance
+    tiles: HashMap<(usize, usize), Tile>,
+    free_slots: Vec<u32>,
+    splits: HashMap<usize, MeshSplit>,
+    billboard_buffer: wgpu::Buffer,
+    billboard_count: u32,
+}
+
+impl Impostors {
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, settings: ImpostorSettings) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Impostor Shader"), source: wgpu::ShaderSource::Wgsl(shader_features::compose(include_str!("../impostor.wgsl")).into()) });
+
+        let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("impostor_atlas_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Impostor Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &atlas_layout],
+            push_constant_ranges: &[],
+        });
+
+        let tile_size = settings.tile_size.max(1);
+        let tiles = settings.tiles.max(1);
+        let atlas = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Impostor Atlas"),
+            size: wgpu::Extent3d { width: tile_size * tiles, height: tile_size * tiles, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Impostor Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("impostor_atlas_bind_group"),
+            layout: &atlas_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&atlas.create_view(&Default::default())) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) },
+            ],
+        });
+
+        let bake_target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Impostor Bake Target"),
+            size: wgpu::Extent3d { width: tile_size, height: tile_size, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let bake_target = Texture { view: bake_target.create_view(&Default::default()), texture: bake_target };
+        let bake_depth = Texture::create_depth_texture(device, [tile_size; 2], 1, "Impostor Bake Depth");
+
+        let bakes = settings.bakes_per_frame.max(1);
+        let bake_cameras = (0..bakes).map(|i| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Impostor Bake {i} Camera Buffer")),
+                contents: bytemuck::bytes_of(&CameraUniform::new()),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("impostor_bake_{i}_camera_bind_group")),
+                layout: camera_layout,
+                entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+            });
+            BakeCamera { buffer, bind_group }
+        }).collect();
+        let bake_instances = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Impostor Bake Instance Buffer"),
+            size: (bakes * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            settings: ImpostorSettings { tile_size, tiles, bakes_per_frame: bakes, ..settings },
+            format,
+            shader,
+            layout,
+            pipelines: HashMap::new(),
+            bake_pipelines: HashMap::new(),
+            atlas,
+            atlas_bind_group,
+            bake_target,
+            bake_depth,
+            bake_cameras,
+            bake_instances,
+            tiles: HashMap::new(),
+            // Popped from the back, so handed out from 0 up
+            free_slots: (0..tiles * tiles).rev().collect(),
+            splits: HashMap::new(),
+            billboard_buffer: Self::create_billboard_buffer(device, 64),
+            billboard_count: 0,
+        }
+    }
+
+    fn create_billboard_buffer(device: &wgpu::Device, count: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Impostor Billboard Buffer"),
+            size: (count.max(1) * std::mem::size_of::<BillboardRaw>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    pub fn settings(&self) -> ImpostorSettings {
+        self.settings
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    // How many instances have a picture in the atlas
+    pub fn tile_count(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Drops a mesh's pictures, for when it's removed or its geometry changes
+    pub fn forget(&mut self, mesh: usize) {
+        let free_slots = &mut self.free_slots;
+        self.tiles.retain(|&(id, _), tile| {
+            if id == mesh {
+                free_slots.push(tile.slot);
+            }
+            id != mesh
+        });
+        self.splits.remove(&mesh);
+    }
+
+    // Sorts every instance of meshes with an impostor distance into near and far as seen from
+    // matrices, bakes the pictures that are missing or out of date and uploads what draw needs.
+    // Positions are relative to origin, like the instances the meshes were uploaded with
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        meshes: &[Option<Mesh>],
+        shaders: &HashMap<ShaderFeatures, wgpu::ShaderModule>,
+        materials: &Materials,
+        lights: &wgpu::BindGroup,
+        tints: &MeshTints,
+        layout: &wgpu::PipelineLayout,
+        matrices: &CameraMatrices,
+        origin: Vector3<f64>,
+    ) {
+        let eye = matrices.inverse_view.w.truncate();
+        let camera_up = matrices.inverse_view.y.truncate().normalize();
+        let camera_right = matrices.inverse_view.x.truncate().normalize();
+        let cos_limit = self.settings.rebake_angle.cos();
+        let tiles_per_side = self.settings.tiles as f32;
+
+        for tile in self.tiles.values_mut() {
+            tile.used = false;
+        }
+        let mut billboards = Vec::new();
+        let mut bakes = Vec::new();
+        let mut splits = HashMap::new();
+        for (id, mesh) in meshes.iter().enumerate() {
+            let Some(mesh) = mesh.as_ref().filter(|mesh| mesh.visible) else { continue };
+            let Some(distance) = mesh.impostor_distance else { continue };
+            let (min, max) = mesh.data().bounds();
+            let center = (Vector3::from(min) + Vector3::from(max)) * 0.5;
+            let radius = (Vector3::from(max) - center).magnitude();
+            if radius <= 0.0 {
+                continue;
+            }
+
+            let mut near = Vec::new();
+            for (index, instance) in mesh.instances().iter().enumerate() {
+                let raw = instance.to_raw(origin);
+                let middle = (Matrix4::from(raw.model) * center.extend(1.0)).truncate();
+                let scale = instance.scale.x.abs().max(instance.scale.y.abs()).max(instance.scale.z.abs());
+                let to_eye = eye - middle;
+                if to_eye.magnitude() <= distance {
+                    near.push(raw);
+                    continue;
+                }
+
+                let towards = to_eye.normalize();
+                let right = camera_up.cross(towards);
+                let right = if right.magnitude2() < 1e-6 { camera_right } else { right.normalize() };
+                let up = towards.cross(right);
+                let inverse = instance.rotation.invert();
+                let (local_towards, local_up) = (inverse.rotate_vector(towards), inverse.rotate_vector(up));
+
+                let key = (id, index);
+                let can_bake = bakes.len() < self.settings.bakes_per_frame;
+                let slot = if let Some(tile) = self.tiles.get_mut(&key) {
+                    tile.used = true;
+                    let stale = tile.towards.dot(local_towards) < cos_limit
+                        || tile.up.dot(local_up) < cos_limit
+                        || tile.color != instance.color.buffer()
+                        || tile.data != instance.data
+                        || tile.scale != instance.scale;
+                    if stale && can_bake {
+                        bakes.push(Self::bake(id, index, tile.slot, raw, middle, radius * scale, towards, up));
+                        *tile = Tile::new(tile.slot, instance, local_towards, local_up);
+                    }
+                    // Out of date pictures are still closer than nothing while they wait
+                    Some(tile.slot)
+                } else if can_bake {
+                    self.free_slots.pop().inspect(|&slot| {
+                        bakes.push(Self::bake(id, index, slot, raw, middle, radius * scale, towards, up));
+                        self.tiles.insert(key, Tile::new(slot, instance, local_towards, local_up));
+                    })
+                } else {
+                    None
+                };
+                match slot {
+                    Some(slot) => {
+                        let (x, y) = ((slot % self.settings.tiles) as f32, (slot / self.settings.tiles) as f32);
+                        billboards.push(BillboardRaw {
+                            sphere: [middle.x, middle.y, middle.z, radius * scale],
+                            rect: [x / tiles_per_side, y / tiles_per_side, (x + 1.0) / tiles_per_side, (y + 1.0) / tiles_per_side],
+                        });
+                    }
+                    // No room in the atlas or no bakes left this frame
+                    None => near.push(raw),
+                }
+            }
+
+            // Nothing far enough away, the mesh is drawn as usual
+            if near.len() == mesh.instances().len() {
+                self.splits.remove(&id);
+                continue;
+            }
+
+            // Reused while it's big enough, the old one is still fine to draw from until this
+            // frame's upload lands since writes are ordered with submissions
+            let near_buffer = match self.splits.remove(&id) {
+                Some(split) if split.near_buffer.size() >= (near.len() * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress => split.near_buffer,
+                _ => device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("{} Near Instance Buffer", mesh.label())),
+                    size: (near.len().max(mesh.instances().len()).max(1) * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+            };
+            queue.write_buffer(&near_buffer, 0, bytemuck::cast_slice(&near));
+            splits.insert(id, MeshSplit { near_buffer, near_count: near.len() as u32 });
+        }
+        self.splits = splits;
+
+        // Pictures of instances that came close, went away or were removed are given up
+        let free_slots = &mut self.free_slots;
+        self.tiles.retain(|_, tile| {
+            if !tile.used {
+                free_slots.push(tile.slot);
+            }
+            tile.used
+        });
+
+        let size = (billboards.len() * std::mem::size_of::<BillboardRaw>()) as wgpu::BufferAddress;
+        if size > self.billboard_buffer.size() {
+            self.billboard_buffer = Self::create_billboard_buffer(device, billboards.len().next_power_of_two());
+        }
+        queue.write_buffer(&self.billboard_buffer, 0, bytemuck::cast_slice(&billboards));
+        self.billboard_count = billboards.len() as u32;
+
+        if bakes.is_empty() {
+            return;
+        }
+        let raws = bakes.iter().map(|bake| bake.raw).collect::<Vec<_>>();
+        queue.write_buffer(&self.bake_instances, 0, bytemuck::cast_slice(&raws));
+        encoder.push_debug_group("Bake Impostors");
+        let stride = std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress;
+        for (i, bake) in bakes.iter().enumerate() {
+            let Some(mesh) = meshes[bake.mesh].as_ref() else { continue };
+            queue.write_buffer(&self.bake_cameras[i].buffer, 0, bytemuck::bytes_of(&bake.uniform));
+            let material = materials.material(mesh.material);
+            let key = (material.faces(mesh.faces), material.key());
+            let format = self.format;
+            let pipeline = self.bake_pipelines.entry(key).or_insert_with(|| {
+                SceneRenderer::create_render_pipeline(device, layout, &shaders[&key.1.features], format, 1, key.0, key.1, false, TransparencyMode::Sorted)
+            });
+            {
+                let label = format!("Impostor Bake ({} {})", mesh.label(), bake.instance);
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some(&label),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &self.bake_target.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                        view: &self.bake_depth.view,
+                        depth_ops: Some(wgpu::Operations { load: wgpu::LoadOp::Clear(1.0), store: wgpu::StoreOp::Store }),
+                        stencil_ops: None,
+                    }),
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(pipeline);
+                render_pass.set_bind_group(0, &self.bake_cameras[i].bind_group, &[]);
+                render_pass.set_bind_group(1, materials.bind_group(mesh.material), &[]);
+                render_pass.set_bind_group(2, lights, &[]);
+                render_pass.set_bind_group(3, tints.bind_group(), &[tints.offset(MeshId::Static(bake.mesh))]);
+                let offset = i as wgpu::BufferAddress * stride;
+                mesh.draw_with_instances(&mut render_pass, self.bake_instances.slice(offset..offset + stride), 1);
+            }
+            let tile_size = self.settings.tile_size;
+            encoder.copy_texture_to_texture(
+                self.bake_target.texture.as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture: &self.atlas,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x: bake.slot % self.settings.tiles * tile_size, y: bake.slot / self.settings.tiles * tile_size, z: 0 },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d { width: tile_size, height: tile_size, depth_or_array_layers: 1 },
+            );
+        }
+        encoder.pop_debug_group();
+    }
+
+    // An orthographic camera fitted around the instance's bounding sphere, looking at it from
+    // the eye's side with the eye's up
+    #[allow(clippy::too_many_arguments)]
+    fn bake(mesh: usize, instance: usize, slot: u32, raw: InstanceRaw, middle: Vector3<f32>, radius: f32, towards: Vector3<f32>, up: Vector3<f32>) -> Bake {
+        let target = Point3::from_vec(middle);
+        let view = Matrix4::look_at_rh(target + towards * radius * 2.0, target, up);
+        let projection = OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-radius, radius, -radius, radius, radius * 0.5, radius * 3.5);
+        let matrices = CameraMatrices::new(view, projection);
+        let mut uniform = CameraUniform::new();
+        uniform.view_proj = matrices.view_projection.into();
+        uniform.view = matrices.view.into();
+        uniform.proj = matrices.projection.into();
+        uniform.inv_view = matrices.inverse_view.into();
+        uniform.inv_proj = matrices.inverse_projection.into();
+        uniform.inv_view_proj = matrices.inverse_view_projection.into();
+        uniform.position = (middle + towards * radius * 2.0).extend(1.0).into();
+        uniform.projection_params = [radius * 0.5, radius * 3.5, 1.0, 0.0];
+        Bake { mesh, instance, slot, uniform, raw }
+    }
+
+    fn pipeline(&mut self, device: &wgpu::Device, sample_count: u32, reversed_z: bool) {
+        if self.pipelines.contains_key(&(sample_count, reversed_z)) {
+            return;
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Impostor Pipeline ({sample_count} samples{})", if reversed_z { ", reversed z" } else { "" })),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[BillboardRaw::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // Always facing the camera, mirrored views included
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: if reversed_z { wgpu::CompareFunction::Greater } else { wgpu::CompareFunction::Less },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert((sample_count, reversed_z), pipeline);
+    }
+
+    // Builds the billboard pipelines the coming passes need, render passes can't
+    pub(crate) fn prepare_pipelines(&mut self, device: &wgpu::Device, sample_count: u32, reversed_z: impl IntoIterator<Item = bool>) {
+        for reversed_z in reversed_z {
+            self.pipeline(device, sample_count, reversed_z);
+        }
+    }
+
+    // Only the instances of a mesh that are still drawn in full and how many, None if the mesh
+    // wasn't split up this frame
+    pub(crate) fn near_instances(&self, mesh: usize) -> Option<(wgpu::BufferSlice<'_>, u32)> {
+        let split = self.splits.get(&mesh)?;
+        Some((split.near_buffer.slice(..), split.near_count))
+    }
+
+    pub fn billboard_count(&self) -> u32 {
+        self.billboard_count
+    }
+
+    // Draws every billboard from the last prepare into a pass that has the camera bound
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, sample_count: u32, reversed_z: bool) {
+        if self.billboard_count == 0 {
+            return;
+        }
+        render_pass.push_debug_group("Impostors");
+        render_pass.set_pipeline(&self.pipelines[&(sample_count, reversed_z)]);
+        render_pass.set_bind_group(1, &self.atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.billboard_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.billboard_count);
+        render_pass.pop_debug_group();
+    }
+}
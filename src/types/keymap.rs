@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+
+use winit::keyboard::KeyCode;
+
+// Things a camera controller can be asked to do from the keyboard. What each one means is up to
+// the controller, the orbit controller turns where the fps controller moves
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CameraAction {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    RollClockwise,
+    RollCounterClockwise,
+    // Prints the camera's state
+    Debug,
+}
+
+// Which keys trigger which camera actions. Physical key codes are by position, so the defaults
+// land in the same place on AZERTY or Dvorak, rebind them for anything else
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: HashMap<CameraAction, Vec<KeyCode>>,
+}
+
+impl KeyMap {
+    // Nothing bound
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // WASD and the arrows turn, Q/E pitch, Z/C roll and backquote prints the camera
+    pub fn orbit() -> Self {
+        Self::new()
+            .with(CameraAction::Forward, &[KeyCode::KeyW, KeyCode::ArrowUp])
+            .with(CameraAction::Backward, &[KeyCode::KeyS, KeyCode::ArrowDown])
+            .with(CameraAction::Left, &[KeyCode::KeyA, KeyCode::ArrowLeft])
+            .with(CameraAction::Right, &[KeyCode::KeyD, KeyCode::ArrowRight])
+            .with(CameraAction::Up, &[KeyCode::KeyE])
+            .with(CameraAction::Down, &[KeyCode::KeyQ])
+            .with(CameraAction::RollClockwise, &[KeyCode::KeyC])
+            .with(CameraAction::RollCounterClockwise, &[KeyCode::KeyZ])
+            .with(CameraAction::Debug, &[KeyCode::Backquote])
+    }
+
+    // WASD and the arrows move, Space and left Shift go up and down
+    pub fn fps() -> Self {
+        Self::new()
+            .with(CameraAction::Forward, &[KeyCode::KeyW, KeyCode::ArrowUp])
+            .with(CameraAction::Backward, &[KeyCode::KeyS, KeyCode::ArrowDown])
+            .with(CameraAction::Left, &[KeyCode::KeyA, KeyCode::ArrowLeft])
+            .with(CameraAction::Right, &[KeyCode::KeyD, KeyCode::ArrowRight])
+            .with(CameraAction::Up, &[KeyCode::Space])
+            .with(CameraAction::Down, &[KeyCode::ShiftLeft])
+    }
+
+    // Replaces whatever was bound to action
+    pub fn with(mut self, action: CameraAction, keys: &[KeyCode]) -> Self {
+        self.bind(action, keys);
+        self
+    }
+
+    // Replaces whatever was bound to action, an empty list unbinds it
+    pub fn bind(&mut self, action: CameraAction, keys: &[KeyCode]) {
+        if keys.is_empty() {
+            self.bindings.remove(&action);
+        } else {
+            self.bindings.insert(action, keys.to_vec());
+        }
+    }
+
+    // Adds another key for action, keeping the ones it already has
+    pub fn add(&mut self, action: CameraAction, key: KeyCode) {
+        let keys = self.bindings.entry(action).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    pub fn keys(&self, action: CameraAction) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    // Every action key is bound to, a key can be bound to more than one
+    pub fn actions(&self, key: KeyCode) -> impl Iterator<Item = CameraAction> + '_ {
+        self.bindings.iter().filter(move |(_, keys)| keys.contains(&key)).map(|(action, _)| *action)
+    }
+}
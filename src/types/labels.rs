@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3};
+
+use crate::types::camera::CameraMatrices;
+
+// Something to write next to a point in the world. The renderer doesn't draw text, this only
+// decides which labels should be shown where, see LabelDeclutter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Label {
+    // Stays the same from frame to frame so the label fades rather than popping
+    pub id: u64,
+    // Same space as the camera's eye
+    pub position: Point3<f32>,
+    // Pixels, the label is centered on its position plus offset
+    pub size: [f32; 2],
+    pub offset: [f32; 2],
+    // Higher wins when labels overlap, nearer wins between equal priorities
+    pub priority: i32,
+}
+
+impl Label {
+    pub fn new(id: u64, position: Point3<f32>, size: [f32; 2]) -> Self {
+        Self { id, position, size, offset: [0.0, 0.0], priority: 0 }
+    }
+
+    pub fn with_offset(mut self, offset: [f32; 2]) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+// Where a label ended up this frame and how strongly to draw it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlacedLabel {
+    pub id: u64,
+    // Index into the labels passed to update
+    pub index: usize,
+    // Pixels, top left corner
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+    // 0 to 1, from distance fading and fading in or out after winning or losing its spot
+    pub opacity: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeclutterSettings {
+    // Labels closer than this are fully opaque, past fade_end they're gone. Distances from the eye
+    pub fade_start: f32,
+    pub fade_end: f32,
+    // Pixels kept clear around every label
+    pub padding: f32,
+    // How much of its opacity a label gains or loses per second when it's shown or hidden, so
+    // labels swapping places don't flicker. Infinite switches straight away
+    pub fade_speed: f32,
+}
+
+impl Default for DeclutterSettings {
+    fn default() -> Self {
+        Self { fade_start: 50.0, fade_end: 100.0, padding: 2.0, fade_speed: 4.0 }
+    }
+}
+
+// Picks which labels are shown so they never overlap on screen. Labels are placed greedily by
+// priority then distance, anything that would cover one already placed is hidden, and labels
+// fade with distance. Screen space is bucketed so hundreds of labels stay cheap
+#[derive(Clone, Debug, Default)]
+pub struct LabelDeclutter {
+    pub settings: DeclutterSettings,
+    // How visible each label was last update, kept until it has faded out
+    fades: HashMap<u64, f32>,
+    placed: Vec<PlacedLabel>,
+}
+
+// Buckets labels are filed in by the screen area they cover
+const CELL_SIZE: f32 = 64.0;
+
+// Top left and bottom right corners in pixels
+type Rect = ([f32; 2], [f32; 2]);
+
+fn overlaps(a: &Rect, b: &Rect) -> bool {
+    a.0[0] < b.1[0] && b.0[0] < a.1[0] && a.0[1] < b.1[1] && b.0[1] < a.1[1]
+}
+
+impl LabelDeclutter {
+    pub fn new(settings: DeclutterSettings) -> Self {
+        Self { settings, fades: HashMap::new(), placed: Vec::new() }
+    }
+
+    // What update placed last time
+    pub fn placed(&self) -> &[PlacedLabel] {
+        &self.placed
+    }
+
+    // Lays the labels out for a frame seen through matrices on a viewport of the given size.
+    // dt drives the fading in and out, in seconds
+    pub fn update(&mut self, labels: &[Label], matrices: &CameraMatrices, viewport: [u32; 2], dt: f32) -> &[PlacedLabel] {
+        let settings = self.settings;
+        let viewport = viewport.map(|x| x as f32);
+        let eye = Point3::from_vec(matrices.inverse_view.w.truncate());
+        let distance_fade = |distance: f32| {
+            let range = settings.fade_end - settings.fade_start;
+            if range > 0.0 { (1.0 - (distance - settings.fade_start) / range).clamp(0.0, 1.0) } else { (distance <= settings.fade_end) as u8 as f32 }
+        };
+
+        // On screen and not faded out by distance, with the rectangle each would take up
+        let mut candidates = labels
+            .iter()
+            .enumerate()
+            .filter_map(|(index, label)| {
+                let (screen, depth) = matrices.project(label.position)?;
+                if !(0.0..=1.0).contains(&depth) {
+                    return None;
+                }
+                let distance = (label.position - eye).magnitude();
+                let fade = distance_fade(distance);
+                if fade <= 0.0 {
+                    return None;
+                }
+                let center = [screen[0] * viewport[0] + label.offset[0], screen[1] * viewport[1] + label.offset[1]];
+                let min = [center[0] - label.size[0] * 0.5, center[1] - label.size[1] * 0.5];
+                let max = [center[0] + label.size[0] * 0.5, center[1] + label.size[1] * 0.5];
+                if max[0] < 0.0 || max[1] < 0.0 || min[0] > viewport[0] || min[1] > viewport[1] {
+                    return None;
+                }
+                Some((index, distance, fade, (min, max)))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| labels[b.0].priority.cmp(&labels[a.0].priority).then(a.1.total_cmp(&b.1)));
+
+        // Labels that won their spot, padded, filed under every cell they touch
+        let mut grid: HashMap<[i32; 2], Vec<Rect>> = HashMap::new();
+        let cells = |(min, max): Rect| {
+            let (x0, y0) = ((min[0] / CELL_SIZE).floor() as i32, (min[1] / CELL_SIZE).floor() as i32);
+            let (x1, y1) = ((max[0] / CELL_SIZE).floor() as i32, (max[1] / CELL_SIZE).floor() as i32);
+            (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| [x, y]))
+        };
+        let mut shown = HashMap::new();
+        for &(index, _, fade, (min, max)) in &candidates {
+            let padded = ([min[0] - settings.padding, min[1] - settings.padding], [max[0] + settings.padding, max[1] + settings.padding]);
+            let blocked = cells(padded).any(|cell| grid.get(&cell).is_some_and(|rects| rects.iter().any(|rect| overlaps(rect, &padded))));
+            if !blocked {
+                for cell in cells(padded) {
+                    grid.entry(cell).or_default().push(padded);
+                }
+                shown.insert(labels[index].id, fade);
+            }
+        }
+
+        // Shown labels fade in towards their distance fade, the rest fade out and are dropped
+        // once they're gone
+        let step = if settings.fade_speed.is_finite() { settings.fade_speed * dt } else { 1.0 };
+        let mut fades = HashMap::new();
+        self.placed.clear();
+        for &(index, _, _, (min, max)) in &candidates {
+            let id = labels[index].id;
+            let previous = self.fades.get(&id).copied().unwrap_or(0.0);
+            let target = shown.get(&id).copied().unwrap_or(0.0);
+            let opacity = if previous < target { (previous + step).min(target) } else { (previous - step).max(target) };
+            if opacity > 0.0 {
+                fades.insert(id, opacity);
+                self.placed.push(PlacedLabel { id, index, min, max, opacity });
+            }
+        }
+        self.fades = fades;
+        &self.placed
+    }
+}
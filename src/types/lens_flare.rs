@@ -0,0 +1,435 @@
+use std::collections::HashMap;
+
+use crate::types::{color::Color, lighting::DirectionalLight, shader_features, texture::{MeshTextures, Texture}};
+
+// Sent in one uniform buffer with the sun
+pub const MAX_FLARE_ELEMENTS: usize = 16;
+// How many occlusion queries a view's sun is tested with, in a square grid
+const PROBES: u32 = 16;
+// Each view's query results are resolved at an offset this far apart
+const VISIBILITY_STRIDE: u64 = wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+
+// One sprite of the flare
+#[derive(Clone, Copy, Debug)]
+pub struct FlareElement {
+    // Where it sits on the line from the sun through the middle of the view. 0 is on the sun, 1
+    // the middle and 2 as far past it on the other side
+    pub position: f32,
+    // Half its width, as a fraction of the view's height
+    pub size: f32,
+    // Multiplies the sun's color, alpha is how strong it is
+    pub color: Color,
+    // A texture added with SceneRenderer::add_texture, None for a soft round glow
+    pub texture: Option<usize>,
+}
+
+impl FlareElement {
+    pub fn new(position: f32, size: f32, color: Color) -> Self {
+        Self { position, size, color, texture: None }
+    }
+
+    pub fn with_texture(mut self, texture: usize) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LensFlareSettings {
+    // Scales the whole flare, on top of the directional light's own intensity
+    pub intensity: f32,
+    // Half the size of the square over the sun that's tested against the scene, as a fraction of
+    // the view's height. The flare fades as more of it is covered
+    pub occlusion_size: f32,
+    // Only the first MAX_FLARE_ELEMENTS are drawn
+    pub elements: Vec<FlareElement>,
+}
+
+impl Default for LensFlareSettings {
+    // A glare over the sun and a few ghosts trailing through the middle of the view
+    fn default() -> Self {
+        Self {
+            intensity: 1.0,
+            occlusion_size: 0.02,
+            elements: vec![
+                FlareElement::new(0.0, 0.35, Color::new_rgba(1.0, 0.9, 0.75, 0.6)),
+                FlareElement::new(0.0, 0.08, Color::new_rgba(1.0, 1.0, 1.0, 0.8)),
+                FlareElement::new(0.45, 0.05, Color::new_rgba(0.5, 0.8, 1.0, 0.25)),
+                FlareElement::new(0.8, 0.03, Color::new_rgba(1.0, 0.6, 0.3, 0.3)),
+                FlareElement::new(1.25, 0.09, Color::new_rgba(0.4, 1.0, 0.6, 0.15)),
+                FlareElement::new(1.6, 0.04, Color::new_rgba(0.7, 0.5, 1.0, 0.25)),
+                FlareElement::new(2.0, 0.14, Color::new_rgba(0.5, 0.7, 1.0, 0.1)),
+            ],
+        }
+    }
+}
+
+impl LensFlareSettings {
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_element(mut self, element: FlareElement) -> Self {
+        self.elements.push(element);
+        self
+    }
+
+    // Starts from no elements at all, for building up a flare of your own
+    pub fn empty() -> Self {
+        Self { elements: Vec::new(), ..Self::default() }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FlareElementRaw {
+    color: [f32; 4],
+    // Position, size, 1 if textured
+    shape: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FlareUniform {
+    // xyz towards the sun, w the occlusion size
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    elements: [FlareElementRaw; MAX_FLARE_ELEMENTS],
+}
+
+impl FlareUniform {
+    fn new(settings: &LensFlareSettings, sun: &DirectionalLight, textures: &MeshTextures) -> Self {
+        // At the light's default intensity the flare is drawn as its colors say
+        let strength = settings.intensity * sun.intensity / std::f32::consts::PI;
+        let [r, g, b, _] = sun.color.buffer();
+        let mut elements = [FlareElementRaw { color: [0.0; 4], shape: [0.0; 4] }; MAX_FLARE_ELEMENTS];
+        for (raw, element) in elements.iter_mut().zip(&settings.elements) {
+            *raw = FlareElementRaw {
+                color: element.color.buffer(),
+                // Falls back to the glow if its texture has been removed
+                shape: [element.position, element.size, if element.texture.and_then(|id| textures.get(id)).is_some() { 1.0 } else { 0.0 }, 0.0],
+            };
+        }
+        Self {
+            sun_direction: [sun.direction.x, sun.direction.y, sun.direction.z, settings.occlusion_size.max(1e-4)],
+            sun_color: [r * strength, g * strength, b * strength, 1.0],
+            elements,
+        }
+    }
+}
+
+// Glare and ghosts from the scene's directional light, drawn over each view after everything else.
+// How much of the sun is hidden is found with occlusion queries against the depth the view left
+// behind, so the flare dims as the sun goes behind something. Created by SceneRenderer::set_lens_flare
+pub struct LensFlare {
+    settings: LensFlareSettings,
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    probe_layout: wgpu::PipelineLayout,
+    layout: wgpu::PipelineLayout,
+    texture_layout: wgpu::BindGroupLayout,
+    visibility_layout: wgpu::BindGroupLayout,
+    // By sample count and depth direction
+    probe_pipelines: HashMap<(u32, bool), wgpu::RenderPipeline>,
+    // By sample count, nothing is depth tested
+    pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // PROBES queries per view, grown when there are more views
+    queries: wgpu::QuerySet,
+    views: u32,
+    // Each view's query results, resolved straight into a uniform
+    visibility: wgpu::Buffer,
+    visibility_bind_group: wgpu::BindGroup,
+    // By texture id, made again when the scene's textures change
+    texture_bind_groups: HashMap<Option<usize>, wgpu::BindGroup>,
+}
+
+impl LensFlare {
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, settings: LensFlareSettings) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Lens Flare Shader"), source: wgpu::ShaderSource::Wgsl(shader_features::compose(include_str!("../lens_flare.wgsl")).into()) });
+        let flare_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lens_flare_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let visibility_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lens_flare_visibility_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                // Offset to the view being drawn
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(PROBES as u64 * 8),
+                },
+                count: None,
+            }],
+        });
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lens_flare_texture_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let probe_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lens Flare Probe Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &flare_layout],
+            push_constant_ranges: &[],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lens Flare Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &flare_layout, &visibility_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+        // Filled in by prepare
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lens Flare Buffer"),
+            size: std::mem::size_of::<FlareUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lens_flare_bind_group"),
+            layout: &flare_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+        let (queries, visibility, visibility_bind_group) = Self::create_queries(device, &visibility_layout, 1);
+        Self {
+            settings,
+            format,
+            shader,
+            probe_layout,
+            layout,
+            texture_layout,
+            visibility_layout,
+            probe_pipelines: HashMap::new(),
+            pipelines: HashMap::new(),
+            buffer,
+            bind_group,
+            queries,
+            views: 1,
+            visibility,
+            visibility_bind_group,
+            texture_bind_groups: HashMap::new(),
+        }
+    }
+
+    fn create_queries(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, views: u32) -> (wgpu::QuerySet, wgpu::Buffer, wgpu::BindGroup) {
+        let queries = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Lens Flare Queries"),
+            ty: wgpu::QueryType::Occlusion,
+            count: views * PROBES,
+        });
+        let visibility = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lens Flare Visibility Buffer"),
+            size: views as u64 * VISIBILITY_STRIDE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lens_flare_visibility_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &visibility,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(PROBES as u64 * 8),
+                }),
+            }],
+        });
+        (queries, visibility, bind_group)
+    }
+
+    pub fn settings(&self) -> &LensFlareSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: LensFlareSettings) {
+        self.settings = settings;
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    // After textures are added or removed, or their samplers change
+    pub(crate) fn rebind(&mut self) {
+        self.texture_bind_groups.clear();
+    }
+
+    fn probe_pipeline(&mut self, device: &wgpu::Device, sample_count: u32, reversed_z: bool) {
+        if self.probe_pipelines.contains_key(&(sample_count, reversed_z)) {
+            return;
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Lens Flare Probe Pipeline ({sample_count} samples{})", if reversed_z { ", reversed z" } else { "" })),
+            layout: Some(&self.probe_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_probe",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            // Only counts samples, nothing is written
+            fragment: None,
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn at the far plane, so equal passes where only the sky is
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: if reversed_z { wgpu::CompareFunction::GreaterEqual } else { wgpu::CompareFunction::LessEqual },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        self.probe_pipelines.insert((sample_count, reversed_z), pipeline);
+    }
+
+    fn pipeline(&mut self, device: &wgpu::Device, sample_count: u32) {
+        if self.pipelines.contains_key(&sample_count) {
+            return;
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Lens Flare Pipeline ({sample_count} samples)")),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: self.format,
+                    // Light adds up, the flare never darkens what's behind it
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert(sample_count, pipeline);
+    }
+
+    // Uploads the settings and the sun, and builds what the coming passes need, render passes can't
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: &MeshTextures,
+        sun: &DirectionalLight,
+        sample_count: u32,
+        views: usize,
+        reversed_z: impl IntoIterator<Item = bool>,
+    ) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&FlareUniform::new(&self.settings, sun, textures)));
+        let views = views.max(1) as u32;
+        if views > self.views {
+            (self.queries, self.visibility, self.visibility_bind_group) = Self::create_queries(device, &self.visibility_layout, views);
+            self.views = views;
+        }
+        for reversed_z in reversed_z {
+            self.probe_pipeline(device, sample_count, reversed_z);
+        }
+        self.pipeline(device, sample_count);
+        for element in self.settings.elements.iter().take(MAX_FLARE_ELEMENTS) {
+            self.texture_bind_groups.entry(element.texture).or_insert_with(|| {
+                let (view, sampler) = textures.binding(element.texture);
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("lens_flare_texture_bind_group"),
+                    layout: &self.texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(view) },
+                        wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) },
+                    ],
+                })
+            });
+        }
+    }
+
+    // Whether there's anything to draw, the sun being off or no elements skips the passes
+    pub(crate) fn is_visible(&self, sun: &DirectionalLight) -> bool {
+        self.settings.intensity > 0.0 && sun.intensity > 0.0 && !self.settings.elements.is_empty()
+    }
+
+    // The query set probe has to be given to its pass
+    pub(crate) fn query_set(&self) -> &wgpu::QuerySet {
+        &self.queries
+    }
+
+    // Tests the sun against the depth of a pass that has the camera bound and the scene's depth
+    // attached read only, one query per probe
+    pub(crate) fn probe<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, view: usize, sample_count: u32, reversed_z: bool) {
+        render_pass.push_debug_group("Lens Flare Probes");
+        render_pass.set_pipeline(&self.probe_pipelines[&(sample_count, reversed_z)]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        for probe in 0..PROBES {
+            render_pass.begin_occlusion_query(view as u32 * PROBES + probe);
+            render_pass.draw(0..6, probe..probe + 1);
+            render_pass.end_occlusion_query();
+        }
+        render_pass.pop_debug_group();
+    }
+
+    // Copies a view's results to where draw reads them, between its probe and flare passes
+    pub(crate) fn resolve(&self, encoder: &mut wgpu::CommandEncoder, view: usize) {
+        let first = view as u32 * PROBES;
+        encoder.resolve_query_set(&self.queries, first..first + PROBES, &self.visibility, view as u64 * VISIBILITY_STRIDE);
+    }
+
+    // Draws over a pass that has the camera bound and the scene's color loaded
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, view: usize, sample_count: u32) {
+        render_pass.push_debug_group("Lens Flare");
+        render_pass.set_pipeline(&self.pipelines[&sample_count]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.set_bind_group(2, &self.visibility_bind_group, &[(view as u64 * VISIBILITY_STRIDE) as u32]);
+        for (i, element) in self.settings.elements.iter().take(MAX_FLARE_ELEMENTS).enumerate() {
+            render_pass.set_bind_group(3, &self.texture_bind_groups[&element.texture], &[]);
+            render_pass.draw(0..6, i as u32..i as u32 + 1);
+        }
+        render_pass.pop_debug_group();
+    }
+}
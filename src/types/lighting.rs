@@ -0,0 +1,173 @@
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::types::color::Color;
+
+// Sent in one uniform buffer with the sun and ambient light
+pub const MAX_POINT_LIGHTS: usize = 16;
+
+// Light from so far away it comes from the same direction everywhere, like the sun
+#[derive(Clone, Copy, Debug)]
+pub struct DirectionalLight {
+    // Towards the light, doesn't need to be normalized
+    pub direction: Vector3<f32>,
+    pub color: Color,
+    // 0 turns it off. At PI a white surface facing the light comes out white
+    pub intensity: f32,
+}
+
+impl Default for DirectionalLight {
+    // Matches the default sky's sun
+    fn default() -> Self {
+        Self { direction: Vector3::new(0.3, 0.6, -0.5), color: Color::new(1.0, 0.95, 0.85), intensity: std::f32::consts::PI }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight {
+    // World position, uploaded relative to the camera's origin like instances are
+    pub position: Vector3<f64>,
+    pub color: Color,
+    pub intensity: f32,
+    // Nothing past this is lit, the light fades out smoothly before it
+    pub range: f32,
+}
+
+impl PointLight {
+    pub fn new(position: Vector3<f64>, color: Color, range: f32) -> Self {
+        Self { position, color, intensity: std::f32::consts::PI, range }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+}
+
+// What lights materials with ShaderFeatures::PBR, the others are drawn as they are
+#[derive(Clone, Debug)]
+pub struct Lighting {
+    pub sun: DirectionalLight,
+    // Added everywhere so nothing in shadow is pitch black
+    pub ambient: Color,
+    // Only the first MAX_POINT_LIGHTS are used
+    pub point_lights: Vec<PointLight>,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self { sun: DirectionalLight::default(), ambient: Color::new(0.08, 0.09, 0.11), point_lights: Vec::new() }
+    }
+}
+
+impl Lighting {
+    pub fn with_sun(mut self, sun: DirectionalLight) -> Self {
+        self.sun = sun;
+        self
+    }
+
+    pub fn with_ambient(mut self, ambient: Color) -> Self {
+        self.ambient = ambient;
+        self
+    }
+
+    pub fn with_point_light(mut self, light: PointLight) -> Self {
+        self.point_lights.push(light);
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightRaw {
+    // xyz relative to the origin, w the range
+    position: [f32; 4],
+    // Color times intensity
+    color: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    // xyz towards the sun, w is how wet surfaces are
+    sun_direction: [f32; 4],
+    // Color times intensity
+    sun_color: [f32; 4],
+    // rgb, w is how many point lights there are
+    ambient: [f32; 4],
+    point_lights: [PointLightRaw; MAX_POINT_LIGHTS],
+}
+
+impl LightingUniform {
+    fn new(lighting: &Lighting, origin: Vector3<f64>, wetness: f32) -> Self {
+        let sun = &lighting.sun;
+        let direction = if sun.direction.magnitude2() > 0.0 { sun.direction.normalize() } else { Vector3::unit_y() };
+        let scaled = |color: Color, intensity: f32| {
+            let [r, g, b, _] = color.buffer();
+            [r * intensity, g * intensity, b * intensity, 1.0]
+        };
+        let mut point_lights = [PointLightRaw { position: [0.0; 4], color: [0.0; 4] }; MAX_POINT_LIGHTS];
+        let count = lighting.point_lights.len().min(MAX_POINT_LIGHTS);
+        for (raw, light) in point_lights.iter_mut().zip(&lighting.point_lights) {
+            let position = (light.position - origin).cast::<f32>().unwrap();
+            *raw = PointLightRaw { position: [position.x, position.y, position.z, light.range.max(1e-3)], color: scaled(light.color, light.intensity) };
+        }
+        let [r, g, b, _] = lighting.ambient.buffer();
+        Self {
+            sun_direction: [direction.x, direction.y, direction.z, wetness.clamp(0.0, 1.0)],
+            sun_color: scaled(sun.color, sun.intensity),
+            ambient: [r, g, b, count as f32],
+            point_lights,
+        }
+    }
+}
+
+// The scene's lights on the GPU, bound at group 2 for shader.wgsl
+pub struct SceneLights {
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl SceneLights {
+    pub fn new(device: &wgpu::Device, lighting: &Lighting) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting Buffer"),
+            contents: bytemuck::bytes_of(&LightingUniform::new(lighting, Vector3::new(0.0, 0.0, 0.0), 0.0)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lighting_bind_group"),
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+        Self { layout, buffer, bind_group }
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    // origin is the active camera's, which the meshes are uploaded relative to. wetness darkens
+    // and smooths upward facing surfaces, from 0 for dry to 1 for soaked
+    pub fn write(&self, queue: &wgpu::Queue, lighting: &Lighting, origin: Vector3<f64>, wetness: f32) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&LightingUniform::new(lighting, origin, wetness)));
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+}
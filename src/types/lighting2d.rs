@@ -0,0 +1,421 @@
+use cgmath::Point2;
+use wgpu::util::DeviceExt;
+use crate::gpu::Gpu;
+use crate::types::{
+    camera2d::{Camera2D, Camera2DUniform},
+    color::Color,
+    texture::Texture,
+};
+
+// What sprites draw their normals into, xyz packed into 0 to 1 and alpha 0 wherever nothing was
+// drawn, which is lit as if it faced the camera
+pub const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+// Everything's sent in one uniform buffer, which WebGL2 caps at 16KB
+pub const MAX_LIGHTS: usize = 64;
+pub const MAX_OCCLUDER_EDGES: usize = 256;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PointLight2D {
+    pub position: Point2<f32>,
+    // How far above the sprites the light sits, in world units. Lower lights rake across normal
+    // maps and bring out more detail
+    pub height: f32,
+    // Nothing past this is lit
+    pub radius: f32,
+    pub color: Color,
+    pub intensity: f32,
+    // Size of the light itself. Bigger gives shadows softer edges, 0 gives hard ones
+    pub source_radius: f32,
+    pub casts_shadows: bool,
+}
+
+impl PointLight2D {
+    pub fn new(position: Point2<f32>, radius: f32, color: Color) -> Self {
+        Self {
+            position,
+            height: radius * 0.2,
+            radius,
+            color,
+            intensity: 1.0,
+            source_radius: radius * 0.05,
+            casts_shadows: true,
+        }
+    }
+
+    pub fn with_height(mut self, height: f32) -> Self {
+        self.height = height;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_source_radius(mut self, source_radius: f32) -> Self {
+        self.source_radius = source_radius;
+        self
+    }
+
+    pub fn with_shadows(mut self, casts_shadows: bool) -> Self {
+        self.casts_shadows = casts_shadows;
+        self
+    }
+}
+
+// A shape that blocks light, made of straight edges between points in the world
+#[derive(Clone, Debug, PartialEq)]
+pub struct Occluder2D {
+    pub points: Vec<Point2<f32>>,
+    // Joins the last point back to the first
+    pub closed: bool,
+}
+
+impl Occluder2D {
+    pub fn polygon(points: Vec<Point2<f32>>) -> Self {
+        Self { points, closed: true }
+    }
+
+    // A wall with no inside
+    pub fn line(from: Point2<f32>, to: Point2<f32>) -> Self {
+        Self { points: vec![from, to], closed: false }
+    }
+
+    pub fn rect(min: Point2<f32>, max: Point2<f32>) -> Self {
+        Self::polygon(vec![min, Point2::new(max.x, min.y), max, Point2::new(min.x, max.y)])
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (Point2<f32>, Point2<f32>)> + '_ {
+        let wrap = if self.closed && self.points.len() > 2 { self.points.len() } else { self.points.len().saturating_sub(1) };
+        (0..wrap).map(|i| (self.points[i], self.points[(i + 1) % self.points.len()]))
+    }
+}
+
+// The lights and shadows of a 2D scene
+#[derive(Clone, Debug)]
+pub struct Lighting2D {
+    // Light everywhere gets even without a light nearby, white leaves the sprites as they are
+    pub ambient: Color,
+    pub lights: Vec<PointLight2D>,
+    pub occluders: Vec<Occluder2D>,
+    // Rays traced across each light for its shadows, more gives smoother penumbras for more work.
+    // 1 gives hard shadows whatever the lights' source radius
+    pub shadow_samples: u32,
+}
+
+impl Default for Lighting2D {
+    fn default() -> Self {
+        Self {
+            ambient: Color::new(0.1, 0.1, 0.15),
+            lights: Vec::new(),
+            occluders: Vec::new(),
+            shadow_samples: 8,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightRaw {
+    // (x, y, height, radius)
+    position: [f32; 4],
+    // Color times intensity, then the source radius
+    color: [f32; 4],
+    // (casts shadows, unused...)
+    params: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    // (edge count, shadow samples, light count, unused)
+    params: [f32; 4],
+    lights: [LightRaw; MAX_LIGHTS],
+    // (from x, from y, to x, to y)
+    edges: [[f32; 4]; MAX_OCCLUDER_EDGES],
+}
+
+// Lights a sprite layer that's already been drawn. Each light adds itself into a light map with a
+// quad covering its radius, shadowed by tracing rays to it past the occluders' edges and shaded
+// with the normals the sprites drew into normal_view. The light map is then multiplied over the
+// frame in a pass of its own
+pub struct Lighting2DRenderer {
+    light_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    camera_uniform: Camera2DUniform,
+    camera_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    light_map_format: wgpu::TextureFormat,
+    light_map: Texture,
+    light_map_bind_group: wgpu::BindGroup,
+    normals: Texture,
+    normals_bind_group: wgpu::BindGroup,
+    size: [u32; 2],
+    lights_drawn: usize,
+}
+
+impl Lighting2DRenderer {
+    // format is what the lit frame is in, size its size in pixels
+    pub fn new(gpu: &Gpu, format: wgpu::TextureFormat, size: [u32; 2]) -> Self {
+        let device = &gpu.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Lighting 2D Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../lighting2d.wgsl").into()) });
+
+        let uniform_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting_2d_uniform_bind_group_layout"),
+            entries: &[0, 1].map(|binding| wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }),
+        });
+        // The normals for the lights, then the light map for the composite. Read a pixel at a time
+        // with textureLoad, so no sampler or filtering
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("lighting_2d_texture_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Lighting 2D Pipeline Layout"),
+            bind_group_layouts: &[&uniform_layout, &texture_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Half floats let lights add up past 1 and brighten what they're over, where the adapter
+        // can draw and blend them
+        let half_float = gpu.adapter.get_texture_format_features(wgpu::TextureFormat::Rgba16Float);
+        let light_map_format = if half_float.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            && half_float.flags.contains(wgpu::TextureFormatFeatureFlags::BLENDABLE) {
+            wgpu::TextureFormat::Rgba16Float
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+
+        let create_pipeline = |label, vs, fs, format, blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: vs,
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: fs,
+                targets: &[Some(wgpu::ColorTargetState { format, blend: Some(blend), write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let add = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add };
+        let light_pipeline = create_pipeline("Light 2D Pipeline", "vs_light", "fs_light", light_map_format, wgpu::BlendState { color: add, alpha: add });
+        // The frame's color times the light, leaving its alpha alone
+        let multiply = wgpu::BlendState {
+            color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Dst, dst_factor: wgpu::BlendFactor::Zero, operation: wgpu::BlendOperation::Add },
+            alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+        };
+        let composite_pipeline = create_pipeline("Light 2D Composite Pipeline", "vs_composite", "fs_composite", format, multiply);
+
+        let camera_uniform = Camera2DUniform::new();
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting 2D Camera Buffer"),
+            contents: bytemuck::bytes_of(&camera_uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Lighting 2D Buffer"),
+            size: std::mem::size_of::<LightingUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("lighting_2d_uniform_bind_group"),
+            layout: &uniform_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: uniform_buffer.as_entire_binding() },
+            ],
+        });
+
+        let size = [size[0].max(1), size[1].max(1)];
+        let [(light_map, light_map_bind_group), (normals, normals_bind_group)] = Self::create_targets(device, &texture_layout, light_map_format, size);
+
+        Self {
+            light_pipeline,
+            composite_pipeline,
+            camera_uniform,
+            camera_buffer,
+            uniform_buffer,
+            uniform_bind_group,
+            texture_layout,
+            light_map_format,
+            light_map,
+            light_map_bind_group,
+            normals,
+            normals_bind_group,
+            size,
+            lights_drawn: 0,
+        }
+    }
+
+    // The light map and the normal buffer, with bind groups to read them
+    fn create_targets(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, light_map_format: wgpu::TextureFormat, size: [u32; 2]) -> [(Texture, wgpu::BindGroup); 2] {
+        let create = |label: &str, format| {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d { width: size[0], height: size[1], depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{label} bind_group")),
+                layout,
+                entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&view) }],
+            });
+            (Texture { texture, view }, bind_group)
+        };
+        [create("Light Map", light_map_format), create("Normal Buffer", NORMAL_FORMAT)]
+    }
+
+    // Has to match the frame being lit
+    pub fn resize(&mut self, gpu: &Gpu, size: [u32; 2]) {
+        let size = [size[0].max(1), size[1].max(1)];
+        if size == self.size {
+            return;
+        }
+        self.size = size;
+        [(self.light_map, self.light_map_bind_group), (self.normals, self.normals_bind_group)] =
+            Self::create_targets(&gpu.device, &self.texture_layout, self.light_map_format, size);
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    // Where sprites draw their normals before render, see TilemapRenderer::render_normals. It's
+    // cleared once render has used it, ready for the next frame
+    pub fn normal_view(&self) -> &wgpu::TextureView {
+        &self.normals.view
+    }
+
+    // Lights that were near enough the view to be drawn last render
+    pub fn lights_drawn(&self) -> usize {
+        self.lights_drawn
+    }
+
+    // Lights view, which should already have the sprites drawn into it
+    pub fn render(&mut self, gpu: &Gpu, view: &wgpu::TextureView, lighting: &Lighting2D, camera: &Camera2D) {
+        trace_span!("lighting 2d render");
+        self.camera_uniform.update(camera);
+        gpu.queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&self.camera_uniform));
+
+        // Lights that can't reach the view are left out before they take up a slot
+        let (view_min, view_max) = camera.visible_bounds();
+        let visible = lighting.lights.iter().filter(|light| {
+            light.position.x + light.radius >= view_min.x && light.position.x - light.radius <= view_max.x
+                && light.position.y + light.radius >= view_min.y && light.position.y - light.radius <= view_max.y
+        });
+        let mut uniform: LightingUniform = bytemuck::Zeroable::zeroed();
+        let mut light_count = 0;
+        for (raw, light) in uniform.lights.iter_mut().zip(visible) {
+            let [r, g, b, _] = light.color.buffer().map(|c| c * light.intensity);
+            *raw = LightRaw {
+                position: [light.position.x, light.position.y, light.height, light.radius],
+                color: [r, g, b, light.source_radius],
+                params: [light.casts_shadows as u32 as f32, 0.0, 0.0, 0.0],
+            };
+            light_count += 1;
+        }
+        let edges = lighting.occluders.iter().flat_map(|occluder| occluder.edges()).collect::<Vec<_>>();
+        if edges.len() > MAX_OCCLUDER_EDGES {
+            log::warn!("Only the first {MAX_OCCLUDER_EDGES} of {} occluder edges cast shadows", edges.len());
+        }
+        let edge_count = edges.len().min(MAX_OCCLUDER_EDGES);
+        for (raw, (from, to)) in uniform.edges.iter_mut().zip(edges) {
+            *raw = [from.x, from.y, to.x, to.y];
+        }
+        uniform.params = [edge_count as f32, lighting.shadow_samples.max(1) as f32, light_count as f32, 0.0];
+        gpu.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&uniform));
+        self.lights_drawn = light_count;
+
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Lighting 2D Encoder"),
+        });
+        {
+            let [r, g, b, _] = lighting.ambient.buffer().map(|c| c as f64);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Light Map Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.light_map.view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color { r, g, b, a: 1.0 }), store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.light_pipeline);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.normals_bind_group, &[]);
+            // A quad per light, built from the vertex index
+            render_pass.draw(0..6, 0..light_count as u32);
+        }
+        // The light map can't be read in the pass that draws it, so the composite gets its own
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Light Composite Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.composite_pipeline);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.set_bind_group(1, &self.light_map_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+        // Ready for the next frame's normals
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear Normals Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.normals.view,
+                resolve_target: None,
+                ops: wgpu::Operations { load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,537 @@
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use crate::types::{
+    color::Color,
+    frames::FramesInFlight,
+    mesh::FaceSettings,
+    sampler::{SamplerCache, SamplerConfig},
+    shader_features::ShaderFeatures,
+    texture::MeshTextures,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    // Covers whatever's behind
+    Opaque,
+    // Mixed over what's behind by alpha, opaque colors come out the same as Opaque. Nothing is
+    // sorted unless the material is transparent, so see-through meshes otherwise have to be
+    // added after whatever's behind them
+    #[default]
+    Alpha,
+    // Added onto what's behind, for glows and sparks
+    Additive,
+}
+
+impl BlendMode {
+    pub fn state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque => wgpu::BlendState::REPLACE,
+            BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::SrcAlpha, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+                alpha: wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add },
+            },
+        }
+    }
+}
+
+// Changes a material every update, given the seconds the scene has advanced, see
+// SceneRenderer::set_material_animator. Send, since the scene can be moved to a render thread
+pub type MaterialAnimator = Box<dyn FnMut(&mut Material, f32) + Send>;
+
+// How a material's vertex colors and base texture go together. Meshes without VERTEX_COLOR
+// have white vertex colors, and materials without a texture white texels. Instance colors, the
+// mesh's tint and base_color multiply whatever comes out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorCombine {
+    #[default]
+    Multiply,
+    // Only the texture, for textured assets whose vertex colors are left over from modelling
+    Replace,
+    // Vertex colors brighten the texture, their alphas still multiply
+    Add,
+    // Ignores the texture, for geometry colored by its vertices alone
+    VertexColorOnly,
+}
+
+impl ColorCombine {
+    // What shader.wgsl's combine switches on
+    fn index(self) -> u32 {
+        match self {
+            ColorCombine::Multiply => 0,
+            ColorCombine::Replace => 1,
+            ColorCombine::Add => 2,
+            ColorCombine::VertexColorOnly => 3,
+        }
+    }
+}
+
+// The parts of a material that need a pipeline of their own, everything else is in its bind group
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialKey {
+    pub features: ShaderFeatures,
+    pub blend: BlendMode,
+    // Leaves the depth buffer alone
+    pub transparent: bool,
+}
+
+// How a mesh's surface is drawn: which shader variant, its parameters and textures, and how it's
+// blended and culled. Meshes point at one by id, see SceneRenderer::add_material, and meshes
+// without one use the scene's default material.
+//
+// The lit parameters follow glTF's metallic-roughness model and only matter with
+// ShaderFeatures::PBR, so an imported material's factors and textures can be copied straight in
+#[derive(Clone, Debug)]
+pub struct Material {
+    // Shows up in graphics debuggers
+    pub name: String,
+    pub features: ShaderFeatures,
+    // Multiplied with the vertex and instance colors
+    pub base_color: Color,
+    // Id of a scene texture, read when features has HAS_UV
+    pub texture: Option<usize>,
+    // Id of a scene texture array, read in place of texture when features has TEXTURE_ARRAY.
+    // Each instance picks its layer, see Instance::layer
+    pub texture_array: Option<usize>,
+    // How the texture above goes with the vertex colors
+    pub color_combine: ColorCombine,
+    // How far every texture slides each second, in texture widths and heights. Needs the
+    // sampler to repeat
+    pub uv_scroll: [f32; 2],
+    pub blend: BlendMode,
+    // Drawn after everything else, furthest first as seen from each view, and without writing
+    // depth, so see-through meshes show what's behind them whatever order they were added in.
+    // Meshes are sorted as a whole, their instances and triangles aren't. See TransparencyMode for
+    // blending them without sorting
+    pub transparent: bool,
+    // Draws the back of every triangle too, whatever the mesh's face settings cull
+    pub double_sided: bool,
+    // 0 is a dielectric like plastic or wood, 1 is bare metal
+    pub metallic: f32,
+    // 0 is a mirror, 1 is completely matte
+    pub roughness: f32,
+    // Blue is metallic and green is roughness, multiplied with the factors above
+    pub metallic_roughness_texture: Option<usize>,
+    // Tangent space normals, only read while features has HAS_NORMAL_MAP. Uses the mesh's
+    // tangents if it has them, see MeshData::generate_tangents
+    pub normal_texture: Option<usize>,
+    // How strongly the normal texture bends the normal
+    pub normal_scale: f32,
+    // Red is how much ambient light reaches each point
+    pub occlusion_texture: Option<usize>,
+    // 0 ignores the occlusion texture, 1 uses all of it
+    pub occlusion_strength: f32,
+    // Light given off whatever's lighting it, black for none. Added after lighting, so it glows
+    // on unlit materials too
+    pub emissive: Color,
+    // Multiplied with emissive
+    pub emissive_texture: Option<usize>,
+    // Scales emissive past what a color can hold. Only shows on float targets like Rgba16Float,
+    // where anything over 1 is what bloom picks out. The rest clamp it to white
+    pub emissive_strength: f32,
+    // Reads every one of the textures above with these settings instead of each texture's own
+    pub sampler: Option<SamplerConfig>,
+}
+
+impl Default for Material {
+    // Exactly how meshes were drawn before there were materials
+    fn default() -> Self {
+        Self {
+            name: "Default Material".to_string(),
+            features: ShaderFeatures::DEFAULT,
+            base_color: Color::WHITE,
+            texture: None,
+            texture_array: None,
+            color_combine: ColorCombine::default(),
+            uv_scroll: [0.0; 2],
+            blend: BlendMode::default(),
+            transparent: false,
+            double_sided: false,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: Color::BLACK,
+            emissive_texture: None,
+            emissive_strength: 1.0,
+            sampler: None,
+        }
+    }
+}
+
+impl Material {
+    pub fn new(name: &str) -> Self {
+        Self { name: name.to_string(), ..Default::default() }
+    }
+
+    // Lit by the scene's lights, with glTF's defaults of fully metallic and fully rough
+    pub fn pbr(name: &str) -> Self {
+        Self::new(name).with_features(ShaderFeatures::DEFAULT | ShaderFeatures::PBR)
+    }
+
+    pub fn with_features(mut self, features: ShaderFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn with_base_color(mut self, color: Color) -> Self {
+        self.base_color = color;
+        self
+    }
+
+    // Textures are read with the mesh's texture coordinates, so this turns on HAS_UV
+    pub fn with_texture(mut self, texture: usize) -> Self {
+        self.texture = Some(texture);
+        self.features = self.features.with(ShaderFeatures::HAS_UV);
+        self
+    }
+
+    // See SceneRenderer::add_texture_array, this turns on HAS_UV and TEXTURE_ARRAY
+    pub fn with_texture_array(mut self, texture_array: usize) -> Self {
+        self.texture_array = Some(texture_array);
+        self.features = self.features.with(ShaderFeatures::HAS_UV | ShaderFeatures::TEXTURE_ARRAY);
+        self
+    }
+
+    pub fn with_metallic_roughness(mut self, metallic: f32, roughness: f32) -> Self {
+        self.metallic = metallic;
+        self.roughness = roughness;
+        self
+    }
+
+    pub fn with_metallic_roughness_texture(mut self, texture: usize) -> Self {
+        self.metallic_roughness_texture = Some(texture);
+        self.features = self.features.with(ShaderFeatures::HAS_UV);
+        self
+    }
+
+    pub fn with_normal_texture(mut self, texture: usize, scale: f32) -> Self {
+        self.normal_texture = Some(texture);
+        self.normal_scale = scale;
+        self.features = self.features.with(ShaderFeatures::HAS_UV | ShaderFeatures::HAS_NORMAL_MAP);
+        self
+    }
+
+    // Turns the normal texture on or off without losing it, off is cheaper and flat looking
+    pub fn with_normal_mapping(mut self, enabled: bool) -> Self {
+        self.features = if enabled {
+            self.features.with(ShaderFeatures::HAS_UV | ShaderFeatures::HAS_NORMAL_MAP)
+        } else {
+            self.features.without(ShaderFeatures::HAS_NORMAL_MAP)
+        };
+        self
+    }
+
+    pub fn normal_mapping(&self) -> bool {
+        self.features.contains(ShaderFeatures::HAS_NORMAL_MAP)
+    }
+
+    pub fn with_occlusion_texture(mut self, texture: usize, strength: f32) -> Self {
+        self.occlusion_texture = Some(texture);
+        self.occlusion_strength = strength;
+        self.features = self.features.with(ShaderFeatures::HAS_UV);
+        self
+    }
+
+    pub fn with_emissive(mut self, emissive: Color) -> Self {
+        self.emissive = emissive;
+        self
+    }
+
+    pub fn with_emissive_texture(mut self, texture: usize) -> Self {
+        self.emissive_texture = Some(texture);
+        self.features = self.features.with(ShaderFeatures::HAS_UV);
+        self
+    }
+
+    pub fn with_emissive_strength(mut self, strength: f32) -> Self {
+        self.emissive_strength = strength;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerConfig) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    pub fn with_color_combine(mut self, combine: ColorCombine) -> Self {
+        self.color_combine = combine;
+        self
+    }
+
+    pub fn with_uv_scroll(mut self, uv_scroll: [f32; 2]) -> Self {
+        self.uv_scroll = uv_scroll;
+        self
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    // Alpha blended unless it's already additive, see transparent
+    pub fn with_transparent(mut self) -> Self {
+        self.transparent = true;
+        if self.blend == BlendMode::Opaque {
+            self.blend = BlendMode::Alpha;
+        }
+        self
+    }
+
+    pub fn with_double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+
+    pub fn key(&self) -> MaterialKey {
+        MaterialKey { features: self.features, blend: self.blend, transparent: self.transparent }
+    }
+
+    // Every texture slot in the order they're bound: base, metallic-roughness, normal, occlusion
+    // and emissive
+    pub fn textures(&self) -> [Option<usize>; 5] {
+        [self.texture, self.metallic_roughness_texture, self.normal_texture, self.occlusion_texture, self.emissive_texture]
+    }
+
+    pub fn uses_texture(&self, texture: usize) -> bool {
+        self.textures().contains(&Some(texture))
+    }
+
+    pub fn uses_texture_array(&self, texture_array: usize) -> bool {
+        self.texture_array == Some(texture_array)
+    }
+
+    // What a mesh with these face settings is drawn with
+    pub fn faces(&self, faces: FaceSettings) -> FaceSettings {
+        if self.double_sided { FaceSettings { cull_mode: None, ..faces } } else { faces }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color: [f32; 4],
+    // Already scaled by the strength
+    emissive: [f32; 4],
+    // Metallic, roughness, normal scale, occlusion strength
+    pbr: [f32; 4],
+    // Color combine, the rest unused
+    options: [u32; 4],
+    uv_scroll: [f32; 4],
+}
+
+impl MaterialUniform {
+    fn new(material: &Material) -> Self {
+        Self {
+            base_color: material.base_color.buffer(),
+            emissive: {
+                let [r, g, b, _] = material.emissive.buffer();
+                let strength = material.emissive_strength;
+                [r * strength, g * strength, b * strength, 1.0]
+            },
+            pbr: [material.metallic, material.roughness, material.normal_scale, material.occlusion_strength],
+            options: [material.color_combine.index(), 0, 0, 0],
+            uv_scroll: [material.uv_scroll[0], material.uv_scroll[1], 0.0, 0.0],
+        }
+    }
+}
+
+struct GpuMaterial {
+    material: Material,
+    buffer: wgpu::Buffer,
+    // Made from Material::sampler, None reads each texture through its own
+    sampler: Option<Arc<wgpu::Sampler>>,
+    bind_group: wgpu::BindGroup,
+}
+
+// Where each of Material::textures goes in the bind group, texture then sampler. The uniform
+// sits at 2 between the base texture and the rest
+const TEXTURE_BINDINGS: [u32; 5] = [0, 3, 5, 7, 9];
+// Material::texture_array and its sampler, after all of them
+const TEXTURE_ARRAY_BINDING: u32 = 11;
+
+// Every material a scene has, each with its uniform buffer and the bind group shader.wgsl reads
+// it through at group 1
+pub struct Materials {
+    layout: wgpu::BindGroupLayout,
+    default: GpuMaterial,
+    // Indexed by id, None once removed so the other ids stay put
+    materials: Vec<Option<GpuMaterial>>,
+}
+
+impl Materials {
+    pub fn new(device: &wgpu::Device, textures: &MeshTextures, samplers: &mut SamplerCache) -> Self {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        for binding in TEXTURE_BINDINGS {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: binding + 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: TEXTURE_ARRAY_BINDING,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                multisampled: false,
+            },
+            count: None,
+        });
+        entries.push(wgpu::BindGroupLayoutEntry {
+            binding: TEXTURE_ARRAY_BINDING + 1,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material_bind_group_layout"),
+            entries: &entries,
+        });
+        let default = Self::upload(device, &layout, textures, samplers, Material::default());
+        Self { layout, default, materials: Vec::new() }
+    }
+
+    fn upload(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, textures: &MeshTextures, samplers: &mut SamplerCache, material: Material) -> GpuMaterial {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Buffer", material.name)),
+            contents: bytemuck::bytes_of(&MaterialUniform::new(&material)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let sampler = Self::sampler(device, samplers, &material);
+        let bind_group = Self::bind(device, layout, textures, &material, &buffer, sampler.as_deref());
+        GpuMaterial { material, buffer, sampler, bind_group }
+    }
+
+    fn sampler(device: &wgpu::Device, samplers: &mut SamplerCache, material: &Material) -> Option<Arc<wgpu::Sampler>> {
+        material.sampler.map(|config| samplers.get(device, &config.filtering()))
+    }
+
+    fn bind(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        textures: &MeshTextures,
+        material: &Material,
+        buffer: &wgpu::Buffer,
+        sampler: Option<&wgpu::Sampler>,
+    ) -> wgpu::BindGroup {
+        let mut entries = vec![wgpu::BindGroupEntry { binding: 2, resource: buffer.as_entire_binding() }];
+        for (binding, texture) in TEXTURE_BINDINGS.into_iter().zip(material.textures()) {
+            let (view, texture_sampler) = textures.binding(texture);
+            entries.push(wgpu::BindGroupEntry { binding, resource: wgpu::BindingResource::TextureView(view) });
+            entries.push(wgpu::BindGroupEntry { binding: binding + 1, resource: wgpu::BindingResource::Sampler(sampler.unwrap_or(texture_sampler)) });
+        }
+        let (view, array_sampler) = textures.array_binding(material.texture_array);
+        entries.push(wgpu::BindGroupEntry { binding: TEXTURE_ARRAY_BINDING, resource: wgpu::BindingResource::TextureView(view) });
+        entries.push(wgpu::BindGroupEntry { binding: TEXTURE_ARRAY_BINDING + 1, resource: wgpu::BindingResource::Sampler(sampler.unwrap_or(array_sampler)) });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} bind_group", material.name)),
+            layout,
+            entries: &entries,
+        })
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn add(&mut self, device: &wgpu::Device, textures: &MeshTextures, samplers: &mut SamplerCache, material: Material) -> usize {
+        self.materials.push(Some(Self::upload(device, &self.layout, textures, samplers, material)));
+        self.materials.len() - 1
+    }
+
+    // Changes a material in place, None is the default material. Returns false if there's no
+    // material with that id
+    pub fn set(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        textures: &MeshTextures,
+        samplers: &mut SamplerCache,
+        id: Option<usize>,
+        material: Material,
+    ) -> bool {
+        let gpu_material = match id {
+            Some(id) => match self.materials.get_mut(id) {
+                Some(Some(gpu_material)) => gpu_material,
+                _ => return false,
+            },
+            None => &mut self.default,
+        };
+        queue.write_buffer(&gpu_material.buffer, 0, bytemuck::bytes_of(&MaterialUniform::new(&material)));
+        if material.sampler != gpu_material.material.sampler {
+            gpu_material.sampler = Self::sampler(device, samplers, &material);
+        }
+        let rebind = material.textures() != gpu_material.material.textures()
+            || material.texture_array != gpu_material.material.texture_array
+            || material.sampler != gpu_material.material.sampler;
+        if rebind {
+            gpu_material.bind_group = Self::bind(device, &self.layout, textures, &material, &gpu_material.buffer, gpu_material.sampler.as_deref());
+        }
+        gpu_material.material = material;
+        true
+    }
+
+    // The buffer is kept until the frames in flight are done with it
+    pub fn remove(&mut self, id: usize, frames: &mut FramesInFlight) -> Option<Material> {
+        let gpu_material = self.materials.get_mut(id)?.take()?;
+        frames.retire(gpu_material.buffer);
+        Some(gpu_material.material)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Material> {
+        Some(&self.materials.get(id)?.as_ref()?.material)
+    }
+
+    // Ids handed out so far, including removed materials
+    pub fn count(&self) -> usize {
+        self.materials.len()
+    }
+
+    fn resolve(&self, id: Option<usize>) -> &GpuMaterial {
+        id.and_then(|id| self.materials.get(id)?.as_ref()).unwrap_or(&self.default)
+    }
+
+    // What a mesh with this material is drawn with, the default if it's None or gone
+    pub fn material(&self, id: Option<usize>) -> &Material {
+        &self.resolve(id).material
+    }
+
+    pub fn bind_group(&self, id: Option<usize>) -> &wgpu::BindGroup {
+        &self.resolve(id).bind_group
+    }
+
+    // Binds every material again, after a texture is removed or the samplers change
+    pub fn rebind(&mut self, device: &wgpu::Device, textures: &MeshTextures, samplers: &mut SamplerCache) {
+        for gpu_material in self.materials.iter_mut().flatten().chain(std::iter::once(&mut self.default)) {
+            gpu_material.sampler = Self::sampler(device, samplers, &gpu_material.material);
+            gpu_material.bind_group = Self::bind(device, &self.layout, textures, &gpu_material.material, &gpu_material.buffer, gpu_material.sampler.as_deref());
+        }
+    }
+}
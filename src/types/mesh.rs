@@ -0,0 +1,740 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+use cgmath::{ElementWise, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, Zero};
+use wgpu::util::DeviceExt;
+use crate::types::{atlas::AtlasRegion, color::Color, geometry::{Vertex, Instance}, ray::Ray};
+
+// CPU side geometry, ready to be uploaded or written to the mesh cache
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>) -> Self {
+        Self { vertices, indices }
+    }
+
+    // Smallest box containing every vertex, (min, max)
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        if self.vertices.is_empty() {
+            return ([0.0; 3], [0.0; 3]);
+        }
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in &self.vertices {
+            for i in 0..3 {
+                min[i] = min[i].min(vertex.position[i]);
+                max[i] = max[i].max(vertex.position[i]);
+            }
+        }
+        (min, max)
+    }
+
+    // Unnormalized cross product of a triangle's edges, its length is twice the triangle's area.
+    // Points towards the side the triangle appears counter-clockwise from
+    pub fn triangle_normal(&self, a: u32, b: u32, c: u32) -> Vector3<f32> {
+        let a = Vector3::from(self.vertices[a as usize].position);
+        let b = Vector3::from(self.vertices[b as usize].position);
+        let c = Vector3::from(self.vertices[c as usize].position);
+        (b - a).cross(c - a)
+    }
+
+    // Works out each vertex's tangent from how its triangles' texture coordinates run, for normal
+    // maps. Vertices without a normal, or whose triangles have no usable texture coordinates,
+    // are left with a zero tangent
+    pub fn generate_tangents(&mut self) {
+        let mut tangents = vec![Vector3::zero(); self.vertices.len()];
+        let mut bitangents = vec![Vector3::zero(); self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| &self.vertices[i as usize]);
+            let e1 = Vector3::from(b.position) - Vector3::from(a.position);
+            let e2 = Vector3::from(c.position) - Vector3::from(a.position);
+            let (du1, dv1) = (b.tex_coords[0] - a.tex_coords[0], b.tex_coords[1] - a.tex_coords[1]);
+            let (du2, dv2) = (c.tex_coords[0] - a.tex_coords[0], c.tex_coords[1] - a.tex_coords[1]);
+            let det = du1 * dv2 - du2 * dv1;
+            if det.abs() < 1e-12 {
+                continue;
+            }
+            // Not normalized, so bigger triangles count for more
+            let tangent = (e1 * dv2 - e2 * dv1) / det;
+            let bitangent = (e2 * du1 - e1 * du2) / det;
+            for &i in triangle {
+                tangents[i as usize] += tangent;
+                bitangents[i as usize] += bitangent;
+            }
+        }
+        for ((vertex, tangent), bitangent) in self.vertices.iter_mut().zip(tangents).zip(bitangents) {
+            let normal = Vector3::from(vertex.normal);
+            // Straightened to be at right angles to the normal
+            let tangent = tangent - normal * normal.dot(tangent);
+            if normal.magnitude2() < 1e-12 || tangent.magnitude2() < 1e-12 {
+                vertex.tangent = [0.0; 4];
+                continue;
+            }
+            let tangent = tangent.normalize();
+            let handedness = if normal.cross(tangent).dot(bitangent) < 0.0 { -1.0 } else { 1.0 };
+            vertex.tangent = [tangent.x, tangent.y, tangent.z, handedness];
+        }
+    }
+
+    // Moves texture coordinates made for a whole image onto its region of an atlas. Coordinates
+    // outside 0 to 1 don't repeat any more, they run into the neighbouring images
+    pub fn remap_uvs(&mut self, region: &AtlasRegion) {
+        for vertex in &mut self.vertices {
+            vertex.tex_coords = region.map_uv(vertex.tex_coords);
+        }
+    }
+
+    // A flat rectangle size across, centered on the origin and facing +z, showing one image of an
+    // atlas. Front faces are clockwise, for FaceSettings::DEFAULT
+    pub fn sprite(size: [f32; 2], region: &AtlasRegion, color: Color) -> Self {
+        let [x, y] = [size[0] * 0.5, size[1] * 0.5];
+        let corners = [([-x, -y], [0.0, 1.0]), ([x, -y], [1.0, 1.0]), ([x, y], [1.0, 0.0]), ([-x, y], [0.0, 0.0])];
+        let vertices = corners.into_iter().map(|([x, y], uv)| Vertex {
+            position: [x, y, 0.0],
+            color,
+            normal: [0.0, 0.0, 1.0],
+            tex_coords: region.map_uv(uv),
+            tangent: [0.0; 4],
+        }).collect();
+        let mut data = Self::new(vertices, vec![0, 3, 2, 0, 2, 1]);
+        data.generate_tangents();
+        data
+    }
+
+    // True if every edge is shared by exactly two triangles, so the mesh has an inside and outside
+    pub fn is_closed(&self) -> bool {
+        let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+        for triangle in self.indices.chunks_exact(3) {
+            for (i, j) in [(triangle[0], triangle[1]), (triangle[1], triangle[2]), (triangle[2], triangle[0])] {
+                *edges.entry((i.min(j), i.max(j))).or_default() += 1;
+            }
+        }
+        !edges.is_empty() && edges.values().all(|&n| n == 2)
+    }
+
+    // Volume enclosed by the mesh, positive if its triangles are counter-clockwise seen from outside
+    pub fn signed_volume(&self) -> f32 {
+        self.indices.chunks_exact(3).map(|triangle| {
+            let a = Vector3::from(self.vertices[triangle[0] as usize].position);
+            let b = Vector3::from(self.vertices[triangle[1] as usize].position);
+            let c = Vector3::from(self.vertices[triangle[2] as usize].position);
+            a.dot(b.cross(c)) / 6.0
+        }).sum()
+    }
+
+    // Works out which winding the mesh's outward faces use. Closed meshes are judged by their
+    // volume, open ones by whether their triangles agree with their vertex normals.
+    // None if there's no way to tell (an open mesh without normals)
+    pub fn detect_winding(&self) -> Option<wgpu::FrontFace> {
+        let score = if self.is_closed() {
+            self.signed_volume()
+        } else {
+            self.indices.chunks_exact(3).map(|triangle| {
+                let normal = triangle.iter()
+                    .map(|&i| Vector3::from(self.vertices[i as usize].normal))
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |sum, n| sum + n);
+                self.triangle_normal(triangle[0], triangle[1], triangle[2]).dot(normal)
+            }).sum()
+        };
+
+        if score > 0.0 {
+            Some(wgpu::FrontFace::Ccw)
+        } else if score < 0.0 {
+            Some(wgpu::FrontFace::Cw)
+        } else {
+            None
+        }
+    }
+
+    // True if the mesh's outward faces don't use the given front face winding
+    pub fn is_inverted(&self, front_face: wgpu::FrontFace) -> bool {
+        self.detect_winding().is_some_and(|winding| winding != front_face)
+    }
+
+    // Reverses every triangle, turning the mesh inside out
+    pub fn flip_winding(&mut self) {
+        for triangle in self.indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    // Flips the mesh if its outward faces don't use the given winding, returns true if it did
+    pub fn fix_winding(&mut self, front_face: wgpu::FrontFace) -> bool {
+        let inverted = self.is_inverted(front_face);
+        if inverted {
+            self.flip_winding();
+        }
+        inverted
+    }
+
+    // Loads a mesh from its binary cache if the cache is newer than the source file,
+    // otherwise imports the source and writes the cache for next time
+    pub fn load_cached<F>(source: &Path, import: F) -> io::Result<MeshData>
+    where
+        F: FnOnce(&Path) -> io::Result<MeshData>,
+    {
+        let cache = cache_path(source);
+        if is_fresh(source, &cache) {
+            match fs::read(&cache).and_then(|bytes| decode(&bytes)) {
+                Ok(mesh) => return Ok(mesh),
+                Err(e) => log::warn!("Ignoring unreadable mesh cache {}: {e}", cache.display()),
+            }
+        }
+
+        let mesh = import(source)?;
+        if let Err(e) = fs::write(&cache, encode(&mesh)) {
+            log::warn!("Couldn't write mesh cache {}: {e}", cache.display());
+        }
+        Ok(mesh)
+    }
+}
+
+// Which faces get drawn. Each distinct combination gets its own cached pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FaceSettings {
+    pub front_face: wgpu::FrontFace,
+    pub cull_mode: Option<wgpu::Face>,
+}
+
+impl FaceSettings {
+    // Clockwise triangles face the camera, the back of them is culled
+    pub const DEFAULT: FaceSettings = FaceSettings { front_face: wgpu::FrontFace::Cw, cull_mode: Some(wgpu::Face::Back) };
+    // Same winding as DEFAULT but both sides are drawn, for flat shapes that can be seen from behind
+    pub const DOUBLE_SIDED: FaceSettings = FaceSettings { front_face: wgpu::FrontFace::Cw, cull_mode: None };
+    // Counter-clockwise front faces, which is what most modelling tools export
+    pub const CCW: FaceSettings = FaceSettings { front_face: wgpu::FrontFace::Ccw, cull_mode: Some(wgpu::Face::Back) };
+
+    // The same faces seen in a mirror, where every triangle's winding is reversed
+    pub fn mirrored(self) -> Self {
+        let front_face = match self.front_face {
+            wgpu::FrontFace::Cw => wgpu::FrontFace::Ccw,
+            wgpu::FrontFace::Ccw => wgpu::FrontFace::Cw,
+        };
+        Self { front_face, ..self }
+    }
+}
+
+impl Default for FaceSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+// Geometry that has been uploaded to the GPU, drawn once per instance
+pub struct Mesh {
+    pub faces: FaceSettings,
+    // Id of the scene material the mesh is drawn with, None for the default material
+    pub material: Option<usize>,
+    // Instances further than this from the active camera are drawn as baked pictures instead,
+    // see Impostors. Only static meshes have them
+    pub impostor_distance: Option<f32>,
+    // Hidden meshes are skipped when drawing and picking but otherwise kept as they are
+    pub visible: bool,
+    // Multiplied with every instance's color in the fragment shader, so one mesh can be drawn in a
+    // different color without touching its vertices or instances
+    pub tint: Color,
+    // Names the buffers and the draws in graphics debuggers like RenderDoc
+    label: String,
+    // Kept around for validation, bounds and picking
+    data: MeshData,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    n_indices: u32,
+    instances: Vec<Instance>,
+    // World position the instance buffer was uploaded relative to
+    origin: Vector3<f64>,
+    // Box around every instance relative to origin, None if there's nothing to draw
+    bounding_box: Option<([f32; 3], [f32; 3])>,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> Self {
+        Self::named(device, "Mesh", data, instances, faces)
+    }
+
+    // Same as new, with a name that shows up in graphics debuggers
+    pub fn named(device: &wgpu::Device, label: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> Self {
+        Self::with_vertex_usage(device, label, data, instances, faces, wgpu::BufferUsages::VERTEX, Vector3::zero())
+    }
+
+    fn with_vertex_usage(
+        device: &wgpu::Device,
+        label: &str,
+        data: &MeshData,
+        instances: &[Instance],
+        faces: FaceSettings,
+        usage: wgpu::BufferUsages,
+        origin: Vector3<f64>,
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label} Vertex Buffer")),
+                contents: bytemuck::cast_slice(&data.vertices),
+                usage,
+            }
+        );
+
+        let index_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label} Index Buffer")),
+                contents: bytemuck::cast_slice(&data.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }
+        );
+
+        Self {
+            faces,
+            material: None,
+            impostor_distance: None,
+            visible: true,
+            tint: Color::WHITE,
+            label: label.to_string(),
+            data: data.clone(),
+            vertex_buffer,
+            index_buffer,
+            instance_buffer: Self::create_instance_buffer(device, label, instances, origin),
+            n_indices: data.indices.len() as u32,
+            instances: instances.to_vec(),
+            origin,
+            bounding_box: Self::instance_bounds(data, instances, origin),
+        }
+    }
+
+    // Wraps a sphere around the mesh's own bounds for each instance, which stays correct however
+    // the instance is rotated, then boxes the spheres
+    fn instance_bounds(data: &MeshData, instances: &[Instance], origin: Vector3<f64>) -> Option<([f32; 3], [f32; 3])> {
+        if data.vertices.is_empty() || instances.is_empty() {
+            return None;
+        }
+        let (min, max) = data.bounds();
+        let center = Vector3::from(min).add_element_wise(Vector3::from(max)) * 0.5;
+        let radius = (Vector3::from(max) - center).magnitude();
+
+        let mut bounds_min = [f32::MAX; 3];
+        let mut bounds_max = [f32::MIN; 3];
+        for instance in instances {
+            let model = Matrix4::from(instance.to_raw(origin).model);
+            let world = (model * center.extend(1.0)).truncate();
+            let scale = instance.scale.x.abs().max(instance.scale.y.abs()).max(instance.scale.z.abs());
+            for i in 0..3 {
+                bounds_min[i] = bounds_min[i].min(world[i] - radius * scale);
+                bounds_max[i] = bounds_max[i].max(world[i] + radius * scale);
+            }
+        }
+        Some((bounds_min, bounds_max))
+    }
+
+    // Relative to the origin the mesh was last uploaded with
+    pub fn bounding_box(&self) -> Option<([f32; 3], [f32; 3])> {
+        self.bounding_box
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounding_box = Self::instance_bounds(&self.data, &self.instances, self.origin);
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, label: &str, instances: &[Instance], origin: Vector3<f64>) -> wgpu::Buffer {
+        let instance_data = instances.iter().map(|instance| instance.to_raw(origin)).collect::<Vec<_>>();
+        device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{label} Instance Buffer")),
+                contents: bytemuck::cast_slice(&instance_data),
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            }
+        )
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn data(&self) -> &MeshData {
+        &self.data
+    }
+
+    // Replaces the geometry, keeping the instances, face settings, material, impostor distance,
+    // visibility, tint and label
+    pub fn set_data(&mut self, device: &wgpu::Device, data: &MeshData) {
+        let usage = self.vertex_buffer.usage();
+        let mut mesh = Self::with_vertex_usage(device, &self.label, data, &[], self.faces, usage, self.origin);
+        mesh.material = self.material;
+        mesh.impostor_distance = self.impostor_distance;
+        mesh.visible = self.visible;
+        mesh.tint = self.tint;
+        std::mem::swap(&mut mesh.instance_buffer, &mut self.instance_buffer);
+        std::mem::swap(&mut mesh.instances, &mut self.instances);
+        *self = mesh;
+        self.update_bounds();
+    }
+
+    // Bytes of GPU memory the mesh's buffers take up
+    pub fn buffer_bytes(&self) -> u64 {
+        self.vertex_buffer.size() + self.index_buffer.size() + self.instance_buffer.size()
+    }
+
+    pub fn instances(&self) -> &[Instance] {
+        &self.instances
+    }
+
+    // Replaces every instance, only reallocating the buffer when it has to grow
+    pub fn set_instances(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, instances: &[Instance]) {
+        let instance_data = instances.iter().map(|instance| instance.to_raw(self.origin)).collect::<Vec<_>>();
+        let contents: &[u8] = bytemuck::cast_slice(&instance_data);
+        if contents.len() as wgpu::BufferAddress > self.instance_buffer.size() {
+            self.instance_buffer = Self::create_instance_buffer(device, &self.label, instances, self.origin);
+        } else {
+            queue.write_buffer(&self.instance_buffer, 0, contents);
+        }
+        self.instances = instances.to_vec();
+        self.update_bounds();
+    }
+
+    // Re-uploads the instances relative to a new origin, which is a no-op if it hasn't moved
+    pub fn set_origin(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, origin: Vector3<f64>) {
+        if origin != self.origin {
+            self.origin = origin;
+            let instances = std::mem::take(&mut self.instances);
+            self.set_instances(device, queue, &instances);
+        }
+    }
+
+    // Wrapped in a debug group named after the mesh, so captures show which draw is which
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        render_pass.push_debug_group(&self.label);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.n_indices, 0, 0..self.instances.len() as u32);
+        render_pass.pop_debug_group();
+    }
+
+    // Just one of the instances
+    pub fn draw_instance<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instance: usize) {
+        render_pass.push_debug_group(&self.label);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.n_indices, 0, instance as u32..instance as u32 + 1);
+        render_pass.pop_debug_group();
+    }
+
+    // The nearest instance the ray goes through a triangle of and how far along it that is. The
+    // ray is relative to the origin the mesh was last uploaded with, like the bounding box
+    pub fn raycast(&self, ray: &Ray) -> Option<(usize, f32)> {
+        let (min, max) = self.bounding_box?;
+        ray.intersect_aabb(min, max)?;
+        let (local_min, local_max) = self.data.bounds();
+        let mut nearest: Option<(usize, f32)> = None;
+        for (i, instance) in self.instances.iter().enumerate() {
+            let model = Matrix4::from(instance.to_raw(self.origin).model);
+            // Flattened instances can't be hit
+            let Some(inverse) = model.invert() else { continue };
+            // Tested in the mesh's own space, so the vertices don't all have to be moved
+            let local = Ray::new(inverse.transform_point(ray.origin), inverse.transform_vector(ray.direction));
+            if local.intersect_aabb(local_min, local_max).is_none() {
+                continue;
+            }
+            let vertex = |index: u32| Point3::from(self.data.vertices[index as usize].position);
+            let hit = self.data.indices.chunks_exact(3)
+                .filter_map(|triangle| local.intersect_triangle(vertex(triangle[0]), vertex(triangle[1]), vertex(triangle[2])))
+                .min_by(f32::total_cmp);
+            let Some(hit) = hit else { continue };
+            // Scaling changes distances, so it's measured again back out in the ray's space
+            let distance = (model.transform_point(local.at(hit)) - ray.origin).magnitude();
+            if nearest.is_none_or(|(_, nearest)| distance < nearest) {
+                nearest = Some((i, distance));
+            }
+        }
+        nearest
+    }
+
+    // Draws the geometry with someone else's instances, laid out like the mesh's own
+    pub fn draw_with_instances<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, instances: wgpu::BufferSlice<'a>, count: u32) {
+        render_pass.push_debug_group(&self.label);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, instances);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.draw_indexed(0..self.n_indices, 0, 0..count);
+        render_pass.pop_debug_group();
+    }
+}
+
+// Size of each chunk in a dynamic mesh's staging belt, big enough for most meshes in one go
+const STAGING_CHUNK_SIZE: wgpu::BufferAddress = 1 << 16;
+
+// A mesh whose vertices can be changed every frame on the CPU (cloth, water, soft bodies).
+// Changes are copied through a staging belt that lives as long as the mesh, so the GPU
+// buffers are never recreated unless set_data changes the vertex count or the indices
+pub struct DynamicMesh {
+    mesh: Mesh,
+    staging_belt: wgpu::util::StagingBelt,
+    dirty: bool,
+}
+
+impl DynamicMesh {
+    pub fn new(device: &wgpu::Device, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> Self {
+        Self::named(device, "Dynamic Mesh", data, instances, faces)
+    }
+
+    pub fn named(device: &wgpu::Device, label: &str, data: &MeshData, instances: &[Instance], faces: FaceSettings) -> Self {
+        Self {
+            mesh: Mesh::with_vertex_usage(device, label, data, instances, faces, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST, Vector3::zero()),
+            staging_belt: wgpu::util::StagingBelt::new(STAGING_CHUNK_SIZE),
+            dirty: false,
+        }
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    pub fn mesh_mut(&mut self) -> &mut Mesh {
+        &mut self.mesh
+    }
+
+    pub fn vertices(&self) -> &[Vertex] {
+        &self.mesh.data.vertices
+    }
+
+    // Marks the mesh as changed, the new vertices are uploaded before the next frame is drawn
+    pub fn vertices_mut(&mut self) -> &mut [Vertex] {
+        self.dirty = true;
+        &mut self.mesh.data.vertices
+    }
+
+    // Replaces the whole mesh. The vertex and index buffers are made again if the vertex count or
+    // the indices are different, otherwise only the vertices are uploaded. Either way everything but
+    // the geometry is kept
+    pub fn set_data(&mut self, device: &wgpu::Device, data: &MeshData) {
+        if data.vertices.len() != self.mesh.data.vertices.len() || data.indices != self.mesh.data.indices {
+            let faces = self.mesh.faces;
+            let instances = self.mesh.instances.clone();
+            let label = std::mem::take(&mut self.mesh.label);
+            let (material, impostor_distance, visible, tint) = (self.mesh.material, self.mesh.impostor_distance, self.mesh.visible, self.mesh.tint);
+            self.mesh = Mesh::with_vertex_usage(device, &label, data, &instances, faces, wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST, self.mesh.origin);
+            self.mesh.material = material;
+            self.mesh.impostor_distance = impostor_distance;
+            self.mesh.visible = visible;
+            self.mesh.tint = tint;
+            self.dirty = false;
+        } else {
+            self.mesh.data.vertices = data.vertices.clone();
+            self.dirty = true;
+        }
+    }
+
+    // Records the copy of any changed vertices into the encoder. finish_upload has to be
+    // called before the encoder is submitted and recall after it is
+    pub fn upload(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if !self.dirty {
+            return;
+        }
+        self.mesh.update_bounds();
+        let contents: &[u8] = bytemuck::cast_slice(&self.mesh.data.vertices);
+        if let Some(size) = wgpu::BufferSize::new(contents.len() as wgpu::BufferAddress) {
+            self.staging_belt
+                .write_buffer(encoder, &self.mesh.vertex_buffer, 0, size, device)
+                .copy_from_slice(contents);
+        }
+        self.dirty = false;
+    }
+
+    pub fn finish_upload(&mut self) {
+        self.staging_belt.finish();
+    }
+
+    // Reclaims the staging chunks the GPU is done with
+    pub fn recall(&mut self) {
+        self.staging_belt.recall();
+    }
+}
+
+/*
+ * Binary mesh cache
+ *
+ * Header (uncompressed, little endian):
+ *   magic "RMSH", format version u32, vertex count u32, index count u32,
+ *   position bounds min/max 6 x f32, texture coordinate bounds min/max 4 x f32
+ * Body (LZ4 compressed), per vertex:
+ *   position 3 x u16 (quantized within the bounds), normal 3 x i16 (snorm),
+ *   texture coordinates 2 x u16 (quantized within the bounds), color 4 x u8 (rgba)
+ * followed by every index as u32. Tangents aren't kept, they're generated again after loading
+ */
+
+const MAGIC: &[u8; 4] = b"RMSH";
+const VERSION: u32 = 2;
+const HEADER_SIZE: usize = 4 + 4 * 3 + 4 * 10;
+// Bytes per vertex and per index in the body
+const VERTEX_SIZE: usize = 3 * 2 + 3 * 2 + 2 * 2 + 4;
+const INDEX_SIZE: usize = 4;
+
+pub fn cache_path(source: &Path) -> PathBuf {
+    let mut path = source.as_os_str().to_owned();
+    path.push(".rmesh");
+    PathBuf::from(path)
+}
+
+fn is_fresh(source: &Path, cache: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|m| m.modified());
+    match (modified(source), modified(cache)) {
+        (Ok(source), Ok(cache)) => cache >= source,
+        // The source may not exist anymore if only the cache was shipped
+        (Err(_), Ok(_)) => true,
+        _ => false,
+    }
+}
+
+fn quantize(value: f32, min: f32, max: f32) -> u16 {
+    let range = max - min;
+    if range <= 0.0 { return 0; }
+    (((value - min) / range).clamp(0.0, 1.0) * u16::MAX as f32).round() as u16
+}
+
+fn dequantize(value: u16, min: f32, max: f32) -> f32 {
+    min + (value as f32 / u16::MAX as f32) * (max - min)
+}
+
+pub fn encode(mesh: &MeshData) -> Vec<u8> {
+    let (pos_min, pos_max) = mesh.bounds();
+    let mut uv_min = [f32::MAX; 2];
+    let mut uv_max = [f32::MIN; 2];
+    for vertex in &mesh.vertices {
+        for i in 0..2 {
+            uv_min[i] = uv_min[i].min(vertex.tex_coords[i]);
+            uv_max[i] = uv_max[i].max(vertex.tex_coords[i]);
+        }
+    }
+    if mesh.vertices.is_empty() {
+        uv_min = [0.0; 2];
+        uv_max = [0.0; 2];
+    }
+
+    let mut body = Vec::with_capacity(mesh.vertices.len() * VERTEX_SIZE + mesh.indices.len() * INDEX_SIZE);
+    for vertex in &mesh.vertices {
+        for i in 0..3 {
+            body.extend_from_slice(&quantize(vertex.position[i], pos_min[i], pos_max[i]).to_le_bytes());
+        }
+        for i in 0..3 {
+            let snorm = (vertex.normal[i].clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            body.extend_from_slice(&snorm.to_le_bytes());
+        }
+        for i in 0..2 {
+            body.extend_from_slice(&quantize(vertex.tex_coords[i], uv_min[i], uv_max[i]).to_le_bytes());
+        }
+        for channel in vertex.color.buffer() {
+            body.push((channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        }
+    }
+    for index in &mesh.indices {
+        body.extend_from_slice(&index.to_le_bytes());
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_SIZE + body.len() / 2);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(mesh.indices.len() as u32).to_le_bytes());
+    for value in pos_min.iter().chain(&pos_max).chain(&uv_min).chain(&uv_max) {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    bytes.extend_from_slice(&lz4_flex::compress_prepend_size(&body));
+    bytes
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        if self.bytes.len() < N {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "mesh cache is truncated"));
+        }
+        let (head, tail) = self.bytes.split_at(N);
+        self.bytes = tail;
+        Ok(head.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> io::Result<u8> { Ok(self.take::<1>()?[0]) }
+    fn u16(&mut self) -> io::Result<u16> { Ok(u16::from_le_bytes(self.take()?)) }
+    fn i16(&mut self) -> io::Result<i16> { Ok(i16::from_le_bytes(self.take()?)) }
+    fn u32(&mut self) -> io::Result<u32> { Ok(u32::from_le_bytes(self.take()?)) }
+    fn f32(&mut self) -> io::Result<f32> { Ok(f32::from_le_bytes(self.take()?)) }
+}
+
+pub fn decode(bytes: &[u8]) -> io::Result<MeshData> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+    let mut header = Reader { bytes };
+    if &header.take::<4>()? != MAGIC {
+        return Err(invalid("not a mesh cache"));
+    }
+    if header.u32()? != VERSION {
+        return Err(invalid("mesh cache was written by a different version"));
+    }
+    let n_vertices = header.u32()? as usize;
+    let n_indices = header.u32()? as usize;
+    let mut bounds = [0.0; 10];
+    for value in bounds.iter_mut() {
+        *value = header.f32()?;
+    }
+    let (pos_min, pos_max) = (&bounds[0..3], &bounds[3..6]);
+    let (uv_min, uv_max) = (&bounds[6..8], &bounds[8..10]);
+
+    // The counts and the body's size all come from the file, so they have to agree before anything
+    // is allocated for them. A corrupt header could ask for gigabytes otherwise
+    let body_size = n_vertices.checked_mul(VERTEX_SIZE)
+        .zip(n_indices.checked_mul(INDEX_SIZE))
+        .and_then(|(vertices, indices)| vertices.checked_add(indices))
+        .ok_or_else(|| invalid("mesh cache counts are too large"))?;
+    let (prepended_size, _) = lz4_flex::block::uncompressed_size(header.bytes)
+        .map_err(|e| invalid(&e.to_string()))?;
+    if prepended_size != body_size {
+        return Err(invalid("mesh cache body doesn't match its vertex and index counts"));
+    }
+    let body = lz4_flex::decompress_size_prepended(header.bytes)
+        .map_err(|e| invalid(&e.to_string()))?;
+    let mut reader = Reader { bytes: &body };
+
+    let mut vertices = Vec::with_capacity(n_vertices);
+    for _ in 0..n_vertices {
+        let mut position = [0.0; 3];
+        for (i, value) in position.iter_mut().enumerate() {
+            *value = dequantize(reader.u16()?, pos_min[i], pos_max[i]);
+        }
+        let mut normal = [0.0; 3];
+        for value in normal.iter_mut() {
+            *value = reader.i16()? as f32 / i16::MAX as f32;
+        }
+        let mut tex_coords = [0.0; 2];
+        for (i, value) in tex_coords.iter_mut().enumerate() {
+            *value = dequantize(reader.u16()?, uv_min[i], uv_max[i]);
+        }
+        let color = Color::new_rgba(
+            reader.u8()? as f32 / 255.0,
+            reader.u8()? as f32 / 255.0,
+            reader.u8()? as f32 / 255.0,
+            reader.u8()? as f32 / 255.0,
+        );
+        vertices.push(Vertex { position, color, normal, tex_coords, tangent: [0.0; 4] });
+    }
+
+    let mut indices = Vec::with_capacity(n_indices);
+    for _ in 0..n_indices {
+        let index = reader.u32()?;
+        if index as usize >= n_vertices {
+            return Err(invalid("mesh cache index out of range"));
+        }
+        indices.push(index);
+    }
+
+    Ok(MeshData { vertices, indices })
+}
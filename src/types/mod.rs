@@ -1,3 +1,55 @@
 pub mod color;
 pub mod geometry;
-pub mod camera;
\ No newline at end of file
+pub mod frame;
+pub mod keymap;
+pub mod gamepad;
+pub mod camera;
+pub mod camera_path;
+pub mod sequence;
+pub mod camera2d;
+pub mod tilemap;
+pub mod tiled;
+pub mod lighting2d;
+pub mod frustum;
+pub mod ray;
+pub mod labels;
+pub mod sampler;
+pub mod residency;
+pub mod mesh;
+pub mod tint;
+pub mod shader_features;
+pub mod impostor;
+pub mod material;
+pub mod oit;
+pub mod sky;
+pub mod skybox;
+pub mod lighting;
+pub mod weather;
+pub mod lens_flare;
+pub mod decal;
+pub mod grading;
+pub mod palette;
+pub mod hover;
+pub mod import;
+pub mod validation;
+#[cfg(feature = "hot-reload")]
+pub mod hot_reload;
+pub mod triangulate;
+pub mod texture;
+pub mod compressed;
+pub mod cubemap;
+pub mod procedural;
+pub mod ping_pong;
+pub mod atlas;
+pub mod voxel;
+pub mod stats;
+pub mod error_overlay;
+pub mod picking;
+pub mod quality;
+pub mod governor;
+pub mod timer;
+pub mod frames;
+pub mod view;
+pub mod settings;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
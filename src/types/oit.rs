@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::{gpu::Gpu, types::texture::Texture};
+
+// How SceneRenderer draws meshes with transparent materials, see Material::transparent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TransparencyMode {
+    // Whole meshes drawn furthest first for each view. Exact as long as transparent meshes don't
+    // cross each other or overlap themselves
+    #[default]
+    Sorted,
+    // Weighted blended order-independent transparency: every transparent fragment is added into
+    // two extra targets in whatever order it comes, and their weighted average is laid over the
+    // view. Nothing pops as meshes cross and pass each other, at the cost of the extra targets and
+    // layers that blend a bit softer than they should, nearer and more opaque ones weighing more.
+    // Every transparent material is alpha blended this way, additive ones included. Sample counts
+    // the targets can't be drawn with fall back to Sorted
+    WeightedBlended,
+}
+
+// Sum of each layer's premultiplied color and alpha, scaled by its weight
+pub(crate) const ACCUM_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// Product of one minus each layer's alpha, how much of what's behind still shows
+pub(crate) const REVEALAGE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R16Float;
+
+// What the mesh pipelines' fs_oit writes to, in order
+pub(crate) fn accumulate_targets() -> [Option<wgpu::ColorTargetState>; 2] {
+    let add = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add };
+    let multiply = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Zero, dst_factor: wgpu::BlendFactor::OneMinusSrc, operation: wgpu::BlendOperation::Add };
+    [
+        Some(wgpu::ColorTargetState { format: ACCUM_FORMAT, blend: Some(wgpu::BlendState { color: add, alpha: add }), write_mask: wgpu::ColorWrites::ALL }),
+        Some(wgpu::ColorTargetState { format: REVEALAGE_FORMAT, blend: Some(wgpu::BlendState { color: multiply, alpha: multiply }), write_mask: wgpu::ColorWrites::ALL }),
+    ]
+}
+
+// A target the composite can read, drawn into straight away without multisampling
+fn create_target(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, label: &str) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Texture { texture, view }
+}
+
+struct Targets {
+    size: [u32; 2],
+    sample_count: u32,
+    accum: Texture,
+    revealage: Texture,
+    // Drawn into and resolved onto the two above when multisampling
+    msaa: Option<(Texture, Texture)>,
+    bind_group: wgpu::BindGroup,
+}
+
+// The accumulation targets and the pass laying them over each view. Created by
+// SceneRenderer::set_transparency_mode
+pub(crate) struct WeightedBlendedOit {
+    format: wgpu::TextureFormat,
+    // Sample counts both targets can be drawn with alongside the depth buffer
+    supported_samples: Vec<u32>,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    // Composite pipelines by sample count
+    pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    // Made by prepare, and again when the size or sample count changes
+    targets: Option<Targets>,
+}
+
+impl WeightedBlendedOit {
+    pub fn new(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let device = &gpu.device;
+        let revealage_samples = gpu.supported_msaa_samples(REVEALAGE_FORMAT, Texture::DEPTH_FORMAT);
+        let supported_samples = gpu.supported_msaa_samples(ACCUM_FORMAT, Texture::DEPTH_FORMAT)
+            .into_iter()
+            .filter(|count| revealage_samples.contains(count))
+            .collect();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("OIT Composite Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../oit.wgsl").into()) });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("oit_bind_group_layout"),
+            entries: &[texture_entry(0), texture_entry(1)],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("OIT Composite Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        Self { format, supported_samples, shader, layout, pipeline_layout, pipelines: HashMap::new(), targets: None }
+    }
+
+    pub fn supports(&self, sample_count: u32) -> bool {
+        self.supported_samples.contains(&sample_count)
+    }
+
+    // The composite pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    // Builds the targets for the scene's size and sample count, and the composite pipeline
+    pub fn prepare(&mut self, device: &wgpu::Device, size: [u32; 2], sample_count: u32) {
+        if !self.targets.as_ref().is_some_and(|targets| targets.size == size && targets.sample_count == sample_count) {
+            let accum = create_target(device, size, ACCUM_FORMAT, "oit_accum_texture");
+            let revealage = create_target(device, size, REVEALAGE_FORMAT, "oit_revealage_texture");
+            let msaa = (sample_count > 1).then(|| (
+                Texture::create_msaa_texture(device, size, ACCUM_FORMAT, sample_count, "oit_msaa_accum_texture"),
+                Texture::create_msaa_texture(device, size, REVEALAGE_FORMAT, sample_count, "oit_msaa_revealage_texture"),
+            ));
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("oit_bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&accum.view) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&revealage.view) },
+                ],
+            });
+            self.targets = Some(Targets { size, sample_count, accum, revealage, msaa, bind_group });
+        }
+        if self.pipelines.contains_key(&sample_count) {
+            return;
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("OIT Composite Pipeline ({sample_count} samples)")),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Shares a pass with the hover highlight, which tests against the depth
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert(sample_count, pipeline);
+    }
+
+    // The accumulation pass's color attachments, cleared to nothing drawn yet
+    pub fn attachments(&self) -> [Option<wgpu::RenderPassColorAttachment<'_>>; 2] {
+        let targets = self.targets.as_ref().expect("WeightedBlendedOit::prepare wasn't called");
+        fn attachment<'a>(texture: &'a Texture, msaa: Option<&'a Texture>, clear: wgpu::Color) -> Option<wgpu::RenderPassColorAttachment<'a>> {
+            let (view, resolve_target) = match msaa {
+                Some(msaa) => (&msaa.view, Some(&texture.view)),
+                None => (&texture.view, None),
+            };
+            // The multisampled targets are only needed until they're resolved
+            let store = if msaa.is_some() { wgpu::StoreOp::Discard } else { wgpu::StoreOp::Store };
+            Some(wgpu::RenderPassColorAttachment { view, resolve_target, ops: wgpu::Operations { load: wgpu::LoadOp::Clear(clear), store } })
+        }
+        let (msaa_accum, msaa_revealage) = match &targets.msaa {
+            Some((accum, revealage)) => (Some(accum), Some(revealage)),
+            None => (None, None),
+        };
+        [
+            attachment(&targets.accum, msaa_accum, wgpu::Color::TRANSPARENT),
+            attachment(&targets.revealage, msaa_revealage, wgpu::Color::WHITE),
+        ]
+    }
+
+    // Draws into a pass over the scene's color with its depth attached
+    pub fn composite<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, sample_count: u32) {
+        let Some(targets) = &self.targets else { return };
+        render_pass.push_debug_group("OIT Composite");
+        render_pass.set_pipeline(&self.pipelines[&sample_count]);
+        render_pass.set_bind_group(0, &targets.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,275 @@
+use wgpu::util::DeviceExt;
+
+use crate::types::{color::{Color, ColorParseError}, texture::Texture};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PaletteError {
+    Empty,
+    TooManyColors(usize),
+    // A line of a .hex file that isn't a color
+    InvalidColor { line: usize, error: ColorParseError },
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PaletteError::Empty => write!(f, "a palette needs at least one color"),
+            PaletteError::TooManyColors(count) => write!(f, "{count} colors is more than the {} a palette can have", Palette::MAX_COLORS),
+            PaletteError::InvalidColor { line, error } => write!(f, "line {line}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+// The only colors a frame is allowed to end up with while it's set on the scene, every pixel
+// snapped to whichever looks nearest. See SceneRenderer::set_palette
+#[derive(Clone, Debug)]
+pub struct Palette {
+    colors: Vec<Color>,
+}
+
+impl Palette {
+    pub const MAX_COLORS: usize = 256;
+
+    // Alpha is ignored, every pixel keeps its own
+    pub fn new(colors: &[Color]) -> Result<Self, PaletteError> {
+        match colors.len() {
+            0 => Err(PaletteError::Empty),
+            count if count > Self::MAX_COLORS => Err(PaletteError::TooManyColors(count)),
+            _ => Ok(Self { colors: colors.to_vec() }),
+        }
+    }
+
+    // Every different color in the image, left to right and top to bottom, skipping transparent
+    // pixels. Works for a strip of one pixel per color as well as swatches of any size
+    pub fn from_image(image: &image::RgbaImage) -> Result<Self, PaletteError> {
+        let mut seen = Vec::new();
+        for pixel in image.pixels().filter(|pixel| pixel.0[3] > 0) {
+            let rgb = [pixel.0[0], pixel.0[1], pixel.0[2]];
+            if !seen.contains(&rgb) {
+                seen.push(rgb);
+            }
+        }
+        let colors = seen.iter()
+            .map(|rgb| { let [r, g, b] = rgb.map(|c| c as f32 / 255.0); Color::new(r, g, b).to_linear() })
+            .collect::<Vec<_>>();
+        Self::new(&colors)
+    }
+
+    // A hex color on each line, as Lospec and most palette editors save them. Blank lines and ones
+    // starting with ; or // are skipped
+    pub fn parse_hex(text: &str) -> Result<Self, PaletteError> {
+        let colors = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with(';') && !line.starts_with("//"))
+            .map(|(line, hex)| Color::from_hex(hex).map_err(|error| PaletteError::InvalidColor { line, error }))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(&colors)
+    }
+
+    // A .hex file, or an image of the colors for anything else
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let palette = if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("hex")) {
+            Self::parse_hex(&std::fs::read_to_string(path)?)
+        } else {
+            let image = image::open(path).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?.to_rgba8();
+            Self::from_image(&image)
+        };
+        palette.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    // The palette color nearest to color in OkLab, the same one the post pass picks without
+    // dithering
+    pub fn nearest(&self, color: Color) -> Color {
+        let [l, a, b] = color.to_oklab();
+        let distance = |other: &Color| {
+            let [l1, a1, b1] = other.to_oklab();
+            (l - l1).powi(2) + (a - a1).powi(2) + (b - b1).powi(2)
+        };
+        let nearest = self.colors.iter().min_by(|x, y| distance(x).total_cmp(&distance(y))).unwrap();
+        nearest.with_alpha(color.alpha())
+    }
+
+    // A color per texel across, linear in the first row and OkLab in the second so the shader
+    // doesn't convert them for every pixel
+    fn texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let width = self.colors.len() as u32;
+        let size = wgpu::Extent3d { width, height: 2, depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("palette_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let linear = self.colors.iter().map(|color| color.with_alpha(1.0).buffer());
+        let oklab = self.colors.iter().map(|color| { let [l, a, b] = color.to_oklab(); [l, a, b, 1.0] });
+        let texels = linear.chain(oklab).collect::<Vec<[f32; 4]>>();
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(16 * width), rows_per_image: None },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Texture { texture, view }
+    }
+}
+
+// The frame the scene is drawn into while there's a palette
+struct Target {
+    size: [u32; 2],
+    texture: Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+// The very last pass of a frame, after color grading, snapping every pixel to the palette on its
+// way onto the real target. Created by SceneRenderer::set_palette
+pub(crate) struct PaletteRenderer {
+    format: wgpu::TextureFormat,
+    count: u32,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    // Made on the first prepare after a format change
+    pipeline: Option<wgpu::RenderPipeline>,
+    palette: Texture,
+    // The color count and dithering strength
+    uniform: wgpu::Buffer,
+    // Made by prepare, and again when the size changes
+    target: Option<Target>,
+}
+
+impl PaletteRenderer {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, format: wgpu::TextureFormat, palette: &Palette) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Palette Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../palette.wgsl").into()) });
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("palette_bind_group_layout"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer { ty: wgpu::BufferBindingType::Uniform, has_dynamic_offset: false, min_binding_size: None },
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Palette Pipeline Layout"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Palette Buffer"),
+            contents: bytemuck::bytes_of(&[0.0f32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        Self {
+            format,
+            count: palette.colors.len() as u32,
+            shader,
+            layout,
+            pipeline_layout,
+            pipeline: None,
+            palette: palette.texture(device, queue),
+            uniform,
+            target: None,
+        }
+    }
+
+    // The pipeline and the frame the scene draws into are both made again on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipeline = None;
+        self.target = None;
+    }
+
+    // dithering is how far pixels between two palette colors are spread over both, 0 for none
+    pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, size: [u32; 2], dithering: f32) {
+        queue.write_buffer(&self.uniform, 0, bytemuck::bytes_of(&[self.count as f32, dithering.clamp(0.0, 1.0), 0.0, 0.0]));
+        if self.target.as_ref().is_none_or(|target| target.size != size) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("palette_frame"),
+                size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: self.format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let texture = Texture { texture, view };
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("palette_bind_group"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&texture.view) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&self.palette.view) },
+                    wgpu::BindGroupEntry { binding: 2, resource: self.uniform.as_entire_binding() },
+                ],
+            });
+            self.target = Some(Target { size, texture, bind_group });
+        }
+        if self.pipeline.is_some() {
+            return;
+        }
+        self.pipeline = Some(device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Palette Pipeline"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        }));
+    }
+
+    // What's drawn into instead of the target, by the scene's passes or the color grading
+    pub fn frame_view(&self) -> &wgpu::TextureView {
+        &self.target.as_ref().expect("PaletteRenderer::prepare wasn't called").texture.view
+    }
+
+    // Draws into a pass over the real target, covering all of it
+    pub fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+        let (Some(target), Some(pipeline)) = (&self.target, &self.pipeline) else { return };
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &target.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use cgmath::{Matrix4, Point3};
+use wgpu::util::DeviceExt;
+use crate::types::{
+    camera::CameraMatrices,
+    geometry::{Vertex, InstanceRaw},
+    mesh::{FaceSettings, Mesh},
+    texture::Texture,
+};
+
+// Which of State's meshes something belongs to, static and dynamic meshes are numbered separately
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MeshId {
+    Static(usize),
+    Dynamic(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Pick {
+    pub mesh: MeshId,
+    pub instance: usize,
+}
+
+// What a ray cast into the scene went through first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub pick: Pick,
+    // Along the ray, in world units
+    pub distance: f32,
+    // Relative to the active camera's origin, like the ray
+    pub point: Point3<f32>,
+}
+
+// Rgba8 with plain byte reads is the one readback WebGL2 is guaranteed to support
+const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+// Copies out of a texture have to use rows this long, even for a single pixel
+const READBACK_SIZE: wgpu::BufferAddress = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as wgpu::BufferAddress;
+
+// Finds what's under a pixel by drawing the scene into a 1x1 target, zoomed so that pixel fills it,
+// with each instance in a color that encodes its id. Only needs a single byte readback and waits
+// on it synchronously, so it works the same on WebGL2 as on native backends
+pub struct Picker {
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    pipelines: HashMap<FaceSettings, wgpu::RenderPipeline>,
+    target: wgpu::Texture,
+    target_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    mesh_layout: wgpu::BindGroupLayout,
+    // One 16 byte id block per mesh, spaced out to the uniform offset alignment
+    mesh_buffer: wgpu::Buffer,
+    mesh_bind_group: wgpu::BindGroup,
+    mesh_stride: wgpu::BufferAddress,
+    readback: wgpu::Buffer,
+}
+
+impl Picker {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Pick Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../pick.wgsl").into()) });
+
+        let uniform_entry = |has_dynamic_offset| wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("pick_camera_bind_group_layout"),
+            entries: &[uniform_entry(false)],
+        });
+        let mesh_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("pick_mesh_bind_group_layout"),
+            entries: &[uniform_entry(true)],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Pick Pipeline Layout"),
+            bind_group_layouts: &[&camera_layout, &mesh_layout],
+            push_constant_ranges: &[],
+        });
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Pick Camera Buffer"),
+            contents: bytemuck::cast_slice(&[[[0.0f32; 4]; 4]]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pick_camera_bind_group"),
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+
+        let size = wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 };
+        let target = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("pick_target"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let target_view = target.create_view(&wgpu::TextureViewDescriptor::default());
+        let depth = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("pick_depth"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Texture::DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let depth_view = depth.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_readback"),
+            size: READBACK_SIZE,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mesh_stride = (device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress).max(16);
+        let (mesh_buffer, mesh_bind_group) = Self::create_mesh_buffer(device, &mesh_layout, mesh_stride, 1);
+
+        Self {
+            shader,
+            layout,
+            pipelines: HashMap::new(),
+            target,
+            target_view,
+            depth_view,
+            camera_buffer,
+            camera_bind_group,
+            mesh_layout,
+            mesh_buffer,
+            mesh_bind_group,
+            mesh_stride,
+            readback,
+        }
+    }
+
+    fn create_mesh_buffer(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, stride: wgpu::BufferAddress, count: usize) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("pick_mesh_ids"),
+            size: stride * count.max(1) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("pick_mesh_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(16),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, faces: FaceSettings) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Pick Pipeline"),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // Culled the same way as when drawn, so only what's visible can be picked
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: faces.front_face,
+                cull_mode: faces.cull_mode,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    // Blows up the area around one point on screen to fill the whole of clip space, so a 1x1
+    // target sees exactly that pixel. screen goes from (0, 0) at the top left to (1, 1)
+    fn pick_matrix(screen: [f32; 2], viewport: [u32; 2]) -> Matrix4<f32> {
+        let x = screen[0] * 2.0 - 1.0;
+        let y = 1.0 - screen[1] * 2.0;
+        let (sx, sy) = (viewport[0] as f32, viewport[1] as f32);
+        Matrix4::new(
+            sx, 0.0, 0.0, 0.0,
+            0.0, sy, 0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            -x * sx, -y * sy, 0.0, 1.0,
+        )
+    }
+
+    // Returns the index into meshes and the instance under the point, or None if there's nothing there.
+    // Blocks until the GPU has finished, so it's meant for clicks rather than every frame.
+    // faces is what each mesh is actually drawn with
+    pub fn pick(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        matrices: &CameraMatrices,
+        screen: [f32; 2],
+        viewport: [u32; 2],
+        meshes: &[(&Mesh, FaceSettings)],
+    ) -> Option<(usize, usize)> {
+        if !(0.0..=1.0).contains(&screen[0]) || !(0.0..=1.0).contains(&screen[1]) {
+            return None;
+        }
+
+        let view_proj: [[f32; 4]; 4] = (Self::pick_matrix(screen, viewport) * matrices.view_projection).into();
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[view_proj]));
+
+        // Ids run on from one mesh's instances to the next
+        if (self.mesh_buffer.size() / self.mesh_stride) < meshes.len() as wgpu::BufferAddress {
+            (self.mesh_buffer, self.mesh_bind_group) = Self::create_mesh_buffer(device, &self.mesh_layout, self.mesh_stride, meshes.len().next_power_of_two());
+        }
+        let mut bases = Vec::with_capacity(meshes.len());
+        let mut next = 0u32;
+        for (i, (mesh, faces)) in meshes.iter().enumerate() {
+            bases.push(next);
+            queue.write_buffer(&self.mesh_buffer, i as wgpu::BufferAddress * self.mesh_stride, bytemuck::cast_slice(&[next, 0, 0, 0]));
+            next += mesh.instances().len() as u32;
+            if !self.pipelines.contains_key(faces) {
+                let pipeline = self.create_pipeline(device, *faces);
+                self.pipelines.insert(*faces, pipeline);
+            }
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Pick Encoder") });
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Pick Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.target_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Discard,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            for (i, (mesh, faces)) in meshes.iter().enumerate() {
+                if mesh.instances().is_empty() {
+                    continue;
+                }
+                render_pass.set_pipeline(&self.pipelines[faces]);
+                render_pass.set_bind_group(1, &self.mesh_bind_group, &[(i as wgpu::BufferAddress * self.mesh_stride) as u32]);
+                mesh.draw(&mut render_pass);
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            self.target.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(READBACK_SIZE as u32),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        // WebGL can't wait on the GPU asynchronously anyway, so just block until the copy is done
+        let slice = self.readback.slice(..4);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = receiver.recv().ok()? {
+            log::warn!("Couldn't read back the pick result: {e}");
+            return None;
+        }
+        let id = {
+            let bytes = slice.get_mapped_range();
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+        };
+        self.readback.unmap();
+
+        let id = id.checked_sub(1)?;
+        let mesh = meshes.iter().zip(&bases)
+            .position(|((mesh, _), &base)| id >= base && id - base < mesh.instances().len() as u32)?;
+        Some((mesh, (id - bases[mesh]) as usize))
+    }
+}
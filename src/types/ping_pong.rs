@@ -0,0 +1,119 @@
+use crate::types::texture::Texture;
+
+// Two render textures the same size and format, one read from while the other is drawn into,
+// then swapped. For effects that keep working on their own output: blur chains, simulations
+// stepped on a texture, feedback trails. A step goes
+//
+//     let mut pass = ping_pong.pass(&mut encoder, None, "Blur Step");
+//     pass.set_bind_group(0, &bind_groups[ping_pong.read_index()], &[]);
+//     ...
+//     drop(pass);
+//     ping_pong.swap();
+//
+// after which read() is what the step drew
+pub struct PingPong {
+    size: [u32; 2],
+    format: wgpu::TextureFormat,
+    label: String,
+    textures: [Texture; 2],
+    // Which of the two holds the latest result
+    read: usize,
+}
+
+impl PingPong {
+    // Both start out cleared to nothing. They can also be copied in and out of, say to seed a
+    // simulation with queue.write_texture on read() or copy the result somewhere else
+    pub fn new(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, label: &str) -> Self {
+        let textures = [0, 1].map(|i| create_texture(device, size, format, &format!("{label}_{i}")));
+        Self { size, format, label: label.to_string(), textures, read: 0 }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    // The latest result, for the next step to sample
+    pub fn read(&self) -> &Texture {
+        &self.textures[self.read]
+    }
+
+    // Where the next step draws
+    pub fn write(&self) -> &Texture {
+        &self.textures[1 - self.read]
+    }
+
+    // Which of the two read() is, to pick from bind_groups
+    pub fn read_index(&self) -> usize {
+        self.read
+    }
+
+    // Call after each step, so what was just drawn becomes read()
+    pub fn swap(&mut self) {
+        self.read = 1 - self.read;
+    }
+
+    // Makes both again at the new size, dropping what was in them. Bind groups made before need
+    // making again. Returns whether anything changed
+    pub fn resize(&mut self, device: &wgpu::Device, size: [u32; 2]) -> bool {
+        if size == self.size {
+            return false;
+        }
+        *self = Self::new(device, size, self.format, &self.label);
+        true
+    }
+
+    // A bind group reading each texture, in the same order as read_index counts. The texture is at
+    // binding 0 and the sampler, if there's one, at binding 1
+    pub fn bind_groups(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, sampler: Option<&wgpu::Sampler>) -> [wgpu::BindGroup; 2] {
+        [0, 1].map(|i| {
+            let mut entries = vec![wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&self.textures[i].view) }];
+            if let Some(sampler) = sampler {
+                entries.push(wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(sampler) });
+            }
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{}_bind_group_{i}", self.label)),
+                layout,
+                entries: &entries,
+            })
+        })
+    }
+
+    // A pass drawing into write(), cleared to clear first or keeping what's there with None. Most
+    // steps cover every texel and don't need clearing
+    pub fn pass<'a>(&'a self, encoder: &'a mut wgpu::CommandEncoder, clear: Option<wgpu::Color>, label: &str) -> wgpu::RenderPass<'a> {
+        let load = clear.map_or(wgpu::LoadOp::Load, wgpu::LoadOp::Clear);
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &self.write().view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: wgpu::StoreOp::Store },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+}
+
+fn create_texture(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, label: &str) -> Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: 1 },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    Texture { texture, view }
+}
@@ -0,0 +1,238 @@
+use std::f32::consts::TAU;
+
+use crate::types::{color::Color, texture::Texture};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseKind {
+    // Smooth blobs on a square grid. Whole numbers of them fit across the image, so it tiles
+    #[default]
+    Perlin,
+    // Less grid-like than Perlin and without its faint straight lines, but doesn't tile
+    Simplex,
+}
+
+// Layers of gradient noise added together, each finer and fainter than the last
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    // Different seeds give unrelated noise
+    pub seed: u32,
+    // Features across the image in the first layer. Rounded to a whole number for Perlin
+    pub frequency: f32,
+    // How many layers, each twice the frequency of the one before
+    pub octaves: u32,
+    // How strong each layer is compared to the one before
+    pub persistence: f32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self { kind: NoiseKind::Perlin, seed: 0, frequency: 4.0, octaves: 4, persistence: 0.5 }
+    }
+}
+
+impl Noise {
+    pub fn perlin(frequency: f32) -> Self {
+        Self { frequency, ..Self::default() }
+    }
+
+    pub fn simplex(frequency: f32) -> Self {
+        Self { kind: NoiseKind::Simplex, frequency, ..Self::default() }
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    pub fn with_persistence(mut self, persistence: f32) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    // 0 to 1 at u and v, which go 0 to 1 across the image
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let mut frequency = match self.kind {
+            NoiseKind::Perlin => self.frequency.round().max(1.0),
+            NoiseKind::Simplex => self.frequency.max(0.0),
+        };
+        let (mut total, mut amplitude, mut weight) = (0.0, 1.0, 0.0);
+        for octave in 0..self.octaves.max(1) {
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+            total += amplitude * match self.kind {
+                NoiseKind::Perlin => perlin(u * frequency, v * frequency, frequency as i32, seed),
+                NoiseKind::Simplex => simplex(u * frequency, v * frequency, seed),
+            };
+            weight += amplitude;
+            amplitude *= self.persistence;
+            frequency *= 2.0;
+        }
+        (total / weight * 0.5 + 0.5).clamp(0.0, 1.0)
+    }
+
+    // A tangent space normal map of the noise as a height field, for Material::with_normal_texture.
+    // strength is how tall the bumps are relative to the image's width. Upload it with
+    // Texture::from_rgba as Rgba8Unorm, not sRGB
+    pub fn normal_image(&self, width: u32, height: u32, strength: f32) -> image::RgbaImage {
+        let (width, height) = (width.max(1), height.max(1));
+        let heights = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.sample((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32))
+            .collect::<Vec<_>>();
+        // Wraps around the edges, which matches up as long as the noise tiles
+        let at = |x: i64, y: i64| heights[(y.rem_euclid(height as i64) * width as i64 + x.rem_euclid(width as i64)) as usize];
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let (x, y) = (x as i64, y as i64);
+            let dx = (at(x + 1, y) - at(x - 1, y)) * strength * width as f32 * 0.5;
+            let dy = (at(x, y + 1) - at(x, y - 1)) * strength * width as f32 * 0.5;
+            // Image rows go down while v goes up in tangent space
+            let length = (dx * dx + dy * dy + 1.0).sqrt();
+            let encode = |c: f32| ((c / length * 0.5 + 0.5) * 255.0).round() as u8;
+            image::Rgba([encode(-dx), encode(dy), encode(1.0), 255])
+        })
+    }
+}
+
+// Something to fill an image with, for trying materials out without any image files
+#[derive(Clone, Copy, Debug)]
+pub enum Pattern {
+    // cells squares across and down, starting with even in the top left
+    Checkerboard { cells: u32, even: Color, odd: Color },
+    // Straight across the image at angle degrees, 0 runs left to right and 90 top to bottom
+    LinearGradient { from: Color, to: Color, angle: f32 },
+    // From inner at the middle to outer at the middle of each edge, and past it in the corners
+    RadialGradient { inner: Color, outer: Color },
+    // From low where the noise is 0 to high where it's 1, straight between the channels so the
+    // noise comes through as it is in data textures
+    Noise { noise: Noise, low: Color, high: Color },
+}
+
+impl Pattern {
+    pub fn checkerboard(cells: u32, even: Color, odd: Color) -> Self {
+        Self::Checkerboard { cells, even, odd }
+    }
+
+    pub fn linear_gradient(from: Color, to: Color, angle: f32) -> Self {
+        Self::LinearGradient { from, to, angle }
+    }
+
+    pub fn radial_gradient(inner: Color, outer: Color) -> Self {
+        Self::RadialGradient { inner, outer }
+    }
+
+    // Black to white, also handy as a roughness or height map through linear_texture
+    pub fn noise(noise: Noise) -> Self {
+        Self::Noise { noise, low: Color::BLACK, high: Color::WHITE }
+    }
+
+    // Gradients go between their colors through OkLab, see Color::mix
+    pub fn color_at(&self, u: f32, v: f32) -> Color {
+        match *self {
+            Pattern::Checkerboard { cells, even, odd } => {
+                let cells = cells.max(1) as f32;
+                let (x, y) = ((u * cells).floor() as i64, (v * cells).floor() as i64);
+                if (x + y).rem_euclid(2) == 0 { even } else { odd }
+            }
+            Pattern::LinearGradient { from, to, angle } => {
+                let (sin, cos) = angle.to_radians().sin_cos();
+                // Projected onto the direction, scaled so the corners it starts and ends in are 0 and 1
+                let extent = sin.abs() + cos.abs();
+                let t = ((u - 0.5) * cos + (v - 0.5) * sin) / extent + 0.5;
+                from.mix(to, t.clamp(0.0, 1.0))
+            }
+            Pattern::RadialGradient { inner, outer } => {
+                let t = ((u - 0.5).powi(2) + (v - 0.5).powi(2)).sqrt() * 2.0;
+                inner.mix(outer, t.min(1.0))
+            }
+            Pattern::Noise { noise, low, high } => low.lerp(high, noise.sample(u, v)),
+        }
+    }
+
+    // Sampled at each texel's middle and encoded to sRGB, ready for Texture::from_image
+    pub fn image(&self, width: u32, height: u32) -> image::RgbaImage {
+        self.fill(width, height, Color::to_srgb)
+    }
+
+    // An sRGB color texture, for Material::with_texture
+    pub fn texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, label: &str) -> Texture {
+        Texture::from_image(device, queue, &self.image(width, height), label)
+    }
+
+    // The colors as plain values rather than sRGB, for textures that hold data like
+    // Material::with_metallic_roughness_texture
+    pub fn linear_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue, width: u32, height: u32, label: &str) -> Texture {
+        Texture::from_rgba(device, queue, &self.fill(width, height, |color| color), wgpu::TextureFormat::Rgba8Unorm, label)
+    }
+
+    fn fill(&self, width: u32, height: u32, encode: impl Fn(Color) -> Color) -> image::RgbaImage {
+        let (width, height) = (width.max(1), height.max(1));
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let color = encode(self.color_at((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32));
+            image::Rgba(color.buffer().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        })
+    }
+}
+
+// A well mixed 32 bit hash of a lattice point
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ seed.wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^ (h >> 16)
+}
+
+// A unit length direction picked by the lattice point
+fn gradient(x: i32, y: i32, seed: u32) -> (f32, f32) {
+    let angle = hash(x, y, seed) as f32 / u32::MAX as f32 * TAU;
+    (angle.cos(), angle.sin())
+}
+
+// Roughly -1 to 1, repeating every period lattice cells
+fn perlin(x: f32, y: f32, period: i32, seed: u32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let (x0, y0) = (x0 as i32, y0 as i32);
+    let corner = |dx: i32, dy: i32| {
+        let (gx, gy) = gradient((x0 + dx).rem_euclid(period), (y0 + dy).rem_euclid(period), seed);
+        gx * (fx - dx as f32) + gy * (fy - dy as f32)
+    };
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (u, v) = (fade(fx), fade(fy));
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let value = lerp(lerp(corner(0, 0), corner(1, 0), u), lerp(corner(0, 1), corner(1, 1), u), v);
+    // The most a 2D Perlin value can be is half the square root of 2
+    value * std::f32::consts::SQRT_2
+}
+
+// Roughly -1 to 1, summed from the corners of the triangle x and y fall in
+fn simplex(x: f32, y: f32, seed: u32) -> f32 {
+    const F2: f32 = 0.366_025_42;
+    const G2: f32 = 0.211_324_87;
+    let skew = (x + y) * F2;
+    let (i, j) = ((x + skew).floor(), (y + skew).floor());
+    let unskew = (i + j) * G2;
+    let (x0, y0) = (x - (i - unskew), y - (j - unskew));
+    let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+    let corners = [
+        (0, 0, x0, y0),
+        (i1, j1, x0 - i1 as f32 + G2, y0 - j1 as f32 + G2),
+        (1, 1, x0 - 1.0 + 2.0 * G2, y0 - 1.0 + 2.0 * G2),
+    ];
+    let (i, j) = (i as i32, j as i32);
+    let value: f32 = corners.iter().map(|&(di, dj, dx, dy)| {
+        let t = 0.5 - dx * dx - dy * dy;
+        if t <= 0.0 {
+            return 0.0;
+        }
+        let (gx, gy) = gradient(i + di, j + dj, seed);
+        t.powi(4) * (gx * dx + gy * dy)
+    }).sum();
+    value * 70.0
+}
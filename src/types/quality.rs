@@ -0,0 +1,109 @@
+use std::{fmt, str::FromStr};
+
+// Everything a quality preset decides. Values the adapter can't do are lowered to what it can
+// when they're applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualitySettings {
+    // 1 turns multisampling off
+    pub msaa_samples: u32,
+    // Fraction of the window's resolution to render at
+    pub render_scale: f32,
+    // Most anisotropy any texture sampler gets, whatever it asks for
+    pub anisotropy: u16,
+    // For the shadow, SSAO and bloom passes. None of those exist yet, so these are only carried
+    // along for now
+    pub shadow_resolution: u32,
+    pub shadow_cascades: u32,
+    pub ssao: bool,
+    pub bloom: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum QualityPreset {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl QualityPreset {
+    pub const ALL: [Self; 4] = [Self::Low, Self::Medium, Self::High, Self::Ultra];
+
+    pub fn settings(self) -> QualitySettings {
+        match self {
+            Self::Low => QualitySettings {
+                msaa_samples: 1,
+                render_scale: 0.75,
+                anisotropy: 1,
+                shadow_resolution: 512,
+                shadow_cascades: 1,
+                ssao: false,
+                bloom: false,
+            },
+            Self::Medium => QualitySettings {
+                msaa_samples: 1,
+                render_scale: 1.0,
+                anisotropy: 4,
+                shadow_resolution: 1024,
+                shadow_cascades: 2,
+                ssao: false,
+                bloom: true,
+            },
+            Self::High => QualitySettings {
+                msaa_samples: 4,
+                render_scale: 1.0,
+                anisotropy: 8,
+                shadow_resolution: 2048,
+                shadow_cascades: 3,
+                ssao: true,
+                bloom: true,
+            },
+            Self::Ultra => QualitySettings {
+                msaa_samples: 8,
+                render_scale: 1.0,
+                anisotropy: 16,
+                shadow_resolution: 4096,
+                shadow_cascades: 4,
+                ssao: true,
+                bloom: true,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+            Self::Ultra => "ultra",
+        }
+    }
+}
+
+impl fmt::Display for QualityPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownQualityPreset(pub String);
+
+impl fmt::Display for UnknownQualityPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown quality preset {:?}, expected low, medium, high or ultra", self.0)
+    }
+}
+
+impl std::error::Error for UnknownQualityPreset {}
+
+impl FromStr for QualityPreset {
+    type Err = UnknownQualityPreset;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| UnknownQualityPreset(s.to_string()))
+    }
+}
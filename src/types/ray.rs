@@ -0,0 +1,73 @@
+use cgmath::{InnerSpace, Point3, Vector3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // Always normalized, so distances along the ray are in world units
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction: direction.normalize() }
+    }
+
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    // Distance to the plane normal . p = offset, if it's hit in front of the origin
+    pub fn intersect_plane(&self, normal: Vector3<f32>, offset: f32) -> Option<f32> {
+        let facing = normal.dot(self.direction);
+        if facing.abs() < 1e-8 {
+            return None;
+        }
+        let distance = (offset - normal.dot(self.origin.to_homogeneous().truncate())) / facing;
+        (distance >= 0.0).then_some(distance)
+    }
+
+    // Distance to where the ray enters the box, 0 if it starts inside
+    pub fn intersect_aabb(&self, min: [f32; 3], max: [f32; 3]) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for i in 0..3 {
+            let inverse = 1.0 / self.direction[i];
+            let mut t0 = (min[i] - self.origin[i]) * inverse;
+            let mut t1 = (max[i] - self.origin[i]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // NaN from a zero direction inside the slab leaves near and far alone
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+
+    // Distance to where the ray goes through the triangle, from either side
+    pub fn intersect_triangle(&self, a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Option<f32> {
+        let e1 = b - a;
+        let e2 = c - a;
+        let p = self.direction.cross(e2);
+        let det = e1.dot(p);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inverse = 1.0 / det;
+        let s = self.origin - a;
+        let u = s.dot(p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = s.cross(e1);
+        let v = self.direction.dot(q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = e2.dot(q) * inverse;
+        (distance >= 0.0).then_some(distance)
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+use crate::types::texture::Texture;
+
+pub type TextureId = u64;
+
+// What the renderer has to do to a texture to get back under budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    // Throw away every mip level below base_mip (the largest ones)
+    DropMips { id: TextureId, base_mip: u32 },
+    // Throw away the whole texture, it will be re-uploaded when it's used again
+    Evict { id: TextureId },
+}
+
+#[derive(Clone, Debug)]
+struct Resident {
+    // Size in bytes of each mip level, largest first
+    mip_sizes: Vec<u64>,
+    // First mip level that is on the GPU, mip_sizes.len() means nothing is resident
+    base_mip: u32,
+    last_used: u64,
+    distance: f32,
+}
+
+impl Resident {
+    fn resident_bytes(&self) -> u64 {
+        self.mip_sizes.iter().skip(self.base_mip as usize).sum()
+    }
+
+    fn is_evicted(&self) -> bool {
+        self.base_mip as usize >= self.mip_sizes.len()
+    }
+}
+
+// Keeps track of how much texture memory is on the GPU and decides what to drop when
+// a budget is exceeded, least recently used and most distant textures first
+pub struct TextureBudget {
+    budget: u64,
+    used: u64,
+    frame: u64,
+    textures: HashMap<TextureId, Resident>,
+}
+
+impl TextureBudget {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            used: 0,
+            frame: 0,
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    pub fn set_budget(&mut self, budget: u64) {
+        self.budget = budget;
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    // Registers a freshly uploaded texture with all of its mips resident
+    pub fn register(&mut self, id: TextureId, mip_sizes: Vec<u64>) {
+        self.unregister(id);
+        let resident = Resident {
+            mip_sizes,
+            base_mip: 0,
+            last_used: self.frame,
+            distance: 0.0,
+        };
+        self.used += resident.resident_bytes();
+        self.textures.insert(id, resident);
+    }
+
+    pub fn unregister(&mut self, id: TextureId) {
+        if let Some(resident) = self.textures.remove(&id) {
+            self.used -= resident.resident_bytes();
+        }
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    // First mip level of the texture currently on the GPU, None if it has been evicted entirely
+    pub fn base_mip(&self, id: TextureId) -> Option<u32> {
+        self.textures.get(&id)
+            .filter(|resident| !resident.is_evicted())
+            .map(|resident| resident.base_mip)
+    }
+
+    // Marks a texture as used this frame by an object at the given distance from the camera.
+    // Returns true if some of its mips were dropped and it needs to be re-uploaded in full
+    pub fn touch(&mut self, id: TextureId, distance: f32) -> bool {
+        self.request(id, distance, 0)
+    }
+
+    // Like touch, but only wants the mips from base_mip down, so far off textures give up detail
+    // they'd never show. Can drop mips as well as bring them back. Returns true if the resident
+    // mips changed
+    pub fn request(&mut self, id: TextureId, distance: f32, base_mip: u32) -> bool {
+        let Some(resident) = self.textures.get_mut(&id) else { return false };
+        resident.last_used = self.frame;
+        resident.distance = distance;
+        let base_mip = base_mip.min((resident.mip_sizes.len() as u32).saturating_sub(1));
+        if resident.base_mip == base_mip {
+            return false;
+        }
+
+        self.used -= resident.resident_bytes();
+        resident.base_mip = base_mip;
+        self.used += resident.resident_bytes();
+        true
+    }
+
+    // Works out what has to go to get back under budget, and assumes the caller does it
+    pub fn enforce(&mut self) -> Vec<Eviction> {
+        let mut evictions = Vec::new();
+        if self.used <= self.budget {
+            return evictions;
+        }
+
+        // Stalest first, then furthest away
+        let mut candidates = self.textures.iter()
+            .filter(|(_, resident)| !resident.is_evicted())
+            .map(|(id, resident)| (*id, resident.last_used, resident.distance))
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.1.cmp(&b.1).then(b.2.total_cmp(&a.2)));
+
+        // Textures used this frame only ever lose their high mips, everything else can go completely
+        for (id, last_used, _) in candidates {
+            if self.used <= self.budget {
+                break;
+            }
+            let resident = self.textures.get_mut(&id).unwrap();
+            let in_use = last_used == self.frame;
+            let lowest_mip = (resident.mip_sizes.len() as u32).saturating_sub(1);
+            while self.used > self.budget && resident.base_mip < lowest_mip {
+                self.used -= resident.mip_sizes[resident.base_mip as usize];
+                resident.base_mip += 1;
+            }
+            if self.used > self.budget && !in_use {
+                self.used -= resident.resident_bytes();
+                resident.base_mip = resident.mip_sizes.len() as u32;
+                evictions.push(Eviction::Evict { id });
+            } else if resident.base_mip > 0 {
+                evictions.push(Eviction::DropMips { id, base_mip: resident.base_mip });
+            }
+        }
+
+        if self.used > self.budget {
+            log::warn!("Texture budget exceeded by {} bytes after evicting everything that isn't in use", self.used - self.budget);
+        }
+        evictions
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StreamingSettings {
+    // Bytes the mips of every streamed texture on the GPU can add up to
+    pub budget: u64,
+    // Visible textures get every mip up to this far from the camera. Each time the distance
+    // doubles past it they lose the largest one left
+    pub full_detail_distance: f32,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self { budget: 256 * 1024 * 1024, full_detail_distance: 10.0 }
+    }
+}
+
+impl StreamingSettings {
+    pub fn with_budget(mut self, budget: u64) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    pub fn with_full_detail_distance(mut self, distance: f32) -> Self {
+        self.full_detail_distance = distance;
+        self
+    }
+
+    // The first mip worth having for something this far away
+    fn base_mip(&self, distance: f32) -> u32 {
+        (distance / self.full_detail_distance.max(1e-3)).log2().floor().max(0.0) as u32
+    }
+}
+
+// A texture's whole mip chain, kept on the CPU so any part of it can be uploaded again
+struct Streamed {
+    mips: Vec<image::RgbaImage>,
+    // First of mips on the GPU right now
+    base_mip: u32,
+    label: String,
+}
+
+// Uploads only as much of each streamed texture as the camera needs, within a TextureBudget. The
+// scene keeps the textures themselves, this hands it a replacement whenever one should change
+pub(crate) struct TextureStreamer {
+    settings: StreamingSettings,
+    budget: TextureBudget,
+    textures: HashMap<usize, Streamed>,
+}
+
+impl TextureStreamer {
+    pub fn new(settings: StreamingSettings) -> Self {
+        Self { settings, budget: TextureBudget::new(settings.budget), textures: HashMap::new() }
+    }
+
+    pub fn settings(&self) -> &StreamingSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: StreamingSettings) {
+        self.settings = settings;
+        self.budget.set_budget(settings.budget);
+    }
+
+    pub fn used(&self) -> u64 {
+        self.budget.used()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    // The texture to start id off with, before anything has asked for more: only the smallest mip
+    pub fn initial_texture(device: &wgpu::Device, queue: &wgpu::Queue, mips: &[image::RgbaImage], label: &str) -> Texture {
+        Texture::from_mips(device, queue, &mips[mips.len() - 1..], label)
+    }
+
+    // mips is the chain id was started off with by initial_texture, see Texture::mip_chain
+    pub fn add(&mut self, id: usize, mips: Vec<image::RgbaImage>, label: &str) {
+        let sizes = mips.iter().map(|mip| mip.as_raw().len() as u64).collect();
+        let base_mip = mips.len() as u32 - 1;
+        self.budget.register(id as TextureId, sizes);
+        self.budget.request(id as TextureId, f32::INFINITY, base_mip);
+        self.textures.insert(id, Streamed { mips, base_mip, label: label.to_string() });
+    }
+
+    // The size id streams up to, however little of it is uploaded right now
+    pub fn full_size(&self, id: usize) -> Option<[u32; 2]> {
+        let (width, height) = self.textures.get(&id)?.mips.first()?.dimensions();
+        Some([width, height])
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        if self.textures.remove(&id).is_some() {
+            self.budget.unregister(id as TextureId);
+        }
+    }
+
+    // Asks for the mips each texture needs at the distance of its nearest visible use, lets the
+    // budget drop what has to go, then makes the textures that came out different. Textures
+    // nothing can see keep what they have until the budget needs the room
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, uses: impl IntoIterator<Item = (usize, f32)>) -> Vec<(usize, Texture)> {
+        let mut nearest = HashMap::new();
+        for (id, distance) in uses {
+            if self.textures.contains_key(&id) {
+                let entry = nearest.entry(id).or_insert(f32::INFINITY);
+                *entry = distance.min(*entry);
+            }
+        }
+        self.budget.begin_frame();
+        for (id, distance) in nearest {
+            self.budget.request(id as TextureId, distance, self.settings.base_mip(distance));
+        }
+        self.budget.enforce();
+
+        let mut changed = Vec::new();
+        for (&id, streamed) in &mut self.textures {
+            let lowest = streamed.mips.len() as u32 - 1;
+            // Evicted textures still keep their smallest mip, a few bytes, so they can be bound
+            let base_mip = self.budget.base_mip(id as TextureId).unwrap_or(lowest).min(lowest);
+            if base_mip != streamed.base_mip {
+                streamed.base_mip = base_mip;
+                changed.push((id, Texture::from_mips(device, queue, &streamed.mips[base_mip as usize..], &streamed.label)));
+            }
+        }
+        changed
+    }
+}
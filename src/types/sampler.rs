@@ -0,0 +1,212 @@
+use std::{collections::HashMap, sync::Arc};
+
+// The highest anisotropy level wgpu will accept on any backend
+pub const MAX_ANISOTROPY: u16 = 16;
+
+// Why a SamplerConfig can't be made as it is. Samplers are still made from it, clamped until it fits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplerError {
+    // 1 is the lowest, which turns anisotropic filtering off
+    ZeroAnisotropy,
+    // Above what the adapter allows, which is 1 where it can't filter anisotropically at all
+    AnisotropyTooHigh { requested: u16, max: u16 },
+    // Anisotropic filtering needs the mag, min and mipmap filters all linear
+    AnisotropyNeedsLinearFilters,
+}
+
+impl std::fmt::Display for SamplerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SamplerError::ZeroAnisotropy => write!(f, "anisotropy has to be at least 1"),
+            SamplerError::AnisotropyTooHigh { requested, max } => write!(f, "anisotropy {requested} is above the adapter's limit of {max}"),
+            SamplerError::AnisotropyNeedsLinearFilters => write!(f, "anisotropic filtering needs every filter to be linear"),
+        }
+    }
+}
+
+impl std::error::Error for SamplerError {}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerConfig {
+    pub address_mode_u: wgpu::AddressMode,
+    pub address_mode_v: wgpu::AddressMode,
+    pub address_mode_w: wgpu::AddressMode,
+    pub mag_filter: wgpu::FilterMode,
+    pub min_filter: wgpu::FilterMode,
+    pub mipmap_filter: wgpu::FilterMode,
+    // 1 disables anisotropic filtering
+    pub anisotropy: u16,
+    // Makes a comparison sampler, for sampler_comparison bindings like shadow maps. Material
+    // textures are read through plain samplers, so theirs ignore this
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+// Trilinear, which is what mip-mapped textures want and what anisotropy needs
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            anisotropy: 1,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_address_mode(mut self, mode: wgpu::AddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    // Magnification, minification and between mip levels alike
+    pub fn with_filter(mut self, filter: wgpu::FilterMode) -> Self {
+        self.mag_filter = filter;
+        self.min_filter = filter;
+        self.mipmap_filter = filter;
+        self
+    }
+
+    pub fn with_compare(mut self, compare: wgpu::CompareFunction) -> Self {
+        self.compare = Some(compare);
+        self
+    }
+
+    // Keeps textures seen at a glancing angle, like the ground stretching away, sharp instead of
+    // smeared. Only works with every filter linear, as they are by default. Other filters are left
+    // as they are and validate reports the mismatch, while clamped turns anisotropy back off
+    pub fn with_anisotropy(mut self, anisotropy: u16) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    // Whether samplers can be made exactly as asked on an adapter allowing max_anisotropy, see
+    // Gpu::max_anisotropy
+    pub fn validate(&self, max_anisotropy: u16) -> Result<(), SamplerError> {
+        if self.anisotropy == 0 {
+            return Err(SamplerError::ZeroAnisotropy);
+        }
+        if self.anisotropy > max_anisotropy.max(1) {
+            return Err(SamplerError::AnisotropyTooHigh { requested: self.anisotropy, max: max_anisotropy.max(1) });
+        }
+        let filters = [self.mag_filter, self.min_filter, self.mipmap_filter];
+        if self.anisotropy > 1 && filters.iter().any(|&filter| filter != wgpu::FilterMode::Linear) {
+            return Err(SamplerError::AnisotropyNeedsLinearFilters);
+        }
+        Ok(())
+    }
+
+    // Highest anisotropy level the adapter can actually use
+    pub fn max_anisotropy(adapter: &wgpu::Adapter) -> u16 {
+        if adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::ANISOTROPIC_FILTERING) {
+            MAX_ANISOTROPY
+        } else {
+            1
+        }
+    }
+
+    pub fn clamped(&self, max_anisotropy: u16) -> Self {
+        let mut config = *self;
+        config.anisotropy = config.anisotropy.clamp(1, max_anisotropy.max(1));
+        // wgpu rejects anisotropic samplers unless every filter is linear
+        if config.anisotropy > 1 && (
+            config.mag_filter != wgpu::FilterMode::Linear
+            || config.min_filter != wgpu::FilterMode::Linear
+            || config.mipmap_filter != wgpu::FilterMode::Linear
+        ) {
+            log::warn!("Anisotropic filtering requires linear filters, disabling it for this sampler");
+            config.anisotropy = 1;
+        }
+        config
+    }
+
+    // The same settings without compare, for bindings that take filtering samplers
+    pub fn filtering(&self) -> Self {
+        if self.compare.is_some() {
+            log::warn!("Comparison samplers can't be used for material textures, ignoring compare");
+        }
+        Self { compare: None, ..*self }
+    }
+
+    pub fn create_sampler(&self, device: &wgpu::Device, label: Option<&str>) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: self.address_mode_w,
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_filter: self.mipmap_filter,
+            anisotropy_clamp: self.anisotropy,
+            compare: self.compare,
+            ..Default::default()
+        })
+    }
+}
+
+// Hands out one sampler per distinct set of settings, so a thousand textures asking for the same
+// thing share a single sampler. Some backends only allow a few thousand to exist at once
+pub struct SamplerCache {
+    // Keyed by the settings asked for, before clamping
+    samplers: HashMap<SamplerConfig, Arc<wgpu::Sampler>>,
+    anisotropy_limit: u16,
+}
+
+impl SamplerCache {
+    pub fn new(anisotropy_limit: u16) -> Self {
+        Self { samplers: HashMap::new(), anisotropy_limit }
+    }
+
+    // The sampler for config, with its anisotropy clamped to the limit
+    // wgpu's types aren't Send or Sync on the web, but there's only the one thread there anyway
+    #[cfg_attr(target_arch = "wasm32", allow(clippy::arc_with_non_send_sync))]
+    pub fn get(&mut self, device: &wgpu::Device, config: &SamplerConfig) -> Arc<wgpu::Sampler> {
+        let limit = self.anisotropy_limit;
+        self.samplers
+            .entry(*config)
+            .or_insert_with(|| Arc::new(config.clamped(limit).create_sampler(device, Some("Sampler"))))
+            .clone()
+    }
+
+    // Samplers got after this are clamped to limit. Ones already handed out keep theirs, so
+    // whatever holds them has to get them again
+    pub fn set_anisotropy_limit(&mut self, limit: u16) {
+        if limit != self.anisotropy_limit {
+            self.anisotropy_limit = limit;
+            self.samplers.clear();
+        }
+    }
+
+    pub fn anisotropy_limit(&self) -> u16 {
+        self.anisotropy_limit
+    }
+
+    // Distinct samplers the cache is holding
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    // Lets go of samplers nothing outside the cache is using anymore
+    pub fn trim(&mut self) {
+        self.samplers.retain(|_, sampler| Arc::strong_count(sampler) > 1);
+    }
+}
@@ -0,0 +1,319 @@
+use cgmath::{InnerSpace, Point3, Quaternion, Vector3};
+use serde::Deserialize;
+
+use crate::types::{
+    camera_path::{CameraPath, Keyframe},
+    color::Color,
+};
+
+// A camera path keyframe as it's written in a sequence file
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct CameraKey {
+    // Seconds from when the path starts, not from the start of the sequence
+    pub time: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    // Degrees
+    #[serde(default = "default_fovy")]
+    pub fovy: f32,
+}
+
+fn default_fovy() -> f32 {
+    45.0
+}
+
+// Where a mesh instance should be at a point in a clip. Anything left out stays however the
+// instance already has it
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct TransformKey {
+    // Seconds from when the clip starts
+    pub time: f32,
+    #[serde(default)]
+    pub position: Option<[f64; 3]>,
+    // x, y, z, w
+    #[serde(default)]
+    pub rotation: Option<[f32; 4]>,
+    #[serde(default)]
+    pub scale: Option<[f32; 3]>,
+}
+
+// Something a sequence does at a cue's time. Meshes are the ids add_mesh hands out
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum Action {
+    // Takes the active camera over with a path, which carries on by itself from here
+    CameraPath {
+        keyframes: Vec<CameraKey>,
+        #[serde(default)]
+        looping: bool,
+    },
+    // Moves an instance through keyframes, straight from one to the next
+    Animate { mesh: usize, instance: usize, keyframes: Vec<TransformKey> },
+    Show { mesh: usize },
+    Hide { mesh: usize },
+    // Changes an instance's color, the closest thing meshes have to a material
+    SetColor { mesh: usize, instance: usize, color: [f32; 3] },
+}
+
+impl Action {
+    // How long it keeps going after its cue, only clips take any time. Camera paths run on their
+    // own once started
+    pub fn duration(&self) -> f32 {
+        match self {
+            Action::Animate { keyframes, .. } => keyframes.iter().map(|k| k.time).fold(0.0, f32::max),
+            _ => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Cue {
+    // Seconds from the start of the sequence
+    pub time: f32,
+    pub action: Action,
+}
+
+// What advancing a sequence wants done to the scene. State::update applies these
+#[derive(Clone, Debug)]
+pub enum SequenceEvent {
+    PlayCameraPath(CameraPath),
+    Transform {
+        mesh: usize,
+        instance: usize,
+        position: Option<Vector3<f64>>,
+        rotation: Option<Quaternion<f32>>,
+        scale: Option<Vector3<f32>>,
+    },
+    SetVisible { mesh: usize, visible: bool },
+    SetColor { mesh: usize, instance: usize, color: Color },
+}
+
+// How the file is laid out, the playback state isn't part of it
+#[derive(Deserialize)]
+struct SequenceFile {
+    #[serde(default)]
+    looping: bool,
+    #[serde(default = "default_speed")]
+    speed: f32,
+    cues: Vec<Cue>,
+}
+
+fn default_speed() -> f32 {
+    1.0
+}
+
+// A timeline of camera paths, clips, visibility and color changes, for demos and guided tours.
+// Plays like CameraPath, see State::set_sequence. Written in RON:
+//
+// (
+//     looping: false,
+//     cues: [
+//         (time: 0.0, action: CameraPath(keyframes: [
+//             (time: 0.0, position: (0.0, 1.0, 3.0), target: (0.0, 0.0, 0.0)),
+//             (time: 4.0, position: (3.0, 1.0, 0.0), target: (0.0, 0.0, 0.0)),
+//         ])),
+//         (time: 1.0, action: Animate(mesh: 0, instance: 0, keyframes: [
+//             (time: 0.0, scale: Some((1.0, 1.0, 1.0))),
+//             (time: 0.5, scale: Some((1.5, 1.5, 1.5))),
+//         ])),
+//         (time: 2.0, action: SetColor(mesh: 0, instance: 0, color: (1.0, 0.2, 0.2))),
+//         (time: 3.0, action: Hide(mesh: 1)),
+//     ],
+// )
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence {
+    // Sorted by time
+    cues: Vec<Cue>,
+    time: f32,
+    playing: bool,
+    // Index of the first cue that hasn't fired yet
+    next: usize,
+    // Clips that have started and not finished, as cue indices
+    running: Vec<usize>,
+    // Start again from the beginning after the last cue is done instead of stopping
+    pub looping: bool,
+    // 2 plays twice as fast
+    pub speed: f32,
+}
+
+impl Default for Sequence {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Linear between the two keyframes around time that have the field, holding at the ends
+fn sample_key<T: Copy>(keyframes: &[TransformKey], time: f32, field: impl Fn(&TransformKey) -> Option<T>, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    let mut before = None;
+    let mut after = None;
+    for key in keyframes {
+        let Some(value) = field(key) else { continue };
+        if key.time <= time {
+            before = Some((key.time, value));
+        } else if after.is_none() {
+            after = Some((key.time, value));
+        }
+    }
+    match (before, after) {
+        (Some((t0, a)), Some((t1, b))) => Some(lerp(a, b, (time - t0) / (t1 - t0))),
+        (Some((_, value)), None) | (None, Some((_, value))) => Some(value),
+        (None, None) => None,
+    }
+}
+
+fn quaternion([x, y, z, w]: [f32; 4]) -> Quaternion<f32> {
+    Quaternion::new(w, x, y, z).normalize()
+}
+
+impl Sequence {
+    pub fn new() -> Self {
+        Self { cues: Vec::new(), time: 0.0, playing: false, next: 0, running: Vec::new(), looping: false, speed: 1.0 }
+    }
+
+    pub fn with_cues(mut self, cues: impl IntoIterator<Item = Cue>) -> Self {
+        for cue in cues {
+            self.add_cue(cue);
+        }
+        self
+    }
+
+    pub fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ron::error::SpannedError> {
+        let file: SequenceFile = ron::from_str(text)?;
+        let mut sequence = Self::new().with_cues(file.cues).with_looping(file.looping);
+        sequence.speed = file.speed;
+        Ok(sequence)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    // Keeps the cues in time order, a cue at the same time as another goes after it. Cues added
+    // behind the playhead wait until the sequence comes round again
+    pub fn add_cue(&mut self, cue: Cue) {
+        let index = self.cues.partition_point(|c| c.time <= cue.time);
+        if index < self.next {
+            self.next += 1;
+        }
+        for running in &mut self.running {
+            if *running >= index {
+                *running += 1;
+            }
+        }
+        self.cues.insert(index, cue);
+    }
+
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    // When the last cue is done, including its clip
+    pub fn duration(&self) -> f32 {
+        self.cues.iter().map(|c| c.time + c.action.duration()).fold(0.0, f32::max)
+    }
+
+    pub fn play(&mut self) {
+        // Playing a finished sequence starts it over
+        if !self.looping && self.next == self.cues.len() && self.running.is_empty() {
+            self.rewind();
+        }
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    // Pauses and goes back to the start. What the cues already did to the scene stays done
+    pub fn stop(&mut self) {
+        self.playing = false;
+        self.rewind();
+    }
+
+    fn rewind(&mut self) {
+        self.time = 0.0;
+        self.next = 0;
+        self.running.clear();
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Moves the playhead on and returns what should happen to the scene, in cue order. Clips
+    // report where their instance is every advance until they finish
+    pub fn advance(&mut self, dt: f32) -> Vec<SequenceEvent> {
+        let mut events = Vec::new();
+        if !self.playing {
+            return events;
+        }
+        let duration = self.duration();
+        self.time += dt * self.speed;
+        loop {
+            while let Some(cue) = self.cues.get(self.next).filter(|cue| cue.time <= self.time) {
+                events.extend(Self::fire(&cue.action));
+                if matches!(cue.action, Action::Animate { .. }) {
+                    self.running.push(self.next);
+                }
+                self.next += 1;
+            }
+            let time = self.time;
+            self.running.retain(|&i| {
+                let cue = &self.cues[i];
+                events.extend(Self::sample_clip(&cue.action, time - cue.time));
+                time - cue.time < cue.action.duration()
+            });
+            if self.time < duration {
+                break;
+            }
+            // Everything's finished, so the leftover time goes to the next time round
+            if self.looping && duration > 0.0 {
+                self.time -= duration;
+                self.next = 0;
+            } else {
+                self.time = duration;
+                self.playing = false;
+                break;
+            }
+        }
+        events
+    }
+
+    fn fire(action: &Action) -> Option<SequenceEvent> {
+        Some(match *action {
+            Action::CameraPath { ref keyframes, looping } => {
+                let keyframes = keyframes.iter().map(|k| Keyframe::new(k.time, Point3::from(k.position), Point3::from(k.target), k.fovy));
+                let mut path = CameraPath::new().with_keyframes(keyframes).with_looping(looping);
+                path.play();
+                SequenceEvent::PlayCameraPath(path)
+            }
+            Action::Show { mesh } => SequenceEvent::SetVisible { mesh, visible: true },
+            Action::Hide { mesh } => SequenceEvent::SetVisible { mesh, visible: false },
+            Action::SetColor { mesh, instance, color: [r, g, b] } => SequenceEvent::SetColor { mesh, instance, color: Color::new(r, g, b) },
+            // Sampled along with the other running clips
+            Action::Animate { .. } => return None,
+        })
+    }
+
+    fn sample_clip(action: &Action, time: f32) -> Option<SequenceEvent> {
+        let Action::Animate { mesh, instance, ref keyframes } = *action else { return None };
+        let time = time.min(action.duration());
+        let position = sample_key(keyframes, time, |k| k.position.map(Vector3::from), |a, b, t| a + (b - a) * t as f64);
+        let rotation = sample_key(keyframes, time, |k| k.rotation.map(quaternion), |a, b, t| {
+            // The short way round
+            let b = if a.dot(b) < 0.0 { -b } else { b };
+            a.nlerp(b, t)
+        });
+        let scale = sample_key(keyframes, time, |k| k.scale.map(Vector3::from), |a, b, t| a + (b - a) * t);
+        Some(SequenceEvent::Transform { mesh, instance, position, rotation, scale })
+    }
+}
@@ -0,0 +1,202 @@
+use std::io;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs, path::PathBuf};
+
+use crate::types::quality::QualityPreset;
+
+// File the settings are kept in, inside the platform's config directory
+const FILE_NAME: &str = "settings.cfg";
+// Directory (and localStorage key on the web) the settings live under
+const APP_NAME: &str = "renderer";
+
+// Where the camera was and where it was looking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraSettings {
+    pub eye: [f32; 3],
+    pub target: [f32; 3],
+    pub up: [f32; 3],
+    // x, y, z, w
+    pub orientation: [f32; 4],
+    pub origin: [f64; 3],
+    pub fovy: f32,
+}
+
+// User preferences that should survive between runs. Stored as "key = value" lines so they can
+// be edited by hand, anything missing or unreadable falls back to the default
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub window_size: Option<[u32; 2]>,
+    pub window_position: Option<[i32; 2]>,
+    pub vsync: bool,
+    // Fraction of the window's resolution to render at
+    pub render_scale: f32,
+    pub quality: QualityPreset,
+    pub camera: Option<CameraSettings>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window_size: None,
+            window_position: None,
+            vsync: true,
+            render_scale: 1.0,
+            quality: QualityPreset::High,
+            camera: None,
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr, const N: usize>(value: &str) -> Option<[T; N]> {
+    let values = value.split_whitespace().map(|v| v.parse().ok()).collect::<Option<Vec<T>>>()?;
+    values.try_into().ok()
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values.iter().map(T::to_string).collect::<Vec<_>>().join(" ")
+}
+
+impl Settings {
+    pub fn parse(text: &str) -> Self {
+        let mut settings = Self::default();
+        let mut camera = CameraSettings { eye: [0.0; 3], target: [0.0; 3], up: [0.0, 1.0, 0.0], orientation: [0.0, 0.0, 0.0, 1.0], origin: [0.0; 3], fovy: 45.0 };
+        let mut has_camera = false;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                log::warn!("Ignoring setting line without a value: {line}");
+                continue;
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let ok = match key {
+                "window_size" => parse(value).map(|v| settings.window_size = Some(v)).is_some(),
+                "window_position" => parse(value).map(|v| settings.window_position = Some(v)).is_some(),
+                "vsync" => value.parse().map(|v| settings.vsync = v).is_ok(),
+                "render_scale" => value.parse().map(|v: f32| settings.render_scale = v.clamp(0.1, 2.0)).is_ok(),
+                "quality" => value.parse().map(|v| settings.quality = v).is_ok(),
+                "camera_eye" => parse(value).map(|v| { camera.eye = v; has_camera = true }).is_some(),
+                "camera_target" => parse(value).map(|v| camera.target = v).is_some(),
+                "camera_up" => parse(value).map(|v| camera.up = v).is_some(),
+                "camera_orientation" => parse(value).map(|v| camera.orientation = v).is_some(),
+                "camera_origin" => parse(value).map(|v| camera.origin = v).is_some(),
+                "camera_fovy" => parse::<f32, 1>(value).map(|[v]| camera.fovy = v).is_some(),
+                _ => {
+                    log::warn!("Ignoring unknown setting {key}");
+                    true
+                }
+            };
+            if !ok {
+                log::warn!("Ignoring bad value for setting {key}: {value}");
+            }
+        }
+        if has_camera {
+            settings.camera = Some(camera);
+        }
+        settings
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(size) = self.window_size {
+            lines.push(format!("window_size = {}", join(&size)));
+        }
+        if let Some(position) = self.window_position {
+            lines.push(format!("window_position = {}", join(&position)));
+        }
+        lines.push(format!("vsync = {}", self.vsync));
+        lines.push(format!("render_scale = {}", self.render_scale));
+        lines.push(format!("quality = {}", self.quality));
+        if let Some(camera) = &self.camera {
+            lines.push(format!("camera_eye = {}", join(&camera.eye)));
+            lines.push(format!("camera_target = {}", join(&camera.target)));
+            lines.push(format!("camera_up = {}", join(&camera.up)));
+            lines.push(format!("camera_orientation = {}", join(&camera.orientation)));
+            lines.push(format!("camera_origin = {}", join(&camera.origin)));
+            lines.push(format!("camera_fovy = {}", camera.fovy));
+        }
+        lines.push(String::new());
+        lines.join("\n")
+    }
+
+    // Missing or unreadable settings just give the defaults, a first run shouldn't be an error
+    pub fn load() -> Self {
+        match read() {
+            Ok(Some(text)) => Self::parse(&text),
+            Ok(None) => Self::default(),
+            Err(e) => {
+                log::warn!("Couldn't read settings: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(e) = write(&self.to_text()) {
+            log::warn!("Couldn't save settings: {e}");
+        }
+    }
+}
+
+// The platform's per-user config directory, worked out from the environment rather than pulling
+// in a crate for it
+#[cfg(not(target_arch = "wasm32"))]
+pub fn config_dir() -> Option<PathBuf> {
+    let env = |name| std::env::var_os(name).filter(|v| !v.is_empty()).map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env("XDG_CONFIG_HOME").or_else(|| env("HOME").map(|home| home.join(".config")))
+    };
+    base.map(|base| base.join(APP_NAME))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn settings_path() -> io::Result<PathBuf> {
+    config_dir()
+        .map(|dir| dir.join(FILE_NAME))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read() -> io::Result<Option<String>> {
+    match fs::read_to_string(settings_path()?) {
+        Ok(text) => Ok(Some(text)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(text: &str) -> io::Result<()> {
+    let path = settings_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, text)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> io::Result<web_sys::Storage> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "localStorage isn't available"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read() -> io::Result<Option<String>> {
+    local_storage()?
+        .get_item(&format!("{APP_NAME}/{FILE_NAME}"))
+        .map_err(|_| io::Error::other("couldn't read from localStorage"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write(text: &str) -> io::Result<()> {
+    local_storage()?
+        .set_item(&format!("{APP_NAME}/{FILE_NAME}"), text)
+        .map_err(|_| io::Error::other("couldn't write to localStorage"))
+}
@@ -0,0 +1,177 @@
+use std::ops::BitOr;
+
+// Snippets any shader can pull in with #include "name", by file name
+const INCLUDES: [(&str, &str); 1] = [
+    ("camera.wgsl", include_str!("../camera.wgsl")),
+];
+
+// Optional parts of the mesh shader, picked per mesh. Each is a #define in shader.wgsl, and the
+// source is cut down to just the features a mesh uses before it's compiled, so there's one shader
+// to maintain but no pipeline runs code it doesn't need
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderFeatures(u32);
+
+impl ShaderFeatures {
+    pub const NONE: ShaderFeatures = ShaderFeatures(0);
+    // The mesh's texture is sampled with its texture coordinates
+    pub const HAS_UV: ShaderFeatures = ShaderFeatures(1);
+    // The material's normal texture bends the lit normal, only read with PBR
+    pub const HAS_NORMAL_MAP: ShaderFeatures = ShaderFeatures(1 << 1);
+    // Reserved for skinning, nothing reads it until meshes carry joints and weights
+    pub const SKINNED: ShaderFeatures = ShaderFeatures(1 << 2);
+    // Vertex colors tint the instance color, without it only the instance color is used
+    pub const VERTEX_COLOR: ShaderFeatures = ShaderFeatures(1 << 3);
+    // Lit by the scene's lights with the glTF metallic-roughness model, without it colors are
+    // drawn as they are
+    pub const PBR: ShaderFeatures = ShaderFeatures(1 << 4);
+    // The base color comes from a layer of the material's texture array, picked by each
+    // instance's layer, instead of its texture. Needs HAS_UV
+    pub const TEXTURE_ARRAY: ShaderFeatures = ShaderFeatures(1 << 5);
+    // What every mesh got before there were features
+    pub const DEFAULT: ShaderFeatures = Self::VERTEX_COLOR;
+
+    const NAMES: [(ShaderFeatures, &'static str); 6] = [
+        (Self::HAS_UV, "HAS_UV"),
+        (Self::HAS_NORMAL_MAP, "HAS_NORMAL_MAP"),
+        (Self::SKINNED, "SKINNED"),
+        (Self::VERTEX_COLOR, "VERTEX_COLOR"),
+        (Self::PBR, "PBR"),
+        (Self::TEXTURE_ARRAY, "TEXTURE_ARRAY"),
+    ];
+
+    pub fn contains(self, other: ShaderFeatures) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn with(self, other: ShaderFeatures) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub fn without(self, other: ShaderFeatures) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    // The #define names that are set
+    pub fn defines(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMES.into_iter().filter(move |&(feature, _)| self.contains(feature)).map(|(_, name)| name)
+    }
+
+    // Whether name is one of these features' defines
+    pub fn defines_name(self, name: &str) -> bool {
+        self.defines().any(|define| define == name)
+    }
+}
+
+impl Default for ShaderFeatures {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl BitOr for ShaderFeatures {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        self.with(other)
+    }
+}
+
+// One of the crate's snippets, None if there isn't one called name
+pub fn include(name: &str) -> Option<&'static str> {
+    INCLUDES.iter().find(|(include, _)| *include == name).map(|(_, source)| *source)
+}
+
+// Replaces every #include "name" line with that snippet, including the snippet's own includes.
+// Each one is pasted in once however many times it's asked for, so snippets can include what
+// they use without declaring things twice. Unknown names are left as they are, which fails to
+// compile. Done before preprocess, so snippets can have #ifdefs too
+pub fn compose(source: &str) -> String {
+    compose_with(source, include)
+}
+
+// Like compose, looking the snippets up with includes instead, for ones that have been edited
+// since they were built in
+pub fn compose_with<'a>(source: &str, includes: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut out = String::with_capacity(source.len());
+    compose_into(source, &includes, &mut Vec::new(), &mut out);
+    out
+}
+
+fn compose_into<'a>(source: &str, includes: &impl Fn(&str) -> Option<&'a str>, included: &mut Vec<String>, out: &mut String) {
+    for line in source.lines() {
+        let name = line.trim().strip_prefix("#include").map(|name| name.trim().trim_matches('"'));
+        match name.map(|name| (name, includes(name))) {
+            Some((name, _)) if included.iter().any(|included| included == name) => {}
+            Some((name, Some(snippet))) => {
+                included.push(name.to_string());
+                compose_into(snippet, includes, included, out);
+                continue;
+            }
+            Some((name, None)) => {
+                log::warn!("Shader includes {name}, which doesn't exist");
+                out.push_str(line);
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+}
+
+// Keeps the lines of source the features ask for. Understands #ifdef NAME, #ifndef NAME, #else
+// and #endif on lines of their own, which can nest. Lines that are left out come back empty so
+// compile errors still point at the right line of what was passed in
+pub fn preprocess(source: &str, features: ShaderFeatures) -> String {
+    preprocess_with(source, features, &[])
+}
+
+// Like preprocess, and every ${NAME} in the lines that are kept is replaced with its value from
+// constants, so sizes shared with the Rust side are only written down once. Names that aren't
+// in constants are left as they are, which fails to compile
+pub fn preprocess_with(source: &str, features: ShaderFeatures, constants: &[(&str, &str)]) -> String {
+    // Whether each open block is being kept, including the blocks around it
+    let mut stack: Vec<bool> = Vec::new();
+    let mut out = String::with_capacity(source.len());
+    for line in source.lines() {
+        let active = stack.last().copied().unwrap_or(true);
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("#ifdef"), Some(name)) => stack.push(active && features.defines_name(name)),
+            (Some("#ifndef"), Some(name)) => stack.push(active && !features.defines_name(name)),
+            (Some("#else"), _) => {
+                let outer = stack.len() < 2 || stack[stack.len() - 2];
+                if let Some(top) = stack.last_mut() {
+                    *top = outer && !*top;
+                }
+            }
+            (Some("#endif"), _) => {
+                stack.pop();
+            }
+            _ if active => substitute(line, constants, &mut out),
+            _ => {}
+        }
+        out.push('\n');
+    }
+    debug_assert!(stack.is_empty(), "unclosed #ifdef in shader source");
+    out
+}
+
+fn substitute(mut line: &str, constants: &[(&str, &str)], out: &mut String) {
+    while let Some(start) = line.find("${") {
+        let Some(length) = line[start..].find('}') else { break };
+        let name = &line[start + 2..start + length];
+        out.push_str(&line[..start]);
+        match constants.iter().find(|(constant, _)| *constant == name) {
+            Some((_, value)) => out.push_str(value),
+            None => {
+                log::warn!("Shader uses ${{{name}}}, which has no value");
+                out.push_str(&line[start..=start + length]);
+            }
+        }
+        line = &line[start + length + 1..];
+    }
+    out.push_str(line);
+}
@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3};
+use wgpu::util::DeviceExt;
+
+use crate::types::{color::Color, texture::Texture};
+
+#[derive(Clone, Copy, Debug)]
+pub struct CloudSettings {
+    // How much of the sky is covered, 0 turns the clouds off and 1 is overcast
+    pub coverage: f32,
+    // How thick the clouds are, low values give wisps the sky shows through
+    pub density: f32,
+    // Sky units per second the clouds drift by, along world x and z
+    pub wind: [f32; 2],
+    // Where the layer starts and how deep it is. The sky is drawn around the camera, so these
+    // are against an imaginary dome with the camera 1 unit below the clouds by default
+    pub height: f32,
+    pub thickness: f32,
+    // Bigger makes smaller clouds
+    pub scale: f32,
+    // The lit side, the shadowed side is a darker shade of it
+    pub color: Color,
+}
+
+impl Default for CloudSettings {
+    fn default() -> Self {
+        Self {
+            coverage: 0.45,
+            density: 1.0,
+            wind: [0.02, 0.01],
+            height: 1.0,
+            thickness: 0.15,
+            scale: 1.5,
+            color: Color::new(0.9, 0.9, 0.92),
+        }
+    }
+}
+
+// A gradient from the horizon up to the zenith and down to the ground, with a sun and a layer
+// of clouds. World y is up
+#[derive(Clone, Copy, Debug)]
+pub struct SkySettings {
+    pub zenith: Color,
+    pub horizon: Color,
+    // Below the horizon, seen wherever the scene doesn't cover it
+    pub ground: Color,
+    // Towards the sun, doesn't need to be normalized
+    pub sun_direction: Vector3<f32>,
+    // Radians across the sun's disc
+    pub sun_size: f32,
+    pub sun_color: Color,
+    pub clouds: CloudSettings,
+}
+
+impl Default for SkySettings {
+    fn default() -> Self {
+        Self {
+            zenith: Color::new(0.08, 0.25, 0.65),
+            horizon: Color::new(0.55, 0.7, 0.85),
+            ground: Color::new(0.25, 0.23, 0.2),
+            sun_direction: Vector3::new(0.3, 0.6, -0.5),
+            sun_size: 0.03,
+            sun_color: Color::new(1.0, 0.95, 0.85),
+            clouds: CloudSettings::default(),
+        }
+    }
+}
+
+impl SkySettings {
+    pub fn with_clouds(mut self, clouds: CloudSettings) -> Self {
+        self.clouds = clouds;
+        self
+    }
+
+    pub fn with_sun(mut self, direction: Vector3<f32>, color: Color) -> Self {
+        self.sun_direction = direction;
+        self.sun_color = color;
+        self
+    }
+
+    // No clouds at all
+    pub fn clear(mut self) -> Self {
+        self.clouds.coverage = 0.0;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyUniform {
+    zenith: [f32; 4],
+    horizon: [f32; 4],
+    ground: [f32; 4],
+    sun_direction: [f32; 4],
+    sun_color: [f32; 4],
+    cloud_color: [f32; 4],
+    // Coverage, density, height, scale
+    cloud_shape: [f32; 4],
+    // Wind, time, thickness
+    cloud_motion: [f32; 4],
+}
+
+impl SkyUniform {
+    fn new(settings: &SkySettings, time: f32) -> Self {
+        let sun = if settings.sun_direction.magnitude2() > 0.0 { settings.sun_direction.normalize() } else { Vector3::unit_y() };
+        let clouds = &settings.clouds;
+        Self {
+            zenith: settings.zenith.buffer(),
+            horizon: settings.horizon.buffer(),
+            ground: settings.ground.buffer(),
+            sun_direction: [sun.x, sun.y, sun.z, (settings.sun_size * 0.5).cos()],
+            sun_color: settings.sun_color.buffer(),
+            cloud_color: clouds.color.buffer(),
+            cloud_shape: [clouds.coverage.clamp(0.0, 1.0), clouds.density.max(0.0), clouds.height.max(1e-3), clouds.scale],
+            cloud_motion: [clouds.wind[0], clouds.wind[1], time, clouds.thickness.max(1e-3)],
+        }
+    }
+}
+
+// Fills in behind the scene with a procedural sky, drawn at the start of every view's pass.
+// Created by SceneRenderer::set_sky
+pub struct Sky {
+    settings: SkySettings,
+    // Seconds the clouds have been drifting for
+    time: f32,
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    // By sample count, depth isn't tested so either direction works
+    pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Sky {
+    // source is sky.wgsl with its includes pasted in, as it's been reloaded if it has
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, source: &str, settings: SkySettings) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Sky Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+        let sky_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("sky_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &sky_layout],
+            push_constant_ranges: &[],
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Buffer"),
+            contents: bytemuck::bytes_of(&SkyUniform::new(&settings, 0.0)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("sky_bind_group"),
+            layout: &sky_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+        Self { settings, time: 0.0, format, shader, layout, pipelines: HashMap::new(), buffer, bind_group }
+    }
+
+    pub fn settings(&self) -> &SkySettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: SkySettings) {
+        self.settings = settings;
+    }
+
+    // Moves the clouds along with the wind
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    // Uploads the settings and builds the pipeline the coming passes need, render passes can't
+    pub(crate) fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, sample_count: u32) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&SkyUniform::new(&self.settings, self.time)));
+        if self.pipelines.contains_key(&sample_count) {
+            return;
+        }
+        let pipeline = self.create_pipeline(device, &self.shader, sample_count);
+        self.pipelines.insert(sample_count, pipeline);
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, shader: &wgpu::ShaderModule, sample_count: u32) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Sky Pipeline ({sample_count} samples)")),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Drawn first and left out of the depth buffer, so everything after lands in front
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    // Compiles source in place of sky.wgsl, with the pipelines that are in use, for
+    // SceneRenderer::reload_shader to swap in with set_shader once everything it changed compiles
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn rebuild(&self, device: &wgpu::Device, source: &str) -> (wgpu::ShaderModule, HashMap<u32, wgpu::RenderPipeline>) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Sky Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+        let pipelines = self.pipelines.keys().map(|&sample_count| (sample_count, self.create_pipeline(device, &shader, sample_count))).collect();
+        (shader, pipelines)
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn set_shader(&mut self, (shader, pipelines): (wgpu::ShaderModule, HashMap<u32, wgpu::RenderPipeline>)) {
+        self.shader = shader;
+        self.pipelines = pipelines;
+    }
+
+    // Covers the viewport of a pass that has the camera bound
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, sample_count: u32) {
+        render_pass.push_debug_group("Sky");
+        render_pass.set_pipeline(&self.pipelines[&sample_count]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
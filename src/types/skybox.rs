@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use crate::types::{cubemap::Cubemap, texture::Texture};
+
+#[derive(Clone, Copy, Debug)]
+pub struct SkyboxSettings {
+    // Multiplies the cubemap's colors, for HDR environments that are too bright or dim
+    pub intensity: f32,
+    // Radians the cubemap is turned around world y, counterclockwise seen from above
+    pub rotation: f32,
+}
+
+impl Default for SkyboxSettings {
+    fn default() -> Self {
+        Self { intensity: 1.0, rotation: 0.0 }
+    }
+}
+
+impl SkyboxSettings {
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: f32) -> Self {
+        self.rotation = rotation;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SkyboxUniform {
+    // Intensity, cos and sin of the rotation
+    params: [f32; 4],
+}
+
+impl SkyboxUniform {
+    fn new(settings: &SkyboxSettings) -> Self {
+        let (sin, cos) = settings.rotation.sin_cos();
+        Self { params: [settings.intensity.max(0.0), cos, sin, 0.0] }
+    }
+}
+
+// Fills in behind the scene with a cubemap, drawn at the start of every view's pass in place of
+// the procedural sky. Created by SceneRenderer::set_skybox
+pub struct Skybox {
+    cubemap: Cubemap,
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    layout: wgpu::PipelineLayout,
+    // By sample count, depth isn't tested so either direction works
+    pipelines: HashMap<u32, wgpu::RenderPipeline>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl Skybox {
+    // source is skybox.wgsl, like Sky::new's
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, source: &str, cubemap: Cubemap) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Skybox Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+        let skybox_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("skybox_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Skybox Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &skybox_layout],
+            push_constant_ranges: &[],
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Skybox Buffer"),
+            size: std::mem::size_of::<SkyboxUniform>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Skybox Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("skybox_bind_group"),
+            layout: &skybox_layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&cubemap.view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(&sampler) },
+            ],
+        });
+        Self { cubemap, format, shader, layout, pipelines: HashMap::new(), buffer, bind_group }
+    }
+
+    pub fn cubemap(&self) -> &Cubemap {
+        &self.cubemap
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    // Uploads the settings and builds the pipeline the coming passes need, render passes can't
+    pub(crate) fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, settings: &SkyboxSettings, sample_count: u32) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&SkyboxUniform::new(settings)));
+        if self.pipelines.contains_key(&sample_count) {
+            return;
+        }
+        let pipeline = self.create_pipeline(device, &self.shader, sample_count);
+        self.pipelines.insert(sample_count, pipeline);
+    }
+
+    fn create_pipeline(&self, device: &wgpu::Device, shader: &wgpu::ShaderModule, sample_count: u32) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Skybox Pipeline ({sample_count} samples)")),
+            layout: Some(&self.layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            // Like the sky, drawn first and left out of the depth buffer
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    // Like Sky::rebuild, for skybox.wgsl
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn rebuild(&self, device: &wgpu::Device, source: &str) -> (wgpu::ShaderModule, HashMap<u32, wgpu::RenderPipeline>) {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Skybox Shader"), source: wgpu::ShaderSource::Wgsl(source.into()) });
+        let pipelines = self.pipelines.keys().map(|&sample_count| (sample_count, self.create_pipeline(device, &shader, sample_count))).collect();
+        (shader, pipelines)
+    }
+
+    #[cfg(feature = "hot-reload")]
+    pub(crate) fn set_shader(&mut self, (shader, pipelines): (wgpu::ShaderModule, HashMap<u32, wgpu::RenderPipeline>)) {
+        self.shader = shader;
+        self.pipelines = pipelines;
+    }
+
+    // Covers the viewport of a pass that has the camera bound
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, sample_count: u32) {
+        render_pass.push_debug_group("Skybox");
+        render_pass.set_pipeline(&self.pipelines[&sample_count]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+        render_pass.pop_debug_group();
+    }
+}
@@ -0,0 +1,206 @@
+use std::fmt::Write;
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use web_time::Instant;
+
+// Counts from the last frame that was rendered
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct FrameStats {
+    // One draw call each, counted again for every view that draws it
+    pub meshes_drawn: usize,
+    // Skipped because none of their instances were in view
+    pub meshes_culled: usize,
+    pub instances_drawn: usize,
+    // Far off instances drawn as baked pictures, not counted in instances_drawn
+    pub impostors_drawn: usize,
+    // GPU memory held by mesh vertex, index and instance buffers
+    pub buffer_bytes: u64,
+    // GPU memory held by the scene's textures and texture arrays, every mip level that's resident
+    pub texture_bytes: u64,
+}
+
+// The passes a frame is timed in, in the order they're drawn. Passes drawn for every view are
+// added up over all of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Pass {
+    // Everything drawn with the view's depth attached: the sky, meshes, impostors, sorted
+    // transparent meshes, the OIT composite and the hover highlight
+    Scene,
+    Decals,
+    OitAccumulate,
+    Weather,
+    // The sun's depth probe and the flare itself
+    LensFlare,
+    ColorGrading,
+    Palette,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 7] = [
+        Pass::Scene,
+        Pass::Decals,
+        Pass::OitAccumulate,
+        Pass::Weather,
+        Pass::LensFlare,
+        Pass::ColorGrading,
+        Pass::Palette,
+    ];
+
+    // Used for the CSV columns and JSON keys
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Scene => "scene",
+            Pass::Decals => "decals",
+            Pass::OitAccumulate => "oit_accumulate",
+            Pass::Weather => "weather",
+            Pass::LensFlare => "lens_flare",
+            Pass::ColorGrading => "color_grading",
+            Pass::Palette => "palette",
+        }
+    }
+}
+
+// A time for each Pass, 0 for ones the frame didn't have
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PassTimes([f32; Pass::ALL.len()]);
+
+impl PassTimes {
+    pub fn get(&self, pass: Pass) -> f32 {
+        self.0[pass as usize]
+    }
+
+    pub fn add(&mut self, pass: Pass, time: f32) {
+        self.0[pass as usize] += time;
+    }
+
+    // Adds the time since since to pass, and moves since up to now for the next one
+    pub fn lap(&mut self, pass: Pass, since: &mut Instant) {
+        let now = Instant::now();
+        self.add(pass, (now - *since).as_secs_f32());
+        *since = now;
+    }
+
+    pub fn total(&self) -> f32 {
+        self.0.iter().sum()
+    }
+
+    fn to_millis(self) -> Self {
+        Self(self.0.map(|time| time * 1000.0))
+    }
+}
+
+// An object keyed by Pass::name
+impl Serialize for PassTimes {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(Pass::ALL.len()))?;
+        for pass in Pass::ALL {
+            map.serialize_entry(pass.name(), &self.get(pass))?;
+        }
+        map.end()
+    }
+}
+
+// One frame of a StatsRecorder run. Times are in milliseconds
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct FrameRecord {
+    pub frame: u64,
+    // Seconds since recording started
+    pub time: f64,
+    // Between this frame and the last, what the camera was moved by
+    pub frame_time: f32,
+    // CPU time spent moving the camera and uploading changes
+    pub update_time: f32,
+    // CPU time spent recording and submitting the frame's commands
+    pub render_time: f32,
+    // Every timed pass on the GPU, which comes back a few frames late and only with timestamp queries
+    pub gpu_time: Option<f32>,
+    // CPU time spent recording each pass, part of render_time
+    pub cpu_passes: PassTimes,
+    // The same split of gpu_time
+    pub gpu_passes: Option<PassTimes>,
+    #[serde(flatten)]
+    pub stats: FrameStats,
+}
+
+// Keeps every frame's timings and counts over a run, to be written out as CSV or JSON and
+// compared between versions. See State::start_stats_recording
+#[derive(Clone, Debug)]
+pub struct StatsRecorder {
+    started: Instant,
+    records: Vec<FrameRecord>,
+}
+
+impl Default for StatsRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+impl StatsRecorder {
+    pub fn new() -> Self {
+        Self { started: Instant::now(), records: Vec::new() }
+    }
+
+    // Times in seconds, as they're measured
+    pub fn record(&mut self, frame_time: f32, update_time: f32, render_time: f32, cpu_passes: PassTimes, gpu_passes: Option<PassTimes>, stats: FrameStats) {
+        self.records.push(FrameRecord {
+            frame: self.records.len() as u64,
+            time: self.started.elapsed().as_secs_f64(),
+            frame_time: frame_time * 1000.0,
+            update_time: update_time * 1000.0,
+            render_time: render_time * 1000.0,
+            gpu_time: gpu_passes.map(|passes| passes.total() * 1000.0),
+            cpu_passes: cpu_passes.to_millis(),
+            gpu_passes: gpu_passes.map(PassTimes::to_millis),
+            stats,
+        });
+    }
+
+    pub fn records(&self) -> &[FrameRecord] {
+        &self.records
+    }
+
+    // A header row then a row per frame, with a cpu_ and gpu_ column for each Pass. Frames without
+    // GPU times leave them empty
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frame,time,frame_time,update_time,render_time,gpu_time");
+        for prefix in ["cpu", "gpu"] {
+            for pass in Pass::ALL {
+                let _ = write!(csv, ",{prefix}_{}", pass.name());
+            }
+        }
+        csv.push_str(",meshes_drawn,meshes_culled,instances_drawn,impostors_drawn,buffer_bytes,texture_bytes\n");
+        for r in &self.records {
+            let gpu_time = r.gpu_time.map_or(String::new(), |t| t.to_string());
+            let _ = write!(csv, "{},{},{},{},{},{}", r.frame, r.time, r.frame_time, r.update_time, r.render_time, gpu_time);
+            for pass in Pass::ALL {
+                let _ = write!(csv, ",{}", r.cpu_passes.get(pass));
+            }
+            for pass in Pass::ALL {
+                let _ = write!(csv, ",{}", r.gpu_passes.map_or(String::new(), |passes| passes.get(pass).to_string()));
+            }
+            let s = r.stats;
+            let _ = writeln!(
+                csv,
+                ",{},{},{},{},{},{}",
+                s.meshes_drawn, s.meshes_culled, s.instances_drawn, s.impostors_drawn, s.buffer_bytes, s.texture_bytes,
+            );
+        }
+        csv
+    }
+
+    // An array of objects with the same fields as the CSV columns, except that the pass times are
+    // objects of their own under cpu_passes and gpu_passes
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.records).unwrap_or_default()
+    }
+
+    // JSON for a .json path, CSV for anything else
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let path = path.as_ref();
+        let json = path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        std::fs::write(path, if json { self.to_json() } else { self.to_csv() })
+    }
+}
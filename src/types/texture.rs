@@ -0,0 +1,427 @@
+use std::{io, sync::Arc};
+
+use crate::types::{compressed, sampler::SamplerConfig};
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl Texture {
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    // What HDR images are loaded as. Filterable everywhere, unlike 32 bit floats
+    pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+    // Depth buffer matching the size of the color target, has to be recreated whenever that's resized.
+    // sample_count has to match the color target's
+    pub fn create_depth_texture(device: &wgpu::Device, size: [u32; 2], sample_count: u32, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: size[0].max(1),
+            height: size[1].max(1),
+            depth_or_array_layers: 1,
+        };
+        // Multisampled depth is only ever drawn into. Making it bindable as well turns it into a
+        // different kind of object on GL than the color target, and the two can't share a pass
+        let usage = if sample_count > 1 {
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::DEPTH_FORMAT,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    // Multisampled color target, drawn into and then resolved onto the frame
+    pub fn create_msaa_texture(device: &wgpu::Device, size: [u32; 2], format: wgpu::TextureFormat, sample_count: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size[0].max(1),
+                height: size[1].max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { texture, view }
+    }
+
+    // An sRGB color texture holding the image, what meshes are textured with
+    pub fn from_image(device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage, label: &str) -> Self {
+        Self::from_rgba(device, queue, image, wgpu::TextureFormat::Rgba8UnormSrgb, label)
+    }
+
+    // The image's bytes as they are in any four channel, 8 bit format
+    pub fn from_rgba(device: &wgpu::Device, queue: &wgpu::Queue, image: &image::RgbaImage, format: wgpu::TextureFormat, label: &str) -> Self {
+        let size = wgpu::Extent3d { width: image.width().max(1), height: image.height().max(1), depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            image,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * image.width()), rows_per_image: None },
+            wgpu::Extent3d { width: image.width(), height: image.height(), depth_or_array_layers: 1 },
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    // An sRGB color texture with every level given, largest first. Each has to be half the size of
+    // the one before, rounded down but never below 1
+    pub fn from_mips(device: &wgpu::Device, queue: &wgpu::Queue, mips: &[image::RgbaImage], label: &str) -> Self {
+        let size = wgpu::Extent3d { width: mips[0].width().max(1), height: mips[0].height().max(1), depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: mips.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        for (level, mip) in mips.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture { texture: &texture, mip_level: level as u32, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                mip,
+                wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * mip.width()), rows_per_image: None },
+                wgpu::Extent3d { width: mip.width(), height: mip.height(), depth_or_array_layers: 1 },
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    // The image and every level below it, each made by halving the last
+    pub fn mip_chain(image: image::RgbaImage) -> Vec<image::RgbaImage> {
+        let mut mips = vec![image];
+        while let Some(last) = mips.last().filter(|last| last.width() > 1 || last.height() > 1) {
+            let (width, height) = ((last.width() / 2).max(1), (last.height() / 2).max(1));
+            mips.push(image::imageops::resize(last, width, height, image::imageops::FilterType::Triangle));
+        }
+        mips
+    }
+
+    // A PNG, JPEG or anything else the image crate can read, as an sRGB color texture. KTX2 files
+    // are uploaded in whatever format they're in, see from_ktx2
+    pub fn from_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> io::Result<Self> {
+        if bytes.starts_with(&KTX2_IDENTIFIER) {
+            return Self::from_ktx2(device, queue, bytes, label);
+        }
+        let image = image::load_from_memory(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::from_image(device, queue, &image.to_rgba8(), label))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::from_bytes(device, queue, &std::fs::read(path)?, &path.display().to_string())
+    }
+
+    // A Radiance .hdr or OpenEXR image kept in linear floats as Rgba16Float, for lighting and skies
+    // brighter than white. Anything else the image crate reads works too, it just tops out at 1
+    pub fn from_hdr_bytes(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> io::Result<Self> {
+        let image = image::load_from_memory(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?.to_rgba32f();
+        let (width, height) = image.dimensions();
+        let size = wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        // Past what 16 bit floats hold is clamped rather than turned into infinity
+        let texels = image.as_raw().iter().map(|&value| half::f16::from_f32(value.min(65504.0)).to_bits()).collect::<Vec<u16>>();
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(8 * width), rows_per_image: None },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(Self { texture, view })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_hdr(device: &wgpu::Device, queue: &wgpu::Queue, path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        Self::from_hdr_bytes(device, queue, &std::fs::read(path)?, &path.display().to_string())
+    }
+
+    // A KTX2 file already in a GPU format, BCn, ETC2, ASTC or plain, uploaded with all its mip
+    // levels as it is. Fails with Unsupported if the device can't sample that format, or it's
+    // Basis Universal, which needs transcoding
+    pub fn from_ktx2(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str) -> io::Result<Self> {
+        let image = compressed::read_ktx2(bytes)?;
+        if !compressed::is_supported(device, image.format) {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{label}: this device can't sample {:?}", image.format)));
+        }
+        let size = wgpu::Extent3d { width: image.size[0], height: image.size[1], depth_or_array_layers: 1 };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: image.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: image.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let (block_width, block_height) = image.format.block_dimensions();
+        let block_size = image.format.block_copy_size(None).unwrap_or(4);
+        for (level, data) in image.levels.iter().enumerate() {
+            // Levels smaller than a block still take up a whole one
+            let extent = size.mip_level_size(level as u32, wgpu::TextureDimension::D2).physical_size(image.format);
+            queue.write_texture(
+                wgpu::ImageCopyTexture { texture: &texture, mip_level: level as u32, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(extent.width / block_width * block_size),
+                    rows_per_image: Some(extent.height / block_height),
+                },
+                extent,
+            );
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(Self { texture, view })
+    }
+
+    // Loads the first of paths this device can use, so a texture can ship as BC7 for desktops,
+    // ASTC or ETC2 for mobiles and a PNG for everything else, in order of preference. Only the
+    // KTX2 files' headers are read to decide
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_first_supported(device: &wgpu::Device, queue: &wgpu::Queue, paths: &[impl AsRef<std::path::Path>]) -> io::Result<Self> {
+        for path in paths {
+            let path = path.as_ref();
+            let bytes = std::fs::read(path)?;
+            if bytes.starts_with(&KTX2_IDENTIFIER) && !compressed::ktx2_format(&bytes).is_ok_and(|format| compressed::is_supported(device, format)) {
+                continue;
+            }
+            return Self::from_bytes(device, queue, &bytes, &path.display().to_string());
+        }
+        Err(io::Error::new(io::ErrorKind::Unsupported, "none of the textures are in a format this device supports"))
+    }
+
+    // Bytes of GPU memory the texture takes up, over every mip level, layer and sample
+    pub fn bytes(&self) -> u64 {
+        let texture = &self.texture;
+        let format = texture.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+        let mips = (0..texture.mip_level_count()).map(|mip| {
+            let size = texture.size().mip_level_size(mip, texture.dimension());
+            size.width.div_ceil(block_width) as u64 * size.height.div_ceil(block_height) as u64 * size.depth_or_array_layers as u64
+        });
+        mips.sum::<u64>() * block_size * texture.sample_count() as u64
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        [self.texture.width(), self.texture.height()]
+    }
+
+    // An empty array of layers with a D2Array view, for texture_2d_array bindings like
+    // SceneRenderer::add_texture_array. Filled a layer at a time with write_layer
+    pub fn create_array(device: &wgpu::Device, size: [u32; 2], layers: u32, format: wgpu::TextureFormat, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: size[0].max(1), height: size[1].max(1), depth_or_array_layers: layers.max(1) },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        // Has to be asked for, a single layer would get a plain 2D view otherwise
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        Self { texture, view }
+    }
+
+    // Each image as a layer of an sRGB array, in order. They all have to be the same size
+    pub fn from_layers(device: &wgpu::Device, queue: &wgpu::Queue, images: &[image::RgbaImage], label: &str) -> io::Result<Self> {
+        let first = images.first().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "a texture array needs at least one layer"))?;
+        let texture = Self::create_array(device, [first.width(), first.height()], images.len() as u32, wgpu::TextureFormat::Rgba8UnormSrgb, label);
+        for (layer, image) in images.iter().enumerate() {
+            texture.write_layer(queue, layer as u32, image)?;
+        }
+        Ok(texture)
+    }
+
+    // Cuts a sprite sheet into frame sized layers, left to right then top to bottom, so sprites
+    // can be animated by changing their instance's layer. Leftovers at the right and bottom
+    // edges that don't make a whole frame are skipped
+    pub fn from_sprite_sheet(device: &wgpu::Device, queue: &wgpu::Queue, sheet: &image::RgbaImage, frame: [u32; 2], label: &str) -> io::Result<Self> {
+        let [width, height] = [frame[0].max(1), frame[1].max(1)];
+        let frames = (0..sheet.height() / height)
+            .flat_map(|row| (0..sheet.width() / width).map(move |column| (column * width, row * height)))
+            .map(|(x, y)| image::imageops::crop_imm(sheet, x, y, width, height).to_image())
+            .collect::<Vec<_>>();
+        Self::from_layers(device, queue, &frames, label)
+    }
+
+    // Replaces one layer of an array with an image its size, in a four channel 8 bit format
+    pub fn write_layer(&self, queue: &wgpu::Queue, layer: u32, image: &image::RgbaImage) -> io::Result<()> {
+        let (width, height) = image.dimensions();
+        if [width, height] != self.size() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("layer {layer} is {width}x{height}, the array is {}x{}", self.size()[0], self.size()[1])));
+        }
+        if layer >= self.layers() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("layer {layer} is past the array's {} layers", self.layers())));
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: 0, y: 0, z: layer },
+                aspect: wgpu::TextureAspect::All,
+            },
+            image,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(4 * width), rows_per_image: None },
+            wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+        Ok(())
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.texture.depth_or_array_layers()
+    }
+}
+
+// What every KTX2 file starts with
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+struct MeshTexture {
+    texture: Texture,
+    // None follows the scene's default sampler settings
+    config: Option<SamplerConfig>,
+    // From the scene's SamplerCache, shared with every texture asking for the same settings
+    sampler: Arc<wgpu::Sampler>,
+}
+
+// The textures a scene's materials can use, each with the sampler it's read through. Materials
+// without a texture get a white pixel, so every material fits the same bind group layout
+pub struct MeshTextures {
+    white: MeshTexture,
+    // Indexed by id, None once removed so the other ids stay put
+    textures: Vec<Option<MeshTexture>>,
+    // Texture arrays have ids of their own, and a single white layer stands in for them
+    white_array: MeshTexture,
+    arrays: Vec<Option<MeshTexture>>,
+}
+
+impl MeshTextures {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, sampler: Arc<wgpu::Sampler>) -> Self {
+        let pixel = image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4]));
+        let white = Texture::from_image(device, queue, &pixel, "White Texture");
+        let white_array = Texture::create_array(device, [1, 1], 1, wgpu::TextureFormat::Rgba8UnormSrgb, "White Texture Array");
+        white_array.write_layer(queue, 0, &pixel).expect("one pixel fits a one pixel array");
+        Self {
+            white: MeshTexture { texture: white, config: None, sampler: sampler.clone() },
+            textures: Vec::new(),
+            white_array: MeshTexture { texture: white_array, config: None, sampler },
+            arrays: Vec::new(),
+        }
+    }
+
+    // sampler has to have been made from config, or the default settings if it's None
+    pub fn add(&mut self, texture: Texture, config: Option<SamplerConfig>, sampler: Arc<wgpu::Sampler>) -> usize {
+        self.textures.push(Some(MeshTexture { texture, config, sampler }));
+        self.textures.len() - 1
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Texture> {
+        self.textures.get_mut(id)?.take().map(|texture| texture.texture)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Texture> {
+        Some(&self.textures.get(id)?.as_ref()?.texture)
+    }
+
+    // Swaps in another texture under the same id and sampler, handing back the old one. Nothing
+    // happens if there's no texture with that id
+    pub fn replace(&mut self, id: usize, texture: Texture) -> Option<Texture> {
+        let slot = self.textures.get_mut(id)?.as_mut()?;
+        Some(std::mem::replace(&mut slot.texture, texture))
+    }
+
+    // Ids handed out so far, including removed textures
+    pub fn count(&self) -> usize {
+        self.textures.len()
+    }
+
+    // The textures still there, with their ids
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Texture)> {
+        self.textures.iter().enumerate().filter_map(|(id, texture)| Some((id, &texture.as_ref()?.texture)))
+    }
+
+    // Like add, for a texture with a D2Array view such as Texture::create_array makes
+    pub fn add_array(&mut self, texture: Texture, config: Option<SamplerConfig>, sampler: Arc<wgpu::Sampler>) -> usize {
+        self.arrays.push(Some(MeshTexture { texture, config, sampler }));
+        self.arrays.len() - 1
+    }
+
+    pub fn remove_array(&mut self, id: usize) -> Option<Texture> {
+        self.arrays.get_mut(id)?.take().map(|texture| texture.texture)
+    }
+
+    pub fn get_array(&self, id: usize) -> Option<&Texture> {
+        Some(&self.arrays.get(id)?.as_ref()?.texture)
+    }
+
+    pub fn iter_arrays(&self) -> impl Iterator<Item = (usize, &Texture)> {
+        self.arrays.iter().enumerate().filter_map(|(id, texture)| Some((id, &texture.as_ref()?.texture)))
+    }
+
+    // Makes every sampler again from its settings, after the defaults change
+    pub fn rebuild_samplers(&mut self, mut create_sampler: impl FnMut(Option<&SamplerConfig>) -> Arc<wgpu::Sampler>) {
+        let whites = [&mut self.white, &mut self.white_array];
+        for texture in self.textures.iter_mut().chain(self.arrays.iter_mut()).flatten().chain(whites) {
+            texture.sampler = create_sampler(texture.config.as_ref());
+        }
+    }
+
+    // What to bind for this texture, white if there's none or it's gone
+    pub fn binding(&self, id: Option<usize>) -> (&wgpu::TextureView, &wgpu::Sampler) {
+        let texture = id.and_then(|id| self.textures.get(id)?.as_ref()).unwrap_or(&self.white);
+        (&texture.texture.view, &texture.sampler)
+    }
+
+    // The same for a texture array, a white layer if there's none or it's gone
+    pub fn array_binding(&self, id: Option<usize>) -> (&wgpu::TextureView, &wgpu::Sampler) {
+        let texture = id.and_then(|id| self.arrays.get(id)?.as_ref()).unwrap_or(&self.white_array);
+        (&texture.texture.view, &texture.sampler)
+    }
+}
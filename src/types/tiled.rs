@@ -0,0 +1,486 @@
+use std::{io::{self, Read}, path::{Path, PathBuf}, str::FromStr};
+use base64::Engine;
+use serde::Deserialize;
+use crate::types::tilemap::{AnimationFrame, TileId, TileLayer, Tilemap, Tileset};
+
+// Loads a map saved by Tiled, as .tmx or .tmj. Tilesets kept in their own .tsx or .tsj files are
+// loaded from next to the map, and image paths are joined onto the directory of the file that
+// names them so TilemapRenderer::load_images finds them. Tile layers come in with their parallax,
+// offsets and opacity, groups are flattened into them, and object and image layers are skipped
+pub fn load_tiled(path: &Path) -> io::Result<Tilemap> {
+    let text = std::fs::read_to_string(path)?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match extension(path).as_str() {
+        "tmx" | "xml" => parse_tmx(&text, dir),
+        "tmj" | "json" => parse_tmj(&text, dir),
+        other => Err(invalid(format!("'{other}' isn't a Tiled map extension, expected tmx or tmj"))),
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension().and_then(|e| e.to_str()).unwrap_or("").to_ascii_lowercase()
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+// What a layer gets from the groups it's in
+#[derive(Clone, Copy)]
+struct Inherited {
+    offset: [f32; 2],
+    parallax: [f32; 2],
+    opacity: f32,
+    visible: bool,
+}
+
+impl Inherited {
+    const ROOT: Inherited = Inherited { offset: [0.0, 0.0], parallax: [1.0, 1.0], opacity: 1.0, visible: true };
+
+    // Offsets are in pixels with y down, like everything else in Tiled
+    fn child(&self, offset: [f32; 2], parallax: [f32; 2], opacity: f32, visible: bool) -> Inherited {
+        Inherited {
+            offset: [self.offset[0] + offset[0], self.offset[1] - offset[1]],
+            parallax: [self.parallax[0] * parallax[0], self.parallax[1] * parallax[1]],
+            opacity: self.opacity * opacity,
+            visible: self.visible && visible,
+        }
+    }
+
+    fn layer(&self, name: &str) -> TileLayer {
+        let mut layer = TileLayer::new(name).with_parallax(self.parallax);
+        layer.offset = self.offset;
+        layer.opacity = self.opacity;
+        layer.visible = self.visible;
+        layer
+    }
+}
+
+// Places a row major block of tiles with its top left at origin
+fn fill(layer: &mut TileLayer, origin: [i32; 2], width: u32, tiles: &[TileId]) {
+    let width = width.max(1) as usize;
+    for (i, &tile) in tiles.iter().enumerate() {
+        layer.set([origin[0] + (i % width) as i32, origin[1] + (i / width) as i32], tile);
+    }
+}
+
+// Layer data written as text, either comma separated or base64 of little endian ids, optionally
+// compressed first
+fn decode_tiles(data: &str, encoding: &str, compression: Option<&str>) -> io::Result<Vec<TileId>> {
+    match encoding {
+        "csv" => data.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().map_err(|_| invalid(format!("'{s}' isn't a tile id"))))
+            .collect(),
+        "base64" => {
+            let data = data.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+            let bytes = base64::engine::general_purpose::STANDARD.decode(data).map_err(invalid_base64)?;
+            let bytes = match compression.unwrap_or("") {
+                "" => bytes,
+                "zlib" => read_all(flate2::read::ZlibDecoder::new(&bytes[..]))?,
+                "gzip" => read_all(flate2::read::GzDecoder::new(&bytes[..]))?,
+                other => return Err(invalid(format!("{other} compressed layers aren't supported, use zlib, gzip or none"))),
+            };
+            if bytes.len() % 4 != 0 {
+                return Err(invalid("Layer data isn't a whole number of tile ids"));
+            }
+            Ok(bytes.chunks_exact(4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+        }
+        other => Err(invalid(format!("Unknown layer encoding '{other}'"))),
+    }
+}
+
+fn invalid_base64(e: base64::DecodeError) -> io::Error {
+    invalid(format!("Bad base64 layer data: {e}"))
+}
+
+fn read_all(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+// "#rrggbb", "#aarrggbb" or either without the #, as red, green and blue
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim_start_matches('#');
+    // Anything else could split a character when sliced
+    if !hex.is_ascii() {
+        return None;
+    }
+    let hex = match hex.len() {
+        6 => hex,
+        8 => &hex[2..],
+        _ => return None,
+    };
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+// Tiled's colors are sRGB, clear colors are linear
+fn background(color: &str) -> Option<wgpu::Color> {
+    let to_linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    parse_color(color).map(|[r, g, b]| wgpu::Color { r: to_linear(r), g: to_linear(g), b: to_linear(b), a: 1.0 })
+}
+
+fn check_orientation(orientation: &str) -> io::Result<()> {
+    if orientation == "orthogonal" {
+        Ok(())
+    } else {
+        Err(invalid(format!("Only orthogonal maps are supported, this one is {orientation}")))
+    }
+}
+
+// The parts of a tileset both formats have, sizes in pixels and durations in milliseconds
+struct TilesetInfo {
+    name: String,
+    first_id: TileId,
+    tile_size: [u32; 2],
+    tile_count: u32,
+    columns: u32,
+    margin: u32,
+    spacing: u32,
+    image: Option<PathBuf>,
+    image_size: [u32; 2],
+    transparent_color: Option<String>,
+    animations: Vec<(u32, Vec<(u32, u32)>)>,
+}
+
+impl TilesetInfo {
+    fn into_tileset(self) -> io::Result<Tileset> {
+        let Some(image) = self.image else {
+            return Err(invalid(format!("Tileset '{}' is a collection of images, only single image tilesets are supported", self.name)));
+        };
+        let mut tileset = Tileset::new(&self.name, self.first_id, self.tile_size, self.columns, self.tile_count);
+        tileset.margin = self.margin;
+        tileset.spacing = self.spacing;
+        if self.image_size[0] > 0 && self.image_size[1] > 0 {
+            tileset.image_size = self.image_size;
+        }
+        tileset.image = Some(image);
+        tileset.transparent_color = self.transparent_color.as_deref().and_then(parse_color);
+        for (tile, frames) in self.animations {
+            let frames = frames.into_iter()
+                .map(|(tile, duration)| AnimationFrame { tile, duration: duration as f32 / 1000.0 })
+                .collect();
+            tileset.set_animation(tile, frames);
+        }
+        Ok(tileset)
+    }
+}
+
+// A tileset in its own file, referenced by a map with the first id it gives it
+fn load_external_tileset(path: &Path, first_id: TileId) -> io::Result<Tileset> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("Couldn't read tileset {}: {e}", path.display())))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    match extension(path).as_str() {
+        "tsx" | "xml" => {
+            let doc = roxmltree::Document::parse(&text).map_err(|e| invalid(e.to_string()))?;
+            xml_tileset(doc.root_element(), first_id, dir)?.into_tileset()
+        }
+        "tsj" | "json" => {
+            let tileset: JsonTileset = serde_json::from_str(&text)?;
+            tileset.info(first_id, dir).into_tileset()
+        }
+        other => Err(invalid(format!("'{other}' isn't a Tiled tileset extension, expected tsx or tsj"))),
+    }
+}
+
+// TMX
+
+pub fn parse_tmx(text: &str, dir: &Path) -> io::Result<Tilemap> {
+    let doc = roxmltree::Document::parse(text).map_err(|e| invalid(e.to_string()))?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "map" {
+        return Err(invalid("Not a Tiled map, the root element isn't <map>"));
+    }
+    check_orientation(root.attribute("orientation").unwrap_or("orthogonal"))?;
+
+    let mut map = Tilemap::new([attribute(root, "tilewidth")?, attribute(root, "tileheight")?]);
+    map.background = root.attribute("backgroundcolor").and_then(background);
+    for node in root.children().filter(|n| n.is_element()) {
+        if node.tag_name().name() == "tileset" {
+            let first_id = attribute(node, "firstgid")?;
+            let tileset = match node.attribute("source") {
+                Some(source) => load_external_tileset(&dir.join(source), first_id)?,
+                None => xml_tileset(node, first_id, dir)?.into_tileset()?,
+            };
+            map.add_tileset(tileset);
+        } else {
+            xml_layer(&mut map, node, Inherited::ROOT)?;
+        }
+    }
+    Ok(map)
+}
+
+fn attribute<T: FromStr>(node: roxmltree::Node, name: &str) -> io::Result<T> {
+    let value = node.attribute(name)
+        .ok_or_else(|| invalid(format!("<{}> is missing {name}", node.tag_name().name())))?;
+    value.parse().map_err(|_| invalid(format!("<{}> has a bad {name} '{value}'", node.tag_name().name())))
+}
+
+fn attribute_or<T: FromStr>(node: roxmltree::Node, name: &str, default: T) -> T {
+    node.attribute(name).and_then(|value| value.parse().ok()).unwrap_or(default)
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn xml_tileset(node: roxmltree::Node, first_id: TileId, dir: &Path) -> io::Result<TilesetInfo> {
+    let image = child(node, "image");
+    let animations = node.children()
+        .filter(|n| n.has_tag_name("tile"))
+        .filter_map(|tile| Some((tile, child(tile, "animation")?)))
+        .map(|(tile, animation)| {
+            let frames = animation.children()
+                .filter(|n| n.has_tag_name("frame"))
+                .map(|frame| Ok((attribute(frame, "tileid")?, attribute(frame, "duration")?)))
+                .collect::<io::Result<Vec<_>>>()?;
+            Ok((attribute(tile, "id")?, frames))
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    Ok(TilesetInfo {
+        name: node.attribute("name").unwrap_or("").to_string(),
+        first_id,
+        tile_size: [attribute(node, "tilewidth")?, attribute(node, "tileheight")?],
+        tile_count: attribute_or(node, "tilecount", 0),
+        columns: attribute_or(node, "columns", 0),
+        margin: attribute_or(node, "margin", 0),
+        spacing: attribute_or(node, "spacing", 0),
+        image: image.and_then(|image| image.attribute("source")).map(|source| dir.join(source)),
+        image_size: image.map_or([0, 0], |image| [attribute_or(image, "width", 0), attribute_or(image, "height", 0)]),
+        transparent_color: image.and_then(|image| image.attribute("trans")).map(str::to_string),
+        animations,
+    })
+}
+
+fn xml_layer(map: &mut Tilemap, node: roxmltree::Node, parent: Inherited) -> io::Result<()> {
+    let inherited = parent.child(
+        [attribute_or(node, "offsetx", 0.0), attribute_or(node, "offsety", 0.0)],
+        [attribute_or(node, "parallaxx", 1.0), attribute_or(node, "parallaxy", 1.0)],
+        attribute_or(node, "opacity", 1.0),
+        attribute_or(node, "visible", 1) != 0,
+    );
+    let name = node.attribute("name").unwrap_or("");
+    match node.tag_name().name() {
+        "group" => {
+            for child in node.children().filter(|n| n.is_element()) {
+                xml_layer(map, child, inherited)?;
+            }
+        }
+        "layer" => {
+            let mut layer = inherited.layer(name);
+            if let Some(data) = child(node, "data") {
+                let encoding = data.attribute("encoding");
+                let compression = data.attribute("compression");
+                let tiles = |node: roxmltree::Node| match encoding {
+                    Some(encoding) => decode_tiles(node.text().unwrap_or(""), encoding, compression),
+                    // Oldest format, a <tile> element per cell
+                    None => Ok(node.children().filter(|n| n.has_tag_name("tile")).map(|tile| attribute_or(tile, "gid", 0)).collect()),
+                };
+                let chunks = data.children().filter(|n| n.has_tag_name("chunk")).collect::<Vec<_>>();
+                if chunks.is_empty() {
+                    fill(&mut layer, [0, 0], attribute(node, "width")?, &tiles(data)?);
+                }
+                // Infinite maps are stored as chunks at any tile position
+                for chunk in chunks {
+                    fill(&mut layer, [attribute(chunk, "x")?, attribute(chunk, "y")?], attribute(chunk, "width")?, &tiles(chunk)?);
+                }
+            }
+            map.add_layer(layer);
+        }
+        "objectgroup" | "imagelayer" => {
+            log::info!("Skipped Tiled {} '{name}', only tile layers are loaded", node.tag_name().name());
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+// TMJ
+
+#[derive(Deserialize)]
+struct JsonMap {
+    #[serde(default = "orthogonal")]
+    orientation: String,
+    tilewidth: u32,
+    tileheight: u32,
+    backgroundcolor: Option<String>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+fn orthogonal() -> String {
+    "orthogonal".to_string()
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "one")]
+    opacity: f32,
+    #[serde(default = "yes")]
+    visible: bool,
+    #[serde(default)]
+    offsetx: f32,
+    #[serde(default)]
+    offsety: f32,
+    #[serde(default = "one")]
+    parallaxx: f32,
+    #[serde(default = "one")]
+    parallaxy: f32,
+    #[serde(default)]
+    width: u32,
+    // An array of ids, or a base64 string
+    data: Option<serde_json::Value>,
+    encoding: Option<String>,
+    compression: Option<String>,
+    #[serde(default)]
+    chunks: Vec<JsonChunk>,
+    // For groups
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+}
+
+#[derive(Deserialize)]
+struct JsonChunk {
+    x: i32,
+    y: i32,
+    width: u32,
+    data: serde_json::Value,
+}
+
+// Inline in a map, in a .tsj of its own, or a reference to one
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: TileId,
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    tilecount: u32,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default)]
+    margin: u32,
+    #[serde(default)]
+    spacing: u32,
+    image: Option<String>,
+    #[serde(default)]
+    imagewidth: u32,
+    #[serde(default)]
+    imageheight: u32,
+    transparentcolor: Option<String>,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    animation: Vec<JsonFrame>,
+}
+
+#[derive(Deserialize)]
+struct JsonFrame {
+    tileid: u32,
+    duration: u32,
+}
+
+impl JsonTileset {
+    fn info(self, first_id: TileId, dir: &Path) -> TilesetInfo {
+        TilesetInfo {
+            name: self.name,
+            first_id,
+            tile_size: [self.tilewidth, self.tileheight],
+            tile_count: self.tilecount,
+            columns: self.columns,
+            margin: self.margin,
+            spacing: self.spacing,
+            image: self.image.map(|image| dir.join(image)),
+            image_size: [self.imagewidth, self.imageheight],
+            transparent_color: self.transparentcolor,
+            animations: self.tiles.into_iter()
+                .filter(|tile| !tile.animation.is_empty())
+                .map(|tile| (tile.id, tile.animation.into_iter().map(|frame| (frame.tileid, frame.duration)).collect()))
+                .collect(),
+        }
+    }
+}
+
+pub fn parse_tmj(text: &str, dir: &Path) -> io::Result<Tilemap> {
+    let json: JsonMap = serde_json::from_str(text)?;
+    check_orientation(&json.orientation)?;
+
+    let mut map = Tilemap::new([json.tilewidth, json.tileheight]);
+    map.background = json.backgroundcolor.as_deref().and_then(background);
+    for tileset in json.tilesets {
+        let first_id = tileset.firstgid;
+        let tileset = match &tileset.source {
+            Some(source) => load_external_tileset(&dir.join(source), first_id)?,
+            None => tileset.info(first_id, dir).into_tileset()?,
+        };
+        map.add_tileset(tileset);
+    }
+    for layer in &json.layers {
+        json_layer(&mut map, layer, Inherited::ROOT)?;
+    }
+    Ok(map)
+}
+
+fn json_tiles(data: &serde_json::Value, layer: &JsonLayer) -> io::Result<Vec<TileId>> {
+    match data {
+        serde_json::Value::Array(ids) => ids.iter()
+            .map(|id| id.as_u64().map(|id| id as TileId).ok_or_else(|| invalid(format!("'{id}' isn't a tile id"))))
+            .collect(),
+        serde_json::Value::String(text) => decode_tiles(text, layer.encoding.as_deref().unwrap_or("base64"), layer.compression.as_deref()),
+        _ => Err(invalid(format!("Layer '{}' has data that's neither an array nor a string", layer.name))),
+    }
+}
+
+fn json_layer(map: &mut Tilemap, json: &JsonLayer, parent: Inherited) -> io::Result<()> {
+    let inherited = parent.child([json.offsetx, json.offsety], [json.parallaxx, json.parallaxy], json.opacity, json.visible);
+    match json.kind.as_str() {
+        "group" => {
+            for child in &json.layers {
+                json_layer(map, child, inherited)?;
+            }
+        }
+        "tilelayer" => {
+            let mut layer = inherited.layer(&json.name);
+            if let Some(data) = &json.data {
+                fill(&mut layer, [0, 0], json.width, &json_tiles(data, json)?);
+            }
+            for chunk in &json.chunks {
+                fill(&mut layer, [chunk.x, chunk.y], chunk.width, &json_tiles(&chunk.data, json)?);
+            }
+            map.add_layer(layer);
+        }
+        other => log::info!("Skipped Tiled {other} '{}', only tile layers are loaded", json.name),
+    }
+    Ok(())
+}
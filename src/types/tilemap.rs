@@ -0,0 +1,935 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use cgmath::Point2;
+use wgpu::util::DeviceExt;
+use crate::gpu::Gpu;
+use crate::types::{
+    camera2d::{Camera2D, Camera2DUniform},
+    lighting2d::NORMAL_FORMAT,
+    sampler::SamplerConfig,
+    texture::Texture,
+};
+
+// Same as Tiled: 0 is no tile, anything else is a global id that belongs to the tileset with the
+// highest first_id at or below it. The top bits flip the tile
+pub type TileId = u32;
+pub const EMPTY: TileId = 0;
+pub const FLIP_HORIZONTAL: TileId = 0x8000_0000;
+pub const FLIP_VERTICAL: TileId = 0x4000_0000;
+// Across the top left to bottom right diagonal, done before the other two. Combined with them it
+// turns the tile by 90 degrees
+pub const FLIP_DIAGONAL: TileId = 0x2000_0000;
+// Tiled's 120 degree turn for hexagonal maps, which aren't supported, so it's only stripped off
+const ROTATE_HEXAGONAL: TileId = 0x1000_0000;
+const FLIPS: TileId = FLIP_HORIZONTAL | FLIP_VERTICAL | FLIP_DIAGONAL;
+const FLAGS: TileId = FLIPS | ROTATE_HEXAGONAL;
+
+// The id without its flip bits
+pub fn tile_index(tile: TileId) -> TileId {
+    tile & !FLAGS
+}
+
+// Chunks are CHUNK_SIZE tiles along each side
+pub const CHUNK_SIZE: usize = 16;
+const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+pub type ChunkPos = [i32; 2];
+
+// Animation lookups are laid out in rows this long, so big tilesets don't run past the texture
+// size limit
+const FRAME_ROW: u32 = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationFrame {
+    // Index within the tileset, not a global id
+    pub tile: u32,
+    // Seconds
+    pub duration: f32,
+}
+
+// A grid of equally sized tiles cut out of one atlas image
+#[derive(Clone, Debug)]
+pub struct Tileset {
+    pub name: String,
+    // Global id of the first tile
+    pub first_id: TileId,
+    // Pixels. Tiles bigger than the map's grid stick out upwards and to the right of their cell
+    pub tile_size: [u32; 2],
+    pub tile_count: u32,
+    pub columns: u32,
+    // Pixels around the edge of the image, and between tiles
+    pub margin: u32,
+    pub spacing: u32,
+    pub image_size: [u32; 2],
+    // Where the atlas was when the tileset was imported, see TilemapRenderer::load_images
+    pub image: Option<PathBuf>,
+    // Normal map laid out the same as the atlas, for 2D lighting. Tiles without one are flat
+    pub normal_image: Option<PathBuf>,
+    // Pixels of this color are made transparent when the image is loaded
+    pub transparent_color: Option<[u8; 3]>,
+    // Keyed by the index of the tile that's animated
+    pub animations: HashMap<u32, Vec<AnimationFrame>>,
+}
+
+impl Tileset {
+    // For an atlas packed edge to edge, columns by tile_count / columns tiles. A first_id of 0 is
+    // filled in by Tilemap::add_tileset
+    pub fn new(name: &str, first_id: TileId, tile_size: [u32; 2], columns: u32, tile_count: u32) -> Self {
+        let columns = columns.max(1);
+        let rows = tile_count.div_ceil(columns);
+        Self {
+            name: name.to_string(),
+            first_id,
+            tile_size,
+            tile_count,
+            columns,
+            margin: 0,
+            spacing: 0,
+            image_size: [columns * tile_size[0], rows * tile_size[1]],
+            image: None,
+            normal_image: None,
+            transparent_color: None,
+            animations: HashMap::new(),
+        }
+    }
+
+    pub fn contains(&self, tile: TileId) -> bool {
+        let index = tile_index(tile);
+        index != EMPTY && index >= self.first_id && index - self.first_id < self.tile_count
+    }
+
+    // Plays frames in a loop wherever tile is placed
+    pub fn set_animation(&mut self, tile: u32, frames: Vec<AnimationFrame>) {
+        if frames.is_empty() {
+            self.animations.remove(&tile);
+        } else {
+            self.animations.insert(tile, frames);
+        }
+    }
+
+    // The tile an animated tile is showing time seconds in, tile itself if it isn't animated
+    pub fn animated_tile(&self, tile: u32, time: f32) -> u32 {
+        let Some(frames) = self.animations.get(&tile) else { return tile };
+        let length: f32 = frames.iter().map(|frame| frame.duration.max(0.0)).sum();
+        if length <= 0.0 {
+            return frames[0].tile;
+        }
+        let mut t = time.rem_euclid(length);
+        for frame in frames {
+            if t < frame.duration {
+                return frame.tile;
+            }
+            t -= frame.duration;
+        }
+        frames[frames.len() - 1].tile
+    }
+
+    // Left, top, right and bottom of a tile in the atlas, in pixels
+    pub fn tile_rect(&self, tile: u32) -> [u32; 4] {
+        let column = tile % self.columns;
+        let row = tile / self.columns;
+        let x = self.margin + column * (self.tile_size[0] + self.spacing);
+        let y = self.margin + row * (self.tile_size[1] + self.spacing);
+        [x, y, x + self.tile_size[0], y + self.tile_size[1]]
+    }
+}
+
+#[derive(Clone)]
+pub struct TileChunk {
+    tiles: Box<[TileId]>,
+}
+
+impl Default for TileChunk {
+    fn default() -> Self {
+        Self { tiles: vec![EMPTY; CHUNK_AREA].into_boxed_slice() }
+    }
+}
+
+impl TileChunk {
+    fn index(local: [usize; 2]) -> usize {
+        local[0] + local[1] * CHUNK_SIZE
+    }
+
+    pub fn get(&self, local: [usize; 2]) -> TileId {
+        self.tiles[Self::index(local)]
+    }
+
+    pub fn set(&mut self, local: [usize; 2], tile: TileId) {
+        self.tiles[Self::index(local)] = tile;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.iter().all(|&tile| tile == EMPTY)
+    }
+}
+
+// One layer of tiles, stored in chunks so maps can be as big as they like in any direction
+#[derive(Clone)]
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    pub opacity: f32,
+    // How much the layer moves as the camera does. 1 moves with the world, less than 1 lags
+    // behind like a distant background and 0 stays put on screen
+    pub parallax: [f32; 2],
+    // World units
+    pub offset: [f32; 2],
+    chunks: HashMap<ChunkPos, TileChunk>,
+    dirty: HashSet<ChunkPos>,
+}
+
+impl TileLayer {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            visible: true,
+            opacity: 1.0,
+            parallax: [1.0, 1.0],
+            offset: [0.0, 0.0],
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    pub fn with_parallax(mut self, parallax: [f32; 2]) -> Self {
+        self.parallax = parallax;
+        self
+    }
+
+    fn split(tile: [i32; 2]) -> (ChunkPos, [usize; 2]) {
+        let size = CHUNK_SIZE as i32;
+        (
+            tile.map(|x| x.div_euclid(size)),
+            tile.map(|x| x.rem_euclid(size) as usize),
+        )
+    }
+
+    // Tile coordinates count columns right and rows down from the top left, like Tiled
+    pub fn get(&self, tile: [i32; 2]) -> TileId {
+        let (chunk, local) = Self::split(tile);
+        self.chunks.get(&chunk).map_or(EMPTY, |chunk| chunk.get(local))
+    }
+
+    pub fn set(&mut self, tile: [i32; 2], id: TileId) {
+        let (chunk_pos, local) = Self::split(tile);
+        if id == EMPTY && !self.chunks.contains_key(&chunk_pos) {
+            return;
+        }
+        let chunk = self.chunks.entry(chunk_pos).or_default();
+        chunk.set(local, id);
+        if id == EMPTY && chunk.is_empty() {
+            self.chunks.remove(&chunk_pos);
+        }
+        self.dirty.insert(chunk_pos);
+    }
+
+    pub fn clear(&mut self) {
+        self.dirty.extend(self.chunks.keys().copied());
+        self.chunks.clear();
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&TileChunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    // Chunks that changed since the last call
+    pub fn take_dirty(&mut self) -> HashSet<ChunkPos> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    // Has every chunk rebuilt, for when the tilesets changed under it
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty.extend(self.chunks.keys().copied());
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TileVertex {
+    pub position: [f32; 2],
+    // Which corner of the tile this is, 0 to 1 from the top left with any flips applied
+    pub corner: [f32; 2],
+    // Index within the tileset, swapped for the current frame on the GPU if it's animated. The
+    // tile's flip bits are kept on top so its normals can be flipped to match
+    pub tile: u32,
+}
+
+impl TileVertex {
+    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+        use std::mem;
+        wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<TileVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Uint32,
+                },
+            ]
+        }
+    }
+}
+
+// Layers of tiles drawn back to front, with world +y up and tile [0, 0]'s top left corner at the
+// origin. Sizes in the world come from the grid's pixel size times scale
+pub struct Tilemap {
+    // Size of a grid cell in pixels
+    pub tile_size: [u32; 2],
+    // World units per pixel, 1 to go with Units2D::Pixels
+    pub scale: f32,
+    pub tilesets: Vec<Tileset>,
+    pub layers: Vec<TileLayer>,
+    // Cleared to before drawing, None draws over whatever's already there
+    pub background: Option<wgpu::Color>,
+    // Seconds of animation played
+    time: f32,
+}
+
+impl Tilemap {
+    pub fn new(tile_size: [u32; 2]) -> Self {
+        Self {
+            tile_size,
+            scale: 1.0,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            background: None,
+            time: 0.0,
+        }
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // A first_id of 0 puts the tileset's ids after the ones already taken. Returns its first id
+    pub fn add_tileset(&mut self, mut tileset: Tileset) -> TileId {
+        if tileset.first_id == EMPTY {
+            tileset.first_id = self.tilesets.iter().map(|t| t.first_id + t.tile_count).max().unwrap_or(1);
+        }
+        let first_id = tileset.first_id;
+        self.tilesets.push(tileset);
+        first_id
+    }
+
+    // Returns the layer's index, layers are drawn in order
+    pub fn add_layer(&mut self, layer: TileLayer) -> usize {
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    pub fn layer_by_name(&self, name: &str) -> Option<&TileLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_by_name_mut(&mut self, name: &str) -> Option<&mut TileLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    // Index of the tileset a tile comes from
+    pub fn tileset_of(&self, tile: TileId) -> Option<usize> {
+        self.tilesets.iter().enumerate()
+            .filter(|(_, tileset)| tileset.contains(tile))
+            .max_by_key(|(_, tileset)| tileset.first_id)
+            .map(|(i, _)| i)
+    }
+
+    // Moves animated tiles along, dt in seconds
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    // Width and height of a grid cell in world units
+    pub fn cell_size(&self) -> [f32; 2] {
+        [self.tile_size[0] as f32 * self.scale, self.tile_size[1] as f32 * self.scale]
+    }
+
+    // The tile a world point is in, ignoring any layer offset
+    pub fn tile_at(&self, point: Point2<f32>) -> [i32; 2] {
+        let cell = self.cell_size();
+        [(point.x / cell[0]).floor() as i32, (-point.y / cell[1]).floor() as i32]
+    }
+
+    // World position of a tile's bottom left corner, where its quad starts
+    pub fn tile_origin(&self, tile: [i32; 2]) -> Point2<f32> {
+        let cell = self.cell_size();
+        Point2::new(tile[0] as f32 * cell[0], -(tile[1] + 1) as f32 * cell[1])
+    }
+
+    // Left, bottom, right and top of a chunk in the world, including tiles that stick out of it
+    fn chunk_bounds(&self, pos: ChunkPos) -> [f32; 4] {
+        let cell = self.cell_size();
+        let size = CHUNK_SIZE as f32;
+        let overhang = self.tilesets.iter().fold([0.0f32; 2], |overhang, tileset| [
+            overhang[0].max(tileset.tile_size[0] as f32 * self.scale - cell[0]),
+            overhang[1].max(tileset.tile_size[1] as f32 * self.scale - cell[1]),
+        ]);
+        let left = pos[0] as f32 * size * cell[0];
+        let top = -(pos[1] as f32) * size * cell[1];
+        [left, top - size * cell[1], left + size * cell[0] + overhang[0], top + overhang[1]]
+    }
+
+    // One quad per tile in a chunk, split up by tileset since each is drawn with its own atlas.
+    // Empty cells and tiles from unknown tilesets are left out
+    pub fn chunk_mesh(&self, layer: usize, pos: ChunkPos) -> Vec<(usize, Vec<TileVertex>)> {
+        let mut meshes: Vec<(usize, Vec<TileVertex>)> = Vec::new();
+        let Some(chunk) = self.layers.get(layer).and_then(|layer| layer.chunk(pos)) else { return meshes };
+        let base = pos.map(|x| x * CHUNK_SIZE as i32);
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let tile = chunk.get([x, y]);
+                if tile == EMPTY {
+                    continue;
+                }
+                let Some(set) = self.tileset_of(tile) else { continue };
+                let tileset = &self.tilesets[set];
+                let vertices = match meshes.iter_mut().find(|(i, _)| *i == set) {
+                    Some((_, vertices)) => vertices,
+                    None => {
+                        meshes.push((set, Vec::new()));
+                        &mut meshes.last_mut().unwrap().1
+                    }
+                };
+
+                let origin = self.tile_origin([base[0] + x as i32, base[1] + y as i32]);
+                let size = [tileset.tile_size[0] as f32 * self.scale, tileset.tile_size[1] as f32 * self.scale];
+                let index = tile_index(tile) - tileset.first_id;
+                // Bottom left, bottom right, top right, top left, matching the shared index buffer
+                for (dx, dy) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                    let mut corner = [dx, 1.0 - dy];
+                    if tile & FLIP_DIAGONAL != 0 {
+                        corner = [corner[1], corner[0]];
+                    }
+                    if tile & FLIP_HORIZONTAL != 0 {
+                        corner[0] = 1.0 - corner[0];
+                    }
+                    if tile & FLIP_VERTICAL != 0 {
+                        corner[1] = 1.0 - corner[1];
+                    }
+                    vertices.push(TileVertex {
+                        position: [origin.x + dx * size[0], origin.y + dy * size[1]],
+                        corner,
+                        tile: index | (tile & FLIPS),
+                    });
+                }
+            }
+        }
+        meshes
+    }
+}
+
+// What the shader knows about a tileset
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TilesetUniform {
+    // (tile width, tile height, margin, spacing) in pixels
+    grid: [f32; 4],
+    // (image width, image height, columns, unused)
+    image: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct LayerUniform {
+    translation: [f32; 2],
+    opacity: f32,
+    _padding: f32,
+}
+
+struct TilesetResources {
+    atlas: Texture,
+    normals: Option<Texture>,
+    // Maps each tile to the one it's currently showing, only rewritten when an animation moves on
+    frames: wgpu::Texture,
+    shown: Vec<u32>,
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+struct ChunkMesh {
+    tileset: usize,
+    vertex_buffer: wgpu::Buffer,
+    quads: u32,
+}
+
+struct LayerResources {
+    uniform: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    chunks: HashMap<ChunkPos, Vec<ChunkMesh>>,
+}
+
+// Draws a Tilemap seen through a Camera2D in a pass of its own. Every chunk is uploaded once and
+// redrawn as it is until one of its tiles changes. Animation is done by swapping tiles in a small
+// lookup texture per tileset, so animated tiles don't rebuild anything
+pub struct TilemapRenderer {
+    pipeline: wgpu::RenderPipeline,
+    // Draws the tiles' normals for Lighting2DRenderer instead of their colors
+    normal_pipeline: wgpu::RenderPipeline,
+    // Stands in for tilesets without a normal map
+    flat_normals: Texture,
+    format: wgpu::TextureFormat,
+    camera_uniform: Camera2DUniform,
+    camera_buffer: wgpu::Buffer,
+    camera_bind_group: wgpu::BindGroup,
+    tileset_layout: wgpu::BindGroupLayout,
+    layer_layout: wgpu::BindGroupLayout,
+    sampler_config: SamplerConfig,
+    sampler: wgpu::Sampler,
+    // Indexed like the map's tilesets, None until an image has been given
+    tilesets: Vec<Option<TilesetResources>>,
+    layers: Vec<LayerResources>,
+    // Every quad is indexed the same way, so one buffer does for every chunk
+    index_buffer: wgpu::Buffer,
+    chunks_drawn: usize,
+}
+
+impl TilemapRenderer {
+    pub fn new(gpu: &Gpu, format: wgpu::TextureFormat) -> Self {
+        let device = &gpu.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Tilemap Shader"), source: wgpu::ShaderSource::Wgsl(include_str!("../tilemap.wgsl").into()) });
+
+        let uniform_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let camera_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tilemap_camera_bind_group_layout"),
+            entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX)],
+        });
+        let tileset_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tileset_bind_group_layout"),
+            entries: &[
+                uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let layer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tile_layer_bind_group_layout"),
+            entries: &[uniform_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Tilemap Pipeline Layout"),
+            bind_group_layouts: &[&camera_layout, &tileset_layout, &layer_layout],
+            push_constant_ranges: &[],
+        });
+
+        let create_pipeline = |label, entry_point, format, blend| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[TileVertex::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point,
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                // Flipped tiles are still the same quads, nothing is ever seen from behind
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let pipeline = create_pipeline("Tilemap Pipeline", "fs_main", format, Some(wgpu::BlendState::ALPHA_BLENDING));
+        // Normals can't be blended, the topmost solid tile's win
+        let normal_pipeline = create_pipeline("Tilemap Normal Pipeline", "fs_normal", NORMAL_FORMAT, None);
+        let flat_normals = Texture::from_rgba(device, &gpu.queue, &image::RgbaImage::from_pixel(1, 1, image::Rgba([128, 128, 255, 255])), NORMAL_FORMAT, "Flat Normals");
+
+        let camera_uniform = Camera2DUniform::new();
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tilemap Camera Buffer"),
+            contents: bytemuck::bytes_of(&camera_uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tilemap_camera_bind_group"),
+            layout: &camera_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: camera_buffer.as_entire_binding() }],
+        });
+
+        let indices = (0..CHUNK_AREA as u32)
+            .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|i| quad * 4 + i))
+            .collect::<Vec<_>>();
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tilemap Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        // Pixel art is the usual, and linear filtering blurs tiles into each other
+        let sampler_config = SamplerConfig::nearest();
+        let sampler = sampler_config.create_sampler(device, Some("Tilemap Sampler"));
+
+        Self {
+            pipeline,
+            normal_pipeline,
+            flat_normals,
+            format,
+            camera_uniform,
+            camera_buffer,
+            camera_bind_group,
+            tileset_layout,
+            layer_layout,
+            sampler_config,
+            sampler,
+            tilesets: Vec::new(),
+            layers: Vec::new(),
+            index_buffer,
+            chunks_drawn: 0,
+        }
+    }
+
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.format
+    }
+
+    pub fn sampler_config(&self) -> &SamplerConfig {
+        &self.sampler_config
+    }
+
+    // Tile edges are kept from bleeding into their neighbours in the atlas either way
+    pub fn set_sampler_config(&mut self, gpu: &Gpu, config: SamplerConfig) {
+        self.sampler_config = config.clamped(gpu.max_anisotropy);
+        self.sampler = self.sampler_config.create_sampler(&gpu.device, Some("Tilemap Sampler"));
+        for tileset in self.tilesets.iter_mut().flatten() {
+            let normals = tileset.normals.as_ref().unwrap_or(&self.flat_normals);
+            tileset.bind_group = Self::tileset_bind_group(&gpu.device, &self.tileset_layout, &self.sampler, &tileset.uniform, &tileset.atlas, normals, &tileset.frames);
+        }
+    }
+
+    // Chunks drawn by the last render, out of every chunk on a visible layer
+    pub fn chunks_drawn(&self) -> usize {
+        self.chunks_drawn
+    }
+
+    // Loads every tileset's image, and normal map if it has one, from where they were imported from
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_images(&mut self, gpu: &Gpu, map: &Tilemap) -> std::io::Result<()> {
+        let open = |path| image::open(path).map_err(std::io::Error::other).map(|image| image.to_rgba8());
+        for (i, tileset) in map.tilesets.iter().enumerate() {
+            let Some(path) = &tileset.image else { continue };
+            self.set_image(gpu, map, i, &open(path)?);
+            if let Some(path) = &tileset.normal_image {
+                self.set_normal_image(gpu, i, &open(path)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Gives a tileset that already has its atlas a normal map to go with it
+    pub fn set_normal_image(&mut self, gpu: &Gpu, index: usize, image: &image::RgbaImage) {
+        let Some(Some(tileset)) = self.tilesets.get_mut(index) else {
+            log::warn!("Tileset {index} needs its image before its normal map");
+            return;
+        };
+        let normals = Texture::from_rgba(&gpu.device, &gpu.queue, image, NORMAL_FORMAT, "Tileset Normals");
+        tileset.bind_group = Self::tileset_bind_group(&gpu.device, &self.tileset_layout, &self.sampler, &tileset.uniform, &tileset.atlas, &normals, &tileset.frames);
+        tileset.normals = Some(normals);
+    }
+
+    // Gives the tileset at index its atlas. The tileset's transparent color is keyed out here
+    pub fn set_image(&mut self, gpu: &Gpu, map: &Tilemap, index: usize, image: &image::RgbaImage) {
+        let Some(tileset) = map.tilesets.get(index) else { return };
+        let device = &gpu.device;
+        let mut image = image.clone();
+        if let Some(key) = tileset.transparent_color {
+            for pixel in image.pixels_mut() {
+                if pixel.0[..3] == key {
+                    pixel.0 = [0; 4];
+                }
+            }
+        }
+
+        let atlas = Texture::from_rgba(device, &gpu.queue, &image, wgpu::TextureFormat::Rgba8UnormSrgb, &format!("{} Atlas", tileset.name));
+
+        let frames = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&format!("{} Frames", tileset.name)),
+            size: wgpu::Extent3d { width: FRAME_ROW, height: tileset.tile_count.div_ceil(FRAME_ROW).max(1), depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        // Uses the image's real size, which Tiled's numbers might not agree with
+        let uniform = TilesetUniform {
+            grid: [tileset.tile_size[0] as f32, tileset.tile_size[1] as f32, tileset.margin as f32, tileset.spacing as f32],
+            image: [image.width() as f32, image.height() as f32, tileset.columns as f32, 0.0],
+        };
+        let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Tileset Buffer", tileset.name)),
+            contents: bytemuck::bytes_of(&uniform),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let bind_group = Self::tileset_bind_group(device, &self.tileset_layout, &self.sampler, &uniform, &atlas, &self.flat_normals, &frames);
+
+        if self.tilesets.len() <= index {
+            self.tilesets.resize_with(index + 1, || None);
+        }
+        // Empty so the first sync writes the whole lookup
+        self.tilesets[index] = Some(TilesetResources { atlas, normals: None, frames, shown: Vec::new(), uniform, bind_group });
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn tileset_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        uniform: &wgpu::Buffer,
+        atlas: &Texture,
+        normals: &Texture,
+        frames: &wgpu::Texture,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tileset_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: uniform.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&atlas.view) },
+                wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(sampler) },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&frames.create_view(&wgpu::TextureViewDescriptor::default())),
+                },
+                wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(&normals.view) },
+            ],
+        })
+    }
+
+    // Uploads the chunks that changed and moves animations on
+    fn sync(&mut self, gpu: &Gpu, map: &mut Tilemap) {
+        let device = &gpu.device;
+        // Layers added or removed, everything's rebuilt rather than working out which moved
+        if self.layers.len() != map.layers.len() {
+            self.layers = map.layers.iter().enumerate().map(|(i, _)| {
+                let uniform = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(&format!("Tile Layer {i} Buffer")),
+                    size: std::mem::size_of::<LayerUniform>() as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(&format!("tile_layer_{i}_bind_group")),
+                    layout: &self.layer_layout,
+                    entries: &[wgpu::BindGroupEntry { binding: 0, resource: uniform.as_entire_binding() }],
+                });
+                LayerResources { uniform, bind_group, chunks: HashMap::new() }
+            }).collect();
+            for layer in &mut map.layers {
+                layer.mark_all_dirty();
+            }
+        }
+
+        for i in 0..map.layers.len() {
+            for pos in map.layers[i].take_dirty() {
+                let meshes = map.chunk_mesh(i, pos).into_iter().map(|(tileset, vertices)| ChunkMesh {
+                    tileset,
+                    vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("Tile Chunk {pos:?} Vertex Buffer")),
+                        contents: bytemuck::cast_slice(&vertices),
+                        usage: wgpu::BufferUsages::VERTEX,
+                    }),
+                    quads: vertices.len() as u32 / 4,
+                }).collect::<Vec<_>>();
+                if meshes.is_empty() {
+                    self.layers[i].chunks.remove(&pos);
+                } else {
+                    self.layers[i].chunks.insert(pos, meshes);
+                }
+            }
+        }
+
+        for (tileset, resources) in map.tilesets.iter().zip(&mut self.tilesets) {
+            let Some(resources) = resources else { continue };
+            let rows = tileset.tile_count.div_ceil(FRAME_ROW).max(1);
+            let mut changed = false;
+            if resources.shown.is_empty() {
+                // Every tile starts out showing itself
+                resources.shown = (0..rows * FRAME_ROW).collect();
+                changed = true;
+            }
+            for &tile in tileset.animations.keys() {
+                let Some(shown) = resources.shown.get_mut(tile as usize) else { continue };
+                let frame = tileset.animated_tile(tile, map.time);
+                if *shown != frame {
+                    *shown = frame;
+                    changed = true;
+                }
+            }
+            if changed {
+                gpu.queue.write_texture(
+                    resources.frames.as_image_copy(),
+                    bytemuck::cast_slice(&resources.shown),
+                    wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(FRAME_ROW * 4), rows_per_image: None },
+                    wgpu::Extent3d { width: FRAME_ROW, height: rows, depth_or_array_layers: 1 },
+                );
+            }
+        }
+    }
+
+    // Draws every visible layer into view. Clears to the map's background first if it has one,
+    // otherwise draws over what's there
+    pub fn render(&mut self, gpu: &Gpu, view: &wgpu::TextureView, map: &mut Tilemap, camera: &Camera2D) {
+        trace_span!("tilemap render");
+        self.draw(gpu, view, map, camera, false);
+    }
+
+    // Draws the tiles' normals into Lighting2DRenderer::normal_view, over whatever else has been
+    // drawn there this frame. Call with the same camera as render
+    pub fn render_normals(&mut self, gpu: &Gpu, view: &wgpu::TextureView, map: &mut Tilemap, camera: &Camera2D) {
+        trace_span!("tilemap normals");
+        self.draw(gpu, view, map, camera, true);
+    }
+
+    fn draw(&mut self, gpu: &Gpu, view: &wgpu::TextureView, map: &mut Tilemap, camera: &Camera2D, normals: bool) {
+        self.sync(gpu, map);
+        self.camera_uniform.update(camera);
+        gpu.queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&self.camera_uniform));
+
+        let (view_min, view_max) = camera.visible_bounds();
+
+        let center = camera.view_center();
+        let translations = map.layers.iter().zip(&self.layers).map(|(layer, resources)| {
+            // Parallax drags the layer along with the camera by whatever it doesn't move with the world
+            let translation = Point2::new(
+                layer.offset[0] + center.x * (1.0 - layer.parallax[0]),
+                layer.offset[1] + center.y * (1.0 - layer.parallax[1]),
+            );
+            let translation = if camera.is_pixel_perfect() { camera.snap(translation) } else { translation };
+            let uniform = LayerUniform { translation: translation.into(), opacity: layer.opacity, _padding: 0.0 };
+            gpu.queue.write_buffer(&resources.uniform, 0, bytemuck::bytes_of(&uniform));
+            translation
+        }).collect::<Vec<_>>();
+
+        let mut encoder = gpu.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Tilemap Encoder"),
+        });
+        let mut chunks_drawn = 0;
+        {
+            let load = match map.background {
+                Some(color) if !normals => wgpu::LoadOp::Clear(color),
+                _ => wgpu::LoadOp::Load,
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(if normals { "Tilemap Normal Pass" } else { "Tilemap Pass" }),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations { load, store: wgpu::StoreOp::Store },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(if normals { &self.normal_pipeline } else { &self.pipeline });
+            render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+
+            for ((layer, resources), translation) in map.layers.iter().zip(&self.layers).zip(translations) {
+                if !layer.visible || layer.opacity <= 0.0 {
+                    continue;
+                }
+                render_pass.push_debug_group(&layer.name);
+                render_pass.set_bind_group(2, &resources.bind_group, &[]);
+                // Grouped by tileset so each atlas is only bound once per layer
+                for (set, tileset) in self.tilesets.iter().enumerate() {
+                    let Some(tileset) = tileset else { continue };
+                    let mut bound = false;
+                    for (pos, meshes) in &resources.chunks {
+                        let bounds = map.chunk_bounds(*pos);
+                        if bounds[0] + translation.x > view_max.x || bounds[2] + translation.x < view_min.x
+                            || bounds[1] + translation.y > view_max.y || bounds[3] + translation.y < view_min.y {
+                            continue;
+                        }
+                        for mesh in meshes.iter().filter(|mesh| mesh.tileset == set) {
+                            if !bound {
+                                render_pass.set_bind_group(1, &tileset.bind_group, &[]);
+                                bound = true;
+                            }
+                            render_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                            render_pass.draw_indexed(0..mesh.quads * 6, 0, 0..1);
+                            chunks_drawn += 1;
+                        }
+                    }
+                }
+                render_pass.pop_debug_group();
+            }
+        }
+        self.chunks_drawn = chunks_drawn;
+        gpu.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+use crate::types::stats::{Pass, PassTimes};
+
+// Where the timer is in measuring a frame. Only one measurement is in flight at a time, frames
+// in between go unmeasured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TimerState {
+    Idle,
+    // Timestamps have been resolved into the readback buffer by an encoder that isn't submitted yet
+    Resolved,
+    // Waiting on map_async
+    Mapping,
+}
+
+// Times how long each render pass of a frame takes on the GPU with timestamp queries. Results come
+// back a few frames late, without ever stalling for them
+pub struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+    // Nanoseconds per timestamp tick
+    period: f32,
+    state: TimerState,
+    // Set by the map_async callback, see MAP_PENDING
+    map_result: Arc<AtomicU8>,
+    // Which pass each pair of queries is timing, in the order they were handed out
+    passes: Vec<Pass>,
+    // The same for the measurement on its way back
+    measured: Vec<Pass>,
+}
+
+// Passes that can be timed in one frame, any after that go untimed. Enough for every pass of a
+// handful of views
+const MAX_PASSES: u32 = 64;
+
+const MAP_PENDING: u8 = 0;
+const MAP_OK: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+impl GpuTimer {
+    // None if the device wasn't created with timestamp queries, which WebGL never has
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let size = 2 * MAX_PASSES as wgpu::BufferAddress * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        Some(Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("GPU Timer Queries"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2 * MAX_PASSES,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("GPU Timer Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("GPU Timer Readback Buffer"),
+                size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+            state: TimerState::Idle,
+            map_result: Arc::new(AtomicU8::new(MAP_PENDING)),
+            passes: Vec::new(),
+            measured: Vec::new(),
+        })
+    }
+
+    // For a pass to be timed as part of pass, None while the last measurement is still on its way
+    // back or once MAX_PASSES have been timed this frame
+    pub fn timestamp_writes(&mut self, pass: Pass) -> Option<wgpu::RenderPassTimestampWrites<'_>> {
+        if self.state != TimerState::Idle || self.passes.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+        let index = 2 * self.passes.len() as u32;
+        self.passes.push(pass);
+        Some(wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(index),
+            end_of_pass_write_index: Some(index + 1),
+        })
+    }
+
+    // After every pass that used timestamp_writes, in the same encoder
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.state != TimerState::Idle || self.passes.is_empty() {
+            return;
+        }
+        let queries = 2 * self.passes.len() as u32;
+        let size = queries as wgpu::BufferAddress * std::mem::size_of::<u64>() as wgpu::BufferAddress;
+        encoder.resolve_query_set(&self.query_set, 0..queries, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, size);
+        self.measured = std::mem::take(&mut self.passes);
+        self.state = TimerState::Resolved;
+    }
+
+    // After the encoder passed to resolve has been submitted
+    pub fn submitted(&mut self) {
+        if self.state != TimerState::Resolved {
+            return;
+        }
+        let map_result = self.map_result.clone();
+        self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(e) = &result {
+                log::warn!("Couldn't read GPU timestamps: {e}");
+            }
+            map_result.store(if result.is_ok() { MAP_OK } else { MAP_FAILED }, Ordering::Release);
+        });
+        self.state = TimerState::Mapping;
+    }
+
+    // Seconds each pass of the last measured frame took, once they've arrived. Checks without blocking
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<PassTimes> {
+        if self.state != TimerState::Mapping {
+            return None;
+        }
+        device.poll(wgpu::Maintain::Poll);
+        match self.map_result.swap(MAP_PENDING, Ordering::Acquire) {
+            MAP_PENDING => return None,
+            MAP_FAILED => {
+                self.state = TimerState::Idle;
+                return None;
+            }
+            _ => {}
+        }
+        let mut times = PassTimes::default();
+        {
+            let data = self.readback_buffer.slice(..).get_mapped_range();
+            let ticks: &[u64] = bytemuck::cast_slice(&data);
+            for (pass, ticks) in self.measured.iter().zip(ticks.chunks_exact(2)) {
+                times.add(*pass, ticks[1].wrapping_sub(ticks[0]) as f32 * self.period * 1e-9);
+            }
+        }
+        self.readback_buffer.unmap();
+        self.state = TimerState::Idle;
+        Some(times)
+    }
+}
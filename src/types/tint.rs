@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+
+use crate::types::{color::Color, picking::MeshId};
+
+// Each tint has its own slice of the buffer, picked with a dynamic offset. 256 is the most any
+// device asks those to be aligned to
+const STRIDE: u64 = 256;
+
+// Every mesh's tint for the frame, bound at group 3 of the mesh pipelines with the mesh's
+// offset. The first slot is always white, so untinted meshes share it
+pub(crate) struct MeshTints {
+    layout: wgpu::BindGroupLayout,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // Byte offsets of the tinted meshes' slots
+    offsets: HashMap<MeshId, u32>,
+}
+
+impl MeshTints {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tint_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<Color>() as u64),
+                },
+                count: None,
+            }],
+        });
+        let (buffer, bind_group) = Self::create_buffer(device, &layout, 16);
+        Self { layout, buffer, bind_group, offsets: HashMap::new() }
+    }
+
+    fn create_buffer(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, slots: u64) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tint Buffer"),
+            size: slots * STRIDE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("tint_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<Color>() as u64),
+                }),
+            }],
+        });
+        (buffer, bind_group)
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    // Gives every tinted mesh a slot and uploads them all, growing the buffer if they don't fit
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, tints: impl IntoIterator<Item = (MeshId, Color)>) {
+        self.offsets.clear();
+        let mut contents = vec![0u8; STRIDE as usize];
+        contents[..std::mem::size_of::<Color>()].copy_from_slice(bytemuck::bytes_of(&Color::WHITE));
+        for (id, tint) in tints {
+            if tint.buffer() == Color::WHITE.buffer() {
+                continue;
+            }
+            self.offsets.insert(id, contents.len() as u32);
+            let start = contents.len();
+            contents.resize(start + STRIDE as usize, 0);
+            contents[start..start + std::mem::size_of::<Color>()].copy_from_slice(bytemuck::bytes_of(&tint));
+        }
+        if contents.len() as u64 > self.buffer.size() {
+            let slots = (contents.len() as u64 / STRIDE).next_power_of_two();
+            (self.buffer, self.bind_group) = Self::create_buffer(device, &self.layout, slots);
+        }
+        queue.write_buffer(&self.buffer, 0, &contents);
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    // Where id's tint is as of the last write, the white slot if it has none
+    pub fn offset(&self, id: MeshId) -> u32 {
+        self.offsets.get(&id).copied().unwrap_or(0)
+    }
+}
@@ -0,0 +1,179 @@
+use crate::types::{geometry::Vertex, mesh::MeshData};
+
+type Point = [f32; 2];
+
+fn cross(o: Point, a: Point, b: Point) -> f32 {
+    (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+}
+
+// Twice the signed area, positive for counter-clockwise polygons
+fn signed_area(points: &[Point], polygon: &[u32]) -> f32 {
+    (0..polygon.len()).map(|i| {
+        let a = points[polygon[i] as usize];
+        let b = points[polygon[(i + 1) % polygon.len()] as usize];
+        a[0] * b[1] - b[0] * a[1]
+    }).sum()
+}
+
+fn in_triangle(p: Point, a: Point, b: Point, c: Point) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+// True if the segments ab and cd cross somewhere other than their ends
+fn segments_cross(a: Point, b: Point, c: Point, d: Point) -> bool {
+    if a == c || a == d || b == c || b == d {
+        return false;
+    }
+    let d1 = cross(a, b, c);
+    let d2 = cross(a, b, d);
+    let d3 = cross(c, d, a);
+    let d4 = cross(c, d, b);
+    ((d1 > 0.0 && d2 < 0.0) || (d1 < 0.0 && d2 > 0.0)) && ((d3 > 0.0 && d4 < 0.0) || (d3 < 0.0 && d4 > 0.0))
+}
+
+// True if q is inside the interior angle of a counter-clockwise polygon at p
+fn in_cone(prev: Point, p: Point, next: Point, q: Point) -> bool {
+    if cross(prev, p, next) >= 0.0 {
+        cross(prev, p, q) > 0.0 && cross(p, next, q) > 0.0
+    } else {
+        cross(prev, p, q) > 0.0 || cross(p, next, q) > 0.0
+    }
+}
+
+fn edges(polygon: &[u32]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    (0..polygon.len()).map(move |i| (polygon[i], polygon[(i + 1) % polygon.len()]))
+}
+
+// Joins a hole onto the outline with a pair of zero-width edges, turning both into one polygon
+fn bridge_hole(points: &[Point], polygon: &mut Vec<u32>, hole: &[u32], other_holes: &[Vec<u32>]) {
+    // The hole's rightmost vertex is guaranteed to see something on the outline
+    let (hole_start, &h) = hole.iter().enumerate()
+        .max_by(|a, b| points[*a.1 as usize][0].total_cmp(&points[*b.1 as usize][0]))
+        .unwrap();
+    let hp = points[h as usize];
+
+    let mut candidates = (0..polygon.len()).collect::<Vec<_>>();
+    let distance = |i: &usize| {
+        let p = points[polygon[*i] as usize];
+        (p[0] - hp[0]).powi(2) + (p[1] - hp[1]).powi(2)
+    };
+    candidates.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+
+    let visible = candidates.iter().copied().find(|&i| {
+        let n = polygon.len();
+        let prev = points[polygon[(i + n - 1) % n] as usize];
+        let p = points[polygon[i] as usize];
+        let next = points[polygon[(i + 1) % n] as usize];
+        in_cone(prev, p, next, hp)
+            && !edges(polygon).chain(edges(hole)).chain(other_holes.iter().flat_map(|h| edges(h)))
+                .any(|(a, b)| segments_cross(p, hp, points[a as usize], points[b as usize]))
+    });
+    // Only happens for self-intersecting input, so just take the closest vertex
+    let bridge = visible.unwrap_or(candidates[0]);
+
+    let mut joined = Vec::with_capacity(polygon.len() + hole.len() + 2);
+    joined.extend_from_slice(&polygon[..=bridge]);
+    joined.extend_from_slice(&hole[hole_start..]);
+    joined.extend_from_slice(&hole[..=hole_start]);
+    joined.extend_from_slice(&polygon[bridge..]);
+    *polygon = joined;
+}
+
+// Triangulates a simple polygon with any number of holes by ear clipping.
+// Points are numbered outline first, then each hole in order. Either winding is accepted for
+// the outline and holes, and the triangles come out counter-clockwise
+pub fn triangulate(outline: &[Point], holes: &[&[Point]]) -> Vec<u32> {
+    // Nothing to fill, and nothing for a hole to be bridged onto
+    if outline.len() < 3 {
+        return Vec::new();
+    }
+    let mut points = outline.to_vec();
+    let mut polygon = (0..outline.len() as u32).collect::<Vec<_>>();
+    if signed_area(&points, &polygon) < 0.0 {
+        polygon.reverse();
+    }
+
+    let mut hole_polygons = Vec::with_capacity(holes.len());
+    for hole in holes {
+        let start = points.len() as u32;
+        // Points of holes too small to cut anything are still numbered, so the later holes' are
+        // where the caller expects
+        points.extend_from_slice(hole);
+        if hole.len() < 3 {
+            continue;
+        }
+        let mut hole_polygon = (start..points.len() as u32).collect::<Vec<_>>();
+        // Holes have to wind the opposite way to the outline
+        if signed_area(&points, &hole_polygon) > 0.0 {
+            hole_polygon.reverse();
+        }
+        hole_polygons.push(hole_polygon);
+    }
+    // Bridging right to left means a bridge never has to cross a hole that hasn't been joined yet
+    let max_x = |hole: &Vec<u32>| hole.iter().map(|&i| points[i as usize][0]).fold(f32::MIN, f32::max);
+    hole_polygons.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+    while !hole_polygons.is_empty() {
+        let hole = hole_polygons.remove(0);
+        bridge_hole(&points, &mut polygon, &hole, &hole_polygons);
+    }
+
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2) * 3);
+    while polygon.len() > 3 {
+        let n = polygon.len();
+        let ear = (0..n).find(|&i| {
+            let a = points[polygon[(i + n - 1) % n] as usize];
+            let b = points[polygon[i] as usize];
+            let c = points[polygon[(i + 1) % n] as usize];
+            if cross(a, b, c) <= 0.0 {
+                return false;
+            }
+            // Bridges duplicate vertices, so compare positions rather than indices
+            !polygon.iter()
+                .map(|&j| points[j as usize])
+                .any(|p| p != a && p != b && p != c && in_triangle(p, a, b, c))
+        });
+
+        // Nothing is a proper ear if the polygon is degenerate, clip anything so we still finish
+        let i = ear.unwrap_or(0);
+        let a = polygon[(i + n - 1) % n];
+        let b = polygon[i];
+        let c = polygon[(i + 1) % n];
+        if cross(points[a as usize], points[b as usize], points[c as usize]) > 0.0 {
+            triangles.extend_from_slice(&[a, b, c]);
+        }
+        polygon.remove(i);
+    }
+    if polygon.len() == 3 && cross(points[polygon[0] as usize], points[polygon[1] as usize], points[polygon[2] as usize]) > 0.0 {
+        triangles.extend_from_slice(&polygon);
+    }
+
+    triangles
+}
+
+// Builds a flat mesh from a polygon in the xy plane, with the triangles wound to face +z
+// for the given front face. Vertices without a normal are given one pointing at +z
+pub fn polygon_mesh(outline: &[Vertex], holes: &[&[Vertex]], front_face: wgpu::FrontFace) -> MeshData {
+    let to_points = |vertices: &[Vertex]| vertices.iter().map(|v| [v.position[0], v.position[1]]).collect::<Vec<_>>();
+    let hole_points = holes.iter().map(|hole| to_points(hole)).collect::<Vec<_>>();
+    let hole_slices = hole_points.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let mut indices = triangulate(&to_points(outline), &hole_slices);
+
+    // Looking down -z, counter-clockwise in xy stays counter-clockwise on screen
+    if front_face == wgpu::FrontFace::Cw {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    let mut vertices = outline.to_vec();
+    for hole in holes {
+        vertices.extend_from_slice(hole);
+    }
+    for vertex in &mut vertices {
+        if vertex.normal == [0.0; 3] {
+            vertex.normal = [0.0, 0.0, 1.0];
+        }
+    }
+
+    MeshData::new(vertices, indices)
+}
@@ -0,0 +1,118 @@
+use std::{collections::HashMap, fmt};
+use cgmath::{InnerSpace, Vector3};
+use crate::types::mesh::{FaceSettings, MeshData};
+
+// Textures bigger than this on either side are flagged, most GPUs handle them but they eat memory fast
+pub const OVERSIZED_TEXTURE_DIMENSION: u32 = 4096;
+
+// Triangles with less area than this are considered degenerate
+const DEGENERATE_AREA: f32 = 1e-12;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    // Vertices with a zero length normal
+    MissingNormals { count: usize },
+    NanVertices { count: usize },
+    DegenerateTriangles { count: usize },
+    // Edges shared by more than two triangles
+    NonManifoldEdges { count: usize },
+    // Indices that point past the end of the vertex list
+    IndicesOutOfRange { count: usize },
+    // Most triangles face the opposite way to the mesh's front face setting, so they get culled
+    InvertedWinding,
+    OversizedTexture { width: u32, height: u32 },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::MissingNormals { count } => write!(f, "{count} vertices have no normal"),
+            ValidationIssue::NanVertices { count } => write!(f, "{count} vertices contain NaN"),
+            ValidationIssue::DegenerateTriangles { count } => write!(f, "{count} triangles have no area"),
+            ValidationIssue::NonManifoldEdges { count } => write!(f, "{count} edges are shared by more than two triangles"),
+            ValidationIssue::IndicesOutOfRange { count } => write!(f, "{count} indices are out of range"),
+            ValidationIssue::InvertedWinding => write!(f, "winding is inverted for the mesh's front face, it will be culled from the outside"),
+            ValidationIssue::OversizedTexture { width, height } => write!(f, "texture is {width}x{height}, over {OVERSIZED_TEXTURE_DIMENSION} on a side"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ValidationReport {
+    // What each issue was found in, e.g. "mesh 3"
+    pub issues: Vec<(String, ValidationIssue)>,
+}
+
+impl ValidationReport {
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn add(&mut self, source: impl Into<String>, issues: impl IntoIterator<Item = ValidationIssue>) {
+        let source = source.into();
+        self.issues.extend(issues.into_iter().map(|issue| (source.clone(), issue)));
+    }
+
+    pub fn log(&self) {
+        for (source, issue) in &self.issues {
+            log::warn!("{source}: {issue}");
+        }
+    }
+}
+
+pub fn validate_texture_size(width: u32, height: u32) -> Option<ValidationIssue> {
+    (width > OVERSIZED_TEXTURE_DIMENSION || height > OVERSIZED_TEXTURE_DIMENSION)
+        .then_some(ValidationIssue::OversizedTexture { width, height })
+}
+
+pub fn validate_mesh(data: &MeshData, faces: FaceSettings) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let missing_normals = data.vertices.iter()
+        .filter(|v| Vector3::from(v.normal).magnitude2() == 0.0)
+        .count();
+    if missing_normals > 0 {
+        issues.push(ValidationIssue::MissingNormals { count: missing_normals });
+    }
+
+    let nans = data.vertices.iter()
+        .filter(|v| v.position.iter().chain(&v.normal).chain(&v.tex_coords).any(|x| x.is_nan()))
+        .count();
+    if nans > 0 {
+        issues.push(ValidationIssue::NanVertices { count: nans });
+    }
+
+    let out_of_range = data.indices.iter().filter(|&&i| i as usize >= data.vertices.len()).count();
+    if out_of_range > 0 {
+        // Everything below indexes the vertices, so stop here
+        issues.push(ValidationIssue::IndicesOutOfRange { count: out_of_range });
+        return issues;
+    }
+
+    let mut degenerate = 0;
+    // How many triangles use each (undirected) edge
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in data.indices.chunks_exact(3) {
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+        if data.triangle_normal(a, b, c).magnitude2() <= DEGENERATE_AREA {
+            degenerate += 1;
+        }
+        for (i, j) in [(a, b), (b, c), (c, a)] {
+            *edges.entry((i.min(j), i.max(j))).or_default() += 1;
+        }
+    }
+    if degenerate > 0 {
+        issues.push(ValidationIssue::DegenerateTriangles { count: degenerate });
+    }
+    let non_manifold = edges.values().filter(|&&n| n > 2).count();
+    if non_manifold > 0 {
+        issues.push(ValidationIssue::NonManifoldEdges { count: non_manifold });
+    }
+
+    // Winding only matters when one side is culled
+    if faces.cull_mode.is_some() && data.is_inverted(faces.front_face) {
+        issues.push(ValidationIssue::InvertedWinding);
+    }
+
+    issues
+}
@@ -0,0 +1,57 @@
+// One camera drawn into part of the target, for split screen and picture in picture. Views are
+// drawn in order, each over the ones before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SceneView {
+    // Index into the scene's cameras
+    pub camera: usize,
+    // x, y, width and height as fractions of the target, from the top left
+    pub rect: [f32; 4],
+}
+
+impl SceneView {
+    pub fn new(camera: usize, rect: [f32; 4]) -> Self {
+        Self { camera, rect }
+    }
+
+    // The whole target
+    pub fn full(camera: usize) -> Self {
+        Self::new(camera, [0.0, 0.0, 1.0, 1.0])
+    }
+
+    // Side by side columns, one per camera
+    pub fn split_columns(cameras: &[usize]) -> Vec<Self> {
+        let width = 1.0 / cameras.len().max(1) as f32;
+        cameras.iter().enumerate()
+            .map(|(i, &camera)| Self::new(camera, [i as f32 * width, 0.0, width, 1.0]))
+            .collect()
+    }
+
+    // Stacked rows, one per camera
+    pub fn split_rows(cameras: &[usize]) -> Vec<Self> {
+        let height = 1.0 / cameras.len().max(1) as f32;
+        cameras.iter().enumerate()
+            .map(|(i, &camera)| Self::new(camera, [0.0, i as f32 * height, 1.0, height]))
+            .collect()
+    }
+
+    // main fills the target and inset is drawn over it in rect
+    pub fn picture_in_picture(main: usize, inset: usize, rect: [f32; 4]) -> Vec<Self> {
+        vec![Self::full(main), Self::new(inset, rect)]
+    }
+
+    // x, y, width and height in pixels of a target this size, None if it covers no pixels
+    pub fn pixel_rect(&self, size: [u32; 2]) -> Option<[u32; 4]> {
+        let edge = |fraction: f32, size: u32| ((fraction * size as f32).round().max(0.0) as u32).min(size);
+        let x0 = edge(self.rect[0], size[0]);
+        let y0 = edge(self.rect[1], size[1]);
+        let x1 = edge(self.rect[0] + self.rect[2], size[0]);
+        let y1 = edge(self.rect[1] + self.rect[3], size[1]);
+        (x1 > x0 && y1 > y0).then_some([x0, y0, x1 - x0, y1 - y0])
+    }
+
+    // Whether a pixel of a target this size is inside the view
+    pub fn contains(&self, pixel: [f32; 2], size: [u32; 2]) -> bool {
+        let Some([x, y, width, height]) = self.pixel_rect(size) else { return false };
+        pixel[0] >= x as f32 && pixel[0] < (x + width) as f32 && pixel[1] >= y as f32 && pixel[1] < (y + height) as f32
+    }
+}
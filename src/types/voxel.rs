@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+use crate::types::{color::Color, geometry::Vertex, mesh::MeshData};
+
+// 0 is always air, everything else is looked up in the world's palette
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+// Chunks are CHUNK_SIZE blocks along each side
+pub const CHUNK_SIZE: usize = 16;
+const CHUNK_VOLUME: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+pub type ChunkPos = [i32; 3];
+
+#[derive(Clone)]
+pub struct Chunk {
+    blocks: Box<[BlockId]>,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self { blocks: vec![AIR; CHUNK_VOLUME].into_boxed_slice() }
+    }
+}
+
+impl Chunk {
+    fn index(local: [usize; 3]) -> usize {
+        local[0] + local[1] * CHUNK_SIZE + local[2] * CHUNK_SIZE * CHUNK_SIZE
+    }
+
+    pub fn get(&self, local: [usize; 3]) -> BlockId {
+        self.blocks[Self::index(local)]
+    }
+
+    pub fn set(&mut self, local: [usize; 3], block: BlockId) {
+        self.blocks[Self::index(local)] = block;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|&block| block == AIR)
+    }
+}
+
+// Block grid split into chunks, each of which becomes one mesh drawn at the chunk's position
+pub struct VoxelWorld {
+    // Color of each block id, index 0 (air) is never drawn
+    pub palette: Vec<Color>,
+    // Length of a block's side in world units
+    pub block_size: f32,
+    chunks: HashMap<ChunkPos, Chunk>,
+    dirty: HashSet<ChunkPos>,
+    // Which mesh each chunk was uploaded as
+    meshes: HashMap<ChunkPos, usize>,
+}
+
+// An empty palette, with the same block size as new
+impl Default for VoxelWorld {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+impl VoxelWorld {
+    pub fn new(palette: Vec<Color>) -> Self {
+        Self {
+            palette,
+            block_size: 1.0,
+            chunks: HashMap::new(),
+            dirty: HashSet::new(),
+            meshes: HashMap::new(),
+        }
+    }
+
+    fn split(block: [i32; 3]) -> (ChunkPos, [usize; 3]) {
+        let size = CHUNK_SIZE as i32;
+        (
+            block.map(|x| x.div_euclid(size)),
+            block.map(|x| x.rem_euclid(size) as usize),
+        )
+    }
+
+    pub fn get_block(&self, block: [i32; 3]) -> BlockId {
+        let (chunk, local) = Self::split(block);
+        self.chunks.get(&chunk).map_or(AIR, |chunk| chunk.get(local))
+    }
+
+    pub fn set_block(&mut self, block: [i32; 3], id: BlockId) {
+        let (chunk_pos, local) = Self::split(block);
+        if id == AIR && !self.chunks.contains_key(&chunk_pos) {
+            return;
+        }
+        self.chunks.entry(chunk_pos).or_default().set(local, id);
+        self.dirty.insert(chunk_pos);
+
+        // Faces on the boundary are hidden or revealed in the neighbouring chunk too
+        for axis in 0..3 {
+            let mut neighbour = chunk_pos;
+            if local[axis] == 0 {
+                neighbour[axis] -= 1;
+            } else if local[axis] == CHUNK_SIZE - 1 {
+                neighbour[axis] += 1;
+            } else {
+                continue;
+            }
+            if self.chunks.contains_key(&neighbour) {
+                self.dirty.insert(neighbour);
+            }
+        }
+    }
+
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    // World position of a chunk's corner
+    pub fn chunk_origin(&self, pos: ChunkPos) -> [f64; 3] {
+        pos.map(|x| (x as f64 * CHUNK_SIZE as f64) * self.block_size as f64)
+    }
+
+    pub fn mesh_id(&self, pos: ChunkPos) -> Option<usize> {
+        self.meshes.get(&pos).copied()
+    }
+
+    pub fn set_mesh_id(&mut self, pos: ChunkPos, id: usize) {
+        self.meshes.insert(pos, id);
+    }
+
+    // Remeshes every chunk that changed since the last call
+    pub fn take_dirty_meshes(&mut self) -> Vec<(ChunkPos, MeshData)> {
+        let dirty = std::mem::take(&mut self.dirty);
+        dirty.into_iter().map(|pos| (pos, self.greedy_mesh(pos))).collect()
+    }
+
+    // Builds a mesh of the faces in a chunk that touch air, merging neighbouring faces of the
+    // same block into as few quads as possible. Positions are relative to the chunk's origin
+    pub fn greedy_mesh(&self, pos: ChunkPos) -> MeshData {
+        let mut mesh = MeshData::default();
+        let Some(chunk) = self.chunks.get(&pos) else { return mesh };
+        let base = pos.map(|x| x * CHUNK_SIZE as i32);
+        let n = CHUNK_SIZE;
+        let mut mask = vec![AIR; n * n];
+
+        for axis in 0..3 {
+            // The two axes spanning each slice, in the order that makes u x v = axis
+            let u = (axis + 1) % 3;
+            let v = (axis + 2) % 3;
+            for dir in [-1i32, 1] {
+                for slice in 0..n {
+                    // Which blocks in this slice have a visible face pointing along dir
+                    for j in 0..n {
+                        for i in 0..n {
+                            let mut local = [0; 3];
+                            local[axis] = slice;
+                            local[u] = i;
+                            local[v] = j;
+                            let block = chunk.get(local);
+                            let mut neighbour = [0; 3];
+                            for k in 0..3 {
+                                neighbour[k] = base[k] + local[k] as i32;
+                            }
+                            neighbour[axis] += dir;
+                            mask[i + j * n] = if block != AIR && self.get_block(neighbour) == AIR { block } else { AIR };
+                        }
+                    }
+
+                    for j in 0..n {
+                        let mut i = 0;
+                        while i < n {
+                            let block = mask[i + j * n];
+                            if block == AIR {
+                                i += 1;
+                                continue;
+                            }
+                            let mut width = 1;
+                            while i + width < n && mask[i + width + j * n] == block {
+                                width += 1;
+                            }
+                            let mut height = 1;
+                            'grow: while j + height < n {
+                                for k in 0..width {
+                                    if mask[i + k + (j + height) * n] != block {
+                                        break 'grow;
+                                    }
+                                }
+                                height += 1;
+                            }
+
+                            let mut corner = [0.0; 3];
+                            corner[axis] = (slice as i32 + (dir > 0) as i32) as f32;
+                            corner[u] = i as f32;
+                            corner[v] = j as f32;
+                            self.push_quad(&mut mesh, corner, axis, u, v, width as f32, height as f32, dir, block);
+
+                            for dy in 0..height {
+                                for dx in 0..width {
+                                    mask[i + dx + (j + dy) * n] = AIR;
+                                }
+                            }
+                            i += width;
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_quad(&self, mesh: &mut MeshData, corner: [f32; 3], axis: usize, u: usize, v: usize, width: f32, height: f32, dir: i32, block: BlockId) {
+        let color = self.palette.get(block as usize).copied().unwrap_or(Color::new(1.0, 1.0, 1.0));
+        let mut normal = [0.0; 3];
+        normal[axis] = dir as f32;
+
+        let base = mesh.vertices.len() as u32;
+        for (du, dv) in [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)] {
+            let mut position = corner;
+            position[u] += du;
+            position[v] += dv;
+            let mut vertex = Vertex::new(position.map(|x| x * self.block_size), color);
+            vertex.normal = normal;
+            // One texture repeat per block
+            vertex.tex_coords = [du, dv];
+            mesh.vertices.push(vertex);
+        }
+        // u x v points along +axis, and front faces are clockwise seen from outside
+        if dir > 0 {
+            mesh.indices.extend_from_slice(&[base, base + 2, base + 1, base, base + 3, base + 2]);
+        } else {
+            mesh.indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::HashMap;
+
+use cgmath::Vector3;
+use wgpu::util::DeviceExt;
+
+use crate::types::{color::Color, shader_features, texture::Texture};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precipitation {
+    // Thin streaks stretched along the way they fall
+    Rain,
+    // Round flakes that sway as they drift down
+    Snow,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct WeatherSettings {
+    pub precipitation: Precipitation,
+    // 0 to 1, scales how many particles fall and how quickly rain soaks the scene
+    pub intensity: f32,
+    // How many fall at an intensity of 1
+    pub max_particles: u32,
+    // Size of the box around the camera they fall through
+    pub area: f32,
+    // Units per second, straight down
+    pub fall_speed: f32,
+    // Added to the fall, in units per second
+    pub wind: Vector3<f32>,
+    // Alpha is how see-through each particle is
+    pub color: Color,
+    // Width of a streak or flake
+    pub size: f32,
+    // How long rain streaks are, unused for snow
+    pub streak_length: f32,
+    // How far flakes swing from side to side, unused for rain
+    pub sway: f32,
+    // Particles fade out over this distance before reaching a surface. Only without MSAA, when
+    // the scene's depth can be read, otherwise they're cut off where they go behind something
+    pub fade_distance: f32,
+    // Seconds of full intensity rain until lit materials are soaked, and for them to dry off
+    // again once it stops
+    pub soak_time: f32,
+    pub dry_time: f32,
+}
+
+impl WeatherSettings {
+    pub fn rain() -> Self {
+        Self {
+            precipitation: Precipitation::Rain,
+            intensity: 1.0,
+            max_particles: 8000,
+            area: 24.0,
+            fall_speed: 9.0,
+            wind: Vector3::new(0.5, 0.0, 0.2),
+            color: Color::new_rgba(0.7, 0.75, 0.8, 0.35),
+            size: 0.015,
+            streak_length: 0.5,
+            sway: 0.0,
+            fade_distance: 0.5,
+            soak_time: 20.0,
+            dry_time: 60.0,
+        }
+    }
+
+    pub fn snow() -> Self {
+        Self {
+            precipitation: Precipitation::Snow,
+            intensity: 1.0,
+            max_particles: 6000,
+            area: 20.0,
+            fall_speed: 1.0,
+            wind: Vector3::new(0.2, 0.0, 0.1),
+            color: Color::new_rgba(1.0, 1.0, 1.0, 0.9),
+            size: 0.04,
+            streak_length: 0.0,
+            sway: 0.3,
+            fade_distance: 0.2,
+            soak_time: 20.0,
+            dry_time: 60.0,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    pub fn with_wind(mut self, wind: Vector3<f32>) -> Self {
+        self.wind = wind;
+        self
+    }
+
+    // How many particles are drawn
+    pub fn particle_count(&self) -> u32 {
+        (self.max_particles as f32 * self.intensity.clamp(0.0, 1.0)) as u32
+    }
+
+    // How wet lit surfaces get if it carries on for long enough, snow doesn't wet anything
+    pub fn target_wetness(&self) -> f32 {
+        match self.precipitation {
+            Precipitation::Rain => self.intensity.clamp(0.0, 1.0),
+            Precipitation::Snow => 0.0,
+        }
+    }
+}
+
+impl Default for WeatherSettings {
+    fn default() -> Self {
+        Self::rain()
+    }
+}
+
+// Moves wetness towards where the weather is taking it over dt seconds. Without any weather
+// surfaces dry off over the default dry_time
+pub fn step_wetness(wetness: f32, settings: Option<&WeatherSettings>, dt: f32) -> f32 {
+    let default = WeatherSettings::default();
+    let settings = settings.unwrap_or(&default);
+    let target = settings.target_wetness();
+    if wetness < target {
+        (wetness + dt / settings.soak_time.max(1e-3)).min(target)
+    } else {
+        (wetness - dt / settings.dry_time.max(1e-3)).max(target)
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct WeatherUniform {
+    color: [f32; 4],
+    // Fall plus wind, w is 0 for rain and 1 for snow
+    velocity: [f32; 4],
+    // Area, time, size, streak length
+    shape: [f32; 4],
+    // Fade distance, sway
+    fade: [f32; 4],
+}
+
+impl WeatherUniform {
+    fn new(settings: &WeatherSettings, time: f32) -> Self {
+        let velocity = settings.wind - Vector3::unit_y() * settings.fall_speed;
+        let snow = if settings.precipitation == Precipitation::Snow { 1.0 } else { 0.0 };
+        Self {
+            color: settings.color.buffer(),
+            velocity: [velocity.x, velocity.y, velocity.z, snow],
+            shape: [settings.area.max(1e-3), time, settings.size, settings.streak_length],
+            fade: [settings.fade_distance, settings.sway, 0.0, 0.0],
+        }
+    }
+}
+
+// Precipitation drawn in a pass of its own after each view, so it can read the depth the scene
+// left behind. Created by SceneRenderer::set_weather
+pub struct Weather {
+    settings: WeatherSettings,
+    // Seconds the particles have been falling for
+    time: f32,
+    format: wgpu::TextureFormat,
+    shader: wgpu::ShaderModule,
+    // Without and with the scene's depth bound
+    layout: wgpu::PipelineLayout,
+    soft_layout: wgpu::PipelineLayout,
+    depth_layout: wgpu::BindGroupLayout,
+    // By sample count and depth direction. Single sampled pipelines test depth in the shader
+    pipelines: HashMap<(u32, bool), wgpu::RenderPipeline>,
+    buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    // Made each frame, the depth texture is replaced on resize
+    depth_bind_group: Option<wgpu::BindGroup>,
+}
+
+impl Weather {
+    pub fn new(device: &wgpu::Device, camera_layout: &wgpu::BindGroupLayout, format: wgpu::TextureFormat, settings: WeatherSettings) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor { label: Some("Weather Shader"), source: wgpu::ShaderSource::Wgsl(shader_features::compose(include_str!("../weather.wgsl")).into()) });
+        let weather_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("weather_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let depth_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("weather_depth_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                // Read as a plain float texture, GL can't load from depth textures
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Weather Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &weather_layout],
+            push_constant_ranges: &[],
+        });
+        let soft_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Soft Weather Pipeline Layout"),
+            bind_group_layouts: &[camera_layout, &weather_layout, &depth_layout],
+            push_constant_ranges: &[],
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Weather Buffer"),
+            contents: bytemuck::bytes_of(&WeatherUniform::new(&settings, 0.0)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("weather_bind_group"),
+            layout: &weather_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: buffer.as_entire_binding() }],
+        });
+        Self {
+            settings,
+            time: 0.0,
+            format,
+            shader,
+            layout,
+            soft_layout,
+            depth_layout,
+            pipelines: HashMap::new(),
+            buffer,
+            bind_group,
+            depth_bind_group: None,
+        }
+    }
+
+    pub fn settings(&self) -> &WeatherSettings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: WeatherSettings) {
+        self.settings = settings;
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+    }
+
+    // The pipelines are made again for the new format on the next prepare
+    pub fn set_format(&mut self, format: wgpu::TextureFormat) {
+        self.format = format;
+        self.pipelines.clear();
+    }
+
+    fn pipeline(&mut self, device: &wgpu::Device, sample_count: u32, reversed_z: bool) {
+        if self.pipelines.contains_key(&(sample_count, reversed_z)) {
+            return;
+        }
+        let soft = sample_count == 1;
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("Weather Pipeline ({sample_count} samples{})", if reversed_z { ", reversed z" } else { "" })),
+            layout: Some(if soft { &self.soft_layout } else { &self.layout }),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: if soft { "fs_soft" } else { "fs_main" },
+                targets: &[Some(wgpu::ColorTargetState { format: self.format, blend: Some(wgpu::BlendState::ALPHA_BLENDING), write_mask: wgpu::ColorWrites::ALL })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            // Turned to face the camera, mirrored views included
+            primitive: wgpu::PrimitiveState::default(),
+            // Read only, particles never hide each other
+            depth_stencil: (!soft).then(|| wgpu::DepthStencilState {
+                format: Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: if reversed_z { wgpu::CompareFunction::Greater } else { wgpu::CompareFunction::Less },
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: sample_count, ..Default::default() },
+            multiview: None,
+            cache: None,
+        });
+        self.pipelines.insert((sample_count, reversed_z), pipeline);
+    }
+
+    // Uploads the settings and builds what the coming passes need, render passes can't. depth is
+    // the scene's depth texture, only read when it isn't multisampled
+    pub(crate) fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, depth: &wgpu::TextureView, sample_count: u32, reversed_z: impl IntoIterator<Item = bool>) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&WeatherUniform::new(&self.settings, self.time)));
+        for reversed_z in reversed_z {
+            self.pipeline(device, sample_count, reversed_z);
+        }
+        self.depth_bind_group = (sample_count == 1).then(|| device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("weather_depth_bind_group"),
+            layout: &self.depth_layout,
+            entries: &[wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(depth) }],
+        }));
+    }
+
+    // Whether draw wants the scene's depth attached read only, rather than bound for the shader
+    pub(crate) fn needs_depth_attachment(sample_count: u32) -> bool {
+        sample_count > 1
+    }
+
+    // Draws into a pass that has the camera bound and the scene's color loaded
+    pub(crate) fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, sample_count: u32, reversed_z: bool) {
+        let count = self.settings.particle_count();
+        if count == 0 {
+            return;
+        }
+        render_pass.push_debug_group("Weather");
+        render_pass.set_pipeline(&self.pipelines[&(sample_count, reversed_z)]);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        if let Some(depth) = &self.depth_bind_group {
+            render_pass.set_bind_group(2, depth, &[]);
+        }
+        render_pass.draw(0..6, 0..count);
+        render_pass.pop_debug_group();
+    }
+}
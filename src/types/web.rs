@@ -0,0 +1,56 @@
+use std::rc::Rc;
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use winit::{dpi::PhysicalSize, platform::web::WindowExtWebSys, window::Window};
+
+// Puts the window's canvas on the page, winit creates it but leaves it detached
+pub fn attach_canvas(window: &Window) {
+    let canvas = window.canvas().expect("window has no canvas");
+    web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.body())
+        .and_then(|body| body.append_child(&canvas).ok())
+        .expect("couldn't add the canvas to the page");
+}
+
+// Size the surface should be to match the canvas's CSS size at full pixel density, which is what a
+// resize on native amounts to. Pages size the canvas with CSS, so this can change at any time
+pub fn canvas_size(window: &Window) -> Option<PhysicalSize<u32>> {
+    let canvas = window.canvas()?;
+    let scale = web_sys::window()?.device_pixel_ratio();
+    let width = (canvas.client_width() as f64 * scale).round() as u32;
+    let height = (canvas.client_height() as f64 * scale).round() as u32;
+    Some(PhysicalSize::new(width, height))
+}
+
+// Resizes the window to fit the canvas if they've drifted apart. winit sends a Resized event for it,
+// which goes through the usual resize path
+pub fn sync_canvas_size(window: &Window) {
+    if let Some(size) = canvas_size(window) {
+        if size.width > 0 && size.height > 0 && size != window.inner_size() {
+            let _ = window.request_inner_size(size);
+        }
+    }
+}
+
+// Calls f when the page is hidden or going away. Browsers don't ask before closing a tab, so this is
+// the last chance to save anything. Mobile browsers often skip pagehide and only send the
+// visibilitychange, so both are listened for
+pub fn on_page_hide(f: impl Fn() + 'static) {
+    let Some(window) = web_sys::window() else { return };
+    let f = Rc::new(f);
+    let hide = {
+        let f = f.clone();
+        Closure::<dyn FnMut()>::new(move || f())
+    };
+    let visibility = Closure::<dyn FnMut()>::new(move || {
+        if web_sys::window().and_then(|window| window.document()).is_some_and(|document| document.hidden()) {
+            f();
+        }
+    });
+    let _ = window.add_event_listener_with_callback("pagehide", hide.as_ref().unchecked_ref());
+    let _ = window.add_event_listener_with_callback("visibilitychange", visibility.as_ref().unchecked_ref());
+    // They're needed for as long as the page is around
+    hide.forget();
+    visibility.forget();
+}
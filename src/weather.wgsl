@@ -0,0 +1,110 @@
+// Rain streaks and snowflakes in a box that follows the camera, see types::weather
+#include "camera.wgsl"
+
+struct WeatherUniform {
+    // rgb, a is the opacity
+    color: vec4<f32>,
+    // xyz is the fall and wind together, w is 0 for rain and 1 for snow
+    velocity: vec4<f32>,
+    // (area, time, size, streak length)
+    shape: vec4<f32>,
+    // (fade distance, sway, 0, 0)
+    fade: vec4<f32>,
+};
+@group(1) @binding(0)
+var<uniform> weather: WeatherUniform;
+
+// The scene's depth, only bound when it isn't multisampled
+@group(2) @binding(0)
+var scene_depth: texture_2d<f32>;
+
+fn hash(n: u32) -> f32 {
+    var x = n * 747796405u + 2891336453u;
+    x = ((x >> ((x >> 28u) + 4u)) ^ x) * 277803737u;
+    x = (x >> 22u) ^ x;
+    return f32(x) / 4294967295.0;
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // -1 to 1 across the particle
+    @location(0) corner: vec2<f32>,
+    // Distance in front of the camera, to compare with the scene's depth
+    @location(1) view_depth: f32,
+    @location(2) alpha: f32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+    );
+    let corner = corners[vertex];
+    let area = weather.shape.x;
+    let time = weather.shape.y;
+    let snow = weather.velocity.w > 0.5;
+
+    // Each particle falls through the world forever, and is wrapped back into the box around
+    // the camera so the same few thousand are always nearby without ever being respawned
+    let seed = vec3<f32>(hash(instance * 4u), hash(instance * 4u + 1u), hash(instance * 4u + 2u));
+    let speed = mix(0.8, 1.2, hash(instance * 4u + 3u));
+    var position = seed * area + weather.velocity.xyz * speed * time;
+    if snow {
+        let phase = seed.x * 40.0 + time * mix(0.8, 1.6, seed.z);
+        position += vec3<f32>(sin(phase), 0.0, cos(phase * 0.7)) * weather.fade.y;
+    }
+    let eye = camera.position.xyz;
+    let offset = position - eye;
+    let wrapped = offset - area * floor(offset / area + 0.5);
+    let center = eye + wrapped;
+
+    var side: vec3<f32>;
+    var along: vec3<f32>;
+    if snow {
+        side = camera.inv_view[0].xyz * weather.shape.z * 0.5;
+        along = camera.inv_view[1].xyz * weather.shape.z * 0.5;
+    } else {
+        // Streaks are stretched along the way they're falling and turned to face the eye
+        along = normalize(weather.velocity.xyz) * weather.shape.w * 0.5;
+        side = normalize(cross(along, eye - center)) * weather.shape.z * 0.5;
+    }
+    let world = center + side * corner.x + along * corner.y;
+
+    var out: VertexOutput;
+    out.corner = corner;
+    out.view_depth = dot(world - eye, -camera.inv_view[2].xyz);
+    // Thinned out towards the edge of the box so particles don't pop in and out as they wrap
+    out.alpha = 1.0 - smoothstep(area * 0.35, area * 0.5, length(wrapped));
+    out.clip_position = apply_depth_mode(camera.view_proj * vec4<f32>(world, 1.0));
+    return out;
+}
+
+fn coverage(in: VertexOutput) -> f32 {
+    var shape: f32;
+    if weather.velocity.w > 0.5 {
+        shape = 1.0 - smoothstep(0.5, 1.0, length(in.corner));
+    } else {
+        shape = (1.0 - in.corner.x * in.corner.x) * (1.0 - in.corner.y * in.corner.y);
+    }
+    return shape * in.alpha * weather.color.a;
+}
+
+// Hidden behind the scene by the depth test
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(weather.color.rgb, coverage(in));
+}
+
+// Tested against the scene's depth by hand, fading out as particles reach whatever they're about
+// to hit instead of cutting off in a hard line
+@fragment
+fn fs_soft(in: VertexOutput) -> @location(0) vec4<f32> {
+    let depth = textureLoad(scene_depth, vec2<i32>(in.clip_position.xy), 0).r;
+    let surface = linearize_depth(depth, camera.projection_params.x);
+    if in.view_depth > surface {
+        discard;
+    }
+    let fade = saturate((surface - in.view_depth) / max(weather.fade.x, 1e-4));
+    return vec4<f32>(weather.color.rgb, coverage(in) * fade);
+}